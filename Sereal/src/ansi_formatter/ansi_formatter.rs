@@ -1,135 +1,484 @@
+use super::inspector;
+use super::osc::{OscCommand, parse_osc};
+use super::parser::{AnsiEvent, AnsiParser, CsiSequence};
+use super::text_style::{StyleOptions, TextStyle};
 use crate::sereal_colors;
-use ansi_parser::AnsiSequence;
-use ansi_parser::{AnsiParser, Output};
-use eframe::egui::RichText;
+use crate::ui::line_store::{Annotation, LineStore, SpanStyle};
+use eframe::egui::text::TextFormat;
+use eframe::egui::{FontFamily, FontId};
+use getset::{Getters, MutGetters};
 
-#[derive(Default)]
+/// ログ表示に用いるフォント
+pub const LOG_FONT: FontId = FontId::new(13.0, FontFamily::Monospace);
+
+/// 復帰 (CR) の扱い
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum CarriageReturnMode {
+    /// 行頭に戻り、続く文字で行を上書きする (CRLF は 1 つの改行)
+    #[default]
+    Overwrite,
+    /// CR だけで改行する (CRLF は 1 つの改行)
+    LineBreak,
+}
+
+/// ログの行の区切り方
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct LineOptions {
+    pub carriage_return_mode: CarriageReturnMode,
+    /// 制御文字や不正なバイトを ␍ や ⟨FF⟩ のような記号として表示する
+    ///
+    /// CR と BS は解釈せず、LF は記号を表示してから改行する。行末の空白も記号にする。
+    pub is_show_control_chars: bool,
+    /// エスケープシーケンスを ⟨ESC[31m⟩ のような記号としても表示する
+    pub is_show_escape_sequences: bool,
+}
+
+/// デバイスから端末への問い合わせ
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TerminalQuery {
+    /// ESC [ 5 n: 端末の状態 (DSR)
+    DeviceStatus,
+    /// ESC [ 6 n: カーソル位置 (CPR)
+    CursorPosition,
+    /// ESC [ c: 端末の種類 (Primary DA)
+    PrimaryDeviceAttributes,
+    /// ESC [ > c: 端末のバージョン (Secondary DA)
+    SecondaryDeviceAttributes,
+}
+
+impl TerminalQuery {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TerminalQuery::DeviceStatus => "device status report",
+            TerminalQuery::CursorPosition => "cursor position report",
+            TerminalQuery::PrimaryDeviceAttributes => "primary device attributes",
+            TerminalQuery::SecondaryDeviceAttributes => "secondary device attributes",
+        }
+    }
+
+    pub fn sequence(&self) -> &'static [u8] {
+        match self {
+            TerminalQuery::DeviceStatus => b"\x1b[5n",
+            TerminalQuery::CursorPosition => b"\x1b[6n",
+            TerminalQuery::PrimaryDeviceAttributes => b"\x1b[c",
+            TerminalQuery::SecondaryDeviceAttributes => b"\x1b[>c",
+        }
+    }
+
+    /// 標準的な応答を返す。カーソル位置は 1 始まりで指定する
+    pub fn response(&self, cursor_row: usize, cursor_column: usize) -> Vec<u8> {
+        match self {
+            // 正常に動作している
+            TerminalQuery::DeviceStatus => b"\x1b[0n".to_vec(),
+            TerminalQuery::CursorPosition => {
+                format!("\x1b[{cursor_row};{cursor_column}R").into_bytes()
+            }
+            // VT100 with Advanced Video Option
+            TerminalQuery::PrimaryDeviceAttributes => b"\x1b[?1;2c".to_vec(),
+            // VT100, ファームウェアバージョン 0
+            TerminalQuery::SecondaryDeviceAttributes => b"\x1b[>0;0;0c".to_vec(),
+        }
+    }
+}
+
+/// 制御文字を含むバイト列を `ESC[6n` のような読める形式に変換する
+pub fn printable_sequence(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for &byte in bytes {
+        match byte {
+            0x1b => text.push_str("ESC"),
+            0x00..=0x1f | 0x7f => text.push_str(&format!("<{byte:02X}>")),
+            _ => text.push_str(&String::from_utf8_lossy(&[byte])),
+        }
+    }
+    text
+}
+
+/// 受信したバイト列を逐次解釈し、書式付きの行として LineStore に書き込む
+///
+/// 書式の状態は行や受信の区切りをまたいで引き継がれる。
+#[derive(Default, Clone, Getters, MutGetters)]
 pub struct AnsiFormatter {
-    color_set: ColorSet,
+    parser: AnsiParser,
+    text_style: TextStyle,
+    #[get = "pub"]
+    #[get_mut = "pub"]
+    style_options: StyleOptions,
+    #[get_mut = "pub"]
+    line_options: LineOptions,
+    is_after_carriage_return: bool, // CRLF の LF を読み飛ばすため
+    queries: Vec<TerminalQuery>,    // 未応答の問い合わせ
+    /// デバイスが OSC 0/2 で設定したタイトル
+    #[get = "pub"]
+    title: Option<String>,
+    link: Option<String>, // OSC 8 で開始したリンクの URL
 }
 
 impl AnsiFormatter {
-    pub fn to_rich_text(&mut self, text: &String) -> Vec<egui::RichText> {
-        let mut rich_texts = Vec::new();
-
-        for block in text.ansi_parse() {
-            // ANSI タグの結果を反映した RichText
-            let mut rich_text = RichText::default();
-            // ANSI タグをパースした色情報を管理する構造体
-            let mut updated_color_set = ColorSet::default();
-
-            match block {
-                Output::TextBlock(text) => rich_text = RichText::from(text),
-                Output::Escape(seq) => match seq {
-                    AnsiSequence::SetGraphicsMode(params) => {
-                        updated_color_set = parse_to_colorset(params.to_vec());
-                    }
-                    _ => {}
-                },
+    pub fn feed(&mut self, bytes: &[u8], line_store: &mut LineStore) {
+        for (event, raw) in self.parser.feed_with_raw(bytes) {
+            if self.line_options.is_show_escape_sequences {
+                self.append_sequence_token(&event, &raw, line_store);
             }
-
-            if let Some(text_color) = updated_color_set.text_color {
-                rich_text = rich_text.color(text_color);
-                self.color_set.text_color = Some(text_color);
-            } else if let Some(text_color) = self.color_set.text_color {
-                rich_text = rich_text.color(text_color);
-            } else {
-                rich_text = rich_text.color(sereal_colors::WHITE.to_egui_color32());
+            let is_carriage_return = event == AnsiEvent::Control(b'\r');
+            match event {
+                AnsiEvent::Text(text) => self.append_text(&text, line_store),
+                AnsiEvent::InvalidBytes(bytes) => self.append_invalid_bytes(&bytes, line_store),
+                AnsiEvent::Control(control) => self.execute(control, line_store),
+                AnsiEvent::Csi(csi) => self.apply_csi(&csi),
+                AnsiEvent::Osc(data) => self.apply_osc(&data),
+                // それ以外は何もしない
+                _ => {}
             }
+            self.is_after_carriage_return = is_carriage_return;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.parser.reset();
+        self.text_style = TextStyle::default();
+        self.is_after_carriage_return = false;
+        self.queries.clear();
+        self.title = None;
+        self.link = None;
+    }
 
-            if let Some(back_color) = updated_color_set.background_color {
-                rich_text = rich_text.background_color(back_color);
-                self.color_set.background_color = Some(back_color);
-            } else if let Some(back_color) = self.color_set.background_color {
-                rich_text = rich_text.background_color(back_color);
+    /// 受信した問い合わせを取り出す
+    pub fn take_queries(&mut self) -> Vec<TerminalQuery> {
+        std::mem::take(&mut self.queries)
+    }
+
+    fn execute(&mut self, control: u8, line_store: &mut LineStore) {
+        let line_options = self.line_options;
+        // CR で改行済みなら CRLF の LF は無視する
+        let is_line_broken_by_carriage_return = line_options.carriage_return_mode
+            == CarriageReturnMode::LineBreak
+            && self.is_after_carriage_return
+            && !line_options.is_show_control_chars;
+
+        if line_options.is_show_control_chars {
+            let symbol = control_picture(control);
+            let style = symbol_style(
+                sereal_colors::UI_GRAY,
+                format!("{} (0x{control:02X})", control_name(control)),
+            );
+            line_store.append(&symbol.to_string(), &style);
+            if control == b'\n' {
+                line_store.mark_trailing_spaces(TRAILING_SPACE_SYMBOL, &style);
+                line_store.end_line();
             }
+            return;
+        }
+
+        match control {
+            b'\n' if is_line_broken_by_carriage_return => {}
+            b'\n' => line_store.end_line(),
+            b'\t' => line_store.append("\t", &self.span_style()),
+            b'\r' => match line_options.carriage_return_mode {
+                CarriageReturnMode::Overwrite => line_store.carriage_return(),
+                CarriageReturnMode::LineBreak => line_store.end_line(),
+            },
+            0x08 => line_store.backspace(),
+            // それ以外は何もしない
+            _ => {}
+        }
+    }
 
-            if updated_color_set.is_reset {
-                self.color_set = ColorSet::default();
+    fn apply_csi(&mut self, csi: &CsiSequence) {
+        if !csi.intermediates.is_empty() {
+            return;
+        }
+
+        // 書式の変更 (SGR) と問い合わせのみを解釈する
+        let query = match (csi.private_marker, csi.final_byte) {
+            (None, b'm') => {
+                self.text_style.apply_graphics_params(&csi.params);
+                None
+            }
+            (None, b'n') => match csi.param(0) {
+                5 => Some(TerminalQuery::DeviceStatus),
+                6 => Some(TerminalQuery::CursorPosition),
+                _ => None,
+            },
+            (None, b'c') if csi.param(0) == 0 => Some(TerminalQuery::PrimaryDeviceAttributes),
+            (Some(b'>'), b'c') if csi.param(0) == 0 => {
+                Some(TerminalQuery::SecondaryDeviceAttributes)
             }
+            _ => None,
+        };
+        self.queries.extend(query);
+    }
+
+    fn append_text(&self, text: &str, line_store: &mut LineStore) {
+        if !self.line_options.is_show_control_chars {
+            line_store.append(text, &self.span_style());
+            return;
+        }
+
+        // UTF-8 で符号化された C1 制御文字は記号にする
+        let mut rest = text;
+        while let Some(position) = rest.find(is_c1_control) {
+            let (before, after) = rest.split_at(position);
+            line_store.append(before, &self.span_style());
+            let ch = after.chars().next().unwrap_or_default();
+            let style = symbol_style(
+                sereal_colors::UI_ORANGE,
+                format!("C1 control character (U+{:04X})", u32::from(ch)),
+            );
+            line_store.append(&format!("⟨{:02X}⟩", u32::from(ch)), &style);
+            rest = &after[ch.len_utf8()..];
+        }
+        if !rest.is_empty() {
+            line_store.append(rest, &self.span_style());
+        }
+    }
+
+    fn append_invalid_bytes(&self, bytes: &[u8], line_store: &mut LineStore) {
+        if !self.line_options.is_show_control_chars {
+            line_store.append("\u{FFFD}", &self.span_style());
+            return;
+        }
+
+        let style = symbol_style(sereal_colors::UI_ORANGE, "Invalid UTF-8 or C1 control byte");
+        let text: String = bytes.iter().map(|byte| format!("⟨{byte:02X}⟩")).collect();
+        line_store.append(&text, &style);
+    }
+
+    /// シーケンスを記号として書き込む。解釈できないものは警告色にする
+    fn append_sequence_token(&self, event: &AnsiEvent, raw: &[u8], line_store: &mut LineStore) {
+        let Some(info) = inspector::describe(event, raw) else {
+            return;
+        };
+        let color = if info.is_recognized {
+            sereal_colors::UI_GRAY
+        } else {
+            sereal_colors::UI_ORANGE
+        };
+        let style = symbol_style(color, info.description);
+        line_store.append(&format!("⟨{}⟩", printable_sequence(raw)), &style);
+    }
 
-            if rich_text.text().len() != 0 {
-                rich_texts.push(rich_text);
+    fn apply_osc(&mut self, data: &[u8]) {
+        match parse_osc(data) {
+            Some(OscCommand::SetTitle(title)) => {
+                self.title = (!title.is_empty()).then_some(title);
             }
+            Some(OscCommand::Hyperlink(link)) => self.link = link,
+            None => {}
         }
+    }
 
-        rich_texts
+    fn span_style(&self) -> SpanStyle {
+        // リンクの文字には下線を引く
+        let mut text_style = self.text_style;
+        text_style.is_underline |= self.link.is_some();
+        SpanStyle {
+            format: text_style.text_format(&self.style_options),
+            is_blink: text_style.is_blink,
+            annotation: self.link.clone().map(Annotation::Link),
+        }
     }
+}
 
-    pub fn reset(&mut self) {
-        self.color_set = ColorSet::default();
+/// 行末の空白を表す記号
+const TRAILING_SPACE_SYMBOL: char = '·';
+
+/// 制御文字などを表す記号の書式。ポインタを重ねると説明を表示する
+fn symbol_style(color: sereal_colors::Color, explanation: impl Into<String>) -> SpanStyle {
+    SpanStyle {
+        format: TextFormat::simple(LOG_FONT, color.to_egui_color32()),
+        is_blink: false,
+        annotation: Some(Annotation::Explanation(explanation.into())),
     }
 }
 
-#[derive(Debug, Copy, Clone, Default, PartialEq)]
-struct ColorSet {
-    text_color: Option<egui::Color32>,
-    background_color: Option<egui::Color32>,
-    is_reset: bool,
+/// C0 制御文字と DEL に対応する Unicode の Control Pictures
+fn control_picture(control: u8) -> char {
+    match control {
+        0x7f => '␡',
+        _ => char::from_u32(0x2400 + u32::from(control)).unwrap_or(char::REPLACEMENT_CHARACTER),
+    }
 }
 
-fn parse_to_colorset(graphics_param: Vec<u8>) -> ColorSet {
-    let mut color_set = ColorSet::default();
-
-    for param in graphics_param {
-        match param {
-            // 0: リセット
-            0 => {
-                return ColorSet {
-                    text_color: None,
-                    background_color: None,
-                    is_reset: true,
-                };
-            }
+fn control_name(control: u8) -> &'static str {
+    const NAMES: [&str; 32] = [
+        "NUL", "SOH", "STX", "ETX", "EOT", "ENQ", "ACK", "BEL", "BS", "HT", "LF", "VT", "FF", "CR",
+        "SO", "SI", "DLE", "DC1", "DC2", "DC3", "DC4", "NAK", "SYN", "ETB", "CAN", "EM", "SUB",
+        "ESC", "FS", "GS", "RS", "US",
+    ];
+    NAMES.get(usize::from(control)).copied().unwrap_or("DEL")
+}
 
-            // 30-37: 文字色 (Normal)
-            30 => color_set.text_color = Some(sereal_colors::BLACK.to_egui_color32()),
-            31 => color_set.text_color = Some(sereal_colors::RED.to_egui_color32()),
-            32 => color_set.text_color = Some(sereal_colors::GREEN.to_egui_color32()),
-            33 => color_set.text_color = Some(sereal_colors::YELLOW.to_egui_color32()),
-            34 => color_set.text_color = Some(sereal_colors::BLUE.to_egui_color32()),
-            35 => color_set.text_color = Some(sereal_colors::MAGENTA.to_egui_color32()),
-            36 => color_set.text_color = Some(sereal_colors::CYAN.to_egui_color32()),
-            37 => color_set.text_color = Some(sereal_colors::WHITE.to_egui_color32()),
-
-            // 40-47: 背景色 (Normal)
-            40 => color_set.background_color = Some(sereal_colors::BLACK.to_egui_color32()),
-            41 => color_set.background_color = Some(sereal_colors::RED.to_egui_color32()),
-            42 => color_set.background_color = Some(sereal_colors::GREEN.to_egui_color32()),
-            43 => color_set.background_color = Some(sereal_colors::YELLOW.to_egui_color32()),
-            44 => color_set.background_color = Some(sereal_colors::BLUE.to_egui_color32()),
-            45 => color_set.background_color = Some(sereal_colors::MAGENTA.to_egui_color32()),
-            46 => color_set.background_color = Some(sereal_colors::CYAN.to_egui_color32()),
-            47 => color_set.background_color = Some(sereal_colors::WHITE.to_egui_color32()),
-
-            // 90-97: 文字色 (Bright)
-            90 => color_set.text_color = Some(sereal_colors::BRIGHT_BLACK.to_egui_color32()),
-            91 => color_set.text_color = Some(sereal_colors::BRIGHT_RED.to_egui_color32()),
-            92 => color_set.text_color = Some(sereal_colors::BRIGHT_GREEN.to_egui_color32()),
-            93 => color_set.text_color = Some(sereal_colors::BRIGHT_YELLOW.to_egui_color32()),
-            94 => color_set.text_color = Some(sereal_colors::BRIGHT_BLUE.to_egui_color32()),
-            95 => color_set.text_color = Some(sereal_colors::BRIGHT_MAGENTA.to_egui_color32()),
-            96 => color_set.text_color = Some(sereal_colors::BRIGHT_CYAN.to_egui_color32()),
-            97 => color_set.text_color = Some(sereal_colors::BRIGHT_WHITE.to_egui_color32()),
-
-            // 100-107: 背景色 (Bright)
-            100 => color_set.background_color = Some(sereal_colors::BRIGHT_BLACK.to_egui_color32()),
-            101 => color_set.background_color = Some(sereal_colors::BRIGHT_RED.to_egui_color32()),
-            102 => color_set.background_color = Some(sereal_colors::BRIGHT_GREEN.to_egui_color32()),
-            103 => {
-                color_set.background_color = Some(sereal_colors::BRIGHT_YELLOW.to_egui_color32())
-            }
-            104 => color_set.background_color = Some(sereal_colors::BRIGHT_BLUE.to_egui_color32()),
-            105 => {
-                color_set.background_color = Some(sereal_colors::BRIGHT_MAGENTA.to_egui_color32())
-            }
-            106 => color_set.background_color = Some(sereal_colors::BRIGHT_CYAN.to_egui_color32()),
-            107 => color_set.background_color = Some(sereal_colors::BRIGHT_WHITE.to_egui_color32()),
+fn is_c1_control(ch: char) -> bool {
+    ('\u{80}'..='\u{9f}').contains(&ch)
+}
 
-            // それ以外は何もしない
-            _ => {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queries(bytes: &[u8]) -> Vec<TerminalQuery> {
+        let mut formatter = AnsiFormatter::default();
+        let mut line_store = LineStore::new(100);
+        formatter.feed(bytes, &mut line_store);
+        formatter.take_queries()
+    }
+
+    fn with_options(line_options: LineOptions, bytes: &[u8]) -> LineStore {
+        let mut formatter = AnsiFormatter::default();
+        *formatter.line_options_mut() = line_options;
+        let mut line_store = LineStore::new(100);
+        formatter.feed(bytes, &mut line_store);
+        line_store
+    }
+
+    fn pictures(bytes: &[u8]) -> LineStore {
+        let line_options = LineOptions {
+            is_show_control_chars: true,
+            ..LineOptions::default()
+        };
+        with_options(line_options, bytes)
+    }
+
+    fn lines(line_store: &LineStore) -> Vec<String> {
+        (0..line_store.len())
+            .filter_map(|index| line_store.text(index))
+            .collect()
+    }
+
+    fn explanation(line_store: &LineStore, index: usize, char_index: usize) -> Option<String> {
+        match line_store.annotation_at(index, char_index) {
+            Some(Annotation::Explanation(explanation)) => Some(explanation),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn shows_control_pictures() {
+        let line_store = pictures(b"a\rb\tc\0d\x08\x07\n");
+        assert_eq!(lines(&line_store), ["a␍b␉c␀d␈␇␊"]);
+        assert_eq!(explanation(&line_store, 0, 1).as_deref(), Some("CR (0x0D)"));
+        assert_eq!(
+            explanation(&line_store, 0, 5).as_deref(),
+            Some("NUL (0x00)")
+        );
+        assert_eq!(explanation(&line_store, 0, 0), None);
+
+        // CR と BS で上書きせず、CRLF はどちらも記号にしてから改行する
+        let line_options = LineOptions {
+            carriage_return_mode: CarriageReturnMode::LineBreak,
+            is_show_control_chars: true,
+            ..LineOptions::default()
+        };
+        let line_store = with_options(line_options, b"abc\rX\r\nnext");
+        assert_eq!(lines(&line_store), ["abc␍X␍␊", "next"]);
+    }
+
+    #[test]
+    fn shows_invalid_and_c1_bytes_as_tokens() {
+        // 続くバイトで不完全と分かった UTF-8 も記号にする
+        let line_store = pictures(b"a\xffb\xc3(");
+        assert_eq!(lines(&line_store), ["a⟨FF⟩b⟨C3⟩("]);
+        assert_eq!(
+            explanation(&line_store, 0, 2).as_deref(),
+            Some("Invalid UTF-8 or C1 control byte")
+        );
+
+        // UTF-8 で符号化された C1 制御文字
+        let line_store = pictures("x\u{85}y\u{9b}".as_bytes());
+        assert_eq!(lines(&line_store), ["x⟨85⟩y⟨9B⟩"]);
+        assert_eq!(
+            explanation(&line_store, 0, 1).as_deref(),
+            Some("C1 control character (U+0085)")
+        );
+        // C1 以外の文字はそのまま
+        assert_eq!(lines(&pictures("é€".as_bytes())), ["é€"]);
+    }
+
+    #[test]
+    fn marks_trailing_spaces() {
+        assert_eq!(lines(&pictures(b"ok  \n")), ["ok··␊"]);
+        // 行の途中の空白はそのまま。行末の記号の前にある空白を置き換える
+        assert_eq!(lines(&pictures(b"a b \r\n")), ["a b·␍␊"]);
+        assert_eq!(lines(&pictures(b"   \n")), ["···␊"]);
+        // 改行が来るまでは行末が決まらない
+        assert_eq!(lines(&pictures(b"ab  ")), ["ab  "]);
+    }
+
+    #[test]
+    fn hides_control_characters_by_default() {
+        let line_store = with_options(LineOptions::default(), b"a\xffb  \r\n\0c");
+        assert_eq!(lines(&line_store), ["a\u{FFFD}b  ", "c"]);
+    }
+
+    #[test]
+    fn detects_status_and_attribute_queries() {
+        assert_eq!(queries(b"\x1b[5n"), [TerminalQuery::DeviceStatus]);
+        assert_eq!(queries(b"\x1b[6n"), [TerminalQuery::CursorPosition]);
+        assert_eq!(
+            queries(b"\x1b[c\x1b[0c"),
+            [
+                TerminalQuery::PrimaryDeviceAttributes,
+                TerminalQuery::PrimaryDeviceAttributes
+            ]
+        );
+        assert_eq!(
+            queries(b"\x1b[>c\x1b[>0c"),
+            [
+                TerminalQuery::SecondaryDeviceAttributes,
+                TerminalQuery::SecondaryDeviceAttributes
+            ]
+        );
+        // 受信の区切りをまたいでも 1 つの問い合わせになる
+        let mut formatter = AnsiFormatter::default();
+        let mut line_store = LineStore::new(100);
+        formatter.feed(b"ok\x1b[", &mut line_store);
+        formatter.feed(b"6n", &mut line_store);
+        assert_eq!(formatter.take_queries(), [TerminalQuery::CursorPosition]);
+        assert!(formatter.take_queries().is_empty());
+    }
+
+    #[test]
+    fn ignores_other_sequences() {
+        // DEC 固有の DSR、引数付きの DA、中間文字付き、応答そのもの
+        for bytes in [
+            &b"\x1b[?6n"[..],
+            b"\x1b[0n",
+            b"\x1b[1c",
+            b"\x1b[>1c",
+            b"\x1b[=c",
+            b"\x1b[ c",
+            b"\x1b[12;40R",
+            b"\x1b[?1;2c",
+        ] {
+            assert!(queries(bytes).is_empty(), "{bytes:?}");
         }
     }
 
-    color_set
+    #[test]
+    fn reset_drops_pending_queries() {
+        let mut formatter = AnsiFormatter::default();
+        let mut line_store = LineStore::new(100);
+        formatter.feed(b"\x1b[5n", &mut line_store);
+        formatter.reset();
+        assert!(formatter.take_queries().is_empty());
+    }
+
+    #[test]
+    fn query_responses() {
+        assert_eq!(TerminalQuery::DeviceStatus.response(1, 1), b"\x1b[0n");
+        assert_eq!(
+            TerminalQuery::CursorPosition.response(12, 40),
+            b"\x1b[12;40R"
+        );
+        assert_eq!(
+            TerminalQuery::PrimaryDeviceAttributes.response(1, 1),
+            b"\x1b[?1;2c"
+        );
+        assert_eq!(
+            TerminalQuery::SecondaryDeviceAttributes.response(1, 1),
+            b"\x1b[>0;0;0c"
+        );
+        assert_eq!(printable_sequence(b"\x1b[6n\r\x7f"), "ESC[6n<0D><7F>");
+    }
 }
@@ -0,0 +1,460 @@
+use super::osc::{OscCommand, parse_osc};
+use super::parser::{AnsiEvent, CsiSequence};
+
+/// エスケープシーケンスの説明
+#[derive(Debug, Clone, PartialEq)]
+pub struct SequenceInfo {
+    pub description: String,
+    /// Sereal が解釈できるシーケンスか
+    pub is_recognized: bool,
+}
+
+impl SequenceInfo {
+    fn recognized(description: impl Into<String>) -> Self {
+        Self {
+            description: description.into(),
+            is_recognized: true,
+        }
+    }
+
+    fn unrecognized(description: impl Into<String>) -> Self {
+        Self {
+            description: description.into(),
+            is_recognized: false,
+        }
+    }
+}
+
+const COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// シーケンスの意味を説明する。シーケンス以外は None を返す
+pub fn describe(event: &AnsiEvent, raw: &[u8]) -> Option<SequenceInfo> {
+    match event {
+        AnsiEvent::Text(_) | AnsiEvent::Control(_) | AnsiEvent::InvalidBytes(_) => None,
+        AnsiEvent::Csi(csi) => Some(describe_csi(csi)),
+        AnsiEvent::Osc(data) => Some(describe_osc(data)),
+        AnsiEvent::Escape {
+            intermediates,
+            final_byte,
+        } => Some(describe_escape(intermediates, *final_byte)),
+        AnsiEvent::Ignored => Some(describe_ignored(raw)),
+    }
+}
+
+fn describe_csi(csi: &CsiSequence) -> SequenceInfo {
+    let count = |index: usize| csi.param_or(index, 1);
+    let description = match (
+        csi.private_marker,
+        csi.intermediates.as_slice(),
+        csi.final_byte,
+    ) {
+        (None, [], b'm') => format!("Select graphic rendition: {}", describe_sgr(csi)),
+        (None, [], b'@') => format!("Insert {} blank character(s)", count(0)),
+        (None, [], b'A') => format!("Cursor up {}", count(0)),
+        (None, [], b'B' | b'e') => format!("Cursor down {}", count(0)),
+        (None, [], b'C' | b'a') => format!("Cursor forward {}", count(0)),
+        (None, [], b'D') => format!("Cursor back {}", count(0)),
+        (None, [], b'E') => format!("Cursor to the start of the line {} down", count(0)),
+        (None, [], b'F') => format!("Cursor to the start of the line {} up", count(0)),
+        (None, [], b'G' | b'`') => format!("Cursor to column {}", count(0)),
+        (None, [], b'H' | b'f') => {
+            format!("Cursor to row {}, column {}", count(0), count(1))
+        }
+        (None, [], b'I') => format!("Cursor forward {} tab stop(s)", count(0)),
+        (None, [], b'J') => match csi.param(0) {
+            0 => "Erase from the cursor to the end of the screen".to_string(),
+            1 => "Erase from the start of the screen to the cursor".to_string(),
+            3 => "Erase the scrollback".to_string(),
+            _ => "Erase the whole screen".to_string(),
+        },
+        (None, [], b'K') => match csi.param(0) {
+            0 => "Erase from the cursor to the end of the line".to_string(),
+            1 => "Erase from the start of the line to the cursor".to_string(),
+            _ => "Erase the whole line".to_string(),
+        },
+        (None, [], b'L') => format!("Insert {} line(s)", count(0)),
+        (None, [], b'M') => format!("Delete {} line(s)", count(0)),
+        (None, [], b'P') => format!("Delete {} character(s)", count(0)),
+        (None, [], b'S') => format!("Scroll up {} line(s)", count(0)),
+        (None, [], b'T') => format!("Scroll down {} line(s)", count(0)),
+        (None, [], b'X') => format!("Erase {} character(s)", count(0)),
+        (None, [], b'Z') => format!("Cursor back {} tab stop(s)", count(0)),
+        (None, [], b'b') => format!("Repeat the last character {} time(s)", count(0)),
+        (None, [], b'd') => format!("Cursor to row {}", count(0)),
+        (None, [], b'g') => match csi.param(0) {
+            0 => "Clear the tab stop at the cursor".to_string(),
+            3 => "Clear all tab stops".to_string(),
+            _ => return SequenceInfo::unrecognized("Clear tab stops (unknown mode)"),
+        },
+        (None, [], final_byte @ (b'h' | b'l')) => {
+            let action = if final_byte == b'h' { "Set" } else { "Reset" };
+            let modes: Vec<&str> = params(csi)
+                .map(|mode| match mode {
+                    4 => "insert mode",
+                    20 => "automatic newline",
+                    _ => "unknown mode",
+                })
+                .collect();
+            format!("{action} {}", modes.join(", "))
+        }
+        (None, [], b'n') => match csi.param(0) {
+            5 => "Request the terminal status".to_string(),
+            6 => "Request the cursor position".to_string(),
+            _ => return SequenceInfo::unrecognized("Device status report (unknown request)"),
+        },
+        (None, [], b'c') => "Request the primary device attributes".to_string(),
+        (Some(b'>'), [], b'c') => "Request the secondary device attributes".to_string(),
+        (None, [], b'r') => match csi.param(1) {
+            0 => format!("Scroll only from row {} to the last row", count(0)),
+            bottom => format!("Scroll only rows {} to {bottom}", count(0)),
+        },
+        (None, [], b's') => "Save the cursor position".to_string(),
+        (None, [], b'u') => "Restore the cursor position".to_string(),
+        (Some(b'?'), [], final_byte @ (b'h' | b'l')) => {
+            let action = if final_byte == b'h' {
+                "Enable"
+            } else {
+                "Disable"
+            };
+            let modes: Vec<&str> = params(csi).map(private_mode_name).collect();
+            format!("{action} {}", modes.join(", "))
+        }
+        (None, [b'!'], b'p') => "Soft terminal reset".to_string(),
+        _ => return SequenceInfo::unrecognized("Unknown CSI sequence"),
+    };
+    SequenceInfo::recognized(description)
+}
+
+fn params(csi: &CsiSequence) -> impl Iterator<Item = u16> + '_ {
+    csi.params
+        .iter()
+        .map(|group| group.first().copied().unwrap_or(0))
+}
+
+fn private_mode_name(mode: u16) -> &'static str {
+    match mode {
+        1 => "application cursor keys",
+        6 => "origin mode",
+        7 => "auto wrap",
+        25 => "cursor visibility",
+        47 | 1047 => "alternate screen",
+        1048 => "saved cursor",
+        1049 => "alternate screen with saved cursor",
+        _ => "unknown private mode",
+    }
+}
+
+fn describe_sgr(csi: &CsiSequence) -> String {
+    if csi.params.is_empty() {
+        return "reset".to_string();
+    }
+
+    let codes: Vec<u16> = params(csi).collect();
+    let mut parts = Vec::new();
+    let mut index = 0;
+    while index < codes.len() {
+        let code = codes[index];
+        index += 1;
+        let part = match code {
+            0 => "reset".to_string(),
+            1 => "bold".to_string(),
+            2 => "dim".to_string(),
+            3 => "italic".to_string(),
+            4 => "underline".to_string(),
+            5 | 6 => "blink".to_string(),
+            7 => "inverse".to_string(),
+            8 => "hidden".to_string(),
+            9 => "strikethrough".to_string(),
+            21 => "double underline".to_string(),
+            22 => "normal intensity".to_string(),
+            23 => "not italic".to_string(),
+            24 => "not underlined".to_string(),
+            25 => "not blinking".to_string(),
+            27 => "not inverse".to_string(),
+            28 => "not hidden".to_string(),
+            29 => "not strikethrough".to_string(),
+            30..=37 => format!("{} text", COLOR_NAMES[usize::from(code - 30)]),
+            39 => "default text color".to_string(),
+            40..=47 => format!("{} background", COLOR_NAMES[usize::from(code - 40)]),
+            49 => "default background".to_string(),
+            90..=97 => format!("bright {} text", COLOR_NAMES[usize::from(code - 90)]),
+            100..=107 => format!("bright {} background", COLOR_NAMES[usize::from(code - 100)]),
+            38 | 48 => {
+                let target = if code == 38 { "text" } else { "background" };
+                // `:` 区切りなら 1 つのパラメータにまとまっている
+                let group = &csi.params[index - 1];
+                let values: Vec<u16> = if 1 < group.len() {
+                    group[1..].to_vec()
+                } else {
+                    let rest = &codes[index..];
+                    let length = match rest.first() {
+                        Some(5) => 2,
+                        Some(2) => 4,
+                        _ => 0,
+                    };
+                    let length = length.min(rest.len());
+                    index += length;
+                    rest[..length].to_vec()
+                };
+                match values.as_slice() {
+                    [5, color, ..] => format!("{target} color {color}"),
+                    // コロン形式では色空間 ID が入ることがあるため末尾の 3 つを使う
+                    [2, .., red, green, blue] => {
+                        format!("{target} color rgb({red}, {green}, {blue})")
+                    }
+                    _ => format!("malformed {target} color"),
+                }
+            }
+            _ => format!("unknown attribute {code}"),
+        };
+        parts.push(part);
+    }
+    parts.join(", ")
+}
+
+fn describe_osc(data: &[u8]) -> SequenceInfo {
+    match parse_osc(data) {
+        Some(OscCommand::SetTitle(title)) => {
+            SequenceInfo::recognized(format!("Set the title to \"{title}\""))
+        }
+        Some(OscCommand::Hyperlink(Some(url))) => {
+            SequenceInfo::recognized(format!("Start a link to {url}"))
+        }
+        Some(OscCommand::Hyperlink(None)) => SequenceInfo::recognized("End the link"),
+        None => SequenceInfo::unrecognized("Unsupported operating system command"),
+    }
+}
+
+fn describe_escape(intermediates: &[u8], final_byte: u8) -> SequenceInfo {
+    let description = match (intermediates, final_byte) {
+        ([], b'7') => "Save the cursor",
+        ([], b'8') => "Restore the cursor",
+        ([], b'D') => "Index (move down, scrolling if needed)",
+        ([], b'E') => "Next line",
+        ([], b'M') => "Reverse index (move up, scrolling if needed)",
+        ([], b'H') => "Set a tab stop at the cursor",
+        ([], b'c') => "Full terminal reset",
+        ([b'#'], b'8') => "Screen alignment test (fill with E)",
+        ([b'(' | b')'], b'0') => "Use DEC special graphics characters",
+        ([b'(' | b')'], _) => "Use ASCII characters",
+        _ => return SequenceInfo::unrecognized("Unknown escape sequence"),
+    };
+    SequenceInfo::recognized(description)
+}
+
+fn describe_ignored(raw: &[u8]) -> SequenceInfo {
+    let description = match raw.get(1) {
+        Some(b'P') => "Device control string (not supported)",
+        Some(b'X') => "Start of string (not supported)",
+        Some(b'^') => "Privacy message (not supported)",
+        Some(b'_') => "Application program command (not supported)",
+        _ => "Malformed or interrupted sequence",
+    };
+    SequenceInfo::unrecognized(description)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ansi_formatter::parser::AnsiParser;
+
+    fn describe_all(bytes: &[u8]) -> Vec<SequenceInfo> {
+        AnsiParser::default()
+            .feed_with_raw(bytes)
+            .iter()
+            .filter_map(|(event, raw)| describe(event, raw))
+            .collect()
+    }
+
+    /// 1 つのシーケンスの説明と、解釈できるかどうか
+    fn described(bytes: &[u8]) -> (String, bool) {
+        let infos = describe_all(bytes);
+        assert_eq!(infos.len(), 1, "{bytes:?}: {infos:?}");
+        (infos[0].description.clone(), infos[0].is_recognized)
+    }
+
+    fn recognized(description: &str) -> (String, bool) {
+        (description.to_string(), true)
+    }
+
+    fn unrecognized(description: &str) -> (String, bool) {
+        (description.to_string(), false)
+    }
+
+    #[test]
+    fn text_and_controls_are_not_sequences() {
+        assert!(describe_all(b"ok\r\n\t\x07\xff").is_empty());
+    }
+
+    #[test]
+    fn cursor_and_erase_sequences() {
+        let cases: [(&[u8], &str); 11] = [
+            (b"\x1b[H", "Cursor to row 1, column 1"),
+            (b"\x1b[5;10f", "Cursor to row 5, column 10"),
+            (b"\x1b[3A", "Cursor up 3"),
+            (b"\x1b[0B", "Cursor down 1"),
+            (b"\x1b[J", "Erase from the cursor to the end of the screen"),
+            (b"\x1b[2J", "Erase the whole screen"),
+            (b"\x1b[1K", "Erase from the start of the line to the cursor"),
+            (b"\x1b[2;20r", "Scroll only rows 2 to 20"),
+            (b"\x1b[5r", "Scroll only from row 5 to the last row"),
+            (b"\x1b[4h", "Set insert mode"),
+            (b"\x1b[!p", "Soft terminal reset"),
+        ];
+        for (bytes, description) in cases {
+            assert_eq!(described(bytes), recognized(description), "{bytes:?}");
+        }
+    }
+
+    #[test]
+    fn private_modes_and_queries() {
+        assert_eq!(
+            described(b"\x1b[?25l"),
+            recognized("Disable cursor visibility")
+        );
+        assert_eq!(
+            described(b"\x1b[?1;1049h"),
+            recognized("Enable application cursor keys, alternate screen with saved cursor")
+        );
+        assert_eq!(
+            described(b"\x1b[?2004h"),
+            recognized("Enable unknown private mode")
+        );
+        assert_eq!(
+            described(b"\x1b[6n"),
+            recognized("Request the cursor position")
+        );
+        assert_eq!(
+            described(b"\x1b[>c"),
+            recognized("Request the secondary device attributes")
+        );
+    }
+
+    #[test]
+    fn graphic_renditions() {
+        let cases: [(&[u8], &str); 8] = [
+            (b"\x1b[m", "reset"),
+            (
+                b"\x1b[0;1;31;42m",
+                "reset, bold, red text, green background",
+            ),
+            (
+                b"\x1b[22;24;39;49m",
+                "normal intensity, not underlined, default text color, default background",
+            ),
+            (
+                b"\x1b[97;100m",
+                "bright white text, bright black background",
+            ),
+            (b"\x1b[38;5;208m", "text color 208"),
+            (
+                b"\x1b[48;2;1;2;3;4m",
+                "background color rgb(1, 2, 3), underline",
+            ),
+            (b"\x1b[38:2::10:20:30m", "text color rgb(10, 20, 30)"),
+            (b"\x1b[58;1m", "unknown attribute 58, bold"),
+        ];
+        for (bytes, description) in cases {
+            assert_eq!(
+                described(bytes),
+                recognized(&format!("Select graphic rendition: {description}")),
+                "{bytes:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_graphic_renditions() {
+        // 色の値が足りない、または色の種類が分からない
+        let cases: [(&[u8], &str); 4] = [
+            (b"\x1b[38;5m", "malformed text color"),
+            (b"\x1b[48;2;1;2m", "malformed background color"),
+            (b"\x1b[38;9m", "malformed text color, strikethrough"),
+            (b"\x1b[38:5m", "malformed text color"),
+        ];
+        for (bytes, description) in cases {
+            assert_eq!(
+                described(bytes),
+                recognized(&format!("Select graphic rendition: {description}")),
+                "{bytes:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_csi_sequences() {
+        assert_eq!(described(b"\x1b[5x"), unrecognized("Unknown CSI sequence"));
+        assert_eq!(described(b"\x1b[?5n"), unrecognized("Unknown CSI sequence"));
+        assert_eq!(
+            described(b"\x1b[7n"),
+            unrecognized("Device status report (unknown request)")
+        );
+        assert_eq!(
+            described(b"\x1b[1g"),
+            unrecognized("Clear tab stops (unknown mode)")
+        );
+    }
+
+    #[test]
+    fn operating_system_commands() {
+        assert_eq!(
+            described(b"\x1b]0;build\x07"),
+            recognized("Set the title to \"build\"")
+        );
+        assert_eq!(
+            described(b"\x1b]8;;https://example.com\x1b\\"),
+            recognized("Start a link to https://example.com")
+        );
+        assert_eq!(described(b"\x1b]8;;\x07"), recognized("End the link"));
+        assert_eq!(
+            described(b"\x1b]52;c;aGk=\x07"),
+            unrecognized("Unsupported operating system command")
+        );
+    }
+
+    #[test]
+    fn escape_sequences() {
+        assert_eq!(described(b"\x1b7"), recognized("Save the cursor"));
+        assert_eq!(
+            described(b"\x1b(0"),
+            recognized("Use DEC special graphics characters")
+        );
+        assert_eq!(described(b"\x1b)B"), recognized("Use ASCII characters"));
+        assert_eq!(
+            described(b"\x1b#8"),
+            recognized("Screen alignment test (fill with E)")
+        );
+        assert_eq!(described(b"\x1bZ"), unrecognized("Unknown escape sequence"));
+    }
+
+    #[test]
+    fn ignored_and_interrupted_sequences() {
+        assert_eq!(
+            described(b"\x1bPq#0\x1b\\"),
+            unrecognized("Device control string (not supported)")
+        );
+        assert_eq!(
+            described(b"\x1b_app\x1b\\"),
+            unrecognized("Application program command (not supported)")
+        );
+        // CAN による中断と、順序の誤ったパラメータ
+        assert_eq!(
+            described(b"\x1b[12\x18"),
+            unrecognized("Malformed or interrupted sequence")
+        );
+        assert_eq!(
+            described(b"\x1b[1?h"),
+            unrecognized("Malformed or interrupted sequence")
+        );
+        // 途中で始まった次のシーケンスは別に説明する
+        assert_eq!(
+            describe_all(b"\x1b[12\x1b[m"),
+            [
+                SequenceInfo::unrecognized("Malformed or interrupted sequence"),
+                SequenceInfo::recognized("Select graphic rendition: reset"),
+            ]
+        );
+    }
+}
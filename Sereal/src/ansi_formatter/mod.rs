@@ -1,3 +1,11 @@
+#[allow(clippy::module_inception)]
 pub mod ansi_formatter;
+pub mod inspector;
+pub mod osc;
+pub mod parser;
+pub mod text_style;
 
 pub use ansi_formatter::AnsiFormatter;
+pub use ansi_formatter::CarriageReturnMode;
+pub use ansi_formatter::LOG_FONT;
+pub use ansi_formatter::printable_sequence;
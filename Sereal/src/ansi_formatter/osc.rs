@@ -0,0 +1,32 @@
+/// 解釈する OSC (Operating System Command)
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscCommand {
+    /// OSC 0 / OSC 2: ウィンドウのタイトルを設定する
+    SetTitle(String),
+    /// OSC 8: ハイパーリンクを開始する。None ならリンクを終える
+    Hyperlink(Option<String>),
+}
+
+// リンクとして開くことを許可するスキーム
+const LINK_SCHEMES: [&str; 3] = ["http://", "https://", "mailto:"];
+
+/// OSC の本体 (ESC ] と終端を除いた部分) を解釈する
+pub fn parse_osc(data: &[u8]) -> Option<OscCommand> {
+    let text = String::from_utf8_lossy(data);
+    let (command, argument) = text.split_once(';')?;
+    match command {
+        "0" | "2" => Some(OscCommand::SetTitle(
+            argument.chars().filter(|ch| !ch.is_control()).collect(),
+        )),
+        "8" => {
+            // OSC 8 ; params ; URI
+            let (_params, uri) = argument.split_once(';')?;
+            // ローカルのファイルなどは開かないよう、許可したスキームのみリンクにする
+            let is_allowed = LINK_SCHEMES
+                .iter()
+                .any(|scheme| uri.to_ascii_lowercase().starts_with(scheme));
+            Some(OscCommand::Hyperlink(is_allowed.then(|| uri.to_string())))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,597 @@
+/// シーケンス 1 つあたりに保持するパラメータ数の上限
+const MAX_PARAMS: usize = 32;
+/// OSC などの文字列シーケンスで保持するバイト数の上限
+const MAX_STRING_LENGTH: usize = 4096;
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+const CAN: u8 = 0x18;
+const SUB: u8 = 0x1a;
+
+/// ストリームから切り出したイベント
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnsiEvent {
+    /// 表示可能な文字列
+    Text(String),
+    /// C0 制御文字 (改行, 復帰, タブなど)
+    Control(u8),
+    /// CSI シーケンス (ESC [ ...)
+    Csi(CsiSequence),
+    /// OSC シーケンス (ESC ] ... BEL/ST) の本文
+    Osc(Vec<u8>),
+    /// CSI/OSC 以外のエスケープシーケンス (ESC 7, ESC ( B など)
+    Escape {
+        intermediates: Vec<u8>,
+        final_byte: u8,
+    },
+    /// UTF-8 として解釈できないバイト列 (C1 制御文字の 1 バイト表現を含む)
+    InvalidBytes(Vec<u8>),
+    /// 解釈せずに読み捨てたシーケンス (DCS などの文字列や、中断された不正なシーケンス)
+    Ignored,
+}
+
+impl AnsiEvent {
+    /// エスケープシーケンスを表すイベントか
+    pub fn is_sequence(&self) -> bool {
+        matches!(
+            self,
+            AnsiEvent::Csi(_) | AnsiEvent::Osc(_) | AnsiEvent::Escape { .. } | AnsiEvent::Ignored
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CsiSequence {
+    /// `?` や `>` などのプライベートパラメータの接頭辞
+    pub private_marker: Option<u8>,
+    /// `;` で区切られたパラメータ。各要素は `:` で区切られたサブパラメータを先頭値に続けて持つ
+    ///
+    /// 省略されたパラメータは 0 として扱う。
+    pub params: Vec<Vec<u16>>,
+    pub intermediates: Vec<u8>,
+    pub final_byte: u8,
+}
+
+impl CsiSequence {
+    /// index 番目のパラメータの値。省略時は 0 を返す
+    pub fn param(&self, index: usize) -> u16 {
+        self.params
+            .get(index)
+            .and_then(|group| group.first())
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// index 番目のパラメータの値。省略時または 0 のときは default を返す
+    pub fn param_or(&self, index: usize, default: u16) -> u16 {
+        match self.param(index) {
+            0 => default,
+            value => value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum State {
+    #[default]
+    Ground,
+    Escape,
+    EscapeIntermediate,
+    CsiParam,
+    CsiIgnore,
+    Osc,
+    OscEscape,
+    // DCS/SOS/PM/APC は中身を読み捨てる
+    IgnoredString,
+    IgnoredStringEscape,
+}
+
+/// バイト列を逐次受け取り、ANSI エスケープシーケンスを切り出すパーサ
+///
+/// 受信の区切りで途切れたシーケンスや UTF-8 の文字は内部に保持され、
+/// 次に渡されたバイト列と合わせて解釈される。
+#[derive(Debug, Clone, Default)]
+pub struct AnsiParser {
+    state: State,
+    text: String,
+    utf8_buffer: Vec<u8>,
+    utf8_remaining: usize,
+    csi: CsiSequence,
+    current_param: Option<u16>,
+    current_group: Vec<u16>,
+    intermediates: Vec<u8>,
+    osc: Vec<u8>,
+    raw: Vec<u8>, // 解釈中のシーケンスの受信したままのバイト列
+}
+
+impl AnsiParser {
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<AnsiEvent> {
+        self.feed_with_raw(bytes)
+            .into_iter()
+            .map(|(event, _)| event)
+            .collect()
+    }
+
+    /// イベントと合わせて、シーケンスの受信したままのバイト列を返す
+    ///
+    /// 文字列と制御文字のバイト列は空になる。
+    pub fn feed_with_raw(&mut self, bytes: &[u8]) -> Vec<(AnsiEvent, Vec<u8>)> {
+        let mut events = Vec::new();
+        for &byte in bytes {
+            self.advance(byte, &mut events);
+        }
+        self.flush_text(&mut events);
+        events
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    fn advance(&mut self, byte: u8, events: &mut Vec<(AnsiEvent, Vec<u8>)>) {
+        // CAN/SUB はどの状態でもシーケンスを中断する
+        if matches!(byte, CAN | SUB) && self.state != State::Ground {
+            self.push_raw(byte);
+            self.emit(events, AnsiEvent::Ignored);
+            self.state = State::Ground;
+            return;
+        }
+
+        match self.state {
+            State::Ground => {}
+            State::Osc | State::IgnoredString => self.push_raw(byte),
+            State::OscEscape | State::IgnoredStringEscape if byte == b'\\' => self.push_raw(byte),
+            State::OscEscape | State::IgnoredStringEscape => {}
+            // 途中で ESC が来たら、それまでのシーケンスは中断されたものとする
+            _ if byte == ESC => {
+                self.emit(events, AnsiEvent::Ignored);
+                self.push_raw(ESC);
+            }
+            // シーケンスの途中の制御文字はシーケンスに含めない
+            _ if 0x20 <= byte => self.push_raw(byte),
+            _ => {}
+        }
+
+        match self.state {
+            State::Ground => self.advance_ground(byte, events),
+            State::Escape => match byte {
+                b'[' => self.enter_csi(),
+                b']' => {
+                    self.osc.clear();
+                    self.state = State::Osc;
+                }
+                b'P' | b'X' | b'^' | b'_' => self.state = State::IgnoredString,
+                0x20..=0x2f => {
+                    self.intermediates.clear();
+                    self.intermediates.push(byte);
+                    self.state = State::EscapeIntermediate;
+                }
+                0x30..=0x7e => {
+                    self.emit(
+                        events,
+                        AnsiEvent::Escape {
+                            intermediates: Vec::new(),
+                            final_byte: byte,
+                        },
+                    );
+                    self.state = State::Ground;
+                }
+                ESC => {}
+                _ => self.execute_in_sequence(byte, events),
+            },
+            State::EscapeIntermediate => match byte {
+                0x20..=0x2f => self.intermediates.push(byte),
+                0x30..=0x7e => {
+                    let intermediates = std::mem::take(&mut self.intermediates);
+                    self.emit(
+                        events,
+                        AnsiEvent::Escape {
+                            intermediates,
+                            final_byte: byte,
+                        },
+                    );
+                    self.state = State::Ground;
+                }
+                ESC => self.state = State::Escape,
+                _ => self.execute_in_sequence(byte, events),
+            },
+            State::CsiParam => match byte {
+                b'0'..=b'9' => {
+                    let digit = u16::from(byte - b'0');
+                    let value = self.current_param.unwrap_or(0);
+                    self.current_param = Some(value.saturating_mul(10).saturating_add(digit));
+                }
+                b':' => {
+                    if self.current_group.len() < MAX_PARAMS {
+                        self.current_group.push(self.current_param.unwrap_or(0));
+                    }
+                    self.current_param = None;
+                }
+                b';' => self.push_param(),
+                b'<'..=b'?' => {
+                    if self.csi.params.is_empty()
+                        && self.current_param.is_none()
+                        && self.current_group.is_empty()
+                        && self.csi.private_marker.is_none()
+                    {
+                        self.csi.private_marker = Some(byte);
+                    } else {
+                        self.state = State::CsiIgnore;
+                    }
+                }
+                0x20..=0x2f => self.csi.intermediates.push(byte),
+                0x40..=0x7e => {
+                    if self.current_param.is_some()
+                        || !self.current_group.is_empty()
+                        || !self.csi.params.is_empty()
+                    {
+                        self.push_param();
+                    }
+                    let mut csi = std::mem::take(&mut self.csi);
+                    csi.final_byte = byte;
+                    self.emit(events, AnsiEvent::Csi(csi));
+                    self.state = State::Ground;
+                }
+                ESC => self.state = State::Escape,
+                _ => self.execute_in_sequence(byte, events),
+            },
+            State::CsiIgnore => match byte {
+                0x40..=0x7e => {
+                    self.emit(events, AnsiEvent::Ignored);
+                    self.state = State::Ground;
+                }
+                ESC => self.state = State::Escape,
+                _ => self.execute_in_sequence(byte, events),
+            },
+            State::Osc => match byte {
+                BEL => {
+                    let osc = std::mem::take(&mut self.osc);
+                    self.emit(events, AnsiEvent::Osc(osc));
+                    self.state = State::Ground;
+                }
+                ESC => self.state = State::OscEscape,
+                _ => {
+                    if self.osc.len() < MAX_STRING_LENGTH {
+                        self.osc.push(byte);
+                    }
+                }
+            },
+            State::OscEscape => {
+                // ESC \ (ST) で終端する。それ以外なら新しいシーケンスの開始とみなす
+                let osc = std::mem::take(&mut self.osc);
+                self.emit(events, AnsiEvent::Osc(osc));
+                self.state = State::Escape;
+                if byte == b'\\' {
+                    self.state = State::Ground;
+                } else {
+                    self.push_raw(ESC);
+                    self.advance(byte, events);
+                }
+            }
+            State::IgnoredString => match byte {
+                BEL => {
+                    self.emit(events, AnsiEvent::Ignored);
+                    self.state = State::Ground;
+                }
+                ESC => self.state = State::IgnoredStringEscape,
+                _ => {}
+            },
+            State::IgnoredStringEscape => {
+                self.emit(events, AnsiEvent::Ignored);
+                self.state = State::Escape;
+                if byte == b'\\' {
+                    self.state = State::Ground;
+                } else {
+                    self.push_raw(ESC);
+                    self.advance(byte, events);
+                }
+            }
+        }
+    }
+
+    fn advance_ground(&mut self, byte: u8, events: &mut Vec<(AnsiEvent, Vec<u8>)>) {
+        // マルチバイト文字の途中
+        if 0 < self.utf8_remaining {
+            if byte & 0xc0 == 0x80 {
+                self.utf8_buffer.push(byte);
+                self.utf8_remaining -= 1;
+                if self.utf8_remaining == 0 {
+                    let buffer = std::mem::take(&mut self.utf8_buffer);
+                    match String::from_utf8(buffer) {
+                        Ok(decoded) => self.text.push_str(&decoded),
+                        // サロゲートや冗長な表現
+                        Err(e) => self.emit_invalid_bytes(e.into_bytes(), events),
+                    }
+                }
+                return;
+            }
+            // 途切れた文字は不正なバイト列とし、このバイトは改めて解釈する
+            let buffer = std::mem::take(&mut self.utf8_buffer);
+            self.utf8_remaining = 0;
+            self.emit_invalid_bytes(buffer, events);
+        }
+
+        match byte {
+            ESC => {
+                self.flush_text(events);
+                self.raw.clear();
+                self.push_raw(ESC);
+                self.state = State::Escape;
+            }
+            0x00..=0x1f | 0x7f => {
+                self.flush_text(events);
+                self.emit(events, AnsiEvent::Control(byte));
+            }
+            0x20..=0x7e => self.text.push(char::from(byte)),
+            0xc2..=0xdf => self.start_utf8(byte, 1),
+            0xe0..=0xef => self.start_utf8(byte, 2),
+            0xf0..=0xf4 => self.start_utf8(byte, 3),
+            _ => self.emit_invalid_bytes(vec![byte], events),
+        }
+    }
+
+    fn emit_invalid_bytes(&mut self, bytes: Vec<u8>, events: &mut Vec<(AnsiEvent, Vec<u8>)>) {
+        self.flush_text(events);
+        self.emit(events, AnsiEvent::InvalidBytes(bytes));
+    }
+
+    fn start_utf8(&mut self, byte: u8, remaining: usize) {
+        self.utf8_buffer.push(byte);
+        self.utf8_remaining = remaining;
+    }
+
+    fn enter_csi(&mut self) {
+        self.csi = CsiSequence::default();
+        self.current_param = None;
+        self.current_group.clear();
+        self.state = State::CsiParam;
+    }
+
+    fn push_param(&mut self) {
+        self.current_group.push(self.current_param.unwrap_or(0));
+        let group = std::mem::take(&mut self.current_group);
+        if self.csi.params.len() < MAX_PARAMS {
+            self.csi.params.push(group);
+        }
+        self.current_param = None;
+    }
+
+    /// シーケンスの途中に現れた制御文字はそのまま実行する
+    fn execute_in_sequence(&mut self, byte: u8, events: &mut Vec<(AnsiEvent, Vec<u8>)>) {
+        if byte < 0x20 {
+            self.emit(events, AnsiEvent::Control(byte));
+        }
+    }
+
+    fn flush_text(&mut self, events: &mut Vec<(AnsiEvent, Vec<u8>)>) {
+        if !self.text.is_empty() {
+            let text = std::mem::take(&mut self.text);
+            self.emit(events, AnsiEvent::Text(text));
+        }
+    }
+
+    fn push_raw(&mut self, byte: u8) {
+        if self.raw.len() < MAX_STRING_LENGTH {
+            self.raw.push(byte);
+        }
+    }
+
+    /// イベントを追加する。シーケンスなら受信したままのバイト列を添える
+    fn emit(&mut self, events: &mut Vec<(AnsiEvent, Vec<u8>)>, event: AnsiEvent) {
+        let raw = if event.is_sequence() {
+            std::mem::take(&mut self.raw)
+        } else {
+            Vec::new()
+        };
+        events.push((event, raw));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> AnsiEvent {
+        AnsiEvent::Text(text.to_string())
+    }
+
+    fn csi(private_marker: Option<u8>, params: &[&[u16]], final_byte: u8) -> AnsiEvent {
+        AnsiEvent::Csi(CsiSequence {
+            private_marker,
+            params: params.iter().map(|group| group.to_vec()).collect(),
+            intermediates: Vec::new(),
+            final_byte,
+        })
+    }
+
+    fn parse(bytes: &[u8]) -> Vec<AnsiEvent> {
+        AnsiParser::default().feed(bytes)
+    }
+
+    #[test]
+    fn text_and_controls() {
+        assert_eq!(
+            parse(b"ab\r\ncd\x7f"),
+            vec![
+                text("ab"),
+                AnsiEvent::Control(b'\r'),
+                AnsiEvent::Control(b'\n'),
+                text("cd"),
+                AnsiEvent::Control(0x7f),
+            ]
+        );
+        assert_eq!(parse("é→😀".as_bytes()), vec![text("é→😀")]);
+    }
+
+    #[test]
+    fn csi_parameters() {
+        assert_eq!(
+            parse(b"\x1b[1;31mX\x1b[m"),
+            vec![
+                csi(None, &[&[1], &[31]], b'm'),
+                text("X"),
+                csi(None, &[], b'm')
+            ]
+        );
+        assert_eq!(
+            parse(b"\x1b[38:2::1:2m"),
+            vec![csi(None, &[&[38, 2, 0, 1, 2]], b'm')]
+        );
+        assert_eq!(parse(b"\x1b[?25l"), vec![csi(Some(b'?'), &[&[25]], b'l')]);
+        assert_eq!(
+            parse(b"\x1b[ q"),
+            vec![AnsiEvent::Csi(CsiSequence {
+                intermediates: vec![b' '],
+                final_byte: b'q',
+                ..Default::default()
+            })]
+        );
+
+        let [AnsiEvent::Csi(sequence)] = &parse(b"\x1b[;5H")[..] else {
+            panic!("not a CSI sequence");
+        };
+        assert_eq!(sequence.params, vec![vec![0], vec![5]]);
+        assert_eq!(sequence.param_or(0, 1), 1);
+        assert_eq!(sequence.param_or(1, 1), 5);
+        assert_eq!(sequence.param(2), 0);
+    }
+
+    #[test]
+    fn csi_limits() {
+        assert_eq!(
+            parse(b"\x1b[99999999A"),
+            vec![csi(None, &[&[u16::MAX]], b'A')]
+        );
+        let mut bytes = b"\x1b[".to_vec();
+        bytes.extend(b"1;".repeat(MAX_PARAMS + 10));
+        bytes.push(b'm');
+        let [AnsiEvent::Csi(sequence)] = &parse(&bytes)[..] else {
+            panic!("not a CSI sequence");
+        };
+        assert_eq!(sequence.params.len(), MAX_PARAMS);
+        // 途中に現れたプライベートマーカーは不正なシーケンスとして読み捨てる
+        assert_eq!(parse(b"\x1b[1?2hX"), vec![AnsiEvent::Ignored, text("X")]);
+    }
+
+    #[test]
+    fn split_across_feeds() {
+        let mut parser = AnsiParser::default();
+        let bytes = "a\x1b[31mé\x1b]0;t\x07".as_bytes();
+        let mut events = Vec::new();
+        for byte in bytes {
+            events.extend(parser.feed(std::slice::from_ref(byte)));
+        }
+        assert_eq!(
+            events,
+            vec![
+                text("a"),
+                csi(None, &[&[31]], b'm'),
+                text("é"),
+                AnsiEvent::Osc(b"0;t".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn osc_and_escape() {
+        assert_eq!(
+            parse(b"\x1b]0;title\x07\x1b]8;;url\x1b\\"),
+            vec![
+                AnsiEvent::Osc(b"0;title".to_vec()),
+                AnsiEvent::Osc(b"8;;url".to_vec())
+            ]
+        );
+        // ST のない OSC は次のシーケンスで終わる
+        assert_eq!(
+            parse(b"\x1b]2;a\x1b[1m"),
+            vec![AnsiEvent::Osc(b"2;a".to_vec()), csi(None, &[&[1]], b'm')]
+        );
+        assert_eq!(
+            parse(b"\x1b7\x1b(B"),
+            vec![
+                AnsiEvent::Escape {
+                    intermediates: Vec::new(),
+                    final_byte: b'7',
+                },
+                AnsiEvent::Escape {
+                    intermediates: vec![b'('],
+                    final_byte: b'B',
+                },
+            ]
+        );
+        assert_eq!(
+            parse(b"\x1bPq#0\x1b\\X"),
+            vec![AnsiEvent::Ignored, text("X")]
+        );
+
+        let mut bytes = b"\x1b]".to_vec();
+        bytes.extend(vec![b'a'; MAX_STRING_LENGTH + 10]);
+        bytes.push(BEL);
+        let [AnsiEvent::Osc(osc)] = &parse(&bytes)[..] else {
+            panic!("not an OSC sequence");
+        };
+        assert_eq!(osc.len(), MAX_STRING_LENGTH);
+    }
+
+    #[test]
+    fn interrupted_sequences() {
+        assert_eq!(parse(b"\x1b[12\x18x"), vec![AnsiEvent::Ignored, text("x")]);
+        assert_eq!(parse(b"\x1b]0;a\x1ax"), vec![AnsiEvent::Ignored, text("x")]);
+        // 途中の制御文字は実行し、シーケンスは続ける
+        assert_eq!(
+            parse(b"\x1b[1\n2m"),
+            vec![AnsiEvent::Control(b'\n'), csi(None, &[&[12]], b'm')]
+        );
+        assert_eq!(
+            AnsiParser::default().feed_with_raw(b"\x1b[1\x1b[2m"),
+            vec![
+                (AnsiEvent::Ignored, b"\x1b[1".to_vec()),
+                (csi(None, &[&[2]], b'm'), b"\x1b[2m".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn raw_bytes() {
+        assert_eq!(
+            AnsiParser::default().feed_with_raw(b"a\x1b[1;31m\r\x1b]0;t\x1b\\"),
+            vec![
+                (text("a"), Vec::new()),
+                (csi(None, &[&[1], &[31]], b'm'), b"\x1b[1;31m".to_vec()),
+                (AnsiEvent::Control(b'\r'), Vec::new()),
+                (AnsiEvent::Osc(b"0;t".to_vec()), b"\x1b]0;t\x1b\\".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        assert_eq!(
+            parse(&[b'a', 0xff, 0xc3, b'b', 0xed, 0xa0, 0x80, 0x9b]),
+            vec![
+                text("a"),
+                AnsiEvent::InvalidBytes(vec![0xff]),
+                AnsiEvent::InvalidBytes(vec![0xc3]),
+                text("b"),
+                AnsiEvent::InvalidBytes(vec![0xed, 0xa0, 0x80]),
+                AnsiEvent::InvalidBytes(vec![0x9b]),
+            ]
+        );
+        // 途切れた文字の後の ESC はシーケンスとして解釈する
+        assert_eq!(
+            parse(&[0xe3, 0x81, 0x1b, b'[', b'm']),
+            vec![
+                AnsiEvent::InvalidBytes(vec![0xe3, 0x81]),
+                csi(None, &[], b'm')
+            ]
+        );
+    }
+
+    #[test]
+    fn reset_drops_pending_sequence() {
+        let mut parser = AnsiParser::default();
+        assert_eq!(parser.feed(b"\x1b[31"), Vec::new());
+        parser.reset();
+        assert_eq!(parser.feed(b"m"), vec![text("m")]);
+    }
+}
@@ -0,0 +1,428 @@
+use super::ansi_formatter::LOG_FONT;
+use crate::sereal_colors;
+use eframe::egui;
+use eframe::egui::text::TextFormat;
+
+/// 暗く表示する文字の輝度の倍率
+const DIM_FACTOR: f32 = 0.6;
+/// 太字を明るさで表現するときに白へ寄せる割合
+const BOLD_HIGHLIGHT_FACTOR: f32 = 0.35;
+
+/// SGR で指定された色
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AnsiColor {
+    /// xterm 256 色パレットの番号
+    Palette(u8),
+    /// 24 bit の直接指定
+    Rgb(egui::Color32),
+}
+
+impl AnsiColor {
+    pub fn to_egui_color32(self) -> egui::Color32 {
+        match self {
+            AnsiColor::Palette(index) => sereal_colors::xterm_color(index).to_egui_color32(),
+            AnsiColor::Rgb(color) => color,
+        }
+    }
+}
+
+/// 描画時の書式の解釈方法
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StyleOptions {
+    /// 太字の標準色 (30-37) を明るい色 (90-97) として描画する
+    pub is_bold_as_bright: bool,
+}
+
+impl Default for StyleOptions {
+    fn default() -> Self {
+        Self {
+            is_bold_as_bright: true,
+        }
+    }
+}
+
+/// SGR によって指定される文字の書式
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct TextStyle {
+    pub text_color: Option<AnsiColor>,
+    pub background_color: Option<AnsiColor>,
+    pub is_bold: bool,
+    pub is_dim: bool,
+    pub is_italic: bool,
+    pub is_underline: bool,
+    pub is_blink: bool,
+    pub is_inverse: bool,
+    pub is_hidden: bool,
+    pub is_strikethrough: bool,
+}
+
+impl TextStyle {
+    /// SGR (ESC [ ... m) のパラメータを反映する
+    pub fn apply_graphics_params(&mut self, graphics_params: &[Vec<u16>]) {
+        // パラメータ省略時 (ESC [ m) はリセットと同じ
+        if graphics_params.is_empty() {
+            *self = TextStyle::default();
+        }
+
+        let mut params = graphics_params.iter();
+        while let Some(group) = params.next() {
+            let param = group.first().copied().unwrap_or(0);
+            match param {
+                // 0: リセット
+                0 => *self = TextStyle::default(),
+
+                // 1-9: 文字属性の設定
+                1 => self.is_bold = true,
+                2 => self.is_dim = true,
+                3 => self.is_italic = true,
+                // 4:0 は下線なし、それ以外のサブパラメータは下線の種類
+                4 => self.is_underline = group.get(1) != Some(&0),
+                5 | 6 => self.is_blink = true,
+                7 => self.is_inverse = true,
+                8 => self.is_hidden = true,
+                9 => self.is_strikethrough = true,
+
+                // 21-29: 文字属性の解除 (21 は二重下線)
+                21 => self.is_underline = true,
+                22 => {
+                    self.is_bold = false;
+                    self.is_dim = false;
+                }
+                23 => self.is_italic = false,
+                24 => self.is_underline = false,
+                25 => self.is_blink = false,
+                27 => self.is_inverse = false,
+                28 => self.is_hidden = false,
+                29 => self.is_strikethrough = false,
+
+                // 30-37: 文字色 (Normal)
+                30..=37 => self.text_color = Some(AnsiColor::Palette((param - 30) as u8)),
+                // 38: 文字色 (256 色 / 24 bit), 39: 文字色をデフォルトに戻す
+                38 => {
+                    if let Some(color) = parse_extended_color(group, &mut params) {
+                        self.text_color = Some(color);
+                    }
+                }
+                39 => self.text_color = None,
+
+                // 40-47: 背景色 (Normal)
+                40..=47 => self.background_color = Some(AnsiColor::Palette((param - 40) as u8)),
+                // 48: 背景色 (256 色 / 24 bit), 49: 背景色をデフォルトに戻す
+                48 => {
+                    if let Some(color) = parse_extended_color(group, &mut params) {
+                        self.background_color = Some(color);
+                    }
+                }
+                49 => self.background_color = None,
+
+                // 90-97: 文字色 (Bright)
+                90..=97 => self.text_color = Some(AnsiColor::Palette((param - 90 + 8) as u8)),
+
+                // 100-107: 背景色 (Bright)
+                100..=107 => {
+                    self.background_color = Some(AnsiColor::Palette((param - 100 + 8) as u8))
+                }
+
+                // それ以外は何もしない
+                _ => {}
+            }
+        }
+    }
+
+    /// 描画に用いる文字色と背景色を求める
+    pub fn resolve_colors(&self, options: &StyleOptions) -> (egui::Color32, egui::Color32) {
+        let text_color = match self.text_color {
+            // 太字の標準色は明るい色に置き換える
+            Some(AnsiColor::Palette(index @ 0..=7))
+                if self.is_bold && options.is_bold_as_bright =>
+            {
+                AnsiColor::Palette(index + 8).to_egui_color32()
+            }
+            None if self.is_bold && options.is_bold_as_bright => {
+                sereal_colors::BRIGHT_WHITE.to_egui_color32()
+            }
+            // egui の既定フォントには太字がないため、白に寄せて強調する
+            Some(color) if self.is_bold => lighten(color.to_egui_color32(), BOLD_HIGHLIGHT_FACTOR),
+            None if self.is_bold => lighten(
+                sereal_colors::WHITE.to_egui_color32(),
+                BOLD_HIGHLIGHT_FACTOR,
+            ),
+            Some(color) => color.to_egui_color32(),
+            None => sereal_colors::WHITE.to_egui_color32(),
+        };
+        let text_color = if self.is_dim {
+            scale(text_color, DIM_FACTOR)
+        } else {
+            text_color
+        };
+        let background_color = self
+            .background_color
+            .map(AnsiColor::to_egui_color32)
+            .unwrap_or_default();
+
+        if self.is_inverse {
+            // 背景色が未指定のときは黒を背景とみなして反転する
+            let inverted_text_color = self
+                .background_color
+                .map(AnsiColor::to_egui_color32)
+                .unwrap_or(sereal_colors::BLACK.to_egui_color32());
+            (inverted_text_color, text_color)
+        } else {
+            (text_color, background_color)
+        }
+    }
+
+    pub fn text_format(&self, options: &StyleOptions) -> TextFormat {
+        let (text_color, background_color) = self.resolve_colors(options);
+        let text_color = if self.is_hidden {
+            egui::Color32::TRANSPARENT
+        } else {
+            text_color
+        };
+        let line_stroke = |is_enabled: bool| {
+            if is_enabled {
+                egui::Stroke::new(1.0, text_color)
+            } else {
+                egui::Stroke::NONE
+            }
+        };
+
+        TextFormat {
+            font_id: LOG_FONT,
+            color: text_color,
+            background: background_color,
+            italics: self.is_italic,
+            underline: line_stroke(self.is_underline),
+            strikethrough: line_stroke(self.is_strikethrough),
+            ..Default::default()
+        }
+    }
+}
+
+fn lighten(color: egui::Color32, factor: f32) -> egui::Color32 {
+    let mix = |value: u8| value + ((255 - value) as f32 * factor) as u8;
+    egui::Color32::from_rgb(mix(color.r()), mix(color.g()), mix(color.b()))
+}
+
+fn scale(color: egui::Color32, factor: f32) -> egui::Color32 {
+    let mix = |value: u8| (value as f32 * factor) as u8;
+    egui::Color32::from_rgb(mix(color.r()), mix(color.g()), mix(color.b()))
+}
+
+/// 38/48 に続く拡張色の指定を解釈する
+///
+/// `38;5;n` や `38;2;r;g;b` のように `;` で区切られた形式では後続のパラメータを消費し、
+/// `38:5:n` や `38:2::r:g:b` のように `:` で区切られた形式ではサブパラメータを用いる。
+fn parse_extended_color<'a>(
+    group: &[u16],
+    params: &mut impl Iterator<Item = &'a Vec<u16>>,
+) -> Option<AnsiColor> {
+    if 1 < group.len() {
+        match group[1] {
+            5 => to_palette_color(*group.get(2)?),
+            2 => {
+                // 色空間 ID の有無で位置が変わる
+                let rgb = if 6 <= group.len() {
+                    &group[3..6]
+                } else {
+                    group.get(2..5)?
+                };
+                to_rgb_color(rgb[0], rgb[1], rgb[2])
+            }
+            _ => None,
+        }
+    } else {
+        let mut next_value = || params.next().and_then(|group| group.first().copied());
+        match next_value()? {
+            5 => to_palette_color(next_value()?),
+            2 => {
+                let (red, green, blue) = (next_value()?, next_value()?, next_value()?);
+                to_rgb_color(red, green, blue)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn to_palette_color(index: u16) -> Option<AnsiColor> {
+    u8::try_from(index).ok().map(AnsiColor::Palette)
+}
+
+fn to_rgb_color(red: u16, green: u16, blue: u16) -> Option<AnsiColor> {
+    Some(AnsiColor::Rgb(egui::Color32::from_rgb(
+        u8::try_from(red).ok()?,
+        u8::try_from(green).ok()?,
+        u8::try_from(blue).ok()?,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ansi_formatter::parser::{AnsiEvent, AnsiParser};
+
+    /// style に SGR のシーケンスを順に反映する
+    fn apply(mut style: TextStyle, sequence: &[u8]) -> TextStyle {
+        for event in AnsiParser::default().feed(sequence) {
+            let AnsiEvent::Csi(csi) = event else {
+                panic!("not a CSI sequence: {event:?}");
+            };
+            style.apply_graphics_params(&csi.params);
+        }
+        style
+    }
+
+    fn sgr(sequence: &[u8]) -> TextStyle {
+        apply(TextStyle::default(), sequence)
+    }
+
+    fn rgb(red: u8, green: u8, blue: u8) -> Option<AnsiColor> {
+        Some(AnsiColor::Rgb(egui::Color32::from_rgb(red, green, blue)))
+    }
+
+    #[test]
+    fn basic_colors() {
+        let style = sgr(b"\x1b[31;42m");
+        assert_eq!(style.text_color, Some(AnsiColor::Palette(1)));
+        assert_eq!(style.background_color, Some(AnsiColor::Palette(2)));
+        let style = sgr(b"\x1b[97;100m");
+        assert_eq!(style.text_color, Some(AnsiColor::Palette(15)));
+        assert_eq!(style.background_color, Some(AnsiColor::Palette(8)));
+
+        let style = apply(style, b"\x1b[39;49m");
+        assert_eq!(style, TextStyle::default());
+    }
+
+    #[test]
+    fn palette_colors() {
+        assert_eq!(
+            sgr(b"\x1b[38;5;196m").text_color,
+            Some(AnsiColor::Palette(196))
+        );
+        assert_eq!(
+            sgr(b"\x1b[48;5;0m").background_color,
+            Some(AnsiColor::Palette(0))
+        );
+        assert_eq!(
+            sgr(b"\x1b[38:5:255m").text_color,
+            Some(AnsiColor::Palette(255))
+        );
+        assert_eq!(
+            sgr(b"\x1b[48:5:17m").background_color,
+            Some(AnsiColor::Palette(17))
+        );
+    }
+
+    #[test]
+    fn rgb_colors() {
+        assert_eq!(sgr(b"\x1b[38;2;1;2;3m").text_color, rgb(1, 2, 3));
+        assert_eq!(
+            sgr(b"\x1b[48;2;255;128;0m").background_color,
+            rgb(255, 128, 0)
+        );
+        // コロン区切りは色空間 ID の有無どちらも受け付ける
+        assert_eq!(sgr(b"\x1b[38:2:1:2:3m").text_color, rgb(1, 2, 3));
+        assert_eq!(sgr(b"\x1b[48:2::4:5:6m").background_color, rgb(4, 5, 6));
+        assert_eq!(sgr(b"\x1b[48:2:0:4:5:6m").background_color, rgb(4, 5, 6));
+    }
+
+    #[test]
+    fn extended_color_is_followed_by_other_params() {
+        let style = sgr(b"\x1b[38;5;1;1;48;2;1;2;3;4m");
+        assert_eq!(style.text_color, Some(AnsiColor::Palette(1)));
+        assert_eq!(style.background_color, rgb(1, 2, 3));
+        assert!(style.is_bold);
+        assert!(style.is_underline);
+
+        let style = sgr(b"\x1b[38:2:1:2:3;1m");
+        assert_eq!(style.text_color, rgb(1, 2, 3));
+        assert!(style.is_bold);
+    }
+
+    #[test]
+    fn truncated_extended_colors_keep_the_color() {
+        let red = sgr(b"\x1b[31;41m");
+        for sequence in [
+            &b"\x1b[38m"[..],
+            b"\x1b[38;5m",
+            b"\x1b[38;2;1;2m",
+            b"\x1b[38:5m",
+            b"\x1b[38:2:1:2m",
+            b"\x1b[48;5m",
+            b"\x1b[48:2:1m",
+        ] {
+            assert_eq!(apply(red, sequence), red, "{sequence:?}");
+        }
+    }
+
+    #[test]
+    fn out_of_range_extended_colors_are_ignored() {
+        let red = sgr(b"\x1b[31;41m");
+        for sequence in [
+            &b"\x1b[38;5;256m"[..],
+            b"\x1b[38;2;256;0;0m",
+            b"\x1b[48;2;0;0;999m",
+            b"\x1b[38:5:300m",
+            b"\x1b[48:2::0:256:0m",
+            // 5 でも 2 でもない色の種類
+            b"\x1b[38;3m",
+            b"\x1b[48:9:1m",
+        ] {
+            assert_eq!(apply(red, sequence), red, "{sequence:?}");
+        }
+        // 範囲外の値を読み飛ばした後のパラメータは反映する
+        let style = apply(red, b"\x1b[38;5;256;1m");
+        assert_eq!(style.text_color, red.text_color);
+        assert!(style.is_bold);
+    }
+
+    #[test]
+    fn attributes_on_and_off() {
+        type Flag = fn(&TextStyle) -> bool;
+        let cases: [(&[u8], &[u8], Flag); 6] = [
+            (b"\x1b[1m", b"\x1b[22m", |style| style.is_bold),
+            (b"\x1b[3m", b"\x1b[23m", |style| style.is_italic),
+            (b"\x1b[4m", b"\x1b[24m", |style| style.is_underline),
+            (b"\x1b[5m", b"\x1b[25m", |style| style.is_blink),
+            (b"\x1b[7m", b"\x1b[27m", |style| style.is_inverse),
+            (b"\x1b[9m", b"\x1b[29m", |style| style.is_strikethrough),
+        ];
+        for (on, off, flag) in cases {
+            let style = sgr(on);
+            assert!(flag(&style), "{on:?}");
+            assert_ne!(style, TextStyle::default(), "{on:?}");
+            // 解除すると設定した属性だけが元に戻る
+            let style = apply(style, off);
+            assert!(!flag(&style), "{off:?}");
+            assert_eq!(style, TextStyle::default(), "{off:?}");
+        }
+    }
+
+    #[test]
+    fn attribute_off_keeps_other_attributes() {
+        let style = sgr(b"\x1b[1;2;3;4;5;7;9;31m");
+        let style = apply(style, b"\x1b[22;25m");
+        assert!(!style.is_bold && !style.is_dim && !style.is_blink);
+        assert!(style.is_italic && style.is_underline && style.is_inverse);
+        assert!(style.is_strikethrough);
+        assert_eq!(style.text_color, Some(AnsiColor::Palette(1)));
+
+        // 6 (高速点滅) も点滅として扱い、4:0 は下線を消す
+        assert!(sgr(b"\x1b[6m").is_blink);
+        assert!(!apply(sgr(b"\x1b[4m"), b"\x1b[4:0m").is_underline);
+        assert!(sgr(b"\x1b[4:3m").is_underline);
+    }
+
+    #[test]
+    fn empty_and_zero_params_reset() {
+        let style = sgr(b"\x1b[1;31;44m");
+        assert_eq!(apply(style, b"\x1b[m"), TextStyle::default());
+        assert_eq!(apply(style, b"\x1b[0m"), TextStyle::default());
+        // 0 の後に続くパラメータは反映する
+        assert_eq!(
+            apply(style, b"\x1b[0;32m").text_color,
+            Some(AnsiColor::Palette(2))
+        );
+        assert!(!apply(style, b"\x1b[0;32m").is_bold);
+    }
+}
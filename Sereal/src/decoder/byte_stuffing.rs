@@ -0,0 +1,342 @@
+use super::decoded_frame;
+use super::{DecodedFrame, Decoder, Direction, Field, PerDirection};
+use chrono::{DateTime, Local};
+
+/// 1 フレームとして扱うバイト数の上限。超えたらその時点で区切る
+const MAX_FRAME_LENGTH: usize = 64 * 1024;
+
+const SLIP_END: u8 = 0xc0;
+const SLIP_ESC: u8 = 0xdb;
+const SLIP_ESC_END: u8 = 0xdc;
+const SLIP_ESC_ESC: u8 = 0xdd;
+
+/// SLIP (RFC 1055) で包む。前後に END を付けて、回線上の雑音と区別できるようにする
+pub fn slip_encode(payload: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![SLIP_END];
+    for &byte in payload {
+        match byte {
+            SLIP_END => encoded.extend([SLIP_ESC, SLIP_ESC_END]),
+            SLIP_ESC => encoded.extend([SLIP_ESC, SLIP_ESC_ESC]),
+            byte => encoded.push(byte),
+        }
+    }
+    encoded.push(SLIP_END);
+    encoded
+}
+
+/// END を除いた SLIP のフレームを復元する。不正なエスケープは元のバイトを残して報告する
+pub fn slip_decode(frame: &[u8]) -> (Vec<u8>, Vec<String>) {
+    let mut payload = Vec::new();
+    let mut errors = Vec::new();
+    let mut bytes = frame.iter().copied().enumerate();
+    while let Some((_, byte)) = bytes.next() {
+        if byte != SLIP_ESC {
+            payload.push(byte);
+            continue;
+        }
+        match bytes.next() {
+            Some((_, SLIP_ESC_END)) => payload.push(SLIP_END),
+            Some((_, SLIP_ESC_ESC)) => payload.push(SLIP_ESC),
+            Some((offset, byte)) => {
+                errors.push(format!(
+                    "Invalid escape DB {byte:02X} at offset {}",
+                    offset - 1
+                ));
+                payload.extend([SLIP_ESC, byte]);
+            }
+            None => {
+                errors.push("Frame ends with an escape byte".to_string());
+                payload.push(SLIP_ESC);
+            }
+        }
+    }
+    (payload, errors)
+}
+
+/// COBS で符号化し、区切りの 0x00 を付ける
+pub fn cobs_encode(payload: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![0];
+    let mut code_index = 0;
+    for &byte in payload {
+        if byte == 0 {
+            encoded[code_index] = (encoded.len() - code_index) as u8;
+            code_index = encoded.len();
+            encoded.push(0);
+            continue;
+        }
+        encoded.push(byte);
+        // 0 を含まない 254 バイトごとにコードを挟む
+        if encoded.len() - code_index == 0xff {
+            encoded[code_index] = 0xff;
+            code_index = encoded.len();
+            encoded.push(0);
+        }
+    }
+    encoded[code_index] = (encoded.len() - code_index) as u8;
+    encoded.push(0);
+    encoded
+}
+
+/// 区切りの 0x00 を除いた COBS のフレームを復元する
+pub fn cobs_decode(frame: &[u8]) -> (Vec<u8>, Vec<String>) {
+    let mut payload = Vec::new();
+    let mut errors = Vec::new();
+    let mut index = 0;
+    while index < frame.len() {
+        let code = usize::from(frame[index]);
+        if code == 0 {
+            errors.push(format!("Zero code byte at offset {index}"));
+            index += 1;
+            continue;
+        }
+        let end = index + code;
+        if frame.len() < end {
+            errors.push(format!(
+                "Code byte {code:02X} at offset {index} runs past the end of the frame"
+            ));
+            payload.extend_from_slice(&frame[index + 1..]);
+            break;
+        }
+        payload.extend_from_slice(&frame[index + 1..end]);
+        // 0xFF 以外のコードの後ろには 0x00 があった (フレームの末尾を除く)
+        if code < 0xff && end < frame.len() {
+            payload.push(0);
+        }
+        index = end;
+    }
+    (payload, errors)
+}
+
+/// 区切りのバイトでフレームを切り出す
+#[derive(Default)]
+struct DelimitedBuffer {
+    bytes: Vec<u8>,
+    start_time: Option<DateTime<Local>>,
+}
+
+impl DelimitedBuffer {
+    /// 区切りを除いたフレームを返す。空のフレームは返さない
+    fn push(
+        &mut self,
+        delimiter: u8,
+        time: DateTime<Local>,
+        bytes: &[u8],
+    ) -> Vec<(DateTime<Local>, Vec<u8>, bool)> {
+        let mut frames = Vec::new();
+        for &byte in bytes {
+            if byte != delimiter {
+                if self.bytes.is_empty() {
+                    self.start_time = Some(time);
+                }
+                self.bytes.push(byte);
+            }
+            let is_too_long = MAX_FRAME_LENGTH <= self.bytes.len();
+            if (byte == delimiter || is_too_long) && !self.bytes.is_empty() {
+                let start_time = self.start_time.take().unwrap_or(time);
+                frames.push((start_time, std::mem::take(&mut self.bytes), is_too_long));
+            }
+        }
+        frames
+    }
+}
+
+/// 包み方
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Stuffing {
+    Slip,
+    Cobs,
+}
+
+impl Stuffing {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stuffing::Slip => "SLIP",
+            Stuffing::Cobs => "COBS",
+        }
+    }
+
+    pub fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        match self {
+            Stuffing::Slip => slip_encode(payload),
+            Stuffing::Cobs => cobs_encode(payload),
+        }
+    }
+
+    fn decode(&self, frame: &[u8]) -> (Vec<u8>, Vec<String>) {
+        match self {
+            Stuffing::Slip => slip_decode(frame),
+            Stuffing::Cobs => cobs_decode(frame),
+        }
+    }
+
+    fn delimiter(&self) -> u8 {
+        match self {
+            Stuffing::Slip => SLIP_END,
+            Stuffing::Cobs => 0x00,
+        }
+    }
+}
+
+/// SLIP または COBS で包まれたパケットを取り出すデコーダ
+pub struct ByteStuffingDecoder {
+    stuffing: Stuffing,
+    buffers: PerDirection<DelimitedBuffer>,
+}
+
+impl ByteStuffingDecoder {
+    pub fn new(stuffing: Stuffing) -> Self {
+        Self {
+            stuffing,
+            buffers: PerDirection::default(),
+        }
+    }
+}
+
+impl Decoder for ByteStuffingDecoder {
+    fn feed(
+        &mut self,
+        direction: Direction,
+        time: DateTime<Local>,
+        bytes: &[u8],
+    ) -> Vec<DecodedFrame> {
+        let delimiter = self.stuffing.delimiter();
+        self.buffers
+            .get_mut(direction)
+            .push(delimiter, time, bytes)
+            .into_iter()
+            .map(|(time, encoded, is_too_long)| {
+                let (payload, errors) = self.stuffing.decode(&encoded);
+                let summary = decoded_frame::hex_summary(&payload);
+                let mut frame = DecodedFrame::new(time, direction, encoded.clone(), summary);
+                frame.fields = vec![
+                    Field::bytes("Payload", &payload),
+                    Field::new("ASCII", decoded_frame::to_ascii(&payload)),
+                    Field::bytes(format!("{} encoded", self.stuffing.name()), &encoded),
+                ];
+                frame.errors = errors;
+                if is_too_long {
+                    frame
+                        .errors
+                        .push(format!("No delimiter within {MAX_FRAME_LENGTH} bytes"));
+                }
+                frame
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payloads() -> Vec<Vec<u8>> {
+        let mut payloads = vec![
+            Vec::new(),
+            vec![0],
+            vec![SLIP_END, SLIP_ESC, SLIP_ESC_END, SLIP_ESC_ESC],
+            (0..=255).collect(),
+        ];
+        // COBS のコードが 0xFF になる長さの前後
+        for length in [253, 254, 255, 508, 600] {
+            payloads.push((0..length).map(|i| (i % 255 + 1) as u8).collect());
+        }
+        payloads
+    }
+
+    /// 前後の区切りを除く
+    fn strip_delimiters(encoded: &[u8], delimiter: u8) -> &[u8] {
+        let encoded = encoded.strip_prefix(&[delimiter]).unwrap_or(encoded);
+        encoded.strip_suffix(&[delimiter]).unwrap()
+    }
+
+    #[test]
+    fn slip_round_trip() {
+        for payload in payloads() {
+            let encoded = slip_encode(&payload);
+            let frame = strip_delimiters(&encoded, SLIP_END);
+            assert!(!frame.contains(&SLIP_END));
+            assert_eq!(slip_decode(frame), (payload, Vec::new()));
+        }
+    }
+
+    #[test]
+    fn slip_reports_invalid_escapes() {
+        assert_eq!(
+            slip_decode(&[0x01, SLIP_ESC, 0x02]),
+            (
+                vec![0x01, SLIP_ESC, 0x02],
+                vec!["Invalid escape DB 02 at offset 1".to_string()]
+            )
+        );
+        assert_eq!(
+            slip_decode(&[0x01, SLIP_ESC]),
+            (
+                vec![0x01, SLIP_ESC],
+                vec!["Frame ends with an escape byte".to_string()]
+            )
+        );
+    }
+
+    #[test]
+    fn cobs_encodes_reference_vectors() {
+        assert_eq!(cobs_encode(&[]), [0x01, 0x00]);
+        assert_eq!(cobs_encode(&[0x00]), [0x01, 0x01, 0x00]);
+        assert_eq!(cobs_encode(&[0x00, 0x00]), [0x01, 0x01, 0x01, 0x00]);
+        assert_eq!(
+            cobs_encode(&[0x11, 0x22, 0x00, 0x33]),
+            [0x03, 0x11, 0x22, 0x02, 0x33, 0x00]
+        );
+        assert_eq!(
+            cobs_encode(&[0x11, 0x00, 0x00, 0x00]),
+            [0x02, 0x11, 0x01, 0x01, 0x01, 0x00]
+        );
+    }
+
+    #[test]
+    fn cobs_round_trip() {
+        for payload in payloads() {
+            let encoded = cobs_encode(&payload);
+            let frame = strip_delimiters(&encoded, 0x00);
+            assert!(!frame.contains(&0x00));
+            assert_eq!(cobs_decode(frame), (payload, Vec::new()));
+        }
+    }
+
+    #[test]
+    fn cobs_reports_malformed_frames() {
+        assert_eq!(
+            cobs_decode(&[0x05, 0x11, 0x22]),
+            (
+                vec![0x11, 0x22],
+                vec!["Code byte 05 at offset 0 runs past the end of the frame".to_string()]
+            )
+        );
+        assert_eq!(
+            cobs_decode(&[0x02, 0x11, 0x00, 0x01]),
+            (
+                vec![0x11, 0x00],
+                vec!["Zero code byte at offset 2".to_string()]
+            )
+        );
+    }
+
+    #[test]
+    fn decoder_splits_packets_across_reads() {
+        let mut decoder = ByteStuffingDecoder::new(Stuffing::Cobs);
+        let mut stream = Stuffing::Cobs.encode(b"ab\0c");
+        stream.extend(Stuffing::Cobs.encode(b""));
+        stream.extend(Stuffing::Cobs.encode(b"xyz"));
+        let time = Local::now();
+        let (first, second) = stream.split_at(3);
+        let mut frames = decoder.feed(Direction::Rx, time, first);
+        assert!(frames.is_empty());
+        frames.extend(decoder.feed(Direction::Rx, time, second));
+
+        let lengths: Vec<&str> = frames
+            .iter()
+            .map(|frame| frame.fields[0].value.as_str())
+            .collect();
+        assert_eq!(lengths, ["4 B", "0 B", "3 B"]);
+        assert!(frames.iter().all(|frame| frame.errors.is_empty()));
+    }
+}
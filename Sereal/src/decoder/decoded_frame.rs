@@ -0,0 +1,157 @@
+use chrono::{DateTime, Local};
+
+// 要約に表示するバイト数の上限
+const SUMMARY_MAX_BYTES: usize = 16;
+const BYTES_PER_ROW: usize = 16;
+
+/// データの向き
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    /// 受信
+    Rx,
+    /// 送信
+    Tx,
+}
+
+impl Direction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Direction::Rx => "RX",
+            Direction::Tx => "TX",
+        }
+    }
+}
+
+/// フレームを構成する値。子を持つことで木構造になる
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub value: String,
+    pub children: Vec<Field>,
+}
+
+impl Field {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            children: Vec::new(),
+        }
+    }
+
+    pub fn with_children(mut self, children: Vec<Field>) -> Self {
+        self.children = children;
+        self
+    }
+
+    /// バイト列を表すフィールド。16 バイトごとの行を子にして、長くても折りたためるようにする
+    pub fn bytes(name: impl Into<String>, bytes: &[u8]) -> Self {
+        let rows = bytes
+            .chunks(BYTES_PER_ROW)
+            .enumerate()
+            .map(|(index, row)| Field::new(format!("{:04X}", index * BYTES_PER_ROW), to_hex(row)))
+            .collect();
+        Self::new(name, format!("{} B", bytes.len())).with_children(rows)
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+    hex.join(" ")
+}
+
+/// 長さと先頭のバイトを並べた要約
+pub fn hex_summary(bytes: &[u8]) -> String {
+    let mut summary = format!(
+        "{} B  {}",
+        bytes.len(),
+        to_hex(&bytes[..bytes.len().min(SUMMARY_MAX_BYTES)])
+    );
+    if SUMMARY_MAX_BYTES < bytes.len() {
+        summary += " …";
+    }
+    summary
+}
+
+/// 表示できない文字を `.` に置き換えた ASCII の表現
+pub fn to_ascii(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                char::from(byte)
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+/// デコーダが解釈した 1 フレーム
+#[derive(Debug, Clone)]
+pub struct DecodedFrame {
+    /// 先頭のバイトを送受信した時刻
+    pub time: DateTime<Local>,
+    pub direction: Direction,
+    /// フレームを構成していたバイト列
+    pub bytes: Vec<u8>,
+    /// 一覧に表示する 1 行の要約
+    pub summary: String,
+    pub fields: Vec<Field>,
+    /// チェックサムの不一致などの問題。空でなければ不正なフレームとして扱う
+    pub errors: Vec<String>,
+}
+
+impl DecodedFrame {
+    pub fn new(
+        time: DateTime<Local>,
+        direction: Direction,
+        bytes: Vec<u8>,
+        summary: impl Into<String>,
+    ) -> Self {
+        Self {
+            time,
+            direction,
+            bytes,
+            summary: summary.into(),
+            fields: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_bytes() {
+        assert_eq!(to_hex(&[0x00, 0x7f, 0xff]), "00 7F FF");
+        assert_eq!(to_hex(&[]), "");
+        assert_eq!(to_ascii(b"a b\r\n\x7f~"), "a b...~");
+    }
+
+    #[test]
+    fn summary_is_truncated() {
+        assert_eq!(hex_summary(&[1, 2]), "2 B  01 02");
+        let summary = hex_summary(&[0xaa; SUMMARY_MAX_BYTES + 1]);
+        assert!(summary.starts_with("17 B  AA AA"));
+        assert!(summary.ends_with("AA …"));
+        assert_eq!(summary.matches("AA").count(), SUMMARY_MAX_BYTES);
+    }
+
+    #[test]
+    fn byte_field_has_a_row_per_16_bytes() {
+        let bytes: Vec<u8> = (0..20).collect();
+        let field = Field::bytes("Data", &bytes);
+        assert_eq!(field.value, "20 B");
+        assert_eq!(field.children.len(), 2);
+        assert_eq!(field.children[1].name, "0010");
+        assert_eq!(field.children[1].value, "10 11 12 13");
+        assert!(Field::bytes("Empty", &[]).children.is_empty());
+    }
+}
@@ -0,0 +1,702 @@
+use super::decoded_frame::to_hex;
+use super::{DecodedFrame, Decoder, Direction, Field, PerDirection};
+use chrono::{DateTime, Local};
+
+/// SysEx の長さの上限。超えたら途中で打ち切る
+const MAX_SYSEX_LENGTH: usize = 64 * 1024;
+
+pub const DIGITAL_MESSAGE: u8 = 0x90;
+pub const ANALOG_MESSAGE: u8 = 0xe0;
+pub const REPORT_ANALOG: u8 = 0xc0;
+pub const REPORT_DIGITAL: u8 = 0xd0;
+pub const SET_PIN_MODE: u8 = 0xf4;
+pub const SET_DIGITAL_PIN_VALUE: u8 = 0xf5;
+pub const PROTOCOL_VERSION: u8 = 0xf9;
+pub const SYSTEM_RESET: u8 = 0xff;
+const START_SYSEX: u8 = 0xf0;
+const END_SYSEX: u8 = 0xf7;
+
+pub const ANALOG_MAPPING_QUERY: u8 = 0x69;
+pub const ANALOG_MAPPING_RESPONSE: u8 = 0x6a;
+pub const CAPABILITY_QUERY: u8 = 0x6b;
+pub const CAPABILITY_RESPONSE: u8 = 0x6c;
+pub const PIN_STATE_QUERY: u8 = 0x6d;
+pub const PIN_STATE_RESPONSE: u8 = 0x6e;
+pub const EXTENDED_ANALOG: u8 = 0x6f;
+pub const STRING_DATA: u8 = 0x71;
+pub const REPORT_FIRMWARE: u8 = 0x79;
+pub const SAMPLING_INTERVAL: u8 = 0x7a;
+
+pub const MODE_INPUT: u8 = 0x00;
+pub const MODE_OUTPUT: u8 = 0x01;
+pub const MODE_ANALOG: u8 = 0x02;
+pub const MODE_PWM: u8 = 0x03;
+pub const MODE_SERVO: u8 = 0x04;
+pub const MODE_INPUT_PULLUP: u8 = 0x0b;
+
+pub fn mode_name(mode: u8) -> &'static str {
+    match mode {
+        0x00 => "Input",
+        0x01 => "Output",
+        0x02 => "Analog",
+        0x03 => "PWM",
+        0x04 => "Servo",
+        0x05 => "Shift",
+        0x06 => "I2C",
+        0x07 => "OneWire",
+        0x08 => "Stepper",
+        0x09 => "Encoder",
+        0x0a => "Serial",
+        0x0b => "Input pullup",
+        0x0c => "SPI",
+        0x0d => "Sonar",
+        0x0e => "Tone",
+        0x0f => "DHT",
+        0x7f => "Ignore",
+        _ => "Unknown mode",
+    }
+}
+
+fn sysex_name(command: u8) -> &'static str {
+    match command {
+        0x61 => "Encoder data",
+        0x62 => "Accelerometer stepper data",
+        0x63 => "Report digital pin",
+        0x64 => "Extended report analog",
+        0x65 => "Report features",
+        0x66 => "Serial data v2",
+        0x67 => "Serial data",
+        0x68 => "Scheduler data",
+        ANALOG_MAPPING_QUERY => "Analog mapping query",
+        ANALOG_MAPPING_RESPONSE => "Analog mapping response",
+        CAPABILITY_QUERY => "Capability query",
+        CAPABILITY_RESPONSE => "Capability response",
+        PIN_STATE_QUERY => "Pin state query",
+        PIN_STATE_RESPONSE => "Pin state response",
+        EXTENDED_ANALOG => "Extended analog",
+        0x70 => "Servo config",
+        STRING_DATA => "String data",
+        0x72 => "Stepper data",
+        0x73 => "OneWire data",
+        0x75 => "Shift data",
+        0x76 => "I2C request",
+        0x77 => "I2C reply",
+        0x78 => "I2C config",
+        REPORT_FIRMWARE => "Report firmware",
+        SAMPLING_INTERVAL => "Sampling interval",
+        0x7b => "Scheduler data",
+        0x7e => "Non-realtime",
+        0x7f => "Realtime",
+        _ => "Unknown SysEx",
+    }
+}
+
+/// Firmata のメッセージ
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// 8 ピン分のデジタル値
+    DigitalPort {
+        port: u8,
+        value: u16,
+    },
+    Analog {
+        channel: u8,
+        value: u16,
+    },
+    ReportAnalog {
+        channel: u8,
+        is_enabled: bool,
+    },
+    ReportDigital {
+        port: u8,
+        is_enabled: bool,
+    },
+    SetPinMode {
+        pin: u8,
+        mode: u8,
+    },
+    SetDigitalPin {
+        pin: u8,
+        value: u8,
+    },
+    ProtocolVersion {
+        major: u8,
+        minor: u8,
+    },
+    /// 引数のない 0xF9。バージョンの問い合わせ
+    ProtocolVersionQuery,
+    SystemReset,
+    SysEx {
+        command: u8,
+        data: Vec<u8>,
+    },
+    /// どのメッセージにも属さないバイト
+    Unexpected(Vec<u8>),
+}
+
+/// 受け取ったバイト列をメッセージに組み立てる
+#[derive(Default)]
+pub struct FirmataParser {
+    buffer: Vec<u8>,
+    start_time: Option<DateTime<Local>>,
+}
+
+impl FirmataParser {
+    /// 組み立て終えたメッセージを、先頭の時刻と元のバイト列とともに返す
+    pub fn push(
+        &mut self,
+        time: DateTime<Local>,
+        bytes: &[u8],
+    ) -> Vec<(DateTime<Local>, Message, Vec<u8>)> {
+        let mut messages = Vec::new();
+        for &byte in bytes {
+            // 途中で新しいコマンドが来たら、組み立て中のメッセージは不完全として捨てる
+            let is_sysex = self.buffer.first() == Some(&START_SYSEX);
+            if 0x80 <= byte && !self.buffer.is_empty() && !(is_sysex && byte == END_SYSEX) {
+                let bytes = std::mem::take(&mut self.buffer);
+                // 単独の 0xF9 はバージョンの問い合わせ
+                let message = if bytes == [PROTOCOL_VERSION] {
+                    Message::ProtocolVersionQuery
+                } else {
+                    Message::Unexpected(bytes.clone())
+                };
+                messages.push((self.start_time.take().unwrap_or(time), message, bytes));
+            }
+            if self.buffer.is_empty() {
+                self.start_time = Some(time);
+            }
+            self.buffer.push(byte);
+            if let Some(message) = self.complete_message() {
+                messages.push((
+                    self.start_time.take().unwrap_or(time),
+                    message,
+                    std::mem::take(&mut self.buffer),
+                ));
+            }
+        }
+        messages
+    }
+
+    fn complete_message(&self) -> Option<Message> {
+        let buffer = &self.buffer;
+        let command = buffer[0];
+        let channel = command & 0x0f;
+        let value14 = |lsb: u8, msb: u8| u16::from(lsb) | (u16::from(msb) << 7);
+        match (command & 0xf0, buffer.as_slice()) {
+            (_, [byte]) if *byte < 0x80 => Some(Message::Unexpected(buffer.clone())),
+            (DIGITAL_MESSAGE, [_, lsb, msb]) => Some(Message::DigitalPort {
+                port: channel,
+                value: value14(*lsb, *msb),
+            }),
+            (ANALOG_MESSAGE, [_, lsb, msb]) => Some(Message::Analog {
+                channel,
+                value: value14(*lsb, *msb),
+            }),
+            (REPORT_ANALOG, [_, enabled]) => Some(Message::ReportAnalog {
+                channel,
+                is_enabled: *enabled != 0,
+            }),
+            (REPORT_DIGITAL, [_, enabled]) => Some(Message::ReportDigital {
+                port: channel,
+                is_enabled: *enabled != 0,
+            }),
+            (0xf0, _) => match (command, buffer.as_slice()) {
+                (SET_PIN_MODE, [_, pin, mode]) => Some(Message::SetPinMode {
+                    pin: *pin,
+                    mode: *mode,
+                }),
+                (SET_DIGITAL_PIN_VALUE, [_, pin, value]) => Some(Message::SetDigitalPin {
+                    pin: *pin,
+                    value: *value,
+                }),
+                (PROTOCOL_VERSION, [_, major, minor]) => Some(Message::ProtocolVersion {
+                    major: *major,
+                    minor: *minor,
+                }),
+                (SYSTEM_RESET, [_]) => Some(Message::SystemReset),
+                (START_SYSEX, [_, .., END_SYSEX]) => Some(match &buffer[1..buffer.len() - 1] {
+                    [] => Message::Unexpected(buffer.clone()),
+                    [command, data @ ..] => Message::SysEx {
+                        command: *command,
+                        data: data.to_vec(),
+                    },
+                }),
+                (START_SYSEX, _) if MAX_SYSEX_LENGTH <= buffer.len() => {
+                    Some(Message::Unexpected(buffer.clone()))
+                }
+                (START_SYSEX | SET_PIN_MODE | SET_DIGITAL_PIN_VALUE | PROTOCOL_VERSION, _) => None,
+                _ => Some(Message::Unexpected(buffer.clone())),
+            },
+            _ => None,
+        }
+    }
+}
+
+/// 下位 7 ビット、上位 7 ビットの順に並んだバイト列を元に戻す
+pub fn decode_two_byte(data: &[u8]) -> Vec<u8> {
+    data.chunks_exact(2)
+        .map(|pair| (pair[0] & 0x7f) | (pair[1] << 7))
+        .collect()
+}
+
+/// ファームウェアの名前とバージョン
+#[derive(Debug, Clone, PartialEq)]
+pub struct Firmware {
+    pub major: u8,
+    pub minor: u8,
+    pub name: String,
+}
+
+pub fn parse_firmware(data: &[u8]) -> Option<Firmware> {
+    let [major, minor, name @ ..] = data else {
+        return None;
+    };
+    Some(Firmware {
+        major: *major,
+        minor: *minor,
+        name: String::from_utf8_lossy(&decode_two_byte(name)).into_owned(),
+    })
+}
+
+/// ピンごとの対応するモードと分解能
+pub fn parse_capabilities(data: &[u8]) -> Vec<Vec<(u8, u8)>> {
+    let mut pins = vec![Vec::new()];
+    let mut index = 0;
+    while index < data.len() {
+        if data[index] == 0x7f {
+            pins.push(Vec::new());
+            index += 1;
+            continue;
+        }
+        let resolution = data.get(index + 1).copied().unwrap_or(0);
+        if let Some(pin) = pins.last_mut() {
+            pin.push((data[index], resolution));
+        }
+        index += 2;
+    }
+    // 最後の 0x7F の後ろには何もない
+    pins.pop();
+    pins
+}
+
+/// ピンごとのアナログチャンネル。アナログに対応しないピンは None
+pub fn parse_analog_mapping(data: &[u8]) -> Vec<Option<u8>> {
+    data.iter()
+        .map(|&channel| (channel != 0x7f).then_some(channel))
+        .collect()
+}
+
+/// 7 ビットずつ下位から並んだ値
+pub fn decode_seven_bit_value(data: &[u8]) -> u32 {
+    data.iter()
+        .take(5)
+        .enumerate()
+        .fold(0, |value, (index, &byte)| {
+            value | (u32::from(byte & 0x7f) << (7 * index))
+        })
+}
+
+pub fn sysex(command: u8, data: &[u8]) -> Vec<u8> {
+    let mut message = vec![START_SYSEX, command];
+    message.extend_from_slice(data);
+    message.push(END_SYSEX);
+    message
+}
+
+/// 接続直後に送る問い合わせ (バージョン、ファームウェア、ピンの機能、アナログの対応)
+pub fn handshake() -> Vec<u8> {
+    let mut message = vec![PROTOCOL_VERSION];
+    message.extend(sysex(REPORT_FIRMWARE, &[]));
+    message.extend(sysex(CAPABILITY_QUERY, &[]));
+    message.extend(sysex(ANALOG_MAPPING_QUERY, &[]));
+    message
+}
+
+pub fn set_pin_mode(pin: u8, mode: u8) -> Vec<u8> {
+    vec![SET_PIN_MODE, pin & 0x7f, mode & 0x7f]
+}
+
+pub fn set_digital_pin(pin: u8, is_high: bool) -> Vec<u8> {
+    vec![SET_DIGITAL_PIN_VALUE, pin & 0x7f, u8::from(is_high)]
+}
+
+pub fn report_digital(port: u8, is_enabled: bool) -> Vec<u8> {
+    vec![REPORT_DIGITAL | (port & 0x0f), u8::from(is_enabled)]
+}
+
+pub fn report_analog(channel: u8, is_enabled: bool) -> Vec<u8> {
+    vec![REPORT_ANALOG | (channel & 0x0f), u8::from(is_enabled)]
+}
+
+/// PWM やサーボの値を書く。ANALOG_MESSAGE で表せない場合は EXTENDED_ANALOG を使う
+pub fn analog_write(pin: u8, value: u32) -> Vec<u8> {
+    if pin < 16 && value < 0x4000 {
+        return vec![
+            ANALOG_MESSAGE | pin,
+            (value & 0x7f) as u8,
+            ((value >> 7) & 0x7f) as u8,
+        ];
+    }
+    let mut data = vec![pin & 0x7f];
+    let mut rest = value;
+    loop {
+        data.push((rest & 0x7f) as u8);
+        rest >>= 7;
+        if rest == 0 {
+            break;
+        }
+    }
+    sysex(EXTENDED_ANALOG, &data)
+}
+
+/// メッセージの要約とフィールド
+pub fn describe(message: &Message, direction: Direction) -> (String, Vec<Field>, Vec<String>) {
+    let mut errors = Vec::new();
+    let (summary, fields) = match message {
+        Message::DigitalPort { port, value } => (
+            format!("Digital port {port} = {:08b}", value & 0xff),
+            (0..8)
+                .map(|bit| {
+                    Field::new(
+                        format!("Pin {}", port * 8 + bit),
+                        if value & (1 << bit) != 0 {
+                            "HIGH"
+                        } else {
+                            "LOW"
+                        },
+                    )
+                })
+                .collect(),
+        ),
+        Message::Analog { channel, value } => (
+            format!("Analog {channel} = {value}"),
+            vec![
+                Field::new("Channel / pin", channel.to_string()),
+                Field::new("Value", value.to_string()),
+            ],
+        ),
+        Message::ReportAnalog {
+            channel,
+            is_enabled,
+        } => (
+            format!(
+                "{} reporting analog {channel}",
+                if *is_enabled { "Start" } else { "Stop" }
+            ),
+            Vec::new(),
+        ),
+        Message::ReportDigital { port, is_enabled } => (
+            format!(
+                "{} reporting digital port {port}",
+                if *is_enabled { "Start" } else { "Stop" }
+            ),
+            Vec::new(),
+        ),
+        Message::SetPinMode { pin, mode } => (
+            format!("Set pin {pin} to {}", mode_name(*mode)),
+            vec![
+                Field::new("Pin", pin.to_string()),
+                Field::new("Mode", format!("0x{mode:02X} {}", mode_name(*mode))),
+            ],
+        ),
+        Message::SetDigitalPin { pin, value } => (
+            format!("Set pin {pin} {}", if *value != 0 { "HIGH" } else { "LOW" }),
+            Vec::new(),
+        ),
+        Message::ProtocolVersion { major, minor } => {
+            (format!("Protocol version {major}.{minor}"), Vec::new())
+        }
+        Message::ProtocolVersionQuery => ("Query the protocol version".to_string(), Vec::new()),
+        Message::SystemReset => ("System reset".to_string(), Vec::new()),
+        Message::SysEx { command, data } => describe_sysex(*command, data, direction, &mut errors),
+        Message::Unexpected(bytes) => {
+            errors.push("Incomplete or unexpected bytes".to_string());
+            (format!("Unexpected {}", to_hex(bytes)), Vec::new())
+        }
+    };
+    (summary, fields, errors)
+}
+
+fn describe_sysex(
+    command: u8,
+    data: &[u8],
+    direction: Direction,
+    errors: &mut Vec<String>,
+) -> (String, Vec<Field>) {
+    let name = sysex_name(command);
+    let mut fields = vec![Field::new(
+        "SysEx command",
+        format!("0x{command:02X} {name}"),
+    )];
+    let summary = match command {
+        REPORT_FIRMWARE if data.is_empty() => "Query the firmware".to_string(),
+        REPORT_FIRMWARE => match parse_firmware(data) {
+            Some(firmware) => {
+                fields.push(Field::new(
+                    "Version",
+                    format!("{}.{}", firmware.major, firmware.minor),
+                ));
+                fields.push(Field::new("Name", &firmware.name));
+                format!(
+                    "Firmware {} {}.{}",
+                    firmware.name, firmware.major, firmware.minor
+                )
+            }
+            None => {
+                errors.push("Firmware report too short".to_string());
+                name.to_string()
+            }
+        },
+        CAPABILITY_RESPONSE => {
+            let pins = parse_capabilities(data);
+            fields.extend(pins.iter().enumerate().map(|(pin, modes)| {
+                let modes: Vec<String> = modes
+                    .iter()
+                    .map(|(mode, resolution)| format!("{} ({resolution} bit)", mode_name(*mode)))
+                    .collect();
+                Field::new(format!("Pin {pin}"), modes.join(", "))
+            }));
+            format!("Capabilities of {} pin(s)", pins.len())
+        }
+        ANALOG_MAPPING_RESPONSE => {
+            let mapping = parse_analog_mapping(data);
+            fields.extend(mapping.iter().enumerate().filter_map(|(pin, channel)| {
+                Some(Field::new(
+                    format!("Pin {pin}"),
+                    format!("A{}", (*channel)?),
+                ))
+            }));
+            format!(
+                "Analog mapping of {} channel(s)",
+                mapping.iter().flatten().count()
+            )
+        }
+        PIN_STATE_QUERY | PIN_STATE_RESPONSE if !data.is_empty() => {
+            fields.push(Field::new("Pin", data[0].to_string()));
+            if let [_, mode, state @ ..] = data {
+                fields.push(Field::new("Mode", mode_name(*mode)));
+                fields.push(Field::new(
+                    "State",
+                    decode_seven_bit_value(state).to_string(),
+                ));
+            }
+            format!("{name} for pin {}", data[0])
+        }
+        EXTENDED_ANALOG if !data.is_empty() => {
+            let value = decode_seven_bit_value(&data[1..]);
+            fields.push(Field::new("Pin", data[0].to_string()));
+            fields.push(Field::new("Value", value.to_string()));
+            format!("Extended analog pin {} = {value}", data[0])
+        }
+        STRING_DATA => {
+            let text = String::from_utf8_lossy(&decode_two_byte(data)).into_owned();
+            fields.push(Field::new("Text", &text));
+            format!("String {text:?}")
+        }
+        SAMPLING_INTERVAL => {
+            let interval = decode_seven_bit_value(data);
+            fields.push(Field::new("Interval", format!("{interval} ms")));
+            format!("Sampling interval {interval} ms")
+        }
+        _ => {
+            fields.push(Field::bytes("Data", data));
+            let kind = match direction {
+                Direction::Tx => "request",
+                Direction::Rx => "reply",
+            };
+            format!("{name} {kind}")
+        }
+    };
+    (summary, fields)
+}
+
+/// Firmata のメッセージと SysEx を解釈するデコーダ
+#[derive(Default)]
+pub struct FirmataDecoder {
+    parsers: PerDirection<FirmataParser>,
+}
+
+impl Decoder for FirmataDecoder {
+    fn feed(
+        &mut self,
+        direction: Direction,
+        time: DateTime<Local>,
+        bytes: &[u8],
+    ) -> Vec<DecodedFrame> {
+        self.parsers
+            .get_mut(direction)
+            .push(time, bytes)
+            .into_iter()
+            .map(|(time, message, bytes)| {
+                let (summary, fields, errors) = describe(&message, direction);
+                let mut frame = DecodedFrame::new(time, direction, bytes, summary);
+                frame.fields = fields;
+                frame.errors = errors;
+                frame
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    fn parse(bytes: &[u8]) -> Vec<Message> {
+        FirmataParser::default()
+            .push(Local::now(), bytes)
+            .into_iter()
+            .map(|(_, message, _)| message)
+            .collect()
+    }
+
+    #[test]
+    fn parses_built_messages() {
+        let mut bytes = set_pin_mode(13, MODE_OUTPUT);
+        bytes.extend(set_digital_pin(13, true));
+        bytes.extend(report_digital(1, true));
+        bytes.extend(report_analog(2, false));
+        bytes.extend(analog_write(3, 1000));
+        assert_eq!(
+            parse(&bytes),
+            [
+                Message::SetPinMode {
+                    pin: 13,
+                    mode: MODE_OUTPUT
+                },
+                Message::SetDigitalPin { pin: 13, value: 1 },
+                Message::ReportDigital {
+                    port: 1,
+                    is_enabled: true
+                },
+                Message::ReportAnalog {
+                    channel: 2,
+                    is_enabled: false
+                },
+                Message::Analog {
+                    channel: 3,
+                    value: 1000
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_handshake() {
+        assert_eq!(
+            parse(&handshake()),
+            [
+                Message::ProtocolVersionQuery,
+                Message::SysEx {
+                    command: REPORT_FIRMWARE,
+                    data: Vec::new()
+                },
+                Message::SysEx {
+                    command: CAPABILITY_QUERY,
+                    data: Vec::new()
+                },
+                Message::SysEx {
+                    command: ANALOG_MAPPING_QUERY,
+                    data: Vec::new()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_extended_analog_values() {
+        let messages = parse(&analog_write(20, 0x12345));
+        let [Message::SysEx { command, data }] = messages.as_slice() else {
+            panic!("not a SysEx");
+        };
+        assert_eq!(*command, EXTENDED_ANALOG);
+        assert_eq!(data[0], 20);
+        assert_eq!(decode_seven_bit_value(&data[1..]), 0x12345);
+    }
+
+    #[test]
+    fn assembles_messages_across_reads() {
+        let mut parser = FirmataParser::default();
+        let time = Local::now();
+        let reply = [DIGITAL_MESSAGE | 2, 0x7f, 0x01];
+        assert!(parser.push(time, &reply[..1]).is_empty());
+        let messages = parser.push(time + TimeDelta::milliseconds(5), &reply[1..]);
+        assert_eq!(
+            messages,
+            [(
+                time,
+                Message::DigitalPort {
+                    port: 2,
+                    value: 0xff
+                },
+                reply.to_vec()
+            )]
+        );
+    }
+
+    #[test]
+    fn reports_unexpected_bytes() {
+        assert_eq!(
+            parse(&[0x12, ANALOG_MESSAGE, 0x01, START_SYSEX, END_SYSEX]),
+            [
+                Message::Unexpected(vec![0x12]),
+                Message::Unexpected(vec![ANALOG_MESSAGE, 0x01]),
+                Message::Unexpected(vec![START_SYSEX, END_SYSEX]),
+            ]
+        );
+        // SysEx の途中で新しいコマンドが来たら、途中までを捨てる
+        let mut bytes = vec![START_SYSEX, STRING_DATA, 0x41];
+        bytes.extend(set_digital_pin(2, false));
+        assert_eq!(
+            parse(&bytes),
+            [
+                Message::Unexpected(vec![START_SYSEX, STRING_DATA, 0x41]),
+                Message::SetDigitalPin { pin: 2, value: 0 },
+            ]
+        );
+
+        let mut too_long = vec![START_SYSEX];
+        too_long.resize(MAX_SYSEX_LENGTH, 0x01);
+        let messages = parse(&too_long);
+        assert!(
+            matches!(messages.as_slice(), [Message::Unexpected(bytes)] if bytes.len() == MAX_SYSEX_LENGTH)
+        );
+    }
+
+    #[test]
+    fn parses_sysex_payloads() {
+        assert_eq!(
+            parse_firmware(&[2, 5, b'A', 0, b'B', 0]),
+            Some(Firmware {
+                major: 2,
+                minor: 5,
+                name: "AB".to_string(),
+            })
+        );
+        assert_eq!(parse_firmware(&[2]), None);
+        assert_eq!(
+            parse_capabilities(&[
+                MODE_INPUT,
+                1,
+                MODE_OUTPUT,
+                1,
+                0x7f,
+                0x7f,
+                MODE_ANALOG,
+                10,
+                0x7f
+            ]),
+            [
+                vec![(MODE_INPUT, 1), (MODE_OUTPUT, 1)],
+                Vec::new(),
+                vec![(MODE_ANALOG, 10)],
+            ]
+        );
+        assert_eq!(
+            parse_analog_mapping(&[0x7f, 0, 1]),
+            [None, Some(0), Some(1)]
+        );
+        assert_eq!(decode_two_byte(&[0x48, 0, 0x69, 0, 0x21]), b"Hi");
+    }
+}
@@ -0,0 +1,114 @@
+use super::{DecodedFrame, Decoder, Direction, Field, PerDirection, decoded_frame};
+use crate::framing::{Frame, Framer, FramingOptions};
+use crate::serial::ReceivedData;
+use chrono::{DateTime, Local};
+
+/// Framing の設定で区切ったバイト列をそのまま 1 フレームとして扱うデコーダ
+///
+/// プロトコルが分からないときや、新しいデコーダを書く前の確認に使う。
+pub struct FramedBytesDecoder {
+    framers: PerDirection<Framer>,
+}
+
+impl FramedBytesDecoder {
+    pub fn new(options: &FramingOptions) -> Self {
+        let mut framers = PerDirection::<Framer>::default();
+        framers.rx.set_options(options.clone());
+        framers.tx.set_options(options.clone());
+        Self { framers }
+    }
+}
+
+impl Decoder for FramedBytesDecoder {
+    fn feed(
+        &mut self,
+        direction: Direction,
+        time: DateTime<Local>,
+        bytes: &[u8],
+    ) -> Vec<DecodedFrame> {
+        let data = ReceivedData {
+            time,
+            bytes: bytes.to_vec(),
+        };
+        self.framers
+            .get_mut(direction)
+            .push(&data)
+            .into_iter()
+            .map(|frame| decode_frame(direction, frame))
+            .collect()
+    }
+
+    fn poll(&mut self, now: DateTime<Local>) -> Vec<DecodedFrame> {
+        [Direction::Rx, Direction::Tx]
+            .into_iter()
+            .filter_map(|direction| {
+                let frame = self.framers.get_mut(direction).poll_idle(now)?;
+                Some(decode_frame(direction, frame))
+            })
+            .collect()
+    }
+
+    fn next_poll(&self) -> Option<std::time::Duration> {
+        let is_waiting =
+            self.framers.rx.is_waiting_for_idle() || self.framers.tx.is_waiting_for_idle();
+        let idle_gap = u64::from(self.framers.rx.options().idle_gap_ms);
+        is_waiting.then(|| std::time::Duration::from_millis(idle_gap))
+    }
+}
+
+fn decode_frame(direction: Direction, frame: Frame) -> DecodedFrame {
+    let summary = decoded_frame::hex_summary(&frame.bytes);
+    let mut decoded = DecodedFrame::new(frame.time, direction, frame.bytes.clone(), summary);
+    decoded.fields = vec![
+        Field::bytes("Bytes", &frame.bytes),
+        Field::new("ASCII", decoded_frame::to_ascii(&frame.bytes)),
+    ];
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framing::FrameRule;
+    use chrono::TimeDelta;
+
+    #[test]
+    fn decodes_delimited_frames() {
+        let options = FramingOptions {
+            rule: FrameRule::Delimiter,
+            delimiter: vec![0x00],
+            ..Default::default()
+        };
+        let mut decoder = FramedBytesDecoder::new(&options);
+        let frames = decoder.feed(Direction::Tx, Local::now(), b"AB\x01\x00rest");
+        assert_eq!(frames.len(), 1);
+        let frame = &frames[0];
+        assert_eq!(frame.direction, Direction::Tx);
+        assert_eq!(frame.bytes, b"AB\x01\x00");
+        assert_eq!(frame.summary, "4 B  41 42 01 00");
+        assert_eq!(frame.fields[0].name, "Bytes");
+        assert_eq!(frame.fields[0].value, "4 B");
+        assert_eq!(frame.fields[1].name, "ASCII");
+        assert_eq!(frame.fields[1].value, "AB..");
+        assert_eq!(decoder.next_poll(), None);
+    }
+
+    #[test]
+    fn poll_ends_frame_after_idle_gap() {
+        let options = FramingOptions::default();
+        let mut decoder = FramedBytesDecoder::new(&options);
+        let start = Local::now();
+        assert!(decoder.feed(Direction::Rx, start, b"\x01\x02").is_empty());
+        assert_eq!(
+            decoder.next_poll(),
+            Some(std::time::Duration::from_millis(20))
+        );
+        assert!(decoder.poll(start + TimeDelta::milliseconds(5)).is_empty());
+
+        let frames = decoder.poll(start + TimeDelta::milliseconds(25));
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].bytes, [1, 2]);
+        assert_eq!(frames[0].time, start);
+        assert_eq!(decoder.next_poll(), None);
+    }
+}
@@ -0,0 +1,124 @@
+use super::text_line::{Line, LineBuffer};
+use super::{DecodedFrame, Decoder, Direction, Field, PerDirection};
+use chrono::{DateTime, Local};
+
+/// `temp=23.5, hum=40` や `temp: 23.5 hum: 40` のような行を値の組に分けるデコーダ
+///
+/// センサーの出力やデバッグ表示によくある形式を想定している。
+#[derive(Default)]
+pub struct KeyValueDecoder {
+    buffers: PerDirection<LineBuffer>,
+}
+
+impl Decoder for KeyValueDecoder {
+    fn feed(
+        &mut self,
+        direction: Direction,
+        time: DateTime<Local>,
+        bytes: &[u8],
+    ) -> Vec<DecodedFrame> {
+        self.buffers
+            .get_mut(direction)
+            .push(time, bytes)
+            .into_iter()
+            .filter(|line| !line.content().is_empty())
+            .map(|line| decode_line(direction, line))
+            .collect()
+    }
+}
+
+fn decode_line(direction: Direction, line: Line) -> DecodedFrame {
+    let text = String::from_utf8_lossy(line.content()).into_owned();
+    let mut frame = DecodedFrame::new(line.time, direction, line.bytes.clone(), "");
+
+    let mut pairs = Vec::new();
+    let mut key: Option<&str> = None;
+    for token in text
+        .split([',', ';', ' ', '\t'])
+        .filter(|token| !token.is_empty())
+    {
+        // `key: value` は区切りの空白で 2 つのトークンに分かれる
+        if let Some(pending) = key.take() {
+            pairs.push((pending, token));
+            continue;
+        }
+        match token.split_once(['=', ':']) {
+            Some((name, "")) => key = Some(name),
+            Some((name, value)) => pairs.push((name, value)),
+            None => frame
+                .errors
+                .push(format!("Not a key/value pair: {token:?}")),
+        }
+    }
+    if let Some(pending) = key {
+        frame.errors.push(format!("Missing value for {pending:?}"));
+    }
+
+    let summary: Vec<String> = pairs
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect();
+    frame.summary = if summary.is_empty() {
+        format!("{text:?}")
+    } else {
+        summary.join(", ")
+    };
+    frame.fields = pairs
+        .into_iter()
+        .map(|(name, value)| Field::new(name, value))
+        .collect();
+    if line.is_too_long() {
+        frame.errors.push("Line too long".to_string());
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(text: &[u8]) -> Vec<DecodedFrame> {
+        KeyValueDecoder::default().feed(Direction::Rx, Local::now(), text)
+    }
+
+    fn pairs(frame: &DecodedFrame) -> Vec<(&str, &str)> {
+        frame
+            .fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn splits_pairs() {
+        let frames = decode(b"temp=23.5, hum=40\r\ntemp: 23.5 hum:40;x=1\n");
+        assert_eq!(frames.len(), 2);
+        assert_eq!(pairs(&frames[0]), [("temp", "23.5"), ("hum", "40")]);
+        assert_eq!(frames[0].summary, "temp=23.5, hum=40");
+        assert!(frames[0].is_valid());
+        assert_eq!(
+            pairs(&frames[1]),
+            [("temp", "23.5"), ("hum", "40"), ("x", "1")]
+        );
+    }
+
+    #[test]
+    fn skips_empty_lines() {
+        assert!(decode(b"\r\n\n").is_empty());
+    }
+
+    #[test]
+    fn reports_malformed_tokens() {
+        let frames = decode(b"a=1 oops b:\n");
+        assert_eq!(pairs(&frames[0]), [("a", "1")]);
+        assert_eq!(
+            frames[0].errors,
+            ["Not a key/value pair: \"oops\"", "Missing value for \"b\""]
+        );
+
+        // 組がなければ行をそのまま要約にする
+        let frames = decode(b"hello\n");
+        assert_eq!(frames[0].summary, "\"hello\"");
+        assert!(frames[0].fields.is_empty());
+    }
+}
@@ -0,0 +1,116 @@
+pub mod byte_stuffing;
+pub mod decoded_frame;
+pub mod firmata;
+pub mod framed_bytes;
+pub mod key_value;
+pub mod modbus;
+pub mod nmea;
+pub mod slcan;
+pub mod text_line;
+
+pub use decoded_frame::DecodedFrame;
+pub use decoded_frame::Direction;
+pub use decoded_frame::Field;
+
+use crate::framing::FramingOptions;
+use chrono::{DateTime, Local};
+
+/// 送受信したバイト列をプロトコルのフレームとして解釈する
+///
+/// 受信 (RX) と送信 (TX) は別々のストリームとして渡される。
+/// 実装は方向ごとに組み立て途中の状態を持ち、解釈し終えたフレームを返す。
+pub trait Decoder {
+    /// バイト列を取り込み、解釈し終えたフレームを返す
+    fn feed(
+        &mut self,
+        direction: Direction,
+        time: DateTime<Local>,
+        bytes: &[u8],
+    ) -> Vec<DecodedFrame>;
+
+    /// 定期的に呼ばれる。受信の間隔でフレームを区切る場合に使う
+    fn poll(&mut self, _now: DateTime<Local>) -> Vec<DecodedFrame> {
+        Vec::new()
+    }
+
+    /// 組み立て途中のフレームを区切るために、次に poll を呼んでほしい時間
+    fn next_poll(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
+
+/// デコーダを作るときに渡す接続の設定
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecoderContext {
+    pub baud_rate: u32,
+    pub framing: FramingOptions,
+}
+
+/// 選択できるデコーダ
+pub struct DecoderInfo {
+    pub name: &'static str,
+    pub create: fn(&DecoderContext) -> Box<dyn Decoder>,
+}
+
+pub const DECODERS: &[DecoderInfo] = &[
+    DecoderInfo {
+        name: "Text lines",
+        create: |_| Box::new(text_line::TextLineDecoder::default()),
+    },
+    DecoderInfo {
+        name: "Key/value lines",
+        create: |_| Box::new(key_value::KeyValueDecoder::default()),
+    },
+    DecoderInfo {
+        name: "Framed bytes",
+        create: |context| Box::new(framed_bytes::FramedBytesDecoder::new(&context.framing)),
+    },
+    DecoderInfo {
+        name: "Modbus RTU",
+        create: |context| Box::new(modbus::ModbusRtuDecoder::new(context.baud_rate)),
+    },
+    DecoderInfo {
+        name: "NMEA 0183",
+        create: |_| Box::new(nmea::NmeaDecoder::default()),
+    },
+    DecoderInfo {
+        name: "SLCAN",
+        create: |_| Box::new(slcan::SlcanDecoder::default()),
+    },
+    DecoderInfo {
+        name: "Firmata",
+        create: |_| Box::new(firmata::FirmataDecoder::default()),
+    },
+    DecoderInfo {
+        name: "SLIP",
+        create: |_| {
+            Box::new(byte_stuffing::ByteStuffingDecoder::new(
+                byte_stuffing::Stuffing::Slip,
+            ))
+        },
+    },
+    DecoderInfo {
+        name: "COBS",
+        create: |_| {
+            Box::new(byte_stuffing::ByteStuffingDecoder::new(
+                byte_stuffing::Stuffing::Cobs,
+            ))
+        },
+    },
+];
+
+/// 方向ごとに持つ状態
+#[derive(Debug, Clone, Default)]
+pub struct PerDirection<T> {
+    pub rx: T,
+    pub tx: T,
+}
+
+impl<T> PerDirection<T> {
+    pub fn get_mut(&mut self, direction: Direction) -> &mut T {
+        match direction {
+            Direction::Rx => &mut self.rx,
+            Direction::Tx => &mut self.tx,
+        }
+    }
+}
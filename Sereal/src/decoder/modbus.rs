@@ -0,0 +1,485 @@
+use super::decoded_frame::to_hex;
+use super::{DecodedFrame, Decoder, Direction, Field, PerDirection};
+use chrono::{DateTime, Local, TimeDelta};
+
+/// RTU の ADU (アドレス + PDU + CRC) の最大長
+const MAX_ADU_LENGTH: usize = 256;
+/// 1 文字のビット数 (スタート + 8 データ + パリティまたはストップ + ストップ)
+const BITS_PER_CHAR: f64 = 11.0;
+
+/// フレームの区切りとみなす無通信時間 (3.5 文字分)
+///
+/// 19200 bps を超える場合は規格に従い 1.75 ms に固定する。
+pub fn silent_interval(baud_rate: u32) -> TimeDelta {
+    if 19200 < baud_rate || baud_rate == 0 {
+        return TimeDelta::microseconds(1750);
+    }
+    let seconds = 3.5 * BITS_PER_CHAR / f64::from(baud_rate);
+    TimeDelta::microseconds((seconds * 1_000_000.0).ceil() as i64)
+}
+
+/// Modbus の CRC-16 (多項式 0xA001、初期値 0xFFFF)
+pub fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0xffff, |crc, &byte| {
+        (0..8).fold(crc ^ u16::from(byte), |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xa001
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// CRC を付けた RTU のフレームを作る
+pub fn build_adu(address: u8, function: u8, data: &[u8]) -> Vec<u8> {
+    let mut adu = vec![address, function];
+    adu.extend_from_slice(data);
+    adu.extend_from_slice(&crc16(&adu).to_le_bytes());
+    adu
+}
+
+/// CRC を確認した RTU のフレーム
+#[derive(Debug, Clone, PartialEq)]
+pub struct Adu<'a> {
+    pub address: u8,
+    pub function: u8,
+    pub data: &'a [u8],
+}
+
+/// フレームを分解する。短すぎるか CRC が合わなければ理由を返す
+pub fn parse_adu(bytes: &[u8]) -> Result<Adu<'_>, String> {
+    let Some((body, crc)) = bytes.split_last_chunk::<2>().filter(|_| 4 <= bytes.len()) else {
+        return Err(format!("Frame too short ({} B)", bytes.len()));
+    };
+    let received = u16::from_le_bytes(*crc);
+    let expected = crc16(body);
+    if received != expected {
+        return Err(format!(
+            "CRC mismatch: received 0x{received:04X}, expected 0x{expected:04X}"
+        ));
+    }
+    Ok(Adu {
+        address: body[0],
+        function: body[1],
+        data: &body[2..],
+    })
+}
+
+pub fn function_name(function: u8) -> &'static str {
+    match function & 0x7f {
+        1 => "Read Coils",
+        2 => "Read Discrete Inputs",
+        3 => "Read Holding Registers",
+        4 => "Read Input Registers",
+        5 => "Write Single Coil",
+        6 => "Write Single Register",
+        7 => "Read Exception Status",
+        8 => "Diagnostics",
+        11 => "Get Comm Event Counter",
+        12 => "Get Comm Event Log",
+        15 => "Write Multiple Coils",
+        16 => "Write Multiple Registers",
+        17 => "Report Server ID",
+        22 => "Mask Write Register",
+        23 => "Read/Write Multiple Registers",
+        43 => "Encapsulated Interface Transport",
+        _ => "Unknown function",
+    }
+}
+
+pub fn exception_name(code: u8) -> &'static str {
+    match code {
+        1 => "Illegal Function",
+        2 => "Illegal Data Address",
+        3 => "Illegal Data Value",
+        4 => "Server Device Failure",
+        5 => "Acknowledge",
+        6 => "Server Device Busy",
+        8 => "Memory Parity Error",
+        10 => "Gateway Path Unavailable",
+        11 => "Gateway Target Device Failed to Respond",
+        _ => "Unknown exception",
+    }
+}
+
+/// 無通信時間で区切る途中のフレーム
+#[derive(Default)]
+struct FrameBuffer {
+    bytes: Vec<u8>,
+    start_time: Option<DateTime<Local>>,
+    last_time: Option<DateTime<Local>>,
+}
+
+/// 3.5 文字分の無通信時間でフレームを区切り、CRC と機能コードを解釈するデコーダ
+///
+/// 時刻は受信スレッドが読み出した単位で付くため、区切りの精度は OS の読み出し間隔に依存する。
+pub struct ModbusRtuDecoder {
+    silent_interval: TimeDelta,
+    buffers: PerDirection<FrameBuffer>,
+}
+
+impl ModbusRtuDecoder {
+    pub fn new(baud_rate: u32) -> Self {
+        Self {
+            silent_interval: silent_interval(baud_rate),
+            buffers: PerDirection::default(),
+        }
+    }
+
+    fn take_frame(&mut self, direction: Direction) -> Option<DecodedFrame> {
+        let buffer = self.buffers.get_mut(direction);
+        if buffer.bytes.is_empty() {
+            return None;
+        }
+        let time = buffer.start_time.take().unwrap_or_else(Local::now);
+        let bytes = std::mem::take(&mut buffer.bytes);
+        Some(decode_frame(direction, time, bytes))
+    }
+}
+
+impl Decoder for ModbusRtuDecoder {
+    fn feed(
+        &mut self,
+        direction: Direction,
+        time: DateTime<Local>,
+        bytes: &[u8],
+    ) -> Vec<DecodedFrame> {
+        let mut frames = Vec::new();
+        let silent_interval = self.silent_interval;
+        let is_silent = self
+            .buffers
+            .get_mut(direction)
+            .last_time
+            .is_some_and(|last_time| silent_interval <= time - last_time);
+        if is_silent {
+            frames.extend(self.take_frame(direction));
+        }
+
+        for &byte in bytes {
+            let buffer = self.buffers.get_mut(direction);
+            if buffer.bytes.is_empty() {
+                buffer.start_time = Some(time);
+            }
+            buffer.bytes.push(byte);
+            if MAX_ADU_LENGTH <= buffer.bytes.len() {
+                frames.extend(self.take_frame(direction));
+            }
+        }
+        self.buffers.get_mut(direction).last_time = Some(time);
+        frames
+    }
+
+    fn poll(&mut self, now: DateTime<Local>) -> Vec<DecodedFrame> {
+        let mut frames = Vec::new();
+        for direction in [Direction::Rx, Direction::Tx] {
+            let is_silent = self
+                .buffers
+                .get_mut(direction)
+                .last_time
+                .is_some_and(|last_time| self.silent_interval <= now - last_time);
+            if is_silent {
+                frames.extend(self.take_frame(direction));
+            }
+        }
+        frames
+    }
+
+    fn next_poll(&self) -> Option<std::time::Duration> {
+        let is_waiting = !self.buffers.rx.bytes.is_empty() || !self.buffers.tx.bytes.is_empty();
+        is_waiting.then(|| self.silent_interval.to_std().unwrap_or_default())
+    }
+}
+
+fn decode_frame(direction: Direction, time: DateTime<Local>, bytes: Vec<u8>) -> DecodedFrame {
+    let mut frame = DecodedFrame::new(time, direction, bytes.clone(), "");
+    if bytes.len() < 4 {
+        frame.summary = format!("Incomplete frame ({} B)", bytes.len());
+        frame
+            .errors
+            .push(format!("Frame too short ({} B)", bytes.len()));
+        return frame;
+    }
+
+    // CRC が合わなくても内容は解釈して表示する
+    let (body, crc) = bytes.split_at(bytes.len() - 2);
+    let received_crc = u16::from_le_bytes([crc[0], crc[1]]);
+    let expected_crc = crc16(body);
+    let (address, function, data) = (body[0], body[1], &body[2..]);
+
+    let target = match address {
+        0 => "Broadcast".to_string(),
+        address => format!("Slave {address}"),
+    };
+    let (kind, fields) = decode_pdu(direction, function, data);
+    frame.summary = format!("{target} {} {kind}", function_name(function));
+    frame
+        .fields
+        .push(Field::new("Slave address", address.to_string()));
+    frame.fields.push(Field::new(
+        "Function",
+        format!("0x{function:02X} {}", function_name(function)),
+    ));
+    match fields {
+        Ok(fields) => frame.fields.extend(fields),
+        Err(error) => {
+            frame.fields.push(Field::new("Data", to_hex(data)));
+            frame.errors.push(error);
+        }
+    }
+    if received_crc == expected_crc {
+        frame
+            .fields
+            .push(Field::new("CRC", format!("0x{received_crc:04X} (ok)")));
+    } else {
+        frame
+            .fields
+            .push(Field::new("CRC", format!("0x{received_crc:04X}")));
+        frame.errors.push(format!(
+            "CRC mismatch: received 0x{received_crc:04X}, expected 0x{expected_crc:04X}"
+        ));
+    }
+    frame
+}
+
+/// PDU の種類 (要求、応答など) とフィールドを返す
+fn decode_pdu(
+    direction: Direction,
+    function: u8,
+    data: &[u8],
+) -> (&'static str, Result<Vec<Field>, String>) {
+    let word = |index: usize| u16::from_be_bytes([data[index], data[index + 1]]);
+    let unexpected_length = || Err(format!("Unexpected data length {} B", data.len()));
+
+    if function & 0x80 != 0 {
+        return match data {
+            [code] => (
+                "exception",
+                Ok(vec![Field::new(
+                    "Exception",
+                    format!("0x{code:02X} {}", exception_name(*code)),
+                )]),
+            ),
+            _ => ("exception", unexpected_length()),
+        };
+    }
+
+    match function {
+        1..=4 => {
+            let is_request_length = data.len() == 4;
+            let is_response_length = data
+                .first()
+                .is_some_and(|&count| data.len() == 1 + usize::from(count));
+            // 両方に当てはまる長さは、送った側を要求とみなす
+            let is_request = match (is_request_length, is_response_length) {
+                (true, true) => direction == Direction::Tx,
+                (is_request, _) => is_request,
+            };
+            if is_request {
+                let fields = vec![
+                    Field::new("Start address", word(0).to_string()),
+                    Field::new("Quantity", word(2).to_string()),
+                ];
+                ("request", Ok(fields))
+            } else if is_response_length {
+                let values = if function <= 2 {
+                    bit_fields(&data[1..], (data.len() - 1) * 8)
+                } else {
+                    register_fields(&data[1..])
+                };
+                let fields = vec![
+                    Field::new("Byte count", data[0].to_string()),
+                    Field::new("Values", format!("{} item(s)", values.len())).with_children(values),
+                ];
+                ("response", Ok(fields))
+            } else {
+                ("", unexpected_length())
+            }
+        }
+        5 | 6 if data.len() == 4 => {
+            let value = if function == 5 {
+                match word(2) {
+                    0xff00 => "ON".to_string(),
+                    0x0000 => "OFF".to_string(),
+                    value => format!("invalid (0x{value:04X})"),
+                }
+            } else {
+                format!("{} (0x{:04X})", word(2), word(2))
+            };
+            let fields = vec![
+                Field::new("Address", word(0).to_string()),
+                Field::new("Value", value),
+            ];
+            let kind = match direction {
+                Direction::Tx => "request",
+                Direction::Rx => "request/echo",
+            };
+            (kind, Ok(fields))
+        }
+        15 | 16 if 5 <= data.len() && data.len() == 5 + usize::from(data[4]) => {
+            let values = if function == 15 {
+                bit_fields(&data[5..], usize::from(word(2)))
+            } else {
+                register_fields(&data[5..])
+            };
+            let fields = vec![
+                Field::new("Start address", word(0).to_string()),
+                Field::new("Quantity", word(2).to_string()),
+                Field::new("Byte count", data[4].to_string()),
+                Field::new("Values", format!("{} item(s)", values.len())).with_children(values),
+            ];
+            ("request", Ok(fields))
+        }
+        15 | 16 if data.len() == 4 => {
+            let fields = vec![
+                Field::new("Start address", word(0).to_string()),
+                Field::new("Quantity", word(2).to_string()),
+            ];
+            ("response", Ok(fields))
+        }
+        5 | 6 | 15 | 16 => ("", unexpected_length()),
+        _ => ("", Ok(vec![Field::new("Data", to_hex(data))])),
+    }
+}
+
+/// ビットを下位から順に並べる
+fn bit_fields(bytes: &[u8], count: usize) -> Vec<Field> {
+    (0..count.min(bytes.len() * 8))
+        .map(|index| {
+            let is_on = bytes[index / 8] & (1 << (index % 8)) != 0;
+            Field::new(format!("[{index}]"), if is_on { "ON" } else { "OFF" })
+        })
+        .collect()
+}
+
+fn register_fields(bytes: &[u8]) -> Vec<Field> {
+    bytes
+        .chunks_exact(2)
+        .enumerate()
+        .map(|(index, pair)| {
+            let value = u16::from_be_bytes([pair[0], pair[1]]);
+            Field::new(format!("[{index}]"), format!("{value} (0x{value:04X})"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field<'a>(fields: &'a [Field], name: &str) -> &'a str {
+        &fields
+            .iter()
+            .find(|field| field.name == name)
+            .unwrap()
+            .value
+    }
+
+    #[test]
+    fn computes_crc16() {
+        assert_eq!(crc16(b"123456789"), 0x4b37);
+        assert_eq!(
+            build_adu(1, 3, &[0x00, 0x00, 0x00, 0x0a]),
+            [0x01, 0x03, 0x00, 0x00, 0x00, 0x0a, 0xc5, 0xcd]
+        );
+    }
+
+    #[test]
+    fn parses_built_adu() {
+        let adu = build_adu(17, 16, &[0x00, 0x01, 0x00, 0x02, 0x04, 1, 2, 3, 4]);
+        assert_eq!(
+            parse_adu(&adu),
+            Ok(Adu {
+                address: 17,
+                function: 16,
+                data: &[0x00, 0x01, 0x00, 0x02, 0x04, 1, 2, 3, 4],
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_adu() {
+        assert_eq!(
+            parse_adu(&[1, 3, 0]),
+            Err("Frame too short (3 B)".to_string())
+        );
+        let mut adu = build_adu(1, 3, &[0x00, 0x00, 0x00, 0x0a]);
+        adu[3] ^= 0x01;
+        assert!(parse_adu(&adu).unwrap_err().starts_with("CRC mismatch"));
+    }
+
+    #[test]
+    fn decodes_read_request_and_response() {
+        let (kind, fields) = decode_pdu(Direction::Tx, 3, &[0x00, 0x6b, 0x00, 0x02]);
+        let fields = fields.unwrap();
+        assert_eq!(kind, "request");
+        assert_eq!(field(&fields, "Start address"), "107");
+        assert_eq!(field(&fields, "Quantity"), "2");
+
+        let (kind, fields) = decode_pdu(Direction::Rx, 3, &[4, 0x02, 0x2b, 0x00, 0x00]);
+        let fields = fields.unwrap();
+        assert_eq!(kind, "response");
+        let values = &fields[1].children;
+        assert_eq!(values[0].value, "555 (0x022B)");
+        assert_eq!(values[1].value, "0 (0x0000)");
+
+        let (_, fields) = decode_pdu(Direction::Rx, 1, &[1, 0b0000_0101]);
+        let values = &fields.unwrap()[1].children;
+        assert_eq!(values.len(), 8);
+        assert_eq!(values[0].value, "ON");
+        assert_eq!(values[1].value, "OFF");
+        assert_eq!(values[2].value, "ON");
+    }
+
+    #[test]
+    fn decodes_writes_and_exceptions() {
+        let (_, fields) = decode_pdu(Direction::Tx, 5, &[0x00, 0xac, 0xff, 0x00]);
+        assert_eq!(field(&fields.unwrap(), "Value"), "ON");
+        let (_, fields) = decode_pdu(Direction::Tx, 5, &[0x00, 0xac, 0x12, 0x34]);
+        assert_eq!(field(&fields.unwrap(), "Value"), "invalid (0x1234)");
+
+        let (kind, fields) = decode_pdu(Direction::Tx, 16, &[0, 1, 0, 1, 2, 0x12, 0x34]);
+        assert_eq!(kind, "request");
+        assert_eq!(fields.unwrap()[3].children[0].value, "4660 (0x1234)");
+        let (kind, _) = decode_pdu(Direction::Rx, 16, &[0, 1, 0, 1]);
+        assert_eq!(kind, "response");
+
+        let (kind, fields) = decode_pdu(Direction::Rx, 0x83, &[0x02]);
+        assert_eq!(kind, "exception");
+        assert_eq!(
+            field(&fields.unwrap(), "Exception"),
+            "0x02 Illegal Data Address"
+        );
+    }
+
+    #[test]
+    fn rejects_unexpected_pdu_lengths() {
+        let error = Err("Unexpected data length 3 B".to_string());
+        assert_eq!(decode_pdu(Direction::Rx, 3, &[4, 0, 0]).1, error);
+        assert_eq!(decode_pdu(Direction::Tx, 6, &[0, 1, 2]).1, error);
+        assert_eq!(decode_pdu(Direction::Tx, 16, &[0, 1, 0]).1, error);
+        assert!(decode_pdu(Direction::Rx, 0x83, &[]).1.is_err());
+    }
+
+    #[test]
+    fn splits_frames_on_silence() {
+        let mut decoder = ModbusRtuDecoder::new(9600);
+        let time = Local::now();
+        let request = build_adu(1, 3, &[0x00, 0x00, 0x00, 0x0a]);
+        assert!(decoder.feed(Direction::Tx, time, &request[..3]).is_empty());
+        assert!(decoder.feed(Direction::Tx, time, &request[3..]).is_empty());
+        assert!(decoder.next_poll().is_some());
+
+        let frames = decoder.poll(time + TimeDelta::milliseconds(10));
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].bytes, request);
+        assert_eq!(frames[0].summary, "Slave 1 Read Holding Registers request");
+        assert!(frames[0].errors.is_empty());
+        assert!(decoder.next_poll().is_none());
+
+        // 途中で切れたフレームは短すぎるフレームとして出す
+        decoder.feed(Direction::Rx, time, &[1, 3]);
+        let frames = decoder.feed(Direction::Rx, time + TimeDelta::milliseconds(10), &[1]);
+        assert_eq!(frames[0].summary, "Incomplete frame (2 B)");
+    }
+}
@@ -0,0 +1,547 @@
+use super::text_line::{Line, LineBuffer};
+use super::{DecodedFrame, Decoder, Direction, Field, PerDirection};
+use crate::hex;
+use chrono::{DateTime, Local, NaiveDate, NaiveTime};
+
+/// 緯度と経度 (度)。南緯と西経は負の値
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Position {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// GGA: 測位の結果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gga {
+    pub time: Option<NaiveTime>,
+    pub position: Option<Position>,
+    /// 0 = 無効, 1 = GPS, 2 = DGPS, 4 = RTK Fixed, 5 = RTK Float, 6 = 推定
+    pub quality: u8,
+    pub satellites_used: Option<u8>,
+    pub hdop: Option<f32>,
+    pub altitude_m: Option<f32>,
+}
+
+/// RMC: 推奨最小限の航法情報
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rmc {
+    pub time: Option<NaiveTime>,
+    pub is_valid: bool,
+    pub position: Option<Position>,
+    pub speed_knots: Option<f32>,
+    pub course: Option<f32>,
+    pub date: Option<NaiveDate>,
+}
+
+/// GSA: 測位に使った衛星と DOP
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gsa {
+    /// 1 = 測位なし, 2 = 2D, 3 = 3D
+    pub fix_type: u8,
+    pub prns: Vec<u16>,
+    pub pdop: Option<f32>,
+    pub hdop: Option<f32>,
+    pub vdop: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Satellite {
+    pub prn: u16,
+    pub elevation: Option<u8>,
+    pub azimuth: Option<u16>,
+    /// 信号強度 (dB-Hz)。追尾していなければ None
+    pub snr: Option<u8>,
+}
+
+/// GSV: 見えている衛星。複数のセンテンスに分かれて届く
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gsv {
+    pub total_messages: u8,
+    pub message_number: u8,
+    pub satellites_in_view: u16,
+    pub satellites: Vec<Satellite>,
+}
+
+/// VTG: 進行方向と対地速度
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vtg {
+    pub course: Option<f32>,
+    pub speed_knots: Option<f32>,
+    pub speed_kmh: Option<f32>,
+}
+
+/// GLL: 緯度と経度
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gll {
+    pub position: Option<Position>,
+    pub time: Option<NaiveTime>,
+    pub is_valid: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SentenceData {
+    Gga(Gga),
+    Rmc(Rmc),
+    Gsa(Gsa),
+    Gsv(Gsv),
+    Vtg(Vtg),
+    Gll(Gll),
+    /// 解釈しない種類のセンテンス
+    Other,
+}
+
+/// チェックサムを確認した 1 センテンス
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sentence {
+    /// GP (GPS), GN (複数のシステム) などの発信元
+    pub talker: String,
+    /// GGA, RMC などの種類
+    pub kind: String,
+    pub data: SentenceData,
+}
+
+/// センテンスを解釈する。チェックサムが合わないか形式が不正なら理由を返す
+pub fn parse_sentence(line: &[u8]) -> Result<Sentence, String> {
+    let text = std::str::from_utf8(line).map_err(|_| "Not ASCII".to_string())?;
+    let body = text
+        .strip_prefix('$')
+        .or_else(|| text.strip_prefix('!'))
+        .ok_or_else(|| "Does not start with $".to_string())?;
+    let (body, checksum) = body
+        .split_once('*')
+        .ok_or_else(|| "Missing checksum".to_string())?;
+    // チェックサムはちょうど 2 桁の 16 進数
+    let received = match hex::decode(checksum).as_deref() {
+        Some(&[received]) => received,
+        _ => return Err(format!("Malformed checksum {checksum:?}")),
+    };
+    let expected = checksum_of(body);
+    if received != expected {
+        return Err(format!(
+            "Checksum mismatch: received {received:02X}, expected {expected:02X}"
+        ));
+    }
+
+    let fields: Vec<&str> = body.split(',').collect();
+    let address = fields[0];
+    // 独自センテンス (P で始まる) は発信元が 1 文字で、PUBX のように 4 文字のこともある
+    let is_proprietary = address.starts_with('P');
+    let min_length = if is_proprietary { 4 } else { 5 };
+    if address.len() < min_length || !address.is_ascii() {
+        return Err(format!("Malformed address {address:?}"));
+    }
+    let (talker, kind) = if is_proprietary {
+        address.split_at(1)
+    } else {
+        address.split_at(2)
+    };
+    let field = |index: usize| fields.get(index).copied().unwrap_or("");
+    let data = match kind {
+        "GGA" => SentenceData::Gga(Gga {
+            time: parse_time(field(1)),
+            position: parse_position(field(2), field(3), field(4), field(5)),
+            quality: field(6).parse().unwrap_or(0),
+            satellites_used: field(7).parse().ok(),
+            hdop: field(8).parse().ok(),
+            altitude_m: field(9).parse().ok(),
+        }),
+        "RMC" => SentenceData::Rmc(Rmc {
+            time: parse_time(field(1)),
+            is_valid: field(2) == "A",
+            position: parse_position(field(3), field(4), field(5), field(6)),
+            speed_knots: field(7).parse().ok(),
+            course: field(8).parse().ok(),
+            date: NaiveDate::parse_from_str(field(9), "%d%m%y").ok(),
+        }),
+        "GSA" => SentenceData::Gsa(Gsa {
+            fix_type: field(2).parse().unwrap_or(1),
+            prns: (3..15)
+                .filter_map(|index| field(index).parse().ok())
+                .collect(),
+            pdop: field(15).parse().ok(),
+            hdop: field(16).parse().ok(),
+            // NMEA 4.1 以降は末尾にシステム ID が付く
+            vdop: field(17).parse().ok(),
+        }),
+        "GSV" => SentenceData::Gsv(Gsv {
+            total_messages: field(1).parse().unwrap_or(1),
+            message_number: field(2).parse().unwrap_or(1),
+            satellites_in_view: field(3).parse().unwrap_or(0),
+            satellites: fields
+                .get(4..)
+                .unwrap_or_default()
+                .chunks_exact(4)
+                .filter_map(|satellite| {
+                    Some(Satellite {
+                        prn: satellite[0].parse().ok()?,
+                        elevation: satellite[1].parse().ok(),
+                        azimuth: satellite[2].parse().ok(),
+                        snr: satellite[3].parse().ok(),
+                    })
+                })
+                .collect(),
+        }),
+        "VTG" => SentenceData::Vtg(Vtg {
+            course: field(1).parse().ok(),
+            speed_knots: field(5).parse().ok(),
+            speed_kmh: field(7).parse().ok(),
+        }),
+        "GLL" => SentenceData::Gll(Gll {
+            position: parse_position(field(1), field(2), field(3), field(4)),
+            time: parse_time(field(5)),
+            is_valid: field(6) == "A",
+        }),
+        _ => SentenceData::Other,
+    };
+    Ok(Sentence {
+        talker: talker.to_string(),
+        kind: kind.to_string(),
+        data,
+    })
+}
+
+/// `$` と `*` の間の全バイトの XOR
+fn checksum_of(body: &str) -> u8 {
+    body.bytes().fold(0, |checksum, byte| checksum ^ byte)
+}
+
+/// hhmmss.ss 形式の UTC 時刻
+fn parse_time(text: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(text, "%H%M%S%.f").ok()
+}
+
+/// ddmm.mmmm 形式の緯度と dddmm.mmmm 形式の経度
+fn parse_position(
+    latitude: &str,
+    north_south: &str,
+    longitude: &str,
+    east_west: &str,
+) -> Option<Position> {
+    let degrees = |text: &str, degree_digits: usize| -> Option<f64> {
+        let degrees: f64 = text.get(..degree_digits)?.parse().ok()?;
+        let minutes: f64 = text.get(degree_digits..)?.parse().ok()?;
+        Some(degrees + minutes / 60.0)
+    };
+    let latitude = degrees(latitude, 2)?;
+    let longitude = degrees(longitude, 3)?;
+    Some(Position {
+        latitude: if north_south == "S" {
+            -latitude
+        } else {
+            latitude
+        },
+        longitude: if east_west == "W" {
+            -longitude
+        } else {
+            longitude
+        },
+    })
+}
+
+pub fn quality_name(quality: u8) -> &'static str {
+    match quality {
+        0 => "No fix",
+        1 => "GPS fix",
+        2 => "DGPS fix",
+        3 => "PPS fix",
+        4 => "RTK fixed",
+        5 => "RTK float",
+        6 => "Estimated",
+        7 => "Manual input",
+        8 => "Simulation",
+        _ => "Unknown",
+    }
+}
+
+pub fn format_position(position: &Position) -> String {
+    let north_south = if position.latitude < 0.0 { 'S' } else { 'N' };
+    let east_west = if position.longitude < 0.0 { 'W' } else { 'E' };
+    format!(
+        "{:.6}°{north_south} {:.6}°{east_west}",
+        position.latitude.abs(),
+        position.longitude.abs()
+    )
+}
+
+/// NMEA 0183 のセンテンスを 1 行ずつ解釈するデコーダ
+#[derive(Default)]
+pub struct NmeaDecoder {
+    buffers: PerDirection<LineBuffer>,
+}
+
+impl Decoder for NmeaDecoder {
+    fn feed(
+        &mut self,
+        direction: Direction,
+        time: DateTime<Local>,
+        bytes: &[u8],
+    ) -> Vec<DecodedFrame> {
+        self.buffers
+            .get_mut(direction)
+            .push(time, bytes)
+            .into_iter()
+            .filter(|line| !line.content().is_empty())
+            .map(|line| decode_line(direction, line))
+            .collect()
+    }
+}
+
+fn decode_line(direction: Direction, line: Line) -> DecodedFrame {
+    let text = String::from_utf8_lossy(line.content()).into_owned();
+    let mut frame = DecodedFrame::new(line.time, direction, line.bytes.clone(), "");
+    let sentence = match parse_sentence(line.content()) {
+        Ok(sentence) => sentence,
+        Err(error) => {
+            frame.summary = format!("{text:?}");
+            frame.fields.push(Field::new("Sentence", text));
+            frame.errors.push(error);
+            return frame;
+        }
+    };
+
+    frame.fields.push(Field::new("Talker", &sentence.talker));
+    frame.fields.push(Field::new("Type", &sentence.kind));
+    let optional = |value: Option<String>| value.unwrap_or_else(|| "—".to_string());
+    let time = |time: Option<NaiveTime>| optional(time.map(|time| time.to_string()));
+    let position = |position: Option<Position>| optional(position.as_ref().map(format_position));
+    let number =
+        |value: Option<f32>, unit: &str| optional(value.map(|value| format!("{value}{unit}")));
+
+    let (summary, fields) = match &sentence.data {
+        SentenceData::Gga(gga) => (
+            format!(
+                "{}, {} satellite(s)",
+                quality_name(gga.quality),
+                optional(gga.satellites_used.map(|count| count.to_string()))
+            ),
+            vec![
+                Field::new("UTC time", time(gga.time)),
+                Field::new("Position", position(gga.position)),
+                Field::new("Quality", quality_name(gga.quality)),
+                Field::new(
+                    "Satellites used",
+                    optional(gga.satellites_used.map(|count| count.to_string())),
+                ),
+                Field::new("HDOP", number(gga.hdop, "")),
+                Field::new("Altitude", number(gga.altitude_m, " m")),
+            ],
+        ),
+        SentenceData::Rmc(rmc) => (
+            format!(
+                "{}, {}",
+                if rmc.is_valid { "Valid" } else { "Void" },
+                position(rmc.position)
+            ),
+            vec![
+                Field::new("UTC time", time(rmc.time)),
+                Field::new("Date", optional(rmc.date.map(|date| date.to_string()))),
+                Field::new("Status", if rmc.is_valid { "Valid" } else { "Void" }),
+                Field::new("Position", position(rmc.position)),
+                Field::new("Speed", number(rmc.speed_knots, " kn")),
+                Field::new("Course", number(rmc.course, "°")),
+            ],
+        ),
+        SentenceData::Gsa(gsa) => {
+            let prns: Vec<String> = gsa.prns.iter().map(u16::to_string).collect();
+            (
+                format!(
+                    "{}, {} satellite(s) used",
+                    fix_type_name(gsa.fix_type),
+                    gsa.prns.len()
+                ),
+                vec![
+                    Field::new("Fix type", fix_type_name(gsa.fix_type)),
+                    Field::new("Satellites used", prns.join(" ")),
+                    Field::new("PDOP", number(gsa.pdop, "")),
+                    Field::new("HDOP", number(gsa.hdop, "")),
+                    Field::new("VDOP", number(gsa.vdop, "")),
+                ],
+            )
+        }
+        SentenceData::Gsv(gsv) => {
+            let satellites = gsv
+                .satellites
+                .iter()
+                .map(|satellite| {
+                    Field::new(
+                        format!("PRN {}", satellite.prn),
+                        format!(
+                            "elevation {}, azimuth {}, SNR {}",
+                            optional(satellite.elevation.map(|value| format!("{value}°"))),
+                            optional(satellite.azimuth.map(|value| format!("{value}°"))),
+                            optional(satellite.snr.map(|value| format!("{value} dB-Hz"))),
+                        ),
+                    )
+                })
+                .collect();
+            (
+                format!(
+                    "{} satellite(s) in view ({}/{})",
+                    gsv.satellites_in_view, gsv.message_number, gsv.total_messages
+                ),
+                vec![
+                    Field::new(
+                        "Message",
+                        format!("{} of {}", gsv.message_number, gsv.total_messages),
+                    ),
+                    Field::new("Satellites in view", gsv.satellites_in_view.to_string()),
+                    Field::new("Satellites", gsv.satellites.len().to_string())
+                        .with_children(satellites),
+                ],
+            )
+        }
+        SentenceData::Vtg(vtg) => (
+            number(vtg.speed_kmh, " km/h"),
+            vec![
+                Field::new("Course", number(vtg.course, "°")),
+                Field::new("Speed", number(vtg.speed_knots, " kn")),
+                Field::new("Speed", number(vtg.speed_kmh, " km/h")),
+            ],
+        ),
+        SentenceData::Gll(gll) => (
+            position(gll.position),
+            vec![
+                Field::new("Position", position(gll.position)),
+                Field::new("UTC time", time(gll.time)),
+                Field::new("Status", if gll.is_valid { "Valid" } else { "Void" }),
+            ],
+        ),
+        SentenceData::Other => (text.clone(), vec![Field::new("Sentence", text.clone())]),
+    };
+    frame.summary = format!("{}{} {summary}", sentence.talker, sentence.kind);
+    frame.fields.extend(fields);
+    frame
+}
+
+pub fn fix_type_name(fix_type: u8) -> &'static str {
+    match fix_type {
+        2 => "2D fix",
+        3 => "3D fix",
+        _ => "No fix",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// チェックサムを付けたセンテンス
+    fn sentence(body: &str) -> String {
+        format!("${body}*{:02X}", checksum_of(body))
+    }
+
+    fn parse(line: &str) -> SentenceData {
+        parse_sentence(line.as_bytes()).unwrap().data
+    }
+
+    #[test]
+    fn verifies_checksum() {
+        let gga = "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47";
+        assert_eq!(sentence(&gga[1..gga.len() - 3]), gga);
+        assert!(parse_sentence(gga.as_bytes()).is_ok());
+
+        let corrupted = gga.replace("545.4", "545.5");
+        assert_eq!(
+            parse_sentence(corrupted.as_bytes()),
+            Err("Checksum mismatch: received 47, expected 46".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_sentences() {
+        let error = |line: &[u8]| parse_sentence(line).unwrap_err();
+        assert_eq!(error(b"GPGGA,1*00"), "Does not start with $");
+        assert_eq!(error(b"$GPGGA,1"), "Missing checksum");
+        assert_eq!(error(b"$GPGGA,1*G1"), "Malformed checksum \"G1\"");
+        assert_eq!(error(b"$GPGGA,1*+A"), "Malformed checksum \"+A\"");
+        assert_eq!(error(b"$GPGGA,1*7"), "Malformed checksum \"7\"");
+        assert_eq!(error(b"$GPGGA,1*"), "Malformed checksum \"\"");
+        assert_eq!(error(b"$GPGGA,1*047"), "Malformed checksum \"047\"");
+        assert_eq!(error(b"$GPGGA,1*\xff"), "Not ASCII");
+        assert_eq!(
+            error(sentence("GPG,1").as_bytes()),
+            "Malformed address \"GPG\""
+        );
+        assert_eq!(
+            error(sentence("GPGGé,1").as_bytes()),
+            "Malformed address \"GPGGé\""
+        );
+    }
+
+    #[test]
+    fn parses_gga() {
+        let SentenceData::Gga(gga) =
+            parse("$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47")
+        else {
+            panic!("not GGA");
+        };
+        assert_eq!(gga.time, NaiveTime::from_hms_opt(12, 35, 19));
+        let position = gga.position.unwrap();
+        assert!((position.latitude - 48.1173).abs() < 1e-6);
+        assert!((position.longitude - 11.516_666).abs() < 1e-6);
+        assert_eq!(gga.quality, 1);
+        assert_eq!(gga.satellites_used, Some(8));
+        assert_eq!(gga.hdop, Some(0.9));
+        assert_eq!(gga.altitude_m, Some(545.4));
+    }
+
+    #[test]
+    fn parses_rmc_in_southern_and_western_hemispheres() {
+        let SentenceData::Rmc(rmc) = parse(&sentence(
+            "GNRMC,083559.00,A,3351.0000,S,15112.0000,W,0.5,54.7,191194,,",
+        )) else {
+            panic!("not RMC");
+        };
+        assert!(rmc.is_valid);
+        let position = rmc.position.unwrap();
+        assert!((position.latitude + 33.85).abs() < 1e-9);
+        assert!((position.longitude + 151.2).abs() < 1e-9);
+        assert_eq!(rmc.date, NaiveDate::from_ymd_opt(1994, 11, 19));
+        assert_eq!(format_position(&position), "33.850000°S 151.200000°W");
+    }
+
+    #[test]
+    fn parses_gsa_and_gsv() {
+        let SentenceData::Gsa(gsa) = parse("$GPGSA,A,3,04,05,,09,12,,,24,,,,,2.5,1.3,2.1*39")
+        else {
+            panic!("not GSA");
+        };
+        assert_eq!(gsa.fix_type, 3);
+        assert_eq!(gsa.prns, [4, 5, 9, 12, 24]);
+        assert_eq!(
+            (gsa.pdop, gsa.hdop, gsa.vdop),
+            (Some(2.5), Some(1.3), Some(2.1))
+        );
+
+        let SentenceData::Gsv(gsv) = parse(&sentence("GPGSV,2,2,06,01,40,083,46,02,17,308,"))
+        else {
+            panic!("not GSV");
+        };
+        assert_eq!((gsv.message_number, gsv.total_messages), (2, 2));
+        assert_eq!(gsv.satellites_in_view, 6);
+        assert_eq!(gsv.satellites.len(), 2);
+        assert_eq!(gsv.satellites[1].snr, None);
+    }
+
+    #[test]
+    fn leaves_empty_fields_unset() {
+        let SentenceData::Gga(gga) = parse(&sentence("GPGGA,,,,,,0,,,,,,,,")) else {
+            panic!("not GGA");
+        };
+        assert_eq!(gga.time, None);
+        assert_eq!(gga.position, None);
+        assert_eq!(gga.satellites_used, None);
+
+        // 度の桁が足りない座標は無視する
+        let SentenceData::Gll(gll) = parse(&sentence("GPGLL,4,N,01131.000,E,123519,V")) else {
+            panic!("not GLL");
+        };
+        assert_eq!(gll.position, None);
+        assert!(!gll.is_valid);
+
+        let proprietary = parse_sentence(sentence("PUBX,00").as_bytes()).unwrap();
+        assert_eq!(proprietary.talker, "P");
+        assert_eq!(proprietary.kind, "UBX");
+        assert_eq!(proprietary.data, SentenceData::Other);
+    }
+}
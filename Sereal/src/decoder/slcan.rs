@@ -0,0 +1,369 @@
+use super::decoded_frame::{self, to_hex};
+use super::{DecodedFrame, Decoder, Direction, Field, PerDirection};
+use crate::hex;
+use chrono::{DateTime, Local};
+
+/// 1 コマンドとして扱うバイト数の上限。最長の拡張フレーム + タイムスタンプより十分長い
+const MAX_COMMAND_LENGTH: usize = 64;
+const BELL: u8 = 0x07;
+
+/// S0 から S8 で選べるビットレート (kbit/s)
+pub const BITRATES_KBPS: [u32; 9] = [10, 20, 50, 100, 125, 250, 500, 800, 1000];
+
+/// CAN のフレーム
+#[derive(Debug, Clone, PartialEq)]
+pub struct CanFrame {
+    pub id: u32,
+    pub is_extended: bool,
+    pub is_remote: bool,
+    pub dlc: u8,
+    pub data: Vec<u8>,
+    /// アダプタが付けたタイムスタンプ (ミリ秒、60000 で一周する)
+    pub timestamp_ms: Option<u16>,
+}
+
+impl CanFrame {
+    /// 表示用の ID。標準フレームは 3 桁、拡張フレームは 8 桁
+    pub fn id_text(&self) -> String {
+        if self.is_extended {
+            format!("{:08X}", self.id)
+        } else {
+            format!("{:03X}", self.id)
+        }
+    }
+
+    /// t/T/r/R コマンドに変換する
+    pub fn encode(&self) -> Vec<u8> {
+        let command = match (self.is_extended, self.is_remote) {
+            (false, false) => 't',
+            (true, false) => 'T',
+            (false, true) => 'r',
+            (true, true) => 'R',
+        };
+        let mut text = format!("{command}{}{:X}", self.id_text(), self.dlc);
+        if !self.is_remote {
+            for byte in &self.data {
+                text += &format!("{byte:02X}");
+            }
+        }
+        text.push('\r');
+        text.into_bytes()
+    }
+}
+
+/// t/T/r/R で始まるフレームを解釈する
+pub fn parse_frame(command: &[u8]) -> Result<CanFrame, String> {
+    let text = std::str::from_utf8(command).map_err(|_| "Not ASCII".to_string())?;
+    let (is_extended, is_remote) = match text.chars().next() {
+        Some('t') => (false, false),
+        Some('T') => (true, false),
+        Some('r') => (false, true),
+        Some('R') => (true, true),
+        _ => return Err("Not a CAN frame".to_string()),
+    };
+    let id_length = if is_extended { 8 } else { 3 };
+    let hex = |range: std::ops::Range<usize>| -> Result<u32, String> {
+        let digits = text
+            .get(range)
+            .ok_or_else(|| format!("Frame too short ({} characters)", text.len()))?;
+        hex::parse_u32(digits).ok_or_else(|| format!("Not hex: {digits:?}"))
+    };
+
+    let id = hex(1..1 + id_length)?;
+    let max_id = if is_extended { 0x1fff_ffff } else { 0x7ff };
+    if max_id < id {
+        return Err(format!("ID {id:X} out of range"));
+    }
+    let dlc = hex(1 + id_length..2 + id_length)? as u8;
+    if 8 < dlc {
+        return Err(format!("DLC {dlc} out of range"));
+    }
+
+    let mut offset = 2 + id_length;
+    let mut data = Vec::new();
+    if !is_remote {
+        for _ in 0..dlc {
+            data.push(hex(offset..offset + 2)? as u8);
+            offset += 2;
+        }
+    }
+    let timestamp_ms = match text.len() - offset {
+        0 => None,
+        4 => Some(hex(offset..offset + 4)? as u16),
+        _ => {
+            return Err(format!(
+                "Unexpected trailing characters {:?}",
+                &text[offset..]
+            ));
+        }
+    };
+    Ok(CanFrame {
+        id,
+        is_extended,
+        is_remote,
+        dlc,
+        data,
+        timestamp_ms,
+    })
+}
+
+/// ビットレートを設定するコマンド
+pub fn bitrate_command(index: usize) -> Vec<u8> {
+    format!("S{index}\r").into_bytes()
+}
+
+pub const OPEN_COMMAND: &[u8] = b"O\r";
+pub const LISTEN_ONLY_COMMAND: &[u8] = b"L\r";
+pub const CLOSE_COMMAND: &[u8] = b"C\r";
+
+pub fn timestamp_command(is_enabled: bool) -> Vec<u8> {
+    format!("Z{}\r", u8::from(is_enabled)).into_bytes()
+}
+
+/// CR または BEL で終わる 1 コマンド (終端を含む)
+pub struct Command {
+    pub time: DateTime<Local>,
+    pub bytes: Vec<u8>,
+}
+
+impl Command {
+    /// 終端を除いた内容
+    pub fn content(&self) -> &[u8] {
+        self.bytes
+            .strip_suffix(b"\r")
+            .or_else(|| self.bytes.strip_suffix(&[BELL]))
+            .unwrap_or(&self.bytes)
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.bytes.ends_with(&[BELL])
+    }
+}
+
+/// バイト列を CR と BEL でコマンドに区切る
+#[derive(Default)]
+pub struct CommandBuffer {
+    buffer: Vec<u8>,
+    start_time: Option<DateTime<Local>>,
+}
+
+impl CommandBuffer {
+    pub fn push(&mut self, time: DateTime<Local>, bytes: &[u8]) -> Vec<Command> {
+        let mut commands = Vec::new();
+        for &byte in bytes {
+            // LF を付けるアダプタもあるため読み飛ばす
+            if byte == b'\n' {
+                continue;
+            }
+            if self.buffer.is_empty() {
+                self.start_time = Some(time);
+            }
+            self.buffer.push(byte);
+            if byte == b'\r' || byte == BELL || MAX_COMMAND_LENGTH <= self.buffer.len() {
+                commands.push(Command {
+                    time: self.start_time.take().unwrap_or(time),
+                    bytes: std::mem::take(&mut self.buffer),
+                });
+            }
+        }
+        commands
+    }
+}
+
+/// SLCAN (Lawicel) の ASCII コマンドと CAN フレームを解釈するデコーダ
+#[derive(Default)]
+pub struct SlcanDecoder {
+    buffers: PerDirection<CommandBuffer>,
+}
+
+impl Decoder for SlcanDecoder {
+    fn feed(
+        &mut self,
+        direction: Direction,
+        time: DateTime<Local>,
+        bytes: &[u8],
+    ) -> Vec<DecodedFrame> {
+        self.buffers
+            .get_mut(direction)
+            .push(time, bytes)
+            .into_iter()
+            .map(|command| decode_command(direction, command))
+            .collect()
+    }
+}
+
+fn decode_command(direction: Direction, command: Command) -> DecodedFrame {
+    let content = command.content();
+    let mut frame = DecodedFrame::new(command.time, direction, command.bytes.clone(), "");
+    if command.is_error() {
+        frame.summary = "Error (BEL)".to_string();
+        frame
+            .errors
+            .push("The adapter rejected the command".to_string());
+        return frame;
+    }
+
+    let text = String::from_utf8_lossy(content).into_owned();
+    let argument = text.get(1..).unwrap_or("");
+    frame.summary = match content.first() {
+        None => "OK".to_string(),
+        Some(b't' | b'T' | b'r' | b'R') => match parse_frame(content) {
+            Ok(can_frame) => {
+                frame.fields = can_frame_fields(&can_frame);
+                let kind = if can_frame.is_remote { " RTR" } else { "" };
+                format!(
+                    "ID {}{kind} [{}] {}",
+                    can_frame.id_text(),
+                    can_frame.dlc,
+                    to_hex(&can_frame.data)
+                )
+            }
+            Err(error) => {
+                frame.errors.push(error);
+                format!("Malformed frame {text:?}")
+            }
+        },
+        Some(b'z' | b'Z') if argument.is_empty() && direction == Direction::Rx => {
+            "Transmitted".to_string()
+        }
+        Some(b'S') => match argument
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| BITRATES_KBPS.get(index))
+        {
+            Some(kbps) => format!("Set bitrate {kbps} kbit/s"),
+            None => {
+                frame.errors.push(format!("Unknown bitrate {argument:?}"));
+                "Set bitrate".to_string()
+            }
+        },
+        Some(b's') => format!("Set bit timing registers {argument}"),
+        Some(b'O') => "Open the channel".to_string(),
+        Some(b'L') => "Open the channel (listen only)".to_string(),
+        Some(b'C') => "Close the channel".to_string(),
+        Some(b'Z') => match argument {
+            "1" => "Enable timestamps".to_string(),
+            _ => "Disable timestamps".to_string(),
+        },
+        Some(b'F') if direction == Direction::Tx => "Read status flags".to_string(),
+        Some(b'F') => format!("Status flags {argument}"),
+        Some(b'V') if direction == Direction::Tx => "Read version".to_string(),
+        Some(b'V') => format!("Version {argument}"),
+        Some(b'N') if direction == Direction::Tx => "Read serial number".to_string(),
+        Some(b'N') => format!("Serial number {argument}"),
+        Some(_) => format!("Unknown command {text:?}"),
+    };
+    if frame.fields.is_empty() {
+        frame
+            .fields
+            .push(Field::new("Command", format!("{text:?}")));
+    }
+    frame
+}
+
+pub fn can_frame_fields(can_frame: &CanFrame) -> Vec<Field> {
+    let mut fields = vec![
+        Field::new("ID", format!("0x{}", can_frame.id_text())),
+        Field::new(
+            "Format",
+            if can_frame.is_extended {
+                "Extended (29-bit)"
+            } else {
+                "Standard (11-bit)"
+            },
+        ),
+        Field::new(
+            "Type",
+            if can_frame.is_remote {
+                "Remote"
+            } else {
+                "Data"
+            },
+        ),
+        Field::new("DLC", can_frame.dlc.to_string()),
+    ];
+    if !can_frame.is_remote {
+        fields.push(Field::bytes("Data", &can_frame.data));
+        fields.push(Field::new(
+            "ASCII",
+            decoded_frame::to_ascii(&can_frame.data),
+        ));
+    }
+    if let Some(timestamp) = can_frame.timestamp_ms {
+        fields.push(Field::new("Timestamp", format!("{timestamp} ms")));
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(id: u32, is_extended: bool, is_remote: bool, data: &[u8]) -> CanFrame {
+        CanFrame {
+            id,
+            is_extended,
+            is_remote,
+            dlc: data.len() as u8,
+            data: if is_remote { Vec::new() } else { data.to_vec() },
+            timestamp_ms: None,
+        }
+    }
+
+    #[test]
+    fn round_trips_frames() {
+        let frames = [
+            frame(0x123, false, false, &[0x11, 0x22, 0xaa]),
+            frame(0x7ff, false, false, &[]),
+            frame(0x1fff_ffff, true, false, &[0, 1, 2, 3, 4, 5, 6, 7]),
+            frame(0x42, false, true, &[0; 4]),
+            frame(0x0123_4567, true, true, &[0; 8]),
+        ];
+        for can_frame in frames {
+            let encoded = can_frame.encode();
+            let command = encoded.strip_suffix(b"\r").unwrap();
+            assert_eq!(parse_frame(command), Ok(can_frame));
+        }
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            parse_frame(b"t12321122"),
+            Ok(frame(0x123, false, false, &[0x11, 0x22]))
+        );
+        let mut can_frame = frame(0x0000_0abc, true, true, &[0; 2]);
+        can_frame.timestamp_ms = Some(0xea5f);
+        assert_eq!(parse_frame(b"R00000ABC2EA5F"), Ok(can_frame));
+    }
+
+    #[test]
+    fn rejects_malformed_frames() {
+        let error = |command: &[u8]| parse_frame(command).unwrap_err();
+        assert_eq!(error(b"O"), "Not a CAN frame");
+        assert_eq!(error(b""), "Not a CAN frame");
+        assert_eq!(error(b"t\xff"), "Not ASCII");
+        assert_eq!(error(b"t12"), "Frame too short (3 characters)");
+        assert_eq!(error(b"t1232112"), "Frame too short (8 characters)");
+        assert_eq!(error(b"t800"), "ID 800 out of range");
+        assert_eq!(error(b"T200000000"), "ID 20000000 out of range");
+        assert_eq!(error(b"t1239"), "DLC 9 out of range");
+        assert_eq!(error(b"t12G0"), "Not hex: \"12G\"");
+        assert_eq!(error(b"t1+10"), "Not hex: \"1+1\"");
+        assert_eq!(error(b"t1231+2"), "Not hex: \"+2\"");
+        assert_eq!(error(b"t123011"), "Unexpected trailing characters \"11\"");
+        assert_eq!(error("t123é".as_bytes()), "Frame too short (6 characters)");
+    }
+
+    #[test]
+    fn splits_commands() {
+        let mut buffer = CommandBuffer::default();
+        let time = Local::now();
+        assert!(buffer.push(time, b"t1230").is_empty());
+        let commands = buffer.push(time, b"\r\nz\r\x07");
+        let contents: Vec<&[u8]> = commands.iter().map(Command::content).collect();
+        assert_eq!(contents, [&b"t1230"[..], b"z", b""]);
+        assert!(!commands[1].is_error());
+        assert!(commands[2].is_error());
+    }
+}
@@ -0,0 +1,179 @@
+use super::{DecodedFrame, Decoder, Direction, Field, PerDirection};
+use chrono::{DateTime, Local};
+
+/// 1 行として扱うバイト数の上限。超えたらその時点で区切る
+pub const MAX_LINE_LENGTH: usize = 4096;
+
+/// LF で区切った 1 行
+pub struct Line {
+    pub time: DateTime<Local>,
+    /// 行末の CR LF を含むバイト列
+    pub bytes: Vec<u8>,
+}
+
+impl Line {
+    /// 行末の CR と LF を除いた内容
+    pub fn content(&self) -> &[u8] {
+        let mut content = self.bytes.as_slice();
+        if let Some(rest) = content.strip_suffix(b"\n") {
+            content = rest;
+        }
+        if let Some(rest) = content.strip_suffix(b"\r") {
+            content = rest;
+        }
+        content
+    }
+
+    pub fn line_ending(&self) -> &'static str {
+        if self.bytes.ends_with(b"\r\n") {
+            "CR LF"
+        } else if self.bytes.ends_with(b"\n") {
+            "LF"
+        } else {
+            "none"
+        }
+    }
+
+    pub fn is_too_long(&self) -> bool {
+        !self.bytes.ends_with(b"\n")
+    }
+}
+
+/// バイト列を LF で行に区切る。行を扱うデコーダで共通に使う
+#[derive(Default)]
+pub struct LineBuffer {
+    buffer: Vec<u8>,
+    start_time: Option<DateTime<Local>>,
+}
+
+impl LineBuffer {
+    pub fn push(&mut self, time: DateTime<Local>, bytes: &[u8]) -> Vec<Line> {
+        let mut lines = Vec::new();
+        for &byte in bytes {
+            if self.buffer.is_empty() {
+                self.start_time = Some(time);
+            }
+            self.buffer.push(byte);
+            if byte == b'\n' || MAX_LINE_LENGTH <= self.buffer.len() {
+                lines.push(Line {
+                    time: self.start_time.take().unwrap_or(time),
+                    bytes: std::mem::take(&mut self.buffer),
+                });
+            }
+        }
+        lines
+    }
+}
+
+/// テキストを 1 行ずつ 1 フレームとして扱うデコーダ
+#[derive(Default)]
+pub struct TextLineDecoder {
+    buffers: PerDirection<LineBuffer>,
+}
+
+impl Decoder for TextLineDecoder {
+    fn feed(
+        &mut self,
+        direction: Direction,
+        time: DateTime<Local>,
+        bytes: &[u8],
+    ) -> Vec<DecodedFrame> {
+        self.buffers
+            .get_mut(direction)
+            .push(time, bytes)
+            .into_iter()
+            .map(|line| decode_line(direction, line))
+            .collect()
+    }
+}
+
+fn decode_line(direction: Direction, line: Line) -> DecodedFrame {
+    let content = line.content();
+    let text = String::from_utf8_lossy(content).into_owned();
+    let mut frame = DecodedFrame::new(
+        line.time,
+        direction,
+        line.bytes.clone(),
+        format!("{text:?}"),
+    );
+    frame.fields = vec![
+        Field::new("Text", format!("{text:?}")),
+        Field::new("Length", format!("{} B", content.len())),
+        Field::new("Line ending", line.line_ending()),
+    ];
+    if std::str::from_utf8(content).is_err() {
+        frame.errors.push("Invalid UTF-8".to_string());
+    }
+    if line.is_too_long() {
+        frame
+            .errors
+            .push(format!("Line longer than {MAX_LINE_LENGTH} bytes"));
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    fn field<'a>(frame: &'a DecodedFrame, name: &str) -> &'a str {
+        &frame
+            .fields
+            .iter()
+            .find(|field| field.name == name)
+            .unwrap()
+            .value
+    }
+
+    #[test]
+    fn decodes_lines() {
+        let mut decoder = TextLineDecoder::default();
+        let frames = decoder.feed(Direction::Rx, Local::now(), b"hello\r\n\"x\"\n");
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].summary, "\"hello\"");
+        assert_eq!(frames[0].bytes, b"hello\r\n");
+        assert_eq!(field(&frames[0], "Text"), "\"hello\"");
+        assert_eq!(field(&frames[0], "Length"), "5 B");
+        assert_eq!(field(&frames[0], "Line ending"), "CR LF");
+        assert!(frames[0].is_valid());
+        assert_eq!(field(&frames[1], "Text"), "\"\\\"x\\\"\"");
+        assert_eq!(field(&frames[1], "Line ending"), "LF");
+    }
+
+    #[test]
+    fn line_keeps_time_of_first_byte() {
+        let mut decoder = TextLineDecoder::default();
+        let start = Local::now();
+        assert!(decoder.feed(Direction::Rx, start, b"ab").is_empty());
+        // 送信側の行は受信側の行と混ざらない
+        assert!(decoder.feed(Direction::Tx, start, b"tx").is_empty());
+        let frames = decoder.feed(Direction::Rx, start + TimeDelta::seconds(1), b"c\n");
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].time, start);
+        assert_eq!(frames[0].direction, Direction::Rx);
+        assert_eq!(frames[0].summary, "\"abc\"");
+    }
+
+    #[test]
+    fn reports_invalid_utf8() {
+        let mut decoder = TextLineDecoder::default();
+        let frames = decoder.feed(Direction::Rx, Local::now(), b"a\xffb\n");
+        assert_eq!(frames[0].errors, ["Invalid UTF-8"]);
+        assert_eq!(field(&frames[0], "Text"), "\"a\u{fffd}b\"");
+    }
+
+    #[test]
+    fn splits_long_lines() {
+        let mut decoder = TextLineDecoder::default();
+        let bytes = vec![b'a'; MAX_LINE_LENGTH + 1];
+        let frames = decoder.feed(Direction::Rx, Local::now(), &bytes);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].bytes.len(), MAX_LINE_LENGTH);
+        assert_eq!(field(&frames[0], "Line ending"), "none");
+        assert_eq!(
+            frames[0].errors,
+            [format!("Line longer than {MAX_LINE_LENGTH} bytes")]
+        );
+    }
+}
@@ -0,0 +1,700 @@
+use super::table::{Location, Table};
+use std::collections::BTreeMap;
+
+/// ネストした Format をたどる深さの上限。壊れたフレームで止まらないようにする
+const MAX_DEPTH: usize = 16;
+
+/// ログのレベル
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn from_tag(tag: &str) -> Option<Option<Level>> {
+        Some(match tag {
+            "defmt_trace" => Some(Level::Trace),
+            "defmt_debug" => Some(Level::Debug),
+            "defmt_info" => Some(Level::Info),
+            "defmt_warn" => Some(Level::Warn),
+            "defmt_error" => Some(Level::Error),
+            // println! はレベルを持たない
+            "defmt_println" => None,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+}
+
+/// 復号したログの 1 行
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFrame {
+    pub level: Option<Level>,
+    pub timestamp: Option<String>,
+    pub message: String,
+    pub location: Option<Location>,
+}
+
+/// 復号したフレームをログの 1 行にする
+pub fn decode_frame(table: &Table, frame: &[u8]) -> Result<LogFrame, String> {
+    let mut reader = Reader::new(frame);
+    let index = reader.u16()?;
+    let entry = table
+        .entry(index)
+        .ok_or_else(|| format!("Unknown defmt index {index}"))?;
+    let level = Level::from_tag(&entry.tag)
+        .ok_or_else(|| format!("Index {index} is not a log statement ({})", entry.tag))?;
+    let timestamp = match table.timestamp() {
+        Some(format) => Some(render(table, format, &mut reader, 0)?),
+        None => None,
+    };
+    let message = render(table, &entry.format, &mut reader, 0)?;
+    // rzCOBS が末尾に補った 0x00 は読まずに残る
+    Ok(LogFrame {
+        level,
+        timestamp,
+        message,
+        location: table.location(index).cloned(),
+    })
+}
+
+/// フォーマット文字列の部品
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Literal(String),
+    Parameter(Parameter),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Parameter {
+    index: usize,
+    kind: Kind,
+    hint: Hint,
+}
+
+/// 引数の型
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Unsigned(usize), // バイト数
+    Signed(usize),
+    F32,
+    F64,
+    Bool,
+    Char,
+    Str,
+    IStr,
+    U8Slice,
+    U8Array(usize),
+    Format,
+    FormatSlice,
+    FormatArray(usize),
+    FormatSequence,
+    BitField(std::ops::Range<u32>),
+}
+
+/// 表示のヒント (`{=u8:#x}` の `#x`)
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Hint {
+    is_alternate: bool,
+    width: usize,
+    style: HintStyle,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+enum HintStyle {
+    #[default]
+    Default,
+    LowerHex,
+    UpperHex,
+    Binary,
+    Octal,
+    Ascii,
+    Debug,
+    Seconds,
+    Milliseconds,
+    Microseconds,
+}
+
+/// 読み出した引数の値
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Unsigned(u128),
+    Signed(i128, usize), // (値, バイト数)
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    Bytes(Vec<u8>),
+    /// ネストした Format を表示した結果
+    Formatted(String),
+    List(Vec<String>),
+}
+
+/// フォーマット文字列に従って引数を読み、表示する文字列を作る
+fn render(
+    table: &Table,
+    format: &str,
+    reader: &mut Reader,
+    depth: usize,
+) -> Result<String, String> {
+    if MAX_DEPTH < depth {
+        return Err("Format nesting is too deep".to_string());
+    }
+    let pieces = parse_format(format)?;
+
+    // 引数は番号の順に送られる。同じ番号を複数回使っても 1 回だけ
+    let mut kinds: BTreeMap<usize, Kind> = BTreeMap::new();
+    for piece in &pieces {
+        let Piece::Parameter(parameter) = piece else {
+            continue;
+        };
+        match (kinds.get_mut(&parameter.index), &parameter.kind) {
+            // ビットフィールドはすべての範囲を含むバイトをまとめて送る
+            (Some(Kind::BitField(range)), Kind::BitField(other)) => {
+                *range = range.start.min(other.start)..range.end.max(other.end);
+            }
+            (Some(_), _) => {}
+            (None, kind) => {
+                kinds.insert(parameter.index, kind.clone());
+            }
+        }
+    }
+    let mut values = BTreeMap::new();
+    for (index, kind) in &kinds {
+        values.insert(*index, read_value(table, kind, reader, depth)?);
+    }
+
+    let mut text = String::new();
+    for piece in &pieces {
+        match piece {
+            Piece::Literal(literal) => text.push_str(literal),
+            Piece::Parameter(parameter) => {
+                let value = &values[&parameter.index];
+                match (&parameter.kind, value) {
+                    (Kind::BitField(range), Value::Unsigned(raw)) => {
+                        let width = range.end - range.start;
+                        let mask = if width >= 128 {
+                            u128::MAX
+                        } else {
+                            (1u128 << width) - 1
+                        };
+                        let bits = (raw >> range.start) & mask;
+                        text.push_str(&format_value(&Value::Unsigned(bits), &parameter.hint));
+                    }
+                    _ => text.push_str(&format_value(value, &parameter.hint)),
+                }
+            }
+        }
+    }
+    Ok(text)
+}
+
+fn read_value(
+    table: &Table,
+    kind: &Kind,
+    reader: &mut Reader,
+    depth: usize,
+) -> Result<Value, String> {
+    Ok(match kind {
+        Kind::Unsigned(size) => Value::Unsigned(reader.unsigned(*size)?),
+        Kind::Signed(size) => {
+            let raw = reader.unsigned(*size)?;
+            // 符号を拡張する
+            let shift = 128 - size * 8;
+            Value::Signed(((raw << shift) as i128) >> shift, *size)
+        }
+        Kind::F32 => Value::F32(f32::from_bits(reader.unsigned(4)? as u32)),
+        Kind::F64 => Value::F64(f64::from_bits(reader.unsigned(8)? as u64)),
+        Kind::Bool => Value::Bool(reader.unsigned(1)? != 0),
+        Kind::Char => {
+            let code = reader.unsigned(4)? as u32;
+            Value::Char(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER))
+        }
+        Kind::Str => {
+            let length = reader.length()?;
+            Value::Str(String::from_utf8_lossy(reader.bytes(length)?).into_owned())
+        }
+        Kind::IStr => {
+            let index = reader.u16()?;
+            let entry = table
+                .entry(index)
+                .ok_or_else(|| format!("Unknown interned string {index}"))?;
+            Value::Str(entry.format.clone())
+        }
+        Kind::U8Slice => {
+            let length = reader.length()?;
+            Value::Bytes(reader.bytes(length)?.to_vec())
+        }
+        Kind::U8Array(length) => Value::Bytes(reader.bytes(*length)?.to_vec()),
+        Kind::Format => Value::Formatted(read_format(table, reader, depth)?),
+        Kind::FormatSlice => {
+            let length = reader.length()?;
+            Value::List(read_formats(table, reader, depth, length)?)
+        }
+        Kind::FormatArray(length) => Value::List(read_formats(table, reader, depth, *length)?),
+        Kind::FormatSequence => {
+            // 番号 0 で終わる
+            let mut text = String::new();
+            loop {
+                let index = reader.u16()?;
+                if index == 0 {
+                    break;
+                }
+                text.push_str(&render_index(table, index, reader, depth)?);
+            }
+            Value::Formatted(text)
+        }
+        Kind::BitField(range) => {
+            // 範囲を含むバイトだけが送られる
+            let first_byte = range.start / 8;
+            let last_byte = (range.end - 1) / 8;
+            let raw = reader.unsigned((last_byte - first_byte + 1) as usize)?;
+            Value::Unsigned(raw << (first_byte * 8))
+        }
+    })
+}
+
+fn read_formats(
+    table: &Table,
+    reader: &mut Reader,
+    depth: usize,
+    length: usize,
+) -> Result<Vec<String>, String> {
+    (0..length)
+        .map(|_| read_format(table, reader, depth))
+        .collect()
+}
+
+/// 番号に続けて送られた Format を読む
+fn read_format(table: &Table, reader: &mut Reader, depth: usize) -> Result<String, String> {
+    let index = reader.u16()?;
+    render_index(table, index, reader, depth)
+}
+
+fn render_index(
+    table: &Table,
+    index: u16,
+    reader: &mut Reader,
+    depth: usize,
+) -> Result<String, String> {
+    let entry = table
+        .entry(index)
+        .ok_or_else(|| format!("Unknown defmt index {index}"))?;
+    let variants = if entry.tag == "defmt_derived" {
+        split_variants(&entry.format)
+    } else {
+        vec![entry.format.as_str()]
+    };
+    if variants.len() <= 1 {
+        return render(table, &entry.format, reader, depth + 1);
+    }
+    // derive した enum は "A|B({=u8})" の形で、バリアントの番号が続く
+    let discriminant = if variants.len() <= 256 {
+        reader.unsigned(1)?
+    } else {
+        reader.unsigned(2)?
+    } as usize;
+    let variant = variants
+        .get(discriminant)
+        .ok_or_else(|| format!("Unknown variant {discriminant} of {}", entry.format))?;
+    render(table, variant, reader, depth + 1)
+}
+
+/// 波括弧の外にある '|' でバリアントに分ける
+fn split_variants(format: &str) -> Vec<&str> {
+    let mut variants = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (position, ch) in format.char_indices() {
+        match ch {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            '|' if depth == 0 => {
+                variants.push(&format[start..position]);
+                start = position + 1;
+            }
+            _ => {}
+        }
+    }
+    variants.push(&format[start..]);
+    variants
+}
+
+fn parse_format(format: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut literal = String::new();
+    let mut next_index = 0;
+    let mut chars = format.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' if chars.next_if_eq(&'{').is_some() => literal.push('{'),
+            '}' if chars.next_if_eq(&'}').is_some() => literal.push('}'),
+            '{' => {
+                let mut content = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(ch) => content.push(ch),
+                        None => return Err(format!("Unclosed parameter in \"{format}\"")),
+                    }
+                }
+                if !literal.is_empty() {
+                    pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                }
+                let parameter = parse_parameter(&content, &mut next_index)
+                    .ok_or_else(|| format!("Unsupported parameter {{{content}}}"))?;
+                pieces.push(Piece::Parameter(parameter));
+            }
+            ch => literal.push(ch),
+        }
+    }
+    if !literal.is_empty() {
+        pieces.push(Piece::Literal(literal));
+    }
+    Ok(pieces)
+}
+
+/// `0=u8:x` のような波括弧の中身を読む
+fn parse_parameter(content: &str, next_index: &mut usize) -> Option<Parameter> {
+    let (specifier, hint) = match content.split_once(':') {
+        Some((specifier, hint)) => (specifier, parse_hint(hint)?),
+        None => (content, Hint::default()),
+    };
+    let (index, kind) = match specifier.split_once('=') {
+        Some((index, kind)) => (index, parse_kind(kind.trim())?),
+        // 型を省いたものは Format として扱う
+        None => (specifier, Kind::Format),
+    };
+    let index = if index.is_empty() {
+        let index = *next_index;
+        *next_index += 1;
+        index
+    } else {
+        index.parse().ok()?
+    };
+    Some(Parameter { index, kind, hint })
+}
+
+fn parse_kind(kind: &str) -> Option<Kind> {
+    Some(match kind {
+        "u8" => Kind::Unsigned(1),
+        "u16" => Kind::Unsigned(2),
+        "u32" | "usize" => Kind::Unsigned(4),
+        "u64" => Kind::Unsigned(8),
+        "u128" => Kind::Unsigned(16),
+        "i8" => Kind::Signed(1),
+        "i16" => Kind::Signed(2),
+        "i32" | "isize" => Kind::Signed(4),
+        "i64" => Kind::Signed(8),
+        "i128" => Kind::Signed(16),
+        "f32" => Kind::F32,
+        "f64" => Kind::F64,
+        "bool" => Kind::Bool,
+        "char" => Kind::Char,
+        "str" | "__internal_Display" => Kind::Str,
+        "istr" => Kind::IStr,
+        "[u8]" => Kind::U8Slice,
+        "?" => Kind::Format,
+        "[?]" => Kind::FormatSlice,
+        "__internal_FormatSequence" => Kind::FormatSequence,
+        _ => {
+            if let Some(length) = array_length(kind, "u8") {
+                Kind::U8Array(length)
+            } else if let Some(length) = array_length(kind, "?") {
+                Kind::FormatArray(length)
+            } else {
+                let (start, end) = kind.split_once("..")?;
+                let range = start.parse().ok()?..end.parse().ok()?;
+                if range.is_empty() || 128 < range.end {
+                    return None;
+                }
+                Kind::BitField(range)
+            }
+        }
+    })
+}
+
+/// `[u8; 4]` の長さ
+fn array_length(kind: &str, element: &str) -> Option<usize> {
+    let inner = kind.strip_prefix('[')?.strip_suffix(']')?;
+    let (name, length) = inner.split_once(';')?;
+    (name.trim() == element)
+        .then(|| length.trim().parse().ok())
+        .flatten()
+}
+
+fn parse_hint(hint: &str) -> Option<Hint> {
+    let mut rest = hint;
+    let is_alternate = rest.starts_with('#');
+    rest = rest.trim_start_matches('#');
+    let digits: String = rest.chars().take_while(|ch| ch.is_ascii_digit()).collect();
+    rest = &rest[digits.len()..];
+    let width = if digits.is_empty() {
+        0
+    } else {
+        digits.parse().ok()?
+    };
+    let style = match rest {
+        "" => HintStyle::Default,
+        "x" => HintStyle::LowerHex,
+        "X" => HintStyle::UpperHex,
+        "b" => HintStyle::Binary,
+        "o" => HintStyle::Octal,
+        "a" => HintStyle::Ascii,
+        "?" => HintStyle::Debug,
+        "s" | "ts" => HintStyle::Seconds,
+        "ms" | "tms" => HintStyle::Milliseconds,
+        "us" | "tus" => HintStyle::Microseconds,
+        // 知らないヒントは無視して既定の表示にする
+        _ => HintStyle::Default,
+    };
+    Some(Hint {
+        is_alternate,
+        width,
+        style,
+    })
+}
+
+fn format_value(value: &Value, hint: &Hint) -> String {
+    match value {
+        Value::Unsigned(value) => format_integer(*value, hint)
+            .unwrap_or_else(|| format!("{value:0width$}", width = hint.width)),
+        Value::Signed(value, size) => {
+            // 16 進などは 2 の補数で表示する
+            let mask = if *size >= 16 {
+                u128::MAX
+            } else {
+                (1u128 << (size * 8)) - 1
+            };
+            format_integer(*value as u128 & mask, hint)
+                .unwrap_or_else(|| format!("{value:0width$}", width = hint.width))
+        }
+        Value::F32(value) => format!("{value}"),
+        Value::F64(value) => format!("{value}"),
+        Value::Bool(value) => format!("{value}"),
+        Value::Char(value) if hint.style == HintStyle::Debug => format!("{value:?}"),
+        Value::Char(value) => value.to_string(),
+        Value::Str(value) if hint.style == HintStyle::Debug => format!("{value:?}"),
+        Value::Str(value) => value.clone(),
+        Value::Bytes(bytes) if hint.style == HintStyle::Ascii => {
+            let text: String = bytes
+                .iter()
+                .flat_map(|&byte| std::ascii::escape_default(byte))
+                .map(char::from)
+                .collect();
+            format!("b\"{text}\"")
+        }
+        Value::Bytes(bytes) => {
+            let items: Vec<String> = bytes
+                .iter()
+                .map(|&byte| format_value(&Value::Unsigned(u128::from(byte)), hint))
+                .collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Formatted(text) => text.clone(),
+        Value::List(items) => format!("[{}]", items.join(", ")),
+    }
+}
+
+/// ヒントに従って整数を表示する。既定の表示なら None
+fn format_integer(value: u128, hint: &Hint) -> Option<String> {
+    let width = hint.width;
+    Some(match (&hint.style, hint.is_alternate) {
+        (HintStyle::LowerHex, false) => format!("{value:0width$x}"),
+        (HintStyle::LowerHex, true) => format!("{value:#0width$x}"),
+        (HintStyle::UpperHex, false) => format!("{value:0width$X}"),
+        (HintStyle::UpperHex, true) => format!("{value:#0width$X}"),
+        (HintStyle::Binary, false) => format!("{value:0width$b}"),
+        (HintStyle::Binary, true) => format!("{value:#0width$b}"),
+        (HintStyle::Octal, false) => format!("{value:0width$o}"),
+        (HintStyle::Octal, true) => format!("{value:#0width$o}"),
+        (HintStyle::Seconds, _) => format!("{value}"),
+        (HintStyle::Milliseconds, _) => format!("{}.{:03}", value / 1_000, value % 1_000),
+        (HintStyle::Microseconds, _) => {
+            format!("{}.{:06}", value / 1_000_000, value % 1_000_000)
+        }
+        _ => return None,
+    })
+}
+
+/// フレームのバイト列を先頭から読む
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < length {
+            return Err("The frame ended before all arguments were read".to_string());
+        }
+        let (head, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    /// リトルエンディアンの符号なし整数
+    fn unsigned(&mut self, size: usize) -> Result<u128, String> {
+        Ok(self
+            .bytes(size)?
+            .iter()
+            .rev()
+            .fold(0u128, |value, &byte| (value << 8) | u128::from(byte)))
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(self.unsigned(2)? as u16)
+    }
+
+    /// スライスや文字列の長さ (usize)
+    fn length(&mut self) -> Result<usize, String> {
+        Ok(self.unsigned(4)? as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Table {
+        Table::from_entries(&[
+            (1, "defmt_info", "x={=u8} y={=i16:#x} z={=i16}"),
+            (2, "defmt_warn", "{=str}! {=[u8]:a} {=[u8; 2]:02x}"),
+            (3, "defmt_str", "interned"),
+            (4, "defmt_error", "{=istr} {=bool} {=char:?} {{literal}}"),
+            (5, "defmt_debug", "{0=0..4} {0=4..8:b} {=8..12:x}"),
+            (6, "defmt_derived", "Idle|Busy({=u8})"),
+            (7, "defmt_trace", "state={} states={=[?]}"),
+            (8, "defmt_println", "{=f32} {=u64:us}"),
+        ])
+    }
+
+    fn message(table: &Table, frame: &[u8]) -> String {
+        decode_frame(table, frame).unwrap().message
+    }
+
+    #[test]
+    fn integers() {
+        let frame = decode_frame(&table(), &[1, 0, 5, 0xfe, 0xff, 0xfe, 0xff]).unwrap();
+        assert_eq!(frame.level, Some(Level::Info));
+        assert_eq!(frame.timestamp, None);
+        assert_eq!(frame.message, "x=5 y=0xfffe z=-2");
+        // rzCOBS が補った 0x00 は無視する
+        assert_eq!(
+            message(&table(), &[1, 0, 5, 0xfe, 0xff, 0xfe, 0xff, 0, 0]),
+            "x=5 y=0xfffe z=-2"
+        );
+    }
+
+    #[test]
+    fn strings_and_bytes() {
+        assert_eq!(
+            message(
+                &table(),
+                &[
+                    2, 0, 3, 0, 0, 0, b'a', b'b', b'c', 2, 0, 0, 0, b'h', 0x0a, 0x0f, 0xa0
+                ]
+            ),
+            "abc! b\"h\\n\" [0f, a0]"
+        );
+        assert_eq!(
+            message(&table(), &[4, 0, 3, 0, 1, b'\n', 0, 0, 0]),
+            "interned true '\\n' {literal}"
+        );
+    }
+
+    #[test]
+    fn bit_fields() {
+        // 0..8 と 8..12 は同じ引数ではないため、別々に送られる
+        assert_eq!(message(&table(), &[5, 0, 0xa5, 0x0c]), "5 1010 c");
+    }
+
+    #[test]
+    fn derived_enum() {
+        assert_eq!(
+            message(
+                &table(),
+                &[7, 0, 6, 0, 1, 42, 2, 0, 0, 0, 6, 0, 0, 6, 0, 1, 7]
+            ),
+            "state=Busy(42) states=[Idle, Busy(7)]"
+        );
+        let error = decode_frame(&table(), &[7, 0, 6, 0, 2]).unwrap_err();
+        assert!(error.contains("Unknown variant 2"), "{error}");
+    }
+
+    #[test]
+    fn timestamp() {
+        let table = Table::from_entries(&[
+            (1, "defmt_timestamp", "{=u32:ms}"),
+            (8, "defmt_println", "{=f32} {=u64:us}"),
+        ]);
+        let mut bytes = vec![8, 0, 0x39, 0x30, 0, 0];
+        bytes.extend_from_slice(&1.5f32.to_le_bytes());
+        bytes.extend_from_slice(&2_000_001u64.to_le_bytes());
+        let frame = decode_frame(&table, &bytes).unwrap();
+        assert_eq!(frame.level, None);
+        assert_eq!(frame.timestamp.as_deref(), Some("12.345"));
+        assert_eq!(frame.message, "1.5 2.000001");
+    }
+
+    #[test]
+    fn malformed() {
+        let table = table();
+        for (frame, expected) in [
+            (&[][..], "ended"),
+            (&[9, 9], "Unknown defmt index 2313"),
+            (&[3, 0], "not a log statement"),
+            (&[1, 0, 5, 0xfe], "ended"),
+            (&[2, 0, 0xff, 0xff, 0xff, 0xff], "ended"),
+            (&[4, 0, 9, 0], "Unknown interned string 9"),
+            (&[7, 0, 9, 0], "Unknown defmt index 9"),
+        ] {
+            let error = decode_frame(&table, frame).unwrap_err();
+            assert!(error.contains(expected), "{frame:?}: {error}");
+        }
+    }
+
+    #[test]
+    fn nesting_limit() {
+        // 自分自身を入れ子にする Format は深さの上限で止める
+        let table = Table::from_entries(&[(1, "defmt_info", "{}"), (2, "defmt_derived", "({})")]);
+        let frame: Vec<u8> = [1, 0]
+            .into_iter()
+            .chain([2, 0].repeat(MAX_DEPTH + 2))
+            .collect();
+        let error = decode_frame(&table, &frame).unwrap_err();
+        assert!(error.contains("too deep"), "{error}");
+    }
+
+    #[test]
+    fn format_errors() {
+        let table = Table::from_entries(&[(1, "defmt_info", "{=u8"), (2, "defmt_info", "{=u7}")]);
+        assert!(
+            decode_frame(&table, &[1, 0, 0])
+                .unwrap_err()
+                .contains("Unclosed")
+        );
+        assert!(
+            decode_frame(&table, &[2, 0, 0])
+                .unwrap_err()
+                .contains("Unsupported")
+        );
+    }
+}
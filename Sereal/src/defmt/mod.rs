@@ -0,0 +1,7 @@
+pub mod format;
+pub mod rzcobs;
+pub mod table;
+
+pub use format::{Level, LogFrame};
+pub use rzcobs::FrameSplitter;
+pub use table::Table;
@@ -0,0 +1,189 @@
+/// 受信待ちのフレームの上限。区切りの 0x00 が届かないまま溜め続けないようにする
+const MAX_FRAME_LENGTH: usize = 64 * 1024;
+
+/// rzCOBS で符号化したフレームを 0x00 で区切る
+#[derive(Default)]
+pub struct FrameSplitter {
+    pending: Vec<u8>,
+}
+
+impl FrameSplitter {
+    /// 区切り終えたフレームを復号して返す
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Result<Vec<u8>, String>> {
+        let mut frames = Vec::new();
+        for &byte in bytes {
+            if byte != 0x00 {
+                if self.pending.len() < MAX_FRAME_LENGTH {
+                    self.pending.push(byte);
+                }
+                continue;
+            }
+            if !self.pending.is_empty() {
+                frames.push(decode(&self.pending));
+                self.pending.clear();
+            }
+        }
+        frames
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
+
+/// rzCOBS (reverse zero-compressing COBS) を復号する
+///
+/// 末尾から読み、先頭に向かって元のバイト列を組み立てる。
+/// 符号化のときに末尾へ 0x00 を補うことがあるが、defmt のフレームは長さが決まっているため残しておく。
+pub fn decode(encoded: &[u8]) -> Result<Vec<u8>, String> {
+    let malformed = || "Malformed rzCOBS frame".to_string();
+    let mut decoded = Vec::new();
+    let mut bytes = encoded.iter().rev().copied();
+    while let Some(header) = bytes.next() {
+        match header {
+            0x00 => return Err(malformed()),
+            // 7 バイトのうち 0x00 の位置をビットで示す
+            0x01..=0x7f => {
+                for bit in (0..7).rev() {
+                    if header & (1 << bit) == 0 {
+                        decoded.push(bytes.next().ok_or_else(malformed)?);
+                    } else {
+                        decoded.push(0x00);
+                    }
+                }
+            }
+            // 0x00 でないバイトが 7 以上続いた後に 0x00 が 1 つ
+            0x80..=0xfe => {
+                decoded.push(0x00);
+                for _ in 0..(header & 0x7f) + 7 {
+                    decoded.push(bytes.next().ok_or_else(malformed)?);
+                }
+            }
+            // 0x00 でないバイトが 134 続く
+            0xff => {
+                for _ in 0..134 {
+                    decoded.push(bytes.next().ok_or_else(malformed)?);
+                }
+            }
+        }
+    }
+    decoded.reverse();
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// decode と対になる符号化。末尾の足りない分は 0x00 で補う
+    fn encode(data: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        let mut position = 0;
+        while position < data.len() {
+            let run = data[position..]
+                .iter()
+                .take_while(|&&byte| byte != 0x00)
+                .count();
+            if 134 <= run {
+                encoded.extend_from_slice(&data[position..position + 134]);
+                encoded.push(0xff);
+                position += 134;
+            } else if 7 <= run {
+                // 続く 0x00 (末尾なら補った 0x00) までをまとめる
+                encoded.extend_from_slice(&data[position..position + run]);
+                encoded.push(0x80 | (run - 7) as u8);
+                position += run + 1;
+            } else {
+                let mut header = 0u8;
+                for bit in 0..7 {
+                    match data.get(position + bit) {
+                        Some(&byte) if byte != 0x00 => encoded.push(byte),
+                        _ => header |= 1 << bit,
+                    }
+                }
+                encoded.push(header);
+                position += 7;
+            }
+        }
+        encoded
+    }
+
+    fn assert_round_trip(data: &[u8]) {
+        let encoded = encode(data);
+        assert!(!encoded.contains(&0x00));
+        let decoded = decode(&encoded).unwrap();
+        assert_eq!(&decoded[..data.len()], data);
+        assert!(decoded[data.len()..].iter().all(|&byte| byte == 0x00));
+    }
+
+    #[test]
+    fn headers() {
+        assert_eq!(decode(&[0x7f]), Ok(vec![0x00; 7]));
+        assert_eq!(
+            decode(&[1, 2, 3, 4, 5, 6, 0x01]),
+            Ok(vec![0, 1, 2, 3, 4, 5, 6])
+        );
+        assert_eq!(
+            decode(&[1, 2, 3, 4, 5, 6, 7, 8, 0x81]),
+            Ok(vec![1, 2, 3, 4, 5, 6, 7, 8, 0])
+        );
+        let mut long_run = vec![0xaa; 134];
+        long_run.push(0xff);
+        assert_eq!(decode(&long_run), Ok(vec![0xaa; 134]));
+        assert_eq!(decode(&[]), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn round_trip() {
+        assert_round_trip(&[0x01]);
+        assert_round_trip(&[0x00; 20]);
+        assert_round_trip(&[0x55; 7]);
+        assert_round_trip(&[0x55; 134]);
+        assert_round_trip(&[0x55; 300]);
+        // 0x00 の間隔をいろいろに変える
+        let data: Vec<u8> = (0..2000u32)
+            .map(|i| {
+                if i % 13 == 0 || i % 151 == 0 {
+                    0x00
+                } else {
+                    (i % 251) as u8 + 1
+                }
+            })
+            .collect();
+        for length in [0, 1, 6, 7, 8, 14, 135, 500, data.len()] {
+            assert_round_trip(&data[..length]);
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(decode(&[0x00]).is_err());
+        assert!(decode(&[0x01]).is_err());
+        assert!(decode(&[1, 2, 3, 4, 5, 6, 0x80]).is_err());
+        let mut short_run = vec![0xaa; 133];
+        short_run.push(0xff);
+        assert!(decode(&short_run).is_err());
+    }
+
+    #[test]
+    fn splitter() {
+        let mut splitter = FrameSplitter::default();
+        assert_eq!(splitter.push(&[0x00, 0x7f]), Vec::new());
+        assert_eq!(
+            splitter.push(&[0x00, 0x00, 1, 2, 3, 4, 5, 6, 0x01, 0x00, 0x01, 0x00]),
+            vec![
+                Ok(vec![0x00; 7]),
+                Ok(vec![0, 1, 2, 3, 4, 5, 6]),
+                Err("Malformed rzCOBS frame".to_string()),
+            ]
+        );
+        splitter.push(&[0x7f]);
+        splitter.clear();
+        assert_eq!(splitter.push(&[0x00]), Vec::new());
+
+        // 区切りが届かないフレームは上限で切り詰める
+        splitter.push(&vec![0x7f; MAX_FRAME_LENGTH + 10]);
+        let frames = splitter.push(&[0x00]);
+        assert_eq!(frames, vec![Ok(vec![0x00; MAX_FRAME_LENGTH * 7])]);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::hex;
+use object::{Object, ObjectSection, ObjectSymbol};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// 対応している defmt のワイヤーフォーマットの版
+const SUPPORTED_VERSIONS: [&str; 1] = ["4"];
+
+/// 文字列テーブルの 1 項目
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// defmt_info や defmt_derived のような種類
+    pub tag: String,
+    /// フォーマット文字列
+    pub format: String,
+}
+
+/// ログを出したソースの位置
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub file: String,
+    pub line: u64,
+}
+
+/// ファームウェアの ELF から読み出した defmt のテーブル
+///
+/// defmt はフォーマット文字列を ELF の .defmt セクションのシンボル名として残し、
+/// デバイスからはその番号 (シンボルのアドレス) と引数だけを送る。
+pub struct Table {
+    entries: HashMap<u16, Entry>,
+    /// フレームの先頭に付くタイムスタンプのフォーマット文字列
+    timestamp: Option<String>,
+    locations: HashMap<u16, Location>,
+    /// rzcobs や raw のような符号化
+    encoding: String,
+}
+
+impl Table {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content =
+            std::fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        let elf = object::File::parse(content.as_slice())
+            .map_err(|e| format!("Failed to parse {}: {e}", path.display()))?;
+        let section = elf
+            .section_by_name(".defmt")
+            .ok_or("The ELF has no .defmt section. Is the firmware built with defmt?")?;
+
+        let mut entries = HashMap::new();
+        let mut version = None;
+        let mut encoding = None;
+        for symbol in elf.symbols() {
+            let Ok(name) = symbol.name() else {
+                continue;
+            };
+            if let Some(value) = name.strip_prefix("_defmt_version_ = ") {
+                version = Some(value.to_string());
+                continue;
+            }
+            if let Some(value) = name.strip_prefix("_defmt_encoding_ = ") {
+                encoding = Some(value.to_string());
+                continue;
+            }
+            if symbol.section_index() != Some(section.index()) {
+                continue;
+            }
+            let Some(fields) = parse_json_object(name) else {
+                continue;
+            };
+            let (Some(tag), Some(format)) = (fields.get("tag"), fields.get("data")) else {
+                continue;
+            };
+            let Ok(index) = u16::try_from(symbol.address()) else {
+                continue;
+            };
+            entries.insert(
+                index,
+                Entry {
+                    tag: tag.clone(),
+                    format: format.clone(),
+                },
+            );
+        }
+
+        match &version {
+            Some(version) if SUPPORTED_VERSIONS.contains(&version.as_str()) => {}
+            Some(version) => {
+                return Err(format!(
+                    "defmt wire format version {version} is not supported (expected {})",
+                    SUPPORTED_VERSIONS.join(", ")
+                ));
+            }
+            None => return Err("The ELF has no defmt version symbol".to_string()),
+        }
+        let timestamp = entries
+            .values()
+            .find(|entry| entry.tag == "defmt_timestamp")
+            .map(|entry| entry.format.clone());
+        // 位置がなくても復号はできるため、DWARF を読めなければ省く
+        let locations = read_locations(&elf, &entries).unwrap_or_default();
+
+        Ok(Self {
+            entries,
+            timestamp,
+            locations,
+            encoding: encoding.unwrap_or_else(|| "raw".to_string()),
+        })
+    }
+
+    pub fn entry(&self, index: u16) -> Option<&Entry> {
+        self.entries.get(&index)
+    }
+
+    pub fn timestamp(&self) -> Option<&str> {
+        self.timestamp.as_deref()
+    }
+
+    pub fn location(&self, index: u16) -> Option<&Location> {
+        self.locations.get(&index)
+    }
+
+    pub fn encoding(&self) -> &str {
+        &self.encoding
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn location_count(&self) -> usize {
+        self.locations.len()
+    }
+}
+
+#[cfg(test)]
+impl Table {
+    /// ELF を読まずに、(番号, 種類, フォーマット文字列) からテーブルを作る
+    pub fn from_entries(entries: &[(u16, &str, &str)]) -> Self {
+        let entries: HashMap<u16, Entry> = entries
+            .iter()
+            .map(|&(index, tag, format)| {
+                let entry = Entry {
+                    tag: tag.to_string(),
+                    format: format.to_string(),
+                };
+                (index, entry)
+            })
+            .collect();
+        let timestamp = entries
+            .values()
+            .find(|entry| entry.tag == "defmt_timestamp")
+            .map(|entry| entry.format.clone());
+        Self {
+            entries,
+            timestamp,
+            locations: HashMap::new(),
+            encoding: "rzcobs".to_string(),
+        }
+    }
+}
+
+/// DWARF から、.defmt に置かれた変数を宣言したソースの位置を読む
+fn read_locations(
+    elf: &object::File,
+    entries: &HashMap<u16, Entry>,
+) -> Result<HashMap<u16, Location>, gimli::Error> {
+    let endian = if elf.is_little_endian() {
+        gimli::RunTimeEndian::Little
+    } else {
+        gimli::RunTimeEndian::Big
+    };
+    let load_section = |id: gimli::SectionId| -> Result<Cow<[u8]>, gimli::Error> {
+        Ok(elf
+            .section_by_name(id.name())
+            .and_then(|section| section.uncompressed_data().ok())
+            .unwrap_or(Cow::Borrowed(&[])))
+    };
+    let sections = gimli::DwarfSections::load(load_section)?;
+    let dwarf = sections.borrow(|section| gimli::EndianSlice::new(section, endian));
+
+    let mut locations = HashMap::new();
+    let mut headers = dwarf.units();
+    while let Some(header) = headers.next()? {
+        let unit = dwarf.unit(header)?;
+        let mut cursor = unit.entries();
+        while let Some((_, entry)) = cursor.next_dfs()? {
+            if entry.tag() != gimli::DW_TAG_variable {
+                continue;
+            }
+            let Some(gimli::AttributeValue::Exprloc(expression)) =
+                entry.attr_value(gimli::DW_AT_location)?
+            else {
+                continue;
+            };
+            let mut operations = expression.operations(unit.encoding());
+            let Ok(Some(gimli::Operation::Address { address })) = operations.next() else {
+                continue;
+            };
+            let Ok(index) = u16::try_from(address) else {
+                continue;
+            };
+            if !entries.contains_key(&index) {
+                continue;
+            }
+            let (Some(gimli::AttributeValue::FileIndex(file)), Some(line)) = (
+                entry.attr_value(gimli::DW_AT_decl_file)?,
+                entry
+                    .attr_value(gimli::DW_AT_decl_line)?
+                    .and_then(|value| value.udata_value()),
+            ) else {
+                continue;
+            };
+            if let Some(file) = file_path(&dwarf, &unit, file) {
+                locations.insert(index, Location { file, line });
+            }
+        }
+    }
+    Ok(locations)
+}
+
+/// 行番号プログラムのファイル番号をパスにする
+fn file_path<R: gimli::Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    unit: &gimli::Unit<R>,
+    index: u64,
+) -> Option<String> {
+    let header = unit.line_program.as_ref()?.header();
+    let file = header.file(index)?;
+    let name = dwarf.attr_string(unit, file.path_name()).ok()?;
+    let name = name.to_string_lossy().ok()?.into_owned();
+    if name.starts_with('/') {
+        return Some(name);
+    }
+    let directory = file
+        .directory(header)
+        .and_then(|directory| dwarf.attr_string(unit, directory).ok())
+        .and_then(|directory| Some(directory.to_string_lossy().ok()?.into_owned()));
+    Some(match directory {
+        Some(directory) if !directory.is_empty() => format!("{directory}/{name}"),
+        _ => name,
+    })
+}
+
+/// シンボル名に書かれた、値がすべて文字列の JSON オブジェクトを読む
+fn parse_json_object(text: &str) -> Option<HashMap<String, String>> {
+    let mut chars = text.trim().chars().peekable();
+    let mut fields = HashMap::new();
+    let skip_whitespace = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        while chars.next_if(|ch| ch.is_whitespace()).is_some() {}
+    };
+    if chars.next()? != '{' {
+        return None;
+    }
+    loop {
+        skip_whitespace(&mut chars);
+        if chars.next_if_eq(&'}').is_some() {
+            return Some(fields);
+        }
+        let key = parse_json_string(&mut chars)?;
+        skip_whitespace(&mut chars);
+        chars.next_if_eq(&':')?;
+        skip_whitespace(&mut chars);
+        let value = parse_json_string(&mut chars)?;
+        fields.insert(key, value);
+        skip_whitespace(&mut chars);
+        match chars.next()? {
+            ',' => {}
+            '}' => return Some(fields),
+            _ => return None,
+        }
+    }
+}
+
+fn parse_json_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+    if chars.next()? != '"' {
+        return None;
+    }
+    let mut value = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => value.push('\r'),
+                'b' => value.push('\u{8}'),
+                'f' => value.push('\u{c}'),
+                'u' => {
+                    let code: String = (0..4).filter_map(|_| chars.next()).collect();
+                    if code.len() != 4 {
+                        return None;
+                    }
+                    value.push(char::from_u32(hex::parse_u32(&code)?)?);
+                }
+                other => value.push(other),
+            },
+            ch => value.push(ch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_object() {
+        let fields = parse_json_object(
+            r#"{"package":"app","tag":"defmt_info","data":"x={=u8}\t\"\u00e9\"","disambiguator":"1"}"#,
+        )
+        .unwrap();
+        assert_eq!(fields["tag"], "defmt_info");
+        assert_eq!(fields["data"], "x={=u8}\t\"é\"");
+        assert_eq!(fields.len(), 4);
+        assert_eq!(parse_json_object(" { } "), Some(HashMap::new()));
+    }
+
+    #[test]
+    fn malformed_json_object() {
+        for text in [
+            "",
+            "[]",
+            r#"{"tag""defmt_info"}"#,
+            r#"{"tag":"defmt_info""#,
+            r#"{"tag":"defmt_info";}"#,
+            r#"{"tag":1}"#,
+            r#"{"data":"\u00"}"#,
+            r#"{"data":"\u+0e9"}"#,
+            r#"{"data":"\ud800"}"#,
+        ] {
+            assert_eq!(parse_json_object(text), None, "{text}");
+        }
+    }
+}
@@ -0,0 +1,5 @@
+pub mod scanner;
+pub mod symbolizer;
+
+pub use scanner::{LineScanner, PanicLine};
+pub use symbolizer::Symbolizer;
@@ -0,0 +1,189 @@
+use crate::decoder::text_line::LineBuffer;
+use crate::hex;
+use chrono::{DateTime, Local};
+
+/// ESP-IDF のパニック出力や起動ログから読み取った 1 行分の内容
+#[derive(Debug, Clone, PartialEq)]
+pub enum PanicLine {
+    /// パニックの始まり。abort() の呼び出し元のようにアドレスを含むこともある
+    Panic {
+        message: String,
+        address: Option<u32>,
+    },
+    /// Backtrace: に続く PC の一覧
+    Backtrace(Vec<u32>),
+    /// レジスタダンプのうちコードを指すレジスタ
+    Registers(Vec<(String, u32)>),
+    /// 実行中のアプリの ELF の SHA256。ESP-IDF は先頭の一部だけを表示する
+    ElfSha256(String),
+}
+
+/// コードのアドレスを持つレジスタ。Xtensa は PC、RISC-V は MEPC と RA
+const CODE_REGISTERS: [&str; 3] = ["PC", "MEPC", "RA"];
+
+/// 受信したバイト列を行に区切り、パニックに関わる行を読み取る
+#[derive(Default)]
+pub struct LineScanner {
+    line_buffer: LineBuffer,
+}
+
+impl LineScanner {
+    pub fn push(&mut self, time: DateTime<Local>, bytes: &[u8]) -> Vec<PanicLine> {
+        self.line_buffer
+            .push(time, bytes)
+            .iter()
+            .filter_map(|line| parse_line(&String::from_utf8_lossy(line.content())))
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.line_buffer = LineBuffer::default();
+    }
+}
+
+/// 1 行を読み取る。パニックに関わらない行は None
+pub fn parse_line(line: &str) -> Option<PanicLine> {
+    // 起動ログは ESC[0;32m のように色付けされている
+    let line = strip_escape_sequences(line);
+    let line = line.trim();
+
+    if let Some((_, rest)) = line.split_once("Backtrace:") {
+        let addresses: Vec<u32> = rest
+            .split_whitespace()
+            .filter_map(|token| parse_hex(token.split(':').next()?))
+            .collect();
+        return (!addresses.is_empty()).then_some(PanicLine::Backtrace(addresses));
+    }
+    if let Some((_, rest)) = line.split_once("ELF file SHA256:") {
+        // 起動ログは "ELF file SHA256:  0123456789abcdef..." のように末尾を省く
+        let sha: String = rest
+            .trim_start()
+            .chars()
+            .take_while(|ch| ch.is_ascii_hexdigit())
+            .collect();
+        return (!sha.is_empty()).then(|| PanicLine::ElfSha256(sha.to_ascii_lowercase()));
+    }
+    if line.starts_with("Guru Meditation Error:")
+        || line.starts_with("assert failed:")
+        || line.starts_with("***ERROR***")
+    {
+        return Some(PanicLine::Panic {
+            message: line.to_string(),
+            address: None,
+        });
+    }
+    if let Some((_, rest)) = line.split_once("abort() was called at PC") {
+        return Some(PanicLine::Panic {
+            message: line.to_string(),
+            address: rest.split_whitespace().next().and_then(parse_hex),
+        });
+    }
+    parse_registers(line)
+}
+
+/// "PC      : 0x400d1234  PS      : 0x00060030" のようなレジスタダンプの行
+fn parse_registers(line: &str) -> Option<PanicLine> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let mut pair_count = 0;
+    let mut registers = Vec::new();
+    for window in tokens.windows(3) {
+        let [name, ":", value] = window else {
+            continue;
+        };
+        let Some(value) = parse_hex(value) else {
+            continue;
+        };
+        pair_count += 1;
+        if CODE_REGISTERS.contains(name) {
+            registers.push((name.to_string(), value));
+        }
+    }
+    // 1 組だけの行はレジスタダンプとみなさない
+    (2 <= pair_count && !registers.is_empty()).then_some(PanicLine::Registers(registers))
+}
+
+fn parse_hex(token: &str) -> Option<u32> {
+    hex::parse_u32(token.strip_prefix("0x")?)
+}
+
+/// CSI シーケンスを取り除く
+fn strip_escape_sequences(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\x1b' {
+            result.push(ch);
+            continue;
+        }
+        if chars.next() != Some('[') {
+            continue;
+        }
+        // 終端のバイトまで読み飛ばす
+        for ch in chars.by_ref() {
+            if ('@'..='~').contains(&ch) {
+                break;
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_boot_log_sha256() {
+        let line = "\x1b[0;32mI (36) app_init: ELF file SHA256:  8cd1c39e5c0f4ad3...\x1b[0m";
+        assert_eq!(
+            parse_line(line),
+            Some(PanicLine::ElfSha256("8cd1c39e5c0f4ad3".to_string()))
+        );
+    }
+
+    #[test]
+    fn reads_panic_handler_sha256() {
+        assert_eq!(
+            parse_line("ELF file SHA256: 8CD1C39E5C0F4AD3"),
+            Some(PanicLine::ElfSha256("8cd1c39e5c0f4ad3".to_string()))
+        );
+        assert_eq!(parse_line("ELF file SHA256: ..."), None);
+    }
+
+    #[test]
+    fn reads_backtrace() {
+        assert_eq!(
+            parse_line("Backtrace: 0x400d1234:0x3ffb1f00 0x400d5678:0x3ffb1f20 |<-CORRUPTED"),
+            Some(PanicLine::Backtrace(vec![0x400d1234, 0x400d5678]))
+        );
+        assert_eq!(parse_line("Backtrace: none"), None);
+    }
+
+    #[test]
+    fn reads_code_registers() {
+        assert_eq!(
+            parse_line("MEPC    : 0x42001234  RA      : 0x42005678  SP      : 0x3fc8f000"),
+            Some(PanicLine::Registers(vec![
+                ("MEPC".to_string(), 0x42001234),
+                ("RA".to_string(), 0x42005678),
+            ]))
+        );
+        // 1 組だけの行は読まない
+        assert_eq!(parse_line("PC : 0x400d1234"), None);
+    }
+
+    #[test]
+    fn reads_panic_messages() {
+        assert_eq!(
+            parse_line("abort() was called at PC 0x400d1234 on core 0"),
+            Some(PanicLine::Panic {
+                message: "abort() was called at PC 0x400d1234 on core 0".to_string(),
+                address: Some(0x400d1234),
+            })
+        );
+        assert!(matches!(
+            parse_line("Guru Meditation Error: Core  0 panic'ed (LoadProhibited)."),
+            Some(PanicLine::Panic { address: None, .. })
+        ));
+    }
+}
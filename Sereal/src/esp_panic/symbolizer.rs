@@ -0,0 +1,113 @@
+use object::{Object, ObjectSection, SectionFlags};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// アドレスに対応するソースコードの位置
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceFrame {
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+impl std::fmt::Display for SourceFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let function = self.function.as_deref().unwrap_or("??");
+        let file = self.file.as_deref().unwrap_or("??");
+        match self.line {
+            Some(line) => write!(f, "{function} at {file}:{line}"),
+            None => write!(f, "{function} at {file}"),
+        }
+    }
+}
+
+/// ローカルの ELF の DWARF でアドレスを関数名とソースの位置に変換する
+pub struct Symbolizer {
+    loader: addr2line::Loader,
+    path: PathBuf,
+    /// ELF ファイル全体の SHA256 (16 進、小文字)。ESP-IDF が起動時に表示する値と同じ
+    sha256: String,
+    /// 実行できるセクションのアドレスの範囲。ほかのアドレスは変換しない
+    code_ranges: Vec<std::ops::Range<u64>>,
+}
+
+impl Symbolizer {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content =
+            std::fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        let sha256 = Sha256::digest(&content)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        let elf = object::File::parse(content.as_slice())
+            .map_err(|e| format!("Failed to parse {}: {e}", path.display()))?;
+        let code_ranges = elf
+            .sections()
+            .filter(|section| {
+                matches!(section.flags(), SectionFlags::Elf { sh_flags }
+                    if sh_flags & u64::from(object::elf::SHF_EXECINSTR) != 0)
+            })
+            .map(|section| section.address()..section.address() + section.size())
+            .collect();
+        let loader = addr2line::Loader::new(path)
+            .map_err(|e| format!("Failed to load {}: {e}", path.display()))?;
+        Ok(Self {
+            loader,
+            path: path.to_path_buf(),
+            sha256,
+            code_ranges,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn sha256(&self) -> &str {
+        &self.sha256
+    }
+
+    /// 表示された SHA256 の先頭部分がこの ELF と一致するか
+    pub fn matches_sha256(&self, printed: &str) -> bool {
+        !printed.is_empty() && self.sha256.starts_with(&printed.to_ascii_lowercase())
+    }
+
+    /// インライン展開された関数を内側から順に返す。ELF に含まれないアドレスは空
+    pub fn symbolize(&self, address: u32) -> Vec<SourceFrame> {
+        let probe = u64::from(address);
+        let mut frames = Vec::new();
+        if !self.code_ranges.iter().any(|range| range.contains(&probe)) {
+            return frames;
+        }
+        if let Ok(mut iter) = self.loader.find_frames(probe) {
+            while let Ok(Some(frame)) = iter.next() {
+                let frame = SourceFrame {
+                    function: frame
+                        .function
+                        .and_then(|name| name.demangle().ok().map(|name| name.into_owned())),
+                    file: frame
+                        .location
+                        .as_ref()
+                        .and_then(|location| location.file.map(str::to_string)),
+                    line: frame.location.as_ref().and_then(|location| location.line),
+                };
+                if frame.function.is_some() || frame.file.is_some() {
+                    frames.push(frame);
+                }
+            }
+        }
+        // DWARF がなければシンボルテーブルの関数名だけを使う
+        let symbol = self.loader.find_symbol(probe).map(str::to_string);
+        match frames.first_mut() {
+            Some(first) if first.function.is_none() => first.function = symbol,
+            Some(_) => {}
+            None if symbol.is_some() => frames.push(SourceFrame {
+                function: symbol,
+                file: None,
+                line: None,
+            }),
+            None => {}
+        }
+        frames
+    }
+}
@@ -0,0 +1,367 @@
+use crate::serial::ReceivedData;
+use chrono::{DateTime, Local};
+
+/// 1 フレームとして扱うバイト数の上限。超えたらその時点で区切る
+const MAX_FRAME_LENGTH: usize = 64 * 1024;
+
+/// 区切り終えた 1 フレーム
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// 先頭のバイトを受信した時刻
+    pub time: DateTime<Local>,
+    pub bytes: Vec<u8>,
+}
+
+/// フレームの終わりを判定する方法
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum FrameRule {
+    /// 受信の間隔が空いたら区切る
+    #[default]
+    IdleGap,
+    /// 区切りのバイト列を受信したら区切る (区切りはフレームに含める)
+    Delimiter,
+    /// 決まったバイト数で区切る
+    FixedLength,
+    /// フレーム内の長さフィールドの値で区切る
+    LengthField,
+}
+
+/// フレームの区切り方の設定
+#[derive(Debug, Clone, PartialEq)]
+pub struct FramingOptions {
+    pub rule: FrameRule,
+    pub idle_gap_ms: u32,
+    pub delimiter: Vec<u8>,
+    pub fixed_length: usize,
+    /// 長さフィールドの位置 (フレーム先頭からのバイト数)
+    pub length_offset: usize,
+    /// 長さフィールドのバイト数 (1, 2, 4)
+    pub length_size: usize,
+    pub is_length_big_endian: bool,
+    /// フレーム長 = 長さフィールドの終わりまで + 値 + length_adjustment
+    ///
+    /// 値がヘッダやチェックサムを含むかどうかの違いを吸収する。
+    pub length_adjustment: i32,
+}
+
+impl Default for FramingOptions {
+    fn default() -> Self {
+        Self {
+            rule: FrameRule::default(),
+            idle_gap_ms: 20,
+            delimiter: b"\n".to_vec(),
+            fixed_length: 16,
+            length_offset: 0,
+            length_size: 1,
+            is_length_big_endian: true,
+            length_adjustment: 0,
+        }
+    }
+}
+
+impl FramingOptions {
+    /// 長さフィールドから求めたフレーム長。フィールドをまだ受信していなければ None
+    fn length_from_field(&self, buffer: &[u8]) -> Option<usize> {
+        let field_end = self.length_offset + self.length_size;
+        let field = buffer.get(self.length_offset..field_end)?;
+        let value = if self.is_length_big_endian {
+            field
+                .iter()
+                .fold(0u64, |value, &byte| (value << 8) | u64::from(byte))
+        } else {
+            field
+                .iter()
+                .rev()
+                .fold(0u64, |value, &byte| (value << 8) | u64::from(byte))
+        };
+        let length = field_end as i64 + value as i64 + i64::from(self.length_adjustment);
+        // 不正な値でも先に進めるよう、最低でも長さフィールドまでを 1 フレームとする
+        Some((length.max(field_end as i64) as usize).min(MAX_FRAME_LENGTH))
+    }
+}
+
+/// 受信したバイト列を設定に従ってフレームに区切る
+///
+/// Controller から受け取ったデータと、表示との間に挟んで使う。
+#[derive(Default)]
+pub struct Framer {
+    options: FramingOptions,
+    buffer: Vec<u8>,
+    start_time: Option<DateTime<Local>>,
+    last_time: Option<DateTime<Local>>, // 最後にバイトを受信した時刻
+}
+
+impl Framer {
+    pub fn options(&self) -> &FramingOptions {
+        &self.options
+    }
+
+    /// 設定を変更する。組み立て途中のフレームは捨てる
+    pub fn set_options(&mut self, options: FramingOptions) {
+        if self.options != options {
+            self.options = options;
+            self.reset();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.start_time = None;
+        self.last_time = None;
+    }
+
+    /// 受信したデータを取り込み、区切り終えたフレームを返す
+    pub fn push(&mut self, data: &ReceivedData) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        if self.is_idle(data.time) {
+            frames.extend(self.take_frame());
+        }
+        self.last_time = Some(data.time);
+
+        for &byte in &data.bytes {
+            if self.buffer.is_empty() {
+                self.start_time = Some(data.time);
+            }
+            self.buffer.push(byte);
+            if self.is_frame_complete() {
+                frames.extend(self.take_frame());
+            }
+        }
+        frames
+    }
+
+    /// 受信が途絶えたまま idle_gap_ms が経過していれば、組み立て途中のフレームを返す
+    pub fn poll_idle(&mut self, now: DateTime<Local>) -> Option<Frame> {
+        if self.is_idle(now) {
+            self.take_frame()
+        } else {
+            None
+        }
+    }
+
+    /// 受信の間隔で区切るのを待っているフレームがあるか
+    pub fn is_waiting_for_idle(&self) -> bool {
+        self.options.rule == FrameRule::IdleGap && !self.buffer.is_empty()
+    }
+
+    fn is_idle(&self, now: DateTime<Local>) -> bool {
+        self.options.rule == FrameRule::IdleGap
+            && self.last_time.is_some_and(|last_time| {
+                i64::from(self.options.idle_gap_ms) <= (now - last_time).num_milliseconds()
+            })
+    }
+
+    fn is_frame_complete(&self) -> bool {
+        let length = self.buffer.len();
+        if MAX_FRAME_LENGTH <= length {
+            return true;
+        }
+        match self.options.rule {
+            FrameRule::IdleGap => false,
+            FrameRule::Delimiter => {
+                !self.options.delimiter.is_empty() && self.buffer.ends_with(&self.options.delimiter)
+            }
+            FrameRule::FixedLength => self.options.fixed_length.max(1) <= length,
+            FrameRule::LengthField => self
+                .options
+                .length_from_field(&self.buffer)
+                .is_some_and(|expected| expected <= length),
+        }
+    }
+
+    fn take_frame(&mut self) -> Option<Frame> {
+        if self.buffer.is_empty() {
+            return None;
+        }
+        Some(Frame {
+            time: self.start_time.take().unwrap_or_else(Local::now),
+            bytes: std::mem::take(&mut self.buffer),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    fn with_options(options: FramingOptions) -> Framer {
+        let mut framer = Framer::default();
+        framer.set_options(options);
+        framer
+    }
+
+    fn data(time: DateTime<Local>, bytes: &[u8]) -> ReceivedData {
+        ReceivedData {
+            time,
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    fn frame_bytes(frames: Vec<Frame>) -> Vec<Vec<u8>> {
+        frames.into_iter().map(|frame| frame.bytes).collect()
+    }
+
+    #[test]
+    fn idle_gap() {
+        let mut framer = Framer::default();
+        let start = Local::now();
+        let gap = TimeDelta::milliseconds(20);
+        assert!(framer.push(&data(start, b"ab")).is_empty());
+        assert!(framer.push(&data(start + gap / 2, b"c")).is_empty());
+        assert!(framer.is_waiting_for_idle());
+        assert!(framer.poll_idle(start + gap).is_none());
+
+        let frame = framer.poll_idle(start + gap / 2 + gap).unwrap();
+        assert_eq!(frame.bytes, b"abc");
+        assert_eq!(frame.time, start);
+        assert!(!framer.is_waiting_for_idle());
+
+        // 間隔が空いた後の受信で、それまでの分を区切る
+        let later = start + gap * 10;
+        assert!(framer.push(&data(later, b"d")).is_empty());
+        let frames = framer.push(&data(later + gap, b"e"));
+        assert_eq!(frame_bytes(frames), vec![b"d".to_vec()]);
+        assert!(framer.poll_idle(later + gap / 2).is_none());
+    }
+
+    #[test]
+    fn delimiter() {
+        let mut framer = with_options(FramingOptions {
+            rule: FrameRule::Delimiter,
+            delimiter: b"\r\n".to_vec(),
+            ..Default::default()
+        });
+        let now = Local::now();
+        assert!(framer.push(&data(now, b"one\r")).is_empty());
+        let frames = framer.push(&data(now, b"\ntwo\r\nthr"));
+        assert_eq!(
+            frame_bytes(frames),
+            vec![b"one\r\n".to_vec(), b"two\r\n".to_vec()]
+        );
+        // 間隔では区切らない
+        assert!(!framer.is_waiting_for_idle());
+        assert!(framer.poll_idle(now + TimeDelta::seconds(1)).is_none());
+
+        // 区切りが空なら上限まで溜める
+        let mut framer = with_options(FramingOptions {
+            rule: FrameRule::Delimiter,
+            delimiter: Vec::new(),
+            ..Default::default()
+        });
+        assert!(framer.push(&data(now, b"\n\n")).is_empty());
+    }
+
+    #[test]
+    fn fixed_length() {
+        let mut framer = with_options(FramingOptions {
+            rule: FrameRule::FixedLength,
+            fixed_length: 3,
+            ..Default::default()
+        });
+        let now = Local::now();
+        let frames = framer.push(&data(now, b"abcdefg"));
+        assert_eq!(frame_bytes(frames), vec![b"abc".to_vec(), b"def".to_vec()]);
+        let frames = framer.push(&data(now, b"hi"));
+        assert_eq!(frame_bytes(frames), vec![b"ghi".to_vec()]);
+
+        // 0 バイトは 1 バイトとして扱う
+        let mut framer = with_options(FramingOptions {
+            rule: FrameRule::FixedLength,
+            fixed_length: 0,
+            ..Default::default()
+        });
+        assert_eq!(framer.push(&data(now, b"ab")).len(), 2);
+    }
+
+    #[test]
+    fn length_field() {
+        let now = Local::now();
+        // 先頭 1 バイトのヘッダの後に 2 バイトのリトルエンディアンの長さ、最後に 1 バイトのチェックサム
+        let mut framer = with_options(FramingOptions {
+            rule: FrameRule::LengthField,
+            length_offset: 1,
+            length_size: 2,
+            is_length_big_endian: false,
+            length_adjustment: 1,
+            ..Default::default()
+        });
+        assert!(framer.push(&data(now, &[0xaa, 0x02])).is_empty());
+        let frames = framer.push(&data(now, &[0x00, 1, 2, 0xcc, 0xaa, 0x00, 0x00, 0xcc]));
+        assert_eq!(
+            frame_bytes(frames),
+            vec![
+                vec![0xaa, 0x02, 0x00, 1, 2, 0xcc],
+                vec![0xaa, 0x00, 0x00, 0xcc]
+            ]
+        );
+
+        // 値が長さフィールドを含む場合
+        let mut framer = with_options(FramingOptions {
+            rule: FrameRule::LengthField,
+            length_size: 2,
+            length_adjustment: -2,
+            ..Default::default()
+        });
+        let frames = framer.push(&data(now, &[0x00, 0x04, 1, 2, 0x00, 0x02]));
+        assert_eq!(
+            frame_bytes(frames),
+            vec![vec![0x00, 0x04, 1, 2], vec![0x00, 0x02]]
+        );
+    }
+
+    #[test]
+    fn malformed_length_field() {
+        // 長さフィールドより短い値でも、フィールドまでを 1 フレームとして進める
+        let mut framer = with_options(FramingOptions {
+            rule: FrameRule::LengthField,
+            length_adjustment: -10,
+            ..Default::default()
+        });
+        let frames = framer.push(&data(Local::now(), &[5, 6, 7]));
+        assert_eq!(frame_bytes(frames), vec![vec![5], vec![6], vec![7]]);
+
+        // 上限を超える長さは上限で区切る
+        let mut framer = with_options(FramingOptions {
+            rule: FrameRule::LengthField,
+            length_size: 4,
+            ..Default::default()
+        });
+        let mut bytes = vec![0xff; 4];
+        bytes.resize(MAX_FRAME_LENGTH + 1, 0x00);
+        let frames = framer.push(&data(Local::now(), &bytes));
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].bytes.len(), MAX_FRAME_LENGTH);
+    }
+
+    #[test]
+    fn frame_length_limit() {
+        let mut framer = Framer::default();
+        let frames = framer.push(&data(Local::now(), &vec![0x55; MAX_FRAME_LENGTH * 2 + 1]));
+        assert_eq!(frames.len(), 2);
+        assert!(
+            frames
+                .iter()
+                .all(|frame| frame.bytes.len() == MAX_FRAME_LENGTH)
+        );
+        assert!(framer.is_waiting_for_idle());
+    }
+
+    #[test]
+    fn set_options_discards_partial_frame() {
+        let mut framer = Framer::default();
+        let now = Local::now();
+        framer.push(&data(now, b"partial"));
+
+        // 同じ設定なら組み立て途中のフレームを残す
+        framer.set_options(FramingOptions::default());
+        assert!(framer.is_waiting_for_idle());
+
+        framer.set_options(FramingOptions {
+            rule: FrameRule::Delimiter,
+            ..Default::default()
+        });
+        let frames = framer.push(&data(now, b"x\n"));
+        assert_eq!(frame_bytes(frames), vec![b"x\n".to_vec()]);
+    }
+}
@@ -0,0 +1,6 @@
+pub mod framer;
+
+pub use framer::Frame;
+pub use framer::FrameRule;
+pub use framer::Framer;
+pub use framer::FramingOptions;
@@ -0,0 +1,62 @@
+//! 16 進数の文字列の変換
+//!
+//! `from_str_radix` は先頭の `+` を符号として受け付けるため、
+//! ここでは桁がすべて 16 進の数字であることを先に確かめる。
+
+fn is_hex_digits(digits: &str) -> bool {
+    !digits.is_empty() && digits.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// 16 進の数字だけからなる文字列を数値にする。桁あふれや空文字列は None
+pub fn parse_u32(digits: &str) -> Option<u32> {
+    is_hex_digits(digits)
+        .then(|| u32::from_str_radix(digits, 16).ok())
+        .flatten()
+}
+
+/// 区切りのない 16 進の数字の並びを、2 桁ずつバイト列にする
+///
+/// 桁数が奇数のときや 16 進でない文字を含むときは None。空文字列は空のバイト列になる。
+pub fn decode(digits: &str) -> Option<Vec<u8>> {
+    if !digits.len().is_multiple_of(2) || !digits.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    // 16 進数字だけなら ASCII のため、バイト位置で切り出せる
+    (0..digits.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&digits[index..index + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_numbers() {
+        assert_eq!(parse_u32("1fffFFFF"), Some(0x1fff_ffff));
+        assert_eq!(parse_u32("0"), Some(0));
+    }
+
+    #[test]
+    fn rejects_signs_and_non_digits() {
+        for digits in ["", "+1", "-1", "+", " 1", "1 ", "0x1", "g"] {
+            assert_eq!(parse_u32(digits), None, "{digits:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_overflow() {
+        assert_eq!(parse_u32("100000000"), None);
+    }
+
+    #[test]
+    fn decodes_byte_pairs() {
+        assert_eq!(decode("00ff7A"), Some(vec![0x00, 0xff, 0x7a]));
+        assert_eq!(decode(""), Some(Vec::new()));
+        assert_eq!(decode("abc"), None);
+        assert_eq!(decode("+1"), None);
+        assert_eq!(decode("0g"), None);
+        assert_eq!(decode("é0"), None);
+    }
+}
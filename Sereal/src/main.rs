@@ -1,8 +1,17 @@
 #![windows_subsystem = "windows"]
 
 mod ansi_formatter;
+mod decoder;
+mod defmt;
+mod esp_panic;
+mod framing;
+mod hex;
+mod micropython;
 mod sereal_colors;
 mod serial;
+mod stk500;
+mod terminal;
+mod transfer;
 mod ui;
 
 use eframe::egui;
@@ -10,20 +19,55 @@ use egui_dock::{DockArea, DockState, Style, TabAddAlign, TabViewer, tab_viewer::
 use std::sync::Arc;
 
 use crate::serial::service::SerialService;
+use crate::ui::data_inspector::{DataInspector, Selection};
+
+/// ドックのタブ
+pub enum AppTab {
+    Serial(Box<ui::SerialView>),
+    /// どのタブで選択したバイト列も解釈する。1 つだけ開く
+    Inspector(DataInspector),
+}
 
 pub struct AppTabViewer<'a> {
     add_nodes: &'a mut Vec<(egui_dock::SurfaceIndex, egui_dock::NodeIndex)>,
+    /// 最後に選択されたバイト列
+    inspected: &'a mut Selection,
+    is_inspector_requested: &'a mut bool,
 }
 
 impl TabViewer for AppTabViewer<'_> {
-    type Tab = ui::SerialView;
+    type Tab = AppTab;
 
     fn title(&mut self, tab: &mut Self::Tab) -> egui::WidgetText {
-        tab.get_port_name().into()
+        match tab {
+            AppTab::Serial(view) => view.title().into(),
+            AppTab::Inspector(_) => "Data inspector".into(),
+        }
+    }
+
+    fn id(&mut self, tab: &mut Self::Tab) -> egui::Id {
+        match tab {
+            AppTab::Serial(view) => view.id(),
+            AppTab::Inspector(_) => egui::Id::new("data_inspector"),
+        }
     }
 
     fn ui(&mut self, ui: &mut egui::Ui, tab: &mut Self::Tab) {
-        tab.ui(ui);
+        match tab {
+            AppTab::Serial(view) => {
+                view.ui(ui);
+                if let Some(bytes) = view.take_selection_update() {
+                    *self.inspected = Selection {
+                        source: view.title(),
+                        bytes,
+                    };
+                }
+                if view.take_inspector_request() {
+                    *self.is_inspector_requested = true;
+                }
+            }
+            AppTab::Inspector(inspector) => inspector.ui(ui, self.inspected),
+        }
     }
 
     fn on_close(&mut self, _tab: &mut Self::Tab) -> OnCloseResponse {
@@ -44,20 +88,22 @@ enum Theme {
 }
 
 pub struct MyApp {
-    dock_state: DockState<ui::SerialView>,
+    dock_state: DockState<AppTab>,
     serial_service: Arc<std::sync::Mutex<SerialService>>,
     theme: Theme,
+    inspected: Selection,
 }
 
 impl Default for MyApp {
     fn default() -> Self {
         let serial_service = Arc::new(std::sync::Mutex::new(SerialService::default()));
         let initial_tab = ui::SerialView::new("Port 0".to_string(), Arc::clone(&serial_service));
-        let dock_state = DockState::new(vec![initial_tab]);
+        let dock_state = DockState::new(vec![AppTab::Serial(Box::new(initial_tab))]);
         Self {
             dock_state,
             serial_service,
             theme: Theme::default(),
+            inspected: Selection::default(),
         }
     }
 }
@@ -96,6 +142,7 @@ impl eframe::App for MyApp {
         });
 
         let mut added_nodes = Vec::new();
+        let mut is_inspector_requested = false;
 
         // DockArea の設定
         let mut style = Style::from_egui(ctx.style().as_ref());
@@ -110,9 +157,15 @@ impl eframe::App for MyApp {
                 ctx,
                 &mut AppTabViewer {
                     add_nodes: &mut added_nodes,
+                    inspected: &mut self.inspected,
+                    is_inspector_requested: &mut is_inspector_requested,
                 },
             );
 
+        if is_inspector_requested {
+            self.open_inspector();
+        }
+
         added_nodes.drain(..).for_each(|(surface, node)| {
             self.dock_state
                 .set_focused_node_and_surface((surface, node));
@@ -121,17 +174,19 @@ impl eframe::App for MyApp {
                 format!("Port {}", unused_port_index),
                 Arc::clone(&self.serial_service),
             );
-            self.dock_state.push_to_focused_leaf(new_tab);
+            self.dock_state
+                .push_to_focused_leaf(AppTab::Serial(Box::new(new_tab)));
         });
 
-        // 最後のタブが閉じられたら新しいタブを追加する
-        if self.dock_state.surfaces_count() == 1 && self.dock_state.iter_all_tabs().count() == 0 {
+        // 最後のポートのタブが閉じられたら新しいタブを追加する
+        if self.serial_views().next().is_none() {
             let unused_port_index = self.get_unused_port_index();
             let new_tab = ui::SerialView::new(
                 format!("Port {}", unused_port_index),
                 Arc::clone(&self.serial_service),
             );
-            self.dock_state.push_to_first_leaf(new_tab);
+            self.dock_state
+                .push_to_first_leaf(AppTab::Serial(Box::new(new_tab)));
         }
 
         ctx.request_repaint();
@@ -139,14 +194,39 @@ impl eframe::App for MyApp {
 }
 
 impl MyApp {
+    fn serial_views(&self) -> impl Iterator<Item = &ui::SerialView> {
+        self.dock_state
+            .iter_all_tabs()
+            .filter_map(|(_, tab)| match tab {
+                AppTab::Serial(view) => Some(view.as_ref()),
+                AppTab::Inspector(_) => None,
+            })
+    }
+
+    /// データインスペクタのタブを開く。開いていれば前に出す
+    fn open_inspector(&mut self) {
+        let existing = self
+            .dock_state
+            .find_tab_from(|tab| matches!(tab, AppTab::Inspector(_)));
+        match existing {
+            Some(location) => self.dock_state.set_active_tab(location),
+            None => {
+                self.dock_state.main_surface_mut().split_right(
+                    egui_dock::NodeIndex::root(),
+                    0.7,
+                    vec![AppTab::Inspector(DataInspector::default())],
+                );
+            }
+        }
+    }
+
     fn get_unused_port_index(&self) -> usize {
         let mut port_index = 0;
         loop {
             let port_name = format!("Port {}", port_index);
             if self
-                .dock_state
-                .iter_all_tabs()
-                .all(|((_, _), tab)| tab.get_port_name() != port_name)
+                .serial_views()
+                .all(|view| view.get_port_name() != port_name)
             {
                 return port_index;
             }
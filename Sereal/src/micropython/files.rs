@@ -0,0 +1,233 @@
+use crate::hex;
+
+/// 1 回の実行で書き込むバイト数。エスケープで最大 4 倍に膨らむ
+pub const UPLOAD_CHUNK_SIZE: usize = 256;
+/// ボードが 1 行で返すバイト数
+const DOWNLOAD_CHUNK_SIZE: usize = 256;
+
+/// ボード上のファイルやディレクトリ
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceEntry {
+    pub name: String,
+    pub is_directory: bool,
+    pub size: u64,
+}
+
+/// Python の文字列リテラルにする
+pub fn python_string(text: &str) -> String {
+    let mut literal = "'".to_string();
+    for char in text.chars() {
+        match char {
+            '\\' => literal += "\\\\",
+            '\'' => literal += "\\'",
+            char if char.is_control() => literal += &format!("\\u{:04x}", u32::from(char)),
+            char => literal.push(char),
+        }
+    }
+    literal.push('\'');
+    literal
+}
+
+/// Python の bytes リテラルにする
+pub fn python_bytes(data: &[u8]) -> String {
+    let mut literal = "b'".to_string();
+    for &byte in data {
+        match byte {
+            b'\\' => literal += "\\\\",
+            b'\'' => literal += "\\'",
+            0x20..=0x7e => literal.push(char::from(byte)),
+            _ => literal += &format!("\\x{byte:02x}"),
+        }
+    }
+    literal.push('\'');
+    literal
+}
+
+/// ディレクトリの中身を「種別 サイズ 名前」の行で出力するコード
+pub fn list_directory_code(path: &str) -> String {
+    format!(
+        "import os\n\
+         p={}\n\
+         for n in sorted(os.listdir(p)):\n \
+         s=os.stat(p.rstrip('/')+'/'+n)\n \
+         print(1 if s[0]&0x4000 else 0,s[6],n)\n",
+        python_string(path)
+    )
+}
+
+pub fn parse_directory_listing(stdout: &[u8]) -> Result<Vec<DeviceEntry>, String> {
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut parts = line.splitn(3, ' ');
+            let kind = parts.next();
+            let size = parts.next().and_then(|size| size.parse().ok());
+            match (kind, size, parts.next()) {
+                (Some(kind @ ("0" | "1")), Some(size), Some(name)) => Ok(DeviceEntry {
+                    name: name.to_string(),
+                    is_directory: kind == "1",
+                    size,
+                }),
+                _ => Err(format!("Unexpected listing line {line:?}")),
+            }
+        })
+        .collect()
+}
+
+/// ボード上のパスを連結する
+pub fn join_path(directory: &str, name: &str) -> String {
+    format!("{}/{name}", directory.trim_end_matches('/'))
+}
+
+/// 書き込み用にファイルを開くコード。続けて write_chunk_code と close_code を実行する
+pub fn open_for_write_code(path: &str) -> String {
+    format!("f=open({},'wb')\nw=f.write\n", python_string(path))
+}
+
+pub fn write_chunk_code(chunk: &[u8]) -> String {
+    format!("w({})\n", python_bytes(chunk))
+}
+
+pub fn close_code() -> &'static str {
+    "f.close()\n"
+}
+
+/// 1 行目にファイルサイズ、続けて内容を 16 進の行で出力するコード
+pub fn read_file_code(path: &str) -> String {
+    format!(
+        "import os\n\
+         try:\n import binascii\n\
+         except ImportError:\n import ubinascii as binascii\n\
+         p={}\n\
+         print(os.stat(p)[6])\n\
+         with open(p,'rb') as f:\n \
+         while 1:\n  \
+         b=f.read({DOWNLOAD_CHUNK_SIZE})\n  \
+         if not b:break\n  \
+         print(binascii.hexlify(b).decode())\n",
+        python_string(path)
+    )
+}
+
+/// read_file_code の出力の 1 行目からファイルサイズを読む
+pub fn parse_file_size(stdout: &[u8]) -> Option<u64> {
+    let end = stdout.iter().position(|&byte| byte == b'\n')?;
+    String::from_utf8_lossy(&stdout[..end]).trim().parse().ok()
+}
+
+/// read_file_code の出力から内容を復元する
+pub fn parse_file_content(stdout: &[u8]) -> Result<Vec<u8>, String> {
+    let text = String::from_utf8_lossy(stdout);
+    let mut lines = text.lines();
+    let size: usize = lines
+        .next()
+        .and_then(|line| line.trim().parse().ok())
+        .ok_or_else(|| "The board did not report the file size".to_string())?;
+    // 壊れたサイズで大きな領域を確保しないよう、出力の長さで抑える
+    let mut content = Vec::with_capacity(size.min(stdout.len() / 2));
+    for line in lines {
+        let line = line.trim();
+        let bytes = hex::decode(line).ok_or_else(|| format!("Not a hex line: {line:?}"))?;
+        content.extend(bytes);
+    }
+    if content.len() != size {
+        return Err(format!(
+            "Received {} bytes but the file has {size}",
+            content.len()
+        ));
+    }
+    Ok(content)
+}
+
+/// 例外の出力から最後の行 (例外の種類とメッセージ) を取り出す
+pub fn exception_summary(stderr: &[u8]) -> String {
+    String::from_utf8_lossy(stderr)
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or("Unknown error")
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// read_file_code が出力する形にする
+    fn file_output(content: &[u8]) -> Vec<u8> {
+        let mut stdout = format!("{}\r\n", content.len());
+        for chunk in content.chunks(DOWNLOAD_CHUNK_SIZE) {
+            let hex: String = chunk.iter().map(|byte| format!("{byte:02x}")).collect();
+            stdout += &format!("{hex}\r\n");
+        }
+        stdout.into_bytes()
+    }
+
+    #[test]
+    fn parses_file_content() {
+        for length in [0, 1, 255, 256, 257, 1000] {
+            let content: Vec<u8> = (0..length).map(|i| (i * 13) as u8).collect();
+            let stdout = file_output(&content);
+            assert_eq!(parse_file_size(&stdout), Some(length as u64));
+            assert_eq!(parse_file_content(&stdout), Ok(content));
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_file_content() {
+        let error = |stdout: &[u8]| parse_file_content(stdout).unwrap_err();
+        assert_eq!(error(b""), "The board did not report the file size");
+        assert_eq!(error(b"x\n00"), "The board did not report the file size");
+        assert_eq!(error(b"2\n0g01\n"), "Not a hex line: \"0g01\"");
+        assert_eq!(error(b"1\n+f\n"), "Not a hex line: \"+f\"");
+        assert_eq!(error(b"1\n012\n"), "Not a hex line: \"012\"");
+        assert_eq!(error("1\né0\n".as_bytes()), "Not a hex line: \"é0\"");
+        assert_eq!(error(b"3\n0102\n"), "Received 2 bytes but the file has 3");
+        assert_eq!(
+            error(b"18446744073709551615\n00\n"),
+            "Received 1 bytes but the file has 18446744073709551615"
+        );
+    }
+
+    #[test]
+    fn parses_directory_listing() {
+        assert_eq!(
+            parse_directory_listing(b"1 0 lib\r\n0 123 main.py\r\n0 5 my file.txt\r\n"),
+            Ok(vec![
+                DeviceEntry {
+                    name: "lib".to_string(),
+                    is_directory: true,
+                    size: 0,
+                },
+                DeviceEntry {
+                    name: "main.py".to_string(),
+                    is_directory: false,
+                    size: 123,
+                },
+                DeviceEntry {
+                    name: "my file.txt".to_string(),
+                    is_directory: false,
+                    size: 5,
+                },
+            ])
+        );
+        assert_eq!(
+            parse_directory_listing(b"2 0 x\n"),
+            Err("Unexpected listing line \"2 0 x\"".to_string())
+        );
+    }
+
+    #[test]
+    fn escapes_literals() {
+        assert_eq!(python_string("a'b\\c\n"), "'a\\'b\\\\c\\u000a'");
+        assert_eq!(python_bytes(b"a'\\\x00\xff"), "b'a\\'\\\\\\x00\\xff'");
+        assert_eq!(join_path("/", "main.py"), "/main.py");
+        assert_eq!(join_path("/lib/", "a.py"), "/lib/a.py");
+        assert_eq!(
+            exception_summary(b"Traceback (most recent call last):\r\n  File \"<stdin>\", line 1\r\nNameError: name 'x' isn't defined\r\n"),
+            "NameError: name 'x' isn't defined"
+        );
+    }
+}
@@ -0,0 +1,6 @@
+pub mod files;
+pub mod raw_repl;
+
+pub use files::DeviceEntry;
+pub use raw_repl::RawRepl;
+pub use raw_repl::ReplEvent;
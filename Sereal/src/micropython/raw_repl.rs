@@ -0,0 +1,399 @@
+use chrono::{DateTime, Local, TimeDelta};
+use std::collections::VecDeque;
+
+/// raw REPL に入ったときにボードが返すバナー
+const RAW_REPL_BANNER: &[u8] = b"raw REPL; CTRL-B to exit\r\n>";
+/// 実行中の Ctrl-C で止め、Ctrl-A で raw REPL に入る
+const ENTER_SEQUENCE: &[u8] = b"\r\x03\x03\r\x01";
+const CTRL_B: u8 = 0x02;
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+/// ボードの受信バッファを溢れさせないように、この単位で間を空けて書く
+const WRITE_CHUNK_SIZE: usize = 256;
+const WRITE_INTERVAL_MS: i64 = 10;
+/// バナーや OK、プロンプトを待つ時間
+const REPLY_TIMEOUT_MS: i64 = 3000;
+
+/// raw REPL で起きたこと
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplEvent {
+    /// raw REPL に入った
+    Entered,
+    /// 実行中のコードの標準出力。届いた分から順に渡す
+    Stdout(Vec<u8>),
+    /// 実行が終わり、次のコードを受け付けられる
+    Finished { stdout: Vec<u8>, stderr: Vec<u8> },
+    /// 応答がない、または想定外の応答
+    Failed(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    /// raw REPL の外にいる
+    Idle,
+    /// バナーを待っている
+    Entering,
+    /// プロンプト `>` でコードを待っている
+    Ready,
+    /// コードを送り終えて OK を待っている
+    WaitingOk,
+    Stdout,
+    Stderr,
+    /// 実行後のプロンプトを待っている
+    WaitingPrompt,
+}
+
+/// MicroPython / CircuitPython の raw REPL (Ctrl-A) でコードを実行する
+///
+/// 送受信は行わず、送るバイト列を poll で返し、受信したバイト列を feed_rx で受け取る。
+pub struct RawRepl {
+    phase: Phase,
+    buffer: Vec<u8>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    outgoing: VecDeque<u8>,
+    last_write: Option<DateTime<Local>>,
+    /// 応答を待ち始めた時刻。送信が残っている間は None
+    waiting_since: Option<DateTime<Local>>,
+    events: Vec<ReplEvent>,
+}
+
+impl Default for RawRepl {
+    fn default() -> Self {
+        Self {
+            phase: Phase::Idle,
+            buffer: Vec::new(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            outgoing: VecDeque::new(),
+            last_write: None,
+            waiting_since: None,
+            events: Vec::new(),
+        }
+    }
+}
+
+impl RawRepl {
+    /// 実行中のプログラムを止めて raw REPL に入る
+    pub fn enter(&mut self) {
+        self.reset_buffers();
+        self.outgoing.extend(ENTER_SEQUENCE);
+        self.phase = Phase::Entering;
+    }
+
+    /// コードを実行する。raw REPL に入っていなければ失敗として扱う
+    pub fn execute(&mut self, code: &[u8]) {
+        if self.phase != Phase::Ready {
+            self.events
+                .push(ReplEvent::Failed("Not in the raw REPL".to_string()));
+            return;
+        }
+        self.reset_buffers();
+        self.outgoing.extend(code);
+        self.outgoing.push_back(CTRL_D);
+        self.phase = Phase::WaitingOk;
+    }
+
+    /// 通常の REPL に戻る
+    pub fn exit(&mut self) {
+        self.outgoing.push_back(CTRL_B);
+        self.phase = Phase::Idle;
+    }
+
+    /// 実行中のコードを止めて通常の REPL に戻る。残りの応答は待たない
+    pub fn cancel(&mut self) {
+        self.reset_buffers();
+        self.outgoing.clear();
+        self.outgoing.extend([CTRL_C, CTRL_C, CTRL_B]);
+        self.phase = Phase::Idle;
+    }
+
+    pub fn take_events(&mut self) -> Vec<ReplEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn feed_rx(&mut self, time: DateTime<Local>, bytes: &[u8]) {
+        if self.phase == Phase::Idle {
+            return;
+        }
+        self.buffer.extend_from_slice(bytes);
+        // 1 回の受信に複数の段階が含まれることがあるため、進めなくなるまで繰り返す
+        while self.advance(time) {}
+    }
+
+    /// バッファを解釈して段階を 1 つ進める。進めたら true
+    fn advance(&mut self, time: DateTime<Local>) -> bool {
+        match self.phase {
+            Phase::Idle | Phase::Ready => {
+                self.buffer.clear();
+                false
+            }
+            Phase::Entering => {
+                // 止めたプログラムの出力やトレースバックは読み捨てる
+                let Some(position) = find(&self.buffer, RAW_REPL_BANNER) else {
+                    return false;
+                };
+                self.buffer.drain(..position + RAW_REPL_BANNER.len());
+                self.phase = Phase::Ready;
+                self.waiting_since = None;
+                self.events.push(ReplEvent::Entered);
+                true
+            }
+            Phase::WaitingOk => {
+                if self.buffer.len() < 2 {
+                    return false;
+                }
+                if !self.buffer.starts_with(b"OK") {
+                    let reply = String::from_utf8_lossy(&self.buffer).into_owned();
+                    self.fail(format!("Unexpected reply {reply:?} instead of OK"));
+                    return false;
+                }
+                self.buffer.drain(..2);
+                self.phase = Phase::Stdout;
+                self.waiting_since = None;
+                true
+            }
+            Phase::Stdout => {
+                let end = self.buffer.iter().position(|&byte| byte == CTRL_D);
+                let output: Vec<u8> = self
+                    .buffer
+                    .drain(..end.unwrap_or(self.buffer.len()))
+                    .collect();
+                if !output.is_empty() {
+                    self.stdout.extend_from_slice(&output);
+                    self.events.push(ReplEvent::Stdout(output));
+                }
+                if end.is_none() {
+                    return false;
+                }
+                self.buffer.remove(0);
+                self.phase = Phase::Stderr;
+                true
+            }
+            Phase::Stderr => {
+                let Some(end) = self.buffer.iter().position(|&byte| byte == CTRL_D) else {
+                    return false;
+                };
+                self.stderr.extend(self.buffer.drain(..end));
+                self.buffer.remove(0);
+                self.phase = Phase::WaitingPrompt;
+                self.waiting_since = Some(time);
+                true
+            }
+            Phase::WaitingPrompt => {
+                let Some(position) = self.buffer.iter().position(|&byte| byte == b'>') else {
+                    return false;
+                };
+                self.buffer.drain(..=position);
+                self.phase = Phase::Ready;
+                self.waiting_since = None;
+                self.events.push(ReplEvent::Finished {
+                    stdout: std::mem::take(&mut self.stdout),
+                    stderr: std::mem::take(&mut self.stderr),
+                });
+                true
+            }
+        }
+    }
+
+    /// 送るべきバイト列があれば返す。応答のタイムアウトもここで判定する
+    pub fn poll(&mut self, now: DateTime<Local>) -> Option<Vec<u8>> {
+        if let Some(since) = self.waiting_since
+            && TimeDelta::milliseconds(REPLY_TIMEOUT_MS) <= now - since
+        {
+            let waiting_for = match self.phase {
+                Phase::Entering => "the raw REPL banner",
+                Phase::WaitingOk => "OK",
+                _ => "the prompt",
+            };
+            self.fail(format!("No response while waiting for {waiting_for}"));
+        }
+
+        if self.outgoing.is_empty()
+            || self.last_write.is_some_and(|last_write| {
+                now - last_write < TimeDelta::milliseconds(WRITE_INTERVAL_MS)
+            })
+        {
+            return None;
+        }
+        let length = self.outgoing.len().min(WRITE_CHUNK_SIZE);
+        let chunk: Vec<u8> = self.outgoing.drain(..length).collect();
+        self.last_write = Some(now);
+        // 送り終えてから応答を待ち始める
+        if self.outgoing.is_empty() && matches!(self.phase, Phase::Entering | Phase::WaitingOk) {
+            self.waiting_since = Some(now);
+        }
+        Some(chunk)
+    }
+
+    /// 次に poll を呼ぶまでの時間
+    pub fn next_poll(&self, now: DateTime<Local>) -> Option<std::time::Duration> {
+        if !self.outgoing.is_empty() {
+            return Some(std::time::Duration::from_millis(WRITE_INTERVAL_MS as u64));
+        }
+        let deadline = self.waiting_since? + TimeDelta::milliseconds(REPLY_TIMEOUT_MS);
+        Some((deadline - now).to_std().unwrap_or_default())
+    }
+
+    fn fail(&mut self, message: String) {
+        self.reset_buffers();
+        self.outgoing.clear();
+        self.phase = Phase::Idle;
+        self.events.push(ReplEvent::Failed(message));
+    }
+
+    fn reset_buffers(&mut self) {
+        self.buffer.clear();
+        self.stdout.clear();
+        self.stderr.clear();
+        self.waiting_since = None;
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// raw REPL に入った状態。now には次の書き込みができる
+    fn ready(now: DateTime<Local>) -> RawRepl {
+        let mut repl = RawRepl::default();
+        repl.enter();
+        let entered_at = now - TimeDelta::seconds(1);
+        assert_eq!(repl.poll(entered_at).as_deref(), Some(ENTER_SEQUENCE));
+        repl.feed_rx(now, b"Traceback\r\nKeyboardInterrupt\r\nraw REPL; CTRL-B");
+        assert!(repl.take_events().is_empty());
+        repl.feed_rx(now, b" to exit\r\n>");
+        assert_eq!(repl.take_events(), [ReplEvent::Entered]);
+        repl
+    }
+
+    #[test]
+    fn executes_code() {
+        let now = Local::now();
+        let mut repl = ready(now);
+        repl.execute(b"print(1)");
+        assert_eq!(repl.poll(now).as_deref(), Some(&b"print(1)\x04"[..]));
+
+        repl.feed_rx(now, b"O");
+        repl.feed_rx(now, b"K1\r");
+        repl.feed_rx(now, b"\n\x04\x04");
+        assert_eq!(
+            repl.take_events(),
+            [
+                ReplEvent::Stdout(b"1\r".to_vec()),
+                ReplEvent::Stdout(b"\n".to_vec())
+            ]
+        );
+        repl.feed_rx(now, b">");
+        assert_eq!(
+            repl.take_events(),
+            [ReplEvent::Finished {
+                stdout: b"1\r\n".to_vec(),
+                stderr: Vec::new(),
+            }]
+        );
+    }
+
+    #[test]
+    fn collects_stderr_in_one_read() {
+        let now = Local::now();
+        let mut repl = ready(now);
+        repl.execute(b"x");
+        repl.poll(now);
+        repl.feed_rx(now, b"OK\x04NameError: x\r\n\x04>");
+        assert_eq!(
+            repl.take_events(),
+            [ReplEvent::Finished {
+                stdout: Vec::new(),
+                stderr: b"NameError: x\r\n".to_vec(),
+            }]
+        );
+        // 続けて実行できる
+        repl.execute(b"pass");
+        assert!(repl.take_events().is_empty());
+    }
+
+    #[test]
+    fn writes_long_code_in_chunks() {
+        let now = Local::now();
+        let mut repl = ready(now);
+        repl.execute(&[b'#'; WRITE_CHUNK_SIZE + 10]);
+        let later = now + TimeDelta::milliseconds(WRITE_INTERVAL_MS);
+        assert_eq!(
+            repl.poll(later).map(|chunk| chunk.len()),
+            Some(WRITE_CHUNK_SIZE)
+        );
+        assert_eq!(repl.poll(later), None);
+        assert!(repl.next_poll(later).is_some());
+        let last = repl
+            .poll(later + TimeDelta::milliseconds(WRITE_INTERVAL_MS))
+            .unwrap();
+        assert_eq!(last.len(), 11);
+        assert_eq!(last.last(), Some(&CTRL_D));
+    }
+
+    #[test]
+    fn fails_on_unexpected_replies() {
+        let now = Local::now();
+        let mut repl = ready(now);
+        repl.execute(b"1");
+        repl.poll(now);
+        repl.feed_rx(now, b"raw REPL");
+        assert_eq!(
+            repl.take_events(),
+            [ReplEvent::Failed(
+                "Unexpected reply \"raw REPL\" instead of OK".to_string()
+            )]
+        );
+        repl.execute(b"1");
+        assert_eq!(
+            repl.take_events(),
+            [ReplEvent::Failed("Not in the raw REPL".to_string())]
+        );
+    }
+
+    #[test]
+    fn times_out_without_replies() {
+        let now = Local::now();
+        let mut repl = RawRepl::default();
+        repl.enter();
+        repl.poll(now);
+        assert_eq!(
+            repl.next_poll(now),
+            Some(std::time::Duration::from_millis(REPLY_TIMEOUT_MS as u64))
+        );
+        assert_eq!(
+            repl.poll(now + TimeDelta::milliseconds(REPLY_TIMEOUT_MS - 1)),
+            None
+        );
+        assert!(repl.take_events().is_empty());
+        repl.poll(now + TimeDelta::milliseconds(REPLY_TIMEOUT_MS));
+        assert_eq!(
+            repl.take_events(),
+            [ReplEvent::Failed(
+                "No response while waiting for the raw REPL banner".to_string()
+            )]
+        );
+        assert_eq!(repl.next_poll(now), None);
+    }
+
+    #[test]
+    fn cancels_running_code() {
+        let now = Local::now();
+        let mut repl = ready(now);
+        repl.execute(b"while 1:pass");
+        repl.poll(now);
+        repl.feed_rx(now, b"OK");
+        repl.cancel();
+        let later = now + TimeDelta::milliseconds(WRITE_INTERVAL_MS);
+        assert_eq!(repl.poll(later), Some(vec![CTRL_C, CTRL_C, CTRL_B]));
+        repl.feed_rx(later, b"\x04Traceback\x04>");
+        assert!(repl.take_events().is_empty());
+    }
+}
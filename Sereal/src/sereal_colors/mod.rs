@@ -1,24 +1,19 @@
 mod color;
 mod pallet;
+mod xterm;
+
+pub use self::color::Color;
 
 pub use self::pallet::BLACK;
-pub use self::pallet::BLUE;
-pub use self::pallet::CYAN;
-pub use self::pallet::GREEN;
-pub use self::pallet::MAGENTA;
-pub use self::pallet::RED;
 pub use self::pallet::WHITE;
 pub use self::pallet::YELLOW;
 
-pub use self::pallet::BRIGHT_BLACK;
-pub use self::pallet::BRIGHT_BLUE;
-pub use self::pallet::BRIGHT_CYAN;
-pub use self::pallet::BRIGHT_GREEN;
-pub use self::pallet::BRIGHT_MAGENTA;
-pub use self::pallet::BRIGHT_RED;
 pub use self::pallet::BRIGHT_WHITE;
-pub use self::pallet::BRIGHT_YELLOW;
 
+pub use self::pallet::UI_GRAY;
 pub use self::pallet::UI_GREEN;
+pub use self::pallet::UI_ORANGE;
 pub use self::pallet::UI_RED;
 pub use self::pallet::UI_WHITE;
+
+pub use self::xterm::xterm_color;
@@ -120,3 +120,15 @@ pub const UI_WHITE: Color = Color {
     blue: 200,
     transparent: 255,
 };
+pub const UI_GRAY: Color = Color {
+    red: 128,
+    green: 128,
+    blue: 128,
+    transparent: 255,
+};
+pub const UI_ORANGE: Color = Color {
+    red: 255,
+    green: 150,
+    blue: 0,
+    transparent: 255,
+};
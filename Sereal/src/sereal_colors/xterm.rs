@@ -0,0 +1,57 @@
+use super::color::Color;
+use super::pallet;
+
+/// 6x6x6 のカラーキューブ (16-231) の各段階の輝度
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// xterm 256 色パレットの色を返す
+///
+/// 0-15 は ANSI 16 色、16-231 はカラーキューブ、232-255 はグレースケール。
+pub fn xterm_color(index: u8) -> Color {
+    match index {
+        0..=15 => ansi_color(index),
+        16..=231 => {
+            let cube_index = index - 16;
+            rgb(
+                CUBE_LEVELS[usize::from(cube_index / 36)],
+                CUBE_LEVELS[usize::from(cube_index / 6 % 6)],
+                CUBE_LEVELS[usize::from(cube_index % 6)],
+            )
+        }
+        232..=255 => {
+            let level = 8 + 10 * (index - 232);
+            rgb(level, level, level)
+        }
+    }
+}
+
+/// ANSI 16 色 (0-7: Normal, 8-15: Bright) を返す
+fn ansi_color(index: u8) -> Color {
+    match index {
+        0 => pallet::BLACK,
+        1 => pallet::RED,
+        2 => pallet::GREEN,
+        3 => pallet::YELLOW,
+        4 => pallet::BLUE,
+        5 => pallet::MAGENTA,
+        6 => pallet::CYAN,
+        7 => pallet::WHITE,
+        8 => pallet::BRIGHT_BLACK,
+        9 => pallet::BRIGHT_RED,
+        10 => pallet::BRIGHT_GREEN,
+        11 => pallet::BRIGHT_YELLOW,
+        12 => pallet::BRIGHT_BLUE,
+        13 => pallet::BRIGHT_MAGENTA,
+        14 => pallet::BRIGHT_CYAN,
+        _ => pallet::BRIGHT_WHITE,
+    }
+}
+
+fn rgb(red: u8, green: u8, blue: u8) -> Color {
+    Color {
+        red,
+        green,
+        blue,
+        transparent: 255,
+    }
+}
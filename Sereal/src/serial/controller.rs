@@ -1,12 +1,11 @@
-use super::types::BaudRate;
+use super::types::{BaudRate, ReceivedData, SentData};
 use getset::{Getters, MutGetters};
 use serialport;
 use std::sync::Mutex;
 use std::sync::atomic::Ordering;
 use std::sync::{Arc, atomic::AtomicBool, mpsc};
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
-use std::u32;
+use std::time::{Duration, Instant};
 
 #[derive(Getters, MutGetters)]
 pub struct Controller {
@@ -18,7 +17,9 @@ pub struct Controller {
     baud_rate: BaudRate,
     is_running_thread: Arc<AtomicBool>,
     is_available_port: Arc<Mutex<Option<bool>>>, // ポートとのアクセスの可否と未試行を区別するためにOptionで宣言
-    pub receiver: Option<mpsc::Receiver<String>>, // とりあえず
+    pub receiver: Option<mpsc::Receiver<ReceivedData>>, // 受信したバイト列
+    transmitter: Option<mpsc::Sender<Vec<u8>>>,  // 送信するバイト列
+    pub sent: Option<mpsc::Receiver<SentData>>,  // 書き込んだバイト列と書き込めなかった理由
     read_thread_handle: Option<JoinHandle<()>>,  // スレッドハンドル
 }
 
@@ -30,6 +31,8 @@ impl Default for Controller {
             is_running_thread: Arc::default(),
             is_available_port: Arc::default(),
             receiver: None,
+            transmitter: None,
+            sent: None,
             read_thread_handle: None,
         }
     }
@@ -46,6 +49,12 @@ impl Controller {
         let (sender, receiver) = mpsc::channel();
         self.receiver = Some(receiver);
 
+        let (transmitter, transmit_receiver) = mpsc::channel();
+        self.transmitter = Some(transmitter);
+
+        let (sent_sender, sent) = mpsc::channel();
+        self.sent = Some(sent);
+
         let port_name = self.port_name.clone();
         let baud_rate = self.baud_rate as u32;
 
@@ -56,6 +65,8 @@ impl Controller {
                 is_running_thread,
                 is_available_port,
                 sender,
+                transmit_receiver,
+                sent_sender,
             );
         });
 
@@ -77,6 +88,8 @@ impl Controller {
         *is_available = None;
 
         self.receiver = None;
+        self.transmitter = None;
+        self.sent = None;
         println!("Disconnected {}", self.port_name);
     }
 
@@ -96,6 +109,16 @@ impl Controller {
     pub fn get_port_name(&self) -> String {
         self.port_name.clone()
     }
+
+    /// 送信するバイト列を通信スレッドに渡す
+    pub fn send(&self, data: Vec<u8>) -> Result<(), std::io::Error> {
+        let not_connected =
+            || std::io::Error::new(std::io::ErrorKind::NotConnected, "port is not connected");
+        match &self.transmitter {
+            Some(transmitter) => transmitter.send(data).map_err(|_| not_connected()),
+            None => Err(not_connected()),
+        }
+    }
 }
 
 fn connection_thread_main(
@@ -103,13 +126,20 @@ fn connection_thread_main(
     baud_rate: u32,
     is_running_thread: Arc<AtomicBool>,
     is_available_port: Arc<Mutex<Option<bool>>>,
-    sender: mpsc::Sender<String>,
+    sender: mpsc::Sender<ReceivedData>,
+    transmit_receiver: mpsc::Receiver<Vec<u8>>,
+    sent_sender: mpsc::Sender<SentData>,
 ) {
     const RETRY_INTERVAL_MS: u64 = 500;
+    // 送信バッファが空くのを待つ時間。0 のままだとバッファが埋まった時点で書き込みが失敗する
+    const WRITE_TIMEOUT_MS: u64 = 100;
     let retry_interval = Duration::from_millis(RETRY_INTERVAL_MS);
 
     while is_running_thread.load(Ordering::Relaxed) {
-        let mut port = match serialport::new(&port_name, baud_rate).open() {
+        let mut port = match serialport::new(&port_name, baud_rate)
+            .timeout(Duration::from_millis(WRITE_TIMEOUT_MS))
+            .open()
+        {
             Ok(p) => {
                 let mut is_available = is_available_port.lock().unwrap();
                 *is_available = Some(true);
@@ -117,6 +147,17 @@ fn connection_thread_main(
                 p // 開いたポートを返す
             }
             Err(_) => {
+                // 接続できていない間に送ろうとしたデータは捨てる
+                for data in transmit_receiver.try_iter() {
+                    let _ = sent_sender.send(SentData {
+                        time: chrono::Local::now(),
+                        bytes: Vec::new(),
+                        error: Some(format!(
+                            "Dropped {} bytes: {port_name} is not open",
+                            data.len()
+                        )),
+                    });
+                }
                 thread::sleep(retry_interval);
                 let mut is_available = is_available_port.lock().unwrap();
                 *is_available = Some(false);
@@ -131,14 +172,32 @@ fn connection_thread_main(
                 matches!(*guard, Some(true))
             }
         } {
+            for data in transmit_receiver.try_iter() {
+                let (written, result) = write_fully(port.as_mut(), &data, &is_running_thread);
+                let error = result
+                    .err()
+                    .map(|e| format!("Sent {written} of {} bytes, then failed: {e}", data.len()));
+                let sent = SentData {
+                    time: chrono::Local::now(),
+                    bytes: data[..written].to_vec(),
+                    error,
+                };
+                if sent_sender.send(sent).is_err() {
+                    break;
+                }
+            }
+
             match port.bytes_to_read() {
                 Ok(bytes_to_read) if 0 < bytes_to_read => {
                     let mut receive_buffer = vec![0; bytes_to_read as usize];
                     match port.read(&mut receive_buffer) {
                         Ok(got_bytes) => {
-                            let received =
-                                String::from_utf8_lossy(&receive_buffer[..got_bytes]).to_string();
-                            if sender.send(received).is_err() {
+                            receive_buffer.truncate(got_bytes);
+                            let data = ReceivedData {
+                                time: chrono::Local::now(),
+                                bytes: receive_buffer,
+                            };
+                            if sender.send(data).is_err() {
                                 break;
                             };
                         }
@@ -169,3 +228,37 @@ fn connection_thread_main(
         }
     }
 }
+
+/// バイト列をすべて書き込む。書き込めたバイト数と結果を返す
+///
+/// 送信バッファが埋まっている間は、書き込みが進まなくなるまで待ち直す。
+fn write_fully(
+    port: &mut dyn serialport::SerialPort,
+    data: &[u8],
+    is_running_thread: &AtomicBool,
+) -> (usize, std::io::Result<()>) {
+    // 書き込みがまったく進まないまま、この時間が過ぎたら諦める
+    const STALL_TIMEOUT: Duration = Duration::from_secs(2);
+
+    let mut written = 0;
+    let mut last_progress = Instant::now();
+    while written < data.len() {
+        match port.write(&data[written..]) {
+            Ok(0) => {
+                let error = std::io::Error::from(std::io::ErrorKind::WriteZero);
+                return (written, Err(error));
+            }
+            Ok(count) => {
+                written += count;
+                last_progress = Instant::now();
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e)
+                if e.kind() == std::io::ErrorKind::TimedOut
+                    && last_progress.elapsed() < STALL_TIMEOUT
+                    && is_running_thread.load(Ordering::Relaxed) => {}
+            Err(e) => return (written, Err(e)),
+        }
+    }
+    (written, Ok(()))
+}
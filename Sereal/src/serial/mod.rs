@@ -4,3 +4,5 @@ pub mod types;
 pub mod utils;
 
 pub use types::BaudRate;
+pub use types::ReceivedData;
+pub use types::SentData;
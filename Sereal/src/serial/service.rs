@@ -39,14 +39,24 @@ impl SerialService {
         self.controllers.contains_key(port_name)
             && self
                 .get_controller(port_name)
-                .map_or(false, |controller| controller.is_activate())
+                .is_some_and(|controller| controller.is_activate())
     }
 
     pub fn is_physical_connected(&self, port_name: &str) -> bool {
         self.controllers.contains_key(port_name)
             && self
                 .get_controller(port_name)
-                .map_or(false, |controller| controller.is_physical_connected())
+                .is_some_and(|controller| controller.is_physical_connected())
+    }
+
+    pub fn send(&self, port_name: &str, data: Vec<u8>) -> Result<(), std::io::Error> {
+        match self.get_controller(port_name) {
+            Some(controller) => controller.send(data),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                "port is not connected",
+            )),
+        }
     }
 
     // TODO: 将来的に非公開にする
@@ -59,13 +69,11 @@ impl SerialService {
         all_ports
             .into_iter()
             .filter(|port| {
-                if let Some(self_port) = self_port_name {
-                    if self_port == port {
-                        return true;
-                    }
+                if self_port_name.is_some_and(|self_port| self_port == port) {
+                    return true;
                 }
                 // 接続済みのポートはリストから除外する
-                !self.is_connected(&port)
+                !self.is_connected(port)
             })
             .collect()
     }
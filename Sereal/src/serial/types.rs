@@ -1,3 +1,4 @@
+use chrono::{DateTime, Local};
 use std::fmt;
 
 #[derive(Default, Debug, PartialEq, Clone, Copy)]
@@ -24,3 +25,20 @@ impl fmt::Display for BaudRate {
         write!(f, "{}", rate)
     }
 }
+
+/// 一度に受信したバイト列と、通信スレッドで受信した時刻
+#[derive(Debug, Clone)]
+pub struct ReceivedData {
+    pub time: DateTime<Local>,
+    pub bytes: Vec<u8>,
+}
+
+/// 通信スレッドが実際に書き込んだバイト列
+///
+/// 途中で書き込めなくなったときは、書き込めた分と理由を返す。
+#[derive(Debug, Clone)]
+pub struct SentData {
+    pub time: DateTime<Local>,
+    pub bytes: Vec<u8>,
+    pub error: Option<String>,
+}
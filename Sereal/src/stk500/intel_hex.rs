@@ -0,0 +1,225 @@
+use crate::hex;
+
+/// 書き込むフラッシュの内容
+///
+/// start から連続したバイト列で、HEX ファイルに書かれていない隙間は 0xFF で埋める。
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlashImage {
+    pub start: u32,
+    pub data: Vec<u8>,
+}
+
+impl FlashImage {
+    /// 最後のバイトの次のアドレス
+    pub fn end(&self) -> u32 {
+        self.start + self.data.len() as u32
+    }
+}
+
+/// 隙間を埋めても扱える大きさ。AVR のフラッシュはこれより小さい
+const MAX_IMAGE_SIZE: u32 = 1024 * 1024;
+
+const RECORD_DATA: u8 = 0x00;
+const RECORD_END_OF_FILE: u8 = 0x01;
+const RECORD_EXTENDED_SEGMENT_ADDRESS: u8 = 0x02;
+const RECORD_START_SEGMENT_ADDRESS: u8 = 0x03;
+const RECORD_EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+const RECORD_START_LINEAR_ADDRESS: u8 = 0x05;
+
+/// Intel HEX のテキストを読み、フラッシュの内容にする
+pub fn parse(text: &str) -> Result<FlashImage, String> {
+    let mut chunks: Vec<(u32, Vec<u8>)> = Vec::new();
+    let mut base: u32 = 0;
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: &str| format!("Line {}: {message}", index + 1);
+        let Some(hex) = line.strip_prefix(':') else {
+            return Err(error("A record must start with ':'"));
+        };
+        if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(error("Invalid hex digit"));
+        }
+        if !hex.len().is_multiple_of(2) {
+            return Err(error("Odd number of hex digits"));
+        }
+        let bytes = hex::decode(hex).ok_or_else(|| error("Invalid hex digit"))?;
+        if bytes.len() < 5 || bytes.len() != usize::from(bytes[0]) + 5 {
+            return Err(error("Record length does not match its byte count"));
+        }
+        if bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) != 0 {
+            return Err(error("Checksum mismatch"));
+        }
+
+        let offset = u32::from(u16::from_be_bytes([bytes[1], bytes[2]]));
+        let record_type = bytes[3];
+        let data = &bytes[4..bytes.len() - 1];
+        match record_type {
+            // 長さ 0 のデータレコードは書き込む範囲に含めない
+            RECORD_DATA if data.is_empty() => {}
+            RECORD_DATA => {
+                let address = base
+                    .checked_add(offset)
+                    .filter(|address| address.checked_add(data.len() as u32).is_some())
+                    .ok_or_else(|| error("Data extends beyond the 32-bit address space"))?;
+                chunks.push((address, data.to_vec()));
+            }
+            RECORD_END_OF_FILE => break,
+            RECORD_EXTENDED_SEGMENT_ADDRESS | RECORD_EXTENDED_LINEAR_ADDRESS => {
+                let [high, low] = data else {
+                    return Err(error("Address record must have 2 bytes of data"));
+                };
+                let value = u32::from(u16::from_be_bytes([*high, *low]));
+                base = if record_type == RECORD_EXTENDED_SEGMENT_ADDRESS {
+                    value << 4
+                } else {
+                    value << 16
+                };
+            }
+            // 実行開始アドレスはブートローダーが決めるため使わない
+            RECORD_START_SEGMENT_ADDRESS | RECORD_START_LINEAR_ADDRESS => {}
+            _ => return Err(error(&format!("Unknown record type {record_type:02X}"))),
+        }
+    }
+
+    let start = chunks
+        .iter()
+        .map(|(address, _)| *address)
+        .min()
+        .ok_or("The file has no data records")?;
+    let end = chunks
+        .iter()
+        .map(|(address, data)| address + data.len() as u32)
+        .max()
+        .unwrap_or(start);
+    if MAX_IMAGE_SIZE < end - start {
+        return Err(format!(
+            "The data spans 0x{start:X}..0x{end:X}, which is too large for a flash image"
+        ));
+    }
+    let mut image = vec![0xFF; (end - start) as usize];
+    for (address, data) in chunks {
+        let offset = (address - start) as usize;
+        image[offset..offset + data.len()].copy_from_slice(&data);
+    }
+    Ok(FlashImage { start, data: image })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// チェックサムを付けたレコード
+    fn record(address: u16, record_type: u8, data: &[u8]) -> String {
+        let mut bytes = vec![data.len() as u8];
+        bytes.extend(address.to_be_bytes());
+        bytes.push(record_type);
+        bytes.extend(data);
+        let sum = bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        bytes.push(sum.wrapping_neg());
+        let digits: String = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+        format!(":{digits}\n")
+    }
+
+    const EOF: &str = ":00000001FF\n";
+
+    #[test]
+    fn parses_data_records() {
+        let text = format!(
+            "{}{}{EOF}",
+            record(0x0100, RECORD_DATA, &[1, 2, 3]),
+            record(0x0103, RECORD_DATA, &[4])
+        );
+        assert_eq!(
+            parse(&text),
+            Ok(FlashImage {
+                start: 0x0100,
+                data: vec![1, 2, 3, 4],
+            })
+        );
+    }
+
+    #[test]
+    fn fills_gaps() {
+        let text = format!(
+            "{}{}{EOF}",
+            record(0x0004, RECORD_DATA, &[0xAA]),
+            record(0x0000, RECORD_DATA, &[0x55, 0x66])
+        );
+        let image = parse(&text).unwrap();
+        assert_eq!(image.start, 0);
+        assert_eq!(image.data, [0x55, 0x66, 0xFF, 0xFF, 0xAA]);
+        assert_eq!(image.end(), 5);
+    }
+
+    #[test]
+    fn applies_extended_addresses() {
+        let text = format!(
+            "{}{}{}{}{EOF}",
+            record(0, RECORD_EXTENDED_LINEAR_ADDRESS, &[0x00, 0x01]),
+            record(0x0010, RECORD_DATA, &[1]),
+            record(0, RECORD_EXTENDED_SEGMENT_ADDRESS, &[0x10, 0x00]),
+            record(0x0020, RECORD_DATA, &[2])
+        );
+        let image = parse(&text).unwrap();
+        assert_eq!(image.start, 0x1_0010);
+        assert_eq!(image.end(), 0x1_0021);
+        assert_eq!(image.data[0x10], 2);
+    }
+
+    #[test]
+    fn stops_at_end_of_file() {
+        let text = format!(
+            "{}{EOF}{}",
+            record(0, RECORD_DATA, &[1]),
+            record(0x10, RECORD_DATA, &[2])
+        );
+        assert_eq!(parse(&text).unwrap().data, [1]);
+        assert_eq!(parse(EOF), Err("The file has no data records".to_string()));
+    }
+
+    #[test]
+    fn ignores_empty_data_records() {
+        let text = format!(
+            "{}{}{EOF}",
+            record(0x0000, RECORD_DATA, &[]),
+            record(0x0100, RECORD_DATA, &[1])
+        );
+        assert_eq!(parse(&text).unwrap().start, 0x0100);
+    }
+
+    #[test]
+    fn rejects_malformed_records() {
+        assert_eq!(
+            parse(":0300000001020301\n"),
+            Err("Line 1: Checksum mismatch".to_string())
+        );
+        assert_eq!(
+            parse("00000001FF\n"),
+            Err("Line 1: A record must start with ':'".to_string())
+        );
+        assert_eq!(
+            parse(":0é0\n"),
+            Err("Line 1: Invalid hex digit".to_string())
+        );
+        assert_eq!(
+            parse(":0000000\n"),
+            Err("Line 1: Odd number of hex digits".to_string())
+        );
+        assert_eq!(
+            parse(":0200000001FD\n"),
+            Err("Line 1: Record length does not match its byte count".to_string())
+        );
+        let overflow = format!(
+            "{}{}",
+            record(0, RECORD_EXTENDED_LINEAR_ADDRESS, &[0xFF, 0xFF]),
+            record(0xFFFF, RECORD_DATA, &[1, 2])
+        );
+        assert_eq!(
+            parse(&overflow),
+            Err("Line 2: Data extends beyond the 32-bit address space".to_string())
+        );
+    }
+}
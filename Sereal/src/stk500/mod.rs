@@ -0,0 +1,7 @@
+pub mod intel_hex;
+pub mod programmer;
+pub mod transport;
+pub mod upload;
+
+pub use intel_hex::FlashImage;
+pub use upload::{UploadEvent, UploadJob};
@@ -0,0 +1,512 @@
+use super::intel_hex::FlashImage;
+use super::transport::Transport;
+use std::time::{Duration, Instant};
+
+// STK500 version 1 の応答
+const STK_OK: u8 = 0x10;
+const STK_FAILED: u8 = 0x11;
+const STK_INSYNC: u8 = 0x14;
+const STK_NOSYNC: u8 = 0x15;
+const CRC_EOP: u8 = 0x20;
+
+// Optiboot が受け付けるコマンド
+const CMD_GET_SYNC: u8 = 0x30;
+const CMD_GET_PARAMETER: u8 = 0x41;
+const CMD_ENTER_PROGMODE: u8 = 0x50;
+const CMD_LEAVE_PROGMODE: u8 = 0x51;
+const CMD_LOAD_ADDRESS: u8 = 0x55;
+const CMD_PROG_PAGE: u8 = 0x64;
+const CMD_READ_PAGE: u8 = 0x74;
+const CMD_READ_SIGN: u8 = 0x75;
+
+const PARM_SW_MAJOR: u8 = 0x81;
+const PARM_SW_MINOR: u8 = 0x82;
+
+/// フラッシュのメモリ種別
+const MEMORY_FLASH: u8 = b'F';
+
+/// リセット後にブートローダーと同期を試みる回数と、1 回の待ち時間
+const SYNC_ATTEMPTS: usize = 10;
+const SYNC_TIMEOUT: Duration = Duration::from_millis(200);
+/// 同期した後のコマンドの応答を待つ時間
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// 書き込めるマイコン
+#[derive(Debug, PartialEq)]
+pub struct Device {
+    pub name: &'static str,
+    pub signature: [u8; 3],
+    pub flash_size: u32,
+    pub page_size: usize,
+    /// フラッシュの末尾にある Optiboot の大きさ。スケッチはここに書けない
+    pub boot_size: u32,
+}
+
+pub const DEVICES: &[Device] = &[
+    Device {
+        name: "ATmega328P",
+        signature: [0x1E, 0x95, 0x0F],
+        flash_size: 32 * 1024,
+        page_size: 128,
+        boot_size: 512,
+    },
+    Device {
+        name: "ATmega328",
+        signature: [0x1E, 0x95, 0x14],
+        flash_size: 32 * 1024,
+        page_size: 128,
+        boot_size: 512,
+    },
+    Device {
+        name: "ATmega168",
+        signature: [0x1E, 0x94, 0x06],
+        flash_size: 16 * 1024,
+        page_size: 128,
+        boot_size: 512,
+    },
+    Device {
+        name: "ATmega168P",
+        signature: [0x1E, 0x94, 0x0B],
+        flash_size: 16 * 1024,
+        page_size: 128,
+        boot_size: 512,
+    },
+    Device {
+        name: "ATmega8",
+        signature: [0x1E, 0x93, 0x07],
+        flash_size: 8 * 1024,
+        page_size: 64,
+        boot_size: 512,
+    },
+    Device {
+        name: "ATmega644P",
+        signature: [0x1E, 0x96, 0x0A],
+        flash_size: 64 * 1024,
+        page_size: 256,
+        boot_size: 1024,
+    },
+    Device {
+        name: "ATmega1284P",
+        signature: [0x1E, 0x97, 0x05],
+        flash_size: 128 * 1024,
+        page_size: 256,
+        boot_size: 1024,
+    },
+];
+
+pub fn find_device(signature: [u8; 3]) -> Option<&'static Device> {
+    DEVICES.iter().find(|device| device.signature == signature)
+}
+
+/// 書き込みの段階
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Stage {
+    Writing,
+    Verifying,
+}
+
+/// 書き込みの進み具合
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Progress {
+    pub stage: Stage,
+    pub bytes_done: usize,
+    pub bytes_total: usize,
+}
+
+/// 書き込みの結果
+#[derive(Debug, Clone, PartialEq)]
+pub struct UploadSummary {
+    pub device: &'static str,
+    /// Optiboot の版 (major, minor)
+    pub bootloader_version: (u8, u8),
+    pub bytes: usize,
+}
+
+/// STK500 version 1 で Optiboot と話す
+pub struct Programmer<T: Transport> {
+    transport: T,
+}
+
+impl<T: Transport> Programmer<T> {
+    pub fn new(transport: T) -> Self {
+        Self { transport }
+    }
+
+    /// ボードをリセットし、書き込み、読み返して確かめ、スケッチを起動する
+    pub fn upload(
+        &mut self,
+        image: &FlashImage,
+        mut on_progress: impl FnMut(Progress),
+    ) -> Result<UploadSummary, String> {
+        self.sync()?;
+        let bootloader_version = (
+            self.get_parameter(PARM_SW_MAJOR)?,
+            self.get_parameter(PARM_SW_MINOR)?,
+        );
+        self.command(&[CMD_ENTER_PROGMODE], 0)?;
+        let signature = self.read_signature()?;
+        let Some(device) = find_device(signature) else {
+            let _ = self.command(&[CMD_LEAVE_PROGMODE], 0);
+            return Err(format!(
+                "Unknown device signature {:02X} {:02X} {:02X}",
+                signature[0], signature[1], signature[2]
+            ));
+        };
+        let available = device.flash_size - device.boot_size;
+        if available < image.end() {
+            let _ = self.command(&[CMD_LEAVE_PROGMODE], 0);
+            return Err(format!(
+                "The sketch ends at 0x{:X}, but the {} has room for 0x{available:X} bytes",
+                image.end(),
+                device.name
+            ));
+        }
+
+        let pages = pages(image, device.page_size);
+        let bytes_total = pages.len() * device.page_size;
+        for (index, (address, page)) in pages.iter().enumerate() {
+            self.load_address(*address)?;
+            let mut request = vec![CMD_PROG_PAGE];
+            request.extend((page.len() as u16).to_be_bytes());
+            request.push(MEMORY_FLASH);
+            request.extend(page);
+            self.command(&request, 0)
+                .map_err(|e| format!("Writing 0x{address:04X}: {e}"))?;
+            on_progress(Progress {
+                stage: Stage::Writing,
+                bytes_done: (index + 1) * device.page_size,
+                bytes_total,
+            });
+        }
+        for (index, (address, page)) in pages.iter().enumerate() {
+            self.load_address(*address)?;
+            let mut request = vec![CMD_READ_PAGE];
+            request.extend((page.len() as u16).to_be_bytes());
+            request.push(MEMORY_FLASH);
+            let read = self
+                .command(&request, page.len())
+                .map_err(|e| format!("Reading 0x{address:04X}: {e}"))?;
+            if let Some(offset) = page.iter().zip(&read).position(|(a, b)| a != b) {
+                return Err(format!(
+                    "Verification failed at 0x{:04X}: wrote {:02X}, read {:02X}",
+                    *address as usize + offset,
+                    page[offset],
+                    read[offset]
+                ));
+            }
+            on_progress(Progress {
+                stage: Stage::Verifying,
+                bytes_done: (index + 1) * device.page_size,
+                bytes_total,
+            });
+        }
+        // Optiboot はプログラミングモードを抜けるとスケッチを起動する
+        self.command(&[CMD_LEAVE_PROGMODE], 0)?;
+
+        Ok(UploadSummary {
+            device: device.name,
+            bootloader_version,
+            bytes: image.data.len(),
+        })
+    }
+
+    /// リセットして、ブートローダーが応答するまで同期を試みる
+    fn sync(&mut self) -> Result<(), String> {
+        self.transport
+            .pulse_reset()
+            .map_err(|e| format!("Failed to reset the board: {e}"))?;
+        for _ in 0..SYNC_ATTEMPTS {
+            // リセット前にスケッチが送っていたデータを捨てる
+            self.transport.clear_input().map_err(|e| e.to_string())?;
+            self.transport
+                .write(&[CMD_GET_SYNC, CRC_EOP])
+                .map_err(|e| e.to_string())?;
+            if let Ok(reply) = self.read_exact(2, SYNC_TIMEOUT)
+                && reply == [STK_INSYNC, STK_OK]
+            {
+                return Ok(());
+            }
+        }
+        Err("The bootloader did not respond. Check the port, the bootloader baud rate and that the board runs Optiboot".to_string())
+    }
+
+    fn get_parameter(&mut self, parameter: u8) -> Result<u8, String> {
+        Ok(self.command(&[CMD_GET_PARAMETER, parameter], 1)?[0])
+    }
+
+    fn read_signature(&mut self) -> Result<[u8; 3], String> {
+        let reply = self.command(&[CMD_READ_SIGN], 3)?;
+        Ok([reply[0], reply[1], reply[2]])
+    }
+
+    /// アドレスはワード単位で送る
+    fn load_address(&mut self, address: u32) -> Result<(), String> {
+        let word = ((address / 2) as u16).to_le_bytes();
+        self.command(&[CMD_LOAD_ADDRESS, word[0], word[1]], 0)?;
+        Ok(())
+    }
+
+    /// コマンドを送り、INSYNC と OK に挟まれた reply_length バイトを返す
+    fn command(&mut self, request: &[u8], reply_length: usize) -> Result<Vec<u8>, String> {
+        let mut bytes = request.to_vec();
+        bytes.push(CRC_EOP);
+        self.transport.write(&bytes).map_err(|e| e.to_string())?;
+        match self.read_exact(1, REPLY_TIMEOUT)?[0] {
+            STK_INSYNC => {}
+            STK_NOSYNC => return Err("Lost sync with the bootloader".to_string()),
+            other => return Err(format!("Unexpected reply {other:02X}")),
+        }
+        let reply = self.read_exact(reply_length, REPLY_TIMEOUT)?;
+        match self.read_exact(1, REPLY_TIMEOUT)?[0] {
+            STK_OK => Ok(reply),
+            STK_FAILED => Err("The bootloader reported a failure".to_string()),
+            other => Err(format!("Unexpected reply {other:02X}")),
+        }
+    }
+
+    fn read_exact(&mut self, length: usize, timeout: Duration) -> Result<Vec<u8>, String> {
+        let deadline = Instant::now() + timeout;
+        let mut bytes = Vec::with_capacity(length);
+        while bytes.len() < length {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err("No reply from the bootloader".to_string());
+            }
+            let mut buffer = vec![0; length - bytes.len()];
+            let read = self
+                .transport
+                .read(&mut buffer, remaining)
+                .map_err(|e| e.to_string())?;
+            bytes.extend_from_slice(&buffer[..read]);
+        }
+        Ok(bytes)
+    }
+}
+
+/// ページ境界に揃えたページの一覧。足りない部分は 0xFF で埋める
+fn pages(image: &FlashImage, page_size: usize) -> Vec<(u32, Vec<u8>)> {
+    let page_size_u32 = page_size as u32;
+    let first = image.start - image.start % page_size_u32;
+    (first..image.end())
+        .step_by(page_size)
+        .map(|address| {
+            let mut page = vec![0xFF; page_size];
+            for (offset, byte) in page.iter_mut().enumerate() {
+                let position = address + offset as u32;
+                if (image.start..image.end()).contains(&position) {
+                    *byte = image.data[(position - image.start) as usize];
+                }
+            }
+            (address, page)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::io;
+
+    /// STK500v1 のコマンドに応答する疑似 Optiboot
+    struct MockOptiboot {
+        signature: [u8; 3],
+        flash: Vec<u8>,
+        /// 応答せずに読み捨てる同期要求の数
+        ignored_syncs: usize,
+        /// 読み返すときに壊すアドレス
+        corrupt_address: Option<usize>,
+        address: usize,
+        replies: VecDeque<u8>,
+        resets: usize,
+        written_pages: Vec<usize>,
+        is_left_progmode: bool,
+    }
+
+    impl MockOptiboot {
+        fn new(signature: [u8; 3]) -> Self {
+            Self {
+                signature,
+                flash: vec![0xFF; 128 * 1024],
+                ignored_syncs: 0,
+                corrupt_address: None,
+                address: 0,
+                replies: VecDeque::new(),
+                resets: 0,
+                written_pages: Vec::new(),
+                is_left_progmode: false,
+            }
+        }
+
+        fn reply(&mut self, data: &[u8]) {
+            self.replies.push_back(STK_INSYNC);
+            self.replies.extend(data);
+            self.replies.push_back(STK_OK);
+        }
+    }
+
+    impl Transport for &mut MockOptiboot {
+        fn pulse_reset(&mut self) -> io::Result<()> {
+            self.resets += 1;
+            Ok(())
+        }
+
+        fn clear_input(&mut self) -> io::Result<()> {
+            self.replies.clear();
+            Ok(())
+        }
+
+        /// Programmer はコマンドを 1 回で書くため、書かれたものを 1 つのコマンドとして扱う
+        fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+            assert_eq!(bytes.last(), Some(&CRC_EOP));
+            let length = |bytes: &[u8]| usize::from(u16::from_be_bytes([bytes[1], bytes[2]]));
+            match bytes[0] {
+                CMD_GET_SYNC if 0 < self.ignored_syncs => self.ignored_syncs -= 1,
+                CMD_GET_SYNC | CMD_ENTER_PROGMODE => self.reply(&[]),
+                CMD_LEAVE_PROGMODE => {
+                    self.is_left_progmode = true;
+                    self.reply(&[]);
+                }
+                CMD_GET_PARAMETER => self.reply(&[if bytes[1] == PARM_SW_MAJOR { 8 } else { 3 }]),
+                CMD_READ_SIGN => self.reply(&self.signature.clone()),
+                CMD_LOAD_ADDRESS => {
+                    self.address = usize::from(u16::from_le_bytes([bytes[1], bytes[2]])) * 2;
+                    self.reply(&[]);
+                }
+                CMD_PROG_PAGE => {
+                    assert_eq!(bytes[3], MEMORY_FLASH);
+                    let data = &bytes[4..4 + length(bytes)];
+                    self.flash[self.address..self.address + data.len()].copy_from_slice(data);
+                    self.written_pages.push(self.address);
+                    self.reply(&[]);
+                }
+                CMD_READ_PAGE => {
+                    let mut data = self.flash[self.address..self.address + length(bytes)].to_vec();
+                    if let Some(address) = self.corrupt_address
+                        && (self.address..self.address + data.len()).contains(&address)
+                    {
+                        data[address - self.address] ^= 0xFF;
+                    }
+                    self.reply(&data);
+                }
+                other => panic!("Unexpected command {other:02X}"),
+            }
+            Ok(())
+        }
+
+        fn read(&mut self, buffer: &mut [u8], timeout: Duration) -> io::Result<usize> {
+            if self.replies.is_empty() {
+                std::thread::sleep(timeout);
+                return Ok(0);
+            }
+            let length = buffer.len().min(self.replies.len());
+            for (slot, byte) in buffer.iter_mut().zip(self.replies.drain(..length)) {
+                *slot = byte;
+            }
+            Ok(length)
+        }
+    }
+
+    const ATMEGA328P: [u8; 3] = [0x1E, 0x95, 0x0F];
+    const ATMEGA1284P: [u8; 3] = [0x1E, 0x97, 0x05];
+
+    fn image(start: u32, length: usize) -> FlashImage {
+        FlashImage {
+            start,
+            data: (0..length).map(|i| (i % 253) as u8).collect(),
+        }
+    }
+
+    fn upload(mock: &mut MockOptiboot, image: &FlashImage) -> Result<UploadSummary, String> {
+        Programmer::new(mock).upload(image, |_| {})
+    }
+
+    #[test]
+    fn writes_and_verifies_pages() {
+        let mut mock = MockOptiboot::new(ATMEGA328P);
+        // ページの途中から始まり、途中で終わる
+        let image = image(0x10, 200);
+        let mut progress = Vec::new();
+        let summary = Programmer::new(&mut mock)
+            .upload(&image, |p| progress.push(p))
+            .unwrap();
+        assert_eq!(
+            summary,
+            UploadSummary {
+                device: "ATmega328P",
+                bootloader_version: (8, 3),
+                bytes: 200,
+            }
+        );
+        assert_eq!(mock.written_pages, [0, 128]);
+        assert_eq!(&mock.flash[0x10..0x10 + 200], &image.data[..]);
+        assert!(mock.flash[..0x10].iter().all(|&byte| byte == 0xFF));
+        assert!(mock.flash[0x10 + 200..256].iter().all(|&byte| byte == 0xFF));
+        assert!(mock.is_left_progmode);
+        assert_eq!(
+            progress.last(),
+            Some(&Progress {
+                stage: Stage::Verifying,
+                bytes_done: 256,
+                bytes_total: 256,
+            })
+        );
+    }
+
+    #[test]
+    fn retries_sync() {
+        let mut mock = MockOptiboot::new(ATMEGA328P);
+        mock.ignored_syncs = 3;
+        assert!(upload(&mut mock, &image(0, 64)).is_ok());
+        assert_eq!(mock.resets, 1);
+        assert_eq!(mock.ignored_syncs, 0);
+    }
+
+    #[test]
+    fn rejects_unknown_signature() {
+        let mut mock = MockOptiboot::new([0x1E, 0x98, 0x01]);
+        let error = upload(&mut mock, &image(0, 64)).unwrap_err();
+        assert_eq!(error, "Unknown device signature 1E 98 01");
+        assert!(mock.written_pages.is_empty());
+        assert!(mock.is_left_progmode);
+    }
+
+    #[test]
+    fn rejects_image_over_bootloader() {
+        let mut mock = MockOptiboot::new(ATMEGA328P);
+        let error = upload(&mut mock, &image(0, 32 * 1024 - 511)).unwrap_err();
+        assert!(error.contains("has room for 0x7E00 bytes"), "{error}");
+        assert!(mock.written_pages.is_empty());
+        assert!(mock.is_left_progmode);
+    }
+
+    #[test]
+    fn reserves_larger_bootloader() {
+        // 1284P の Optiboot は 1 KB を使う
+        let mut mock = MockOptiboot::new(ATMEGA1284P);
+        let error = upload(&mut mock, &image(0, 127 * 1024 + 1)).unwrap_err();
+        assert!(error.contains("has room for 0x1FC00 bytes"), "{error}");
+        assert!(mock.written_pages.is_empty());
+
+        let mut mock = MockOptiboot::new(ATMEGA1284P);
+        assert!(upload(&mut mock, &image(127 * 1024 - 256, 256)).is_ok());
+        assert_eq!(mock.written_pages, [127 * 1024 - 256]);
+    }
+
+    #[test]
+    fn reports_verify_mismatch() {
+        let mut mock = MockOptiboot::new(ATMEGA328P);
+        mock.corrupt_address = Some(0x85);
+        let image = image(0, 300);
+        let error = upload(&mut mock, &image).unwrap_err();
+        assert_eq!(
+            error,
+            format!(
+                "Verification failed at 0x0085: wrote {:02X}, read {:02X}",
+                image.data[0x85],
+                image.data[0x85] ^ 0xFF
+            )
+        );
+        assert!(!mock.is_left_progmode);
+    }
+}
@@ -0,0 +1,65 @@
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+/// ブートローダーとやり取りする経路
+///
+/// 実際のシリアルポートのほか、試験用の疑似ブートローダーに差し替えられる。
+pub trait Transport {
+    /// ボードをリセットしてブートローダーを起動する
+    fn pulse_reset(&mut self) -> io::Result<()>;
+
+    /// 受信済みで読んでいないデータを捨てる
+    fn clear_input(&mut self) -> io::Result<()>;
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()>;
+
+    /// timeout まで待って届いた分を読む。何も届かなければ 0 を返す
+    fn read(&mut self, buffer: &mut [u8], timeout: Duration) -> io::Result<usize>;
+}
+
+/// DTR をリセットに繋いだ Arduino のシリアルポート
+pub struct SerialTransport {
+    port: Box<dyn serialport::SerialPort>,
+}
+
+impl SerialTransport {
+    pub fn open(port_name: &str, baud_rate: u32) -> Result<Self, serialport::Error> {
+        let port = serialport::new(port_name, baud_rate)
+            .timeout(Duration::from_millis(100))
+            .open()?;
+        Ok(Self { port })
+    }
+}
+
+impl Transport for SerialTransport {
+    fn pulse_reset(&mut self) -> io::Result<()> {
+        // avrdude の arduino と同じく、DTR と RTS を一度落としてから上げる。
+        // 上げたときの変化がコンデンサを通してリセットを引く
+        self.port.write_data_terminal_ready(false)?;
+        self.port.write_request_to_send(false)?;
+        std::thread::sleep(Duration::from_millis(250));
+        self.port.write_data_terminal_ready(true)?;
+        self.port.write_request_to_send(true)?;
+        std::thread::sleep(Duration::from_millis(50));
+        Ok(())
+    }
+
+    fn clear_input(&mut self) -> io::Result<()> {
+        self.port.clear(serialport::ClearBuffer::Input)?;
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.port.write_all(bytes)?;
+        self.port.flush()
+    }
+
+    fn read(&mut self, buffer: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        self.port.set_timeout(timeout)?;
+        match self.port.read(buffer) {
+            Ok(length) => Ok(length),
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+}
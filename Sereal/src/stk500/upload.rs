@@ -0,0 +1,71 @@
+use super::intel_hex::FlashImage;
+use super::programmer::{Programmer, Progress, UploadSummary};
+use super::transport::SerialTransport;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+
+/// 書き込み中のスレッドから届く知らせ
+#[derive(Debug, Clone, PartialEq)]
+pub enum UploadEvent {
+    Progress(Progress),
+    Finished(Result<UploadSummary, String>),
+}
+
+/// 別スレッドでポートを開いて書き込む
+///
+/// 書き込みは数秒かかり、応答を待つ間 UI を止めないようにする。
+pub struct UploadJob {
+    receiver: mpsc::Receiver<UploadEvent>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl UploadJob {
+    pub fn start(port_name: &str, baud_rate: u32, image: FlashImage) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let port_name = port_name.to_string();
+        let handle = thread::spawn(move || {
+            let result = match SerialTransport::open(&port_name, baud_rate) {
+                Ok(transport) => Programmer::new(transport).upload(&image, |progress| {
+                    let _ = sender.send(UploadEvent::Progress(progress));
+                }),
+                Err(e) => Err(format!("Failed to open {port_name}: {e}")),
+            };
+            let _ = sender.send(UploadEvent::Finished(result));
+        });
+        Self {
+            receiver,
+            handle: Some(handle),
+        }
+    }
+
+    /// 届いた知らせを取り出す。Finished の後はポートが閉じている
+    pub fn try_events(&mut self) -> Vec<UploadEvent> {
+        let mut events = Vec::new();
+        loop {
+            match self.receiver.try_recv() {
+                Ok(event) => events.push(event),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    // Finished を送らずにスレッドが終わった
+                    if self.handle.is_some() {
+                        events.push(UploadEvent::Finished(Err(
+                            "The upload stopped unexpectedly".to_string(),
+                        )));
+                    }
+                    break;
+                }
+            }
+        }
+        if events
+            .iter()
+            .any(|event| matches!(event, UploadEvent::Finished(_)))
+            && let Some(handle) = self.handle.take()
+        {
+            // ポートを閉じ終えてから再接続できるように、スレッドの終了を待つ
+            if handle.join().is_err() {
+                eprintln!("Upload thread panicked");
+            }
+        }
+        events
+    }
+}
@@ -0,0 +1,998 @@
+use super::screen::{Cell, Cursor, Row, Screen, Scrollback, WriteModes};
+use crate::ansi_formatter::osc::{OscCommand, parse_osc};
+use crate::ansi_formatter::parser::{AnsiEvent, AnsiParser, CsiSequence};
+use crate::ansi_formatter::text_style::{StyleOptions, TextStyle};
+use eframe::egui::text::LayoutJob;
+use getset::{Getters, MutGetters};
+use std::collections::HashMap;
+
+const DEFAULT_COLUMNS: usize = 80;
+const DEFAULT_ROWS: usize = 24;
+const TAB_WIDTH: usize = 8;
+const SCROLLBACK_MAX_LINES: usize = 10_000;
+/// リンク先がこの数に達したら、どのセルからも参照されていないものを捨てる
+const LINKS_PRUNE_MIN: usize = 256;
+
+/// 文字集合 (G0/G1) の指定
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+enum Charset {
+    #[default]
+    Ascii,
+    /// 罫線などを描く DEC Special Graphics
+    DecSpecialGraphics,
+}
+
+/// DECSC で保存するカーソルの状態
+#[derive(Debug, Copy, Clone, Default)]
+struct SavedCursor {
+    cursor: Cursor,
+    style: TextStyle,
+    is_origin_mode: bool,
+    charsets: [Charset; 2],
+    active_charset: usize,
+}
+
+/// セルから番号で参照するリンク先 (OSC 8)
+///
+/// 同じ URL には同じ番号を割り当てる。捨てた番号は次の URL に使い回す。
+#[derive(Clone, Default)]
+struct Links {
+    urls: Vec<Option<String>>,
+    ids: HashMap<String, usize>,
+    free_ids: Vec<usize>,
+}
+
+impl Links {
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn get(&self, id: usize) -> Option<&str> {
+        self.urls.get(id)?.as_deref()
+    }
+
+    fn intern(&mut self, url: String) -> usize {
+        if let Some(&id) = self.ids.get(&url) {
+            return id;
+        }
+        let id = match self.free_ids.pop() {
+            Some(id) => {
+                self.urls[id] = Some(url.clone());
+                id
+            }
+            None => {
+                self.urls.push(Some(url.clone()));
+                self.urls.len() - 1
+            }
+        };
+        self.ids.insert(url, id);
+        id
+    }
+
+    /// is_referenced で参照されていない番号のリンク先を捨てる
+    fn retain(&mut self, is_referenced: &[bool]) {
+        for (id, url) in self.urls.iter_mut().enumerate() {
+            if !is_referenced[id]
+                && let Some(url) = url.take()
+            {
+                self.ids.remove(&url);
+                self.free_ids.push(id);
+            }
+        }
+    }
+}
+
+/// 端末の回線制御の設定
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LineDiscipline {
+    /// LF を受信したら行頭にも戻る (LF のみで改行するデバイス向け)
+    pub is_lf_implies_cr: bool,
+}
+
+impl Default for LineDiscipline {
+    fn default() -> Self {
+        Self {
+            is_lf_implies_cr: true,
+        }
+    }
+}
+
+/// VT100/xterm 互換の端末エミュレータ
+///
+/// 受信したバイト列を解釈して文字グリッドを更新する。
+#[derive(Clone, Getters, MutGetters)]
+pub struct Terminal {
+    parser: AnsiParser,
+    primary: Screen,
+    alternate: Screen,
+    is_alternate_screen: bool,
+    scrollback: Scrollback,
+    style: TextStyle,
+    saved_cursor: SavedCursor,
+    is_autowrap: bool,
+    is_insert_mode: bool,
+    is_origin_mode: bool,
+    is_newline_mode: bool,
+    #[get = "pub"]
+    is_cursor_visible: bool,
+    /// カーソルキーをアプリケーションモード (ESC O A など) で送る (DECCKM)
+    #[get = "pub"]
+    is_application_cursor_keys: bool,
+    tab_stops: Vec<bool>,
+    charsets: [Charset; 2],
+    active_charset: usize,
+    last_char: Option<char>,
+    #[get = "pub"]
+    #[get_mut = "pub"]
+    line_discipline: LineDiscipline,
+    links: Links,
+    links_prune_at: usize, // リンク先がこの数に達したら参照されていないものを捨てる
+    link: Option<usize>,   // 書き込む文字に付けるリンクの番号
+}
+
+impl Default for Terminal {
+    fn default() -> Self {
+        Self {
+            parser: AnsiParser::default(),
+            primary: Screen::new(DEFAULT_COLUMNS, DEFAULT_ROWS),
+            alternate: Screen::new(DEFAULT_COLUMNS, DEFAULT_ROWS),
+            is_alternate_screen: false,
+            scrollback: Scrollback::new(SCROLLBACK_MAX_LINES),
+            style: TextStyle::default(),
+            saved_cursor: SavedCursor::default(),
+            is_autowrap: true,
+            is_insert_mode: false,
+            is_origin_mode: false,
+            is_newline_mode: false,
+            is_cursor_visible: true,
+            is_application_cursor_keys: false,
+            tab_stops: default_tab_stops(DEFAULT_COLUMNS),
+            charsets: [Charset::Ascii; 2],
+            active_charset: 0,
+            last_char: None,
+            line_discipline: LineDiscipline::default(),
+            links: Links::default(),
+            links_prune_at: LINKS_PRUNE_MIN,
+            link: None,
+        }
+    }
+}
+
+impl Terminal {
+    pub fn feed(&mut self, bytes: &[u8]) {
+        for event in self.parser.feed(bytes) {
+            match event {
+                AnsiEvent::Text(text) => {
+                    for ch in text.chars() {
+                        self.print(ch);
+                    }
+                }
+                AnsiEvent::Control(byte) => self.execute(byte),
+                AnsiEvent::Csi(csi) => self.dispatch_csi(&csi),
+                AnsiEvent::Escape {
+                    intermediates,
+                    final_byte,
+                } => self.dispatch_escape(&intermediates, final_byte),
+                AnsiEvent::Osc(data) => self.dispatch_osc(&data),
+                AnsiEvent::InvalidBytes(_) => self.print(char::REPLACEMENT_CHARACTER),
+                AnsiEvent::Ignored => {}
+            }
+        }
+    }
+
+    /// 端末を初期状態に戻す。画面サイズと回線制御の設定は維持する
+    pub fn reset(&mut self) {
+        let (columns, rows) = (self.screen().columns(), self.screen().rows());
+        *self = Self {
+            line_discipline: self.line_discipline,
+            ..Default::default()
+        };
+        self.resize(columns, rows);
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.screen().columns(), self.screen().rows())
+    }
+
+    pub fn resize(&mut self, columns: usize, rows: usize) {
+        let columns = columns.max(1);
+        let rows = rows.max(1);
+        if self.size() == (columns, rows) {
+            return;
+        }
+        self.primary
+            .resize(columns, rows, Some(&mut self.scrollback));
+        self.alternate.resize(columns, rows, None);
+        self.tab_stops = default_tab_stops(columns);
+    }
+
+    /// 画面上のカーソル位置 (行, 列)。0 始まり
+    pub fn cursor_position(&self) -> (usize, usize) {
+        let cursor = self.screen().cursor;
+        (cursor.row, cursor.column)
+    }
+
+    /// 描画対象の行数 (scrollback + 画面)
+    pub fn total_rows(&self) -> usize {
+        self.visible_scrollback_len() + self.screen().rows()
+    }
+
+    /// 描画対象の行のうちカーソルがある行の番号
+    pub fn cursor_row(&self) -> usize {
+        self.visible_scrollback_len() + self.screen().cursor.row
+    }
+
+    /// 描画対象の index 行目を LayoutJob に変換する
+    pub fn layout_row(&self, index: usize, options: &StyleOptions) -> LayoutJob {
+        let cursor_column = (self.is_cursor_visible && index == self.cursor_row())
+            .then_some(self.screen().cursor.column);
+
+        self.row(index)
+            .map(|row| layout_cells(row, cursor_column, options))
+            .unwrap_or_default()
+    }
+
+    /// index 行目の column 列目のセルがリンクならその URL を返す
+    pub fn link_at(&self, index: usize, column: usize) -> Option<&str> {
+        let link = self.row(index)?.get(column)?.link?;
+        self.links.get(link)
+    }
+
+    /// scrollback と画面を通した index 行目
+    fn row(&self, index: usize) -> Option<&Row> {
+        let scrollback_len = self.visible_scrollback_len();
+        if index < scrollback_len {
+            self.scrollback.get(index)
+        } else {
+            let screen = self.screen();
+            (index - scrollback_len < screen.rows()).then(|| screen.row(index - scrollback_len))
+        }
+    }
+
+    fn visible_scrollback_len(&self) -> usize {
+        // 代替画面の表示中は scrollback を表示しない
+        if self.is_alternate_screen {
+            0
+        } else {
+            self.scrollback.len()
+        }
+    }
+
+    fn screen(&self) -> &Screen {
+        if self.is_alternate_screen {
+            &self.alternate
+        } else {
+            &self.primary
+        }
+    }
+
+    fn screen_mut(&mut self) -> &mut Screen {
+        if self.is_alternate_screen {
+            &mut self.alternate
+        } else {
+            &mut self.primary
+        }
+    }
+
+    /// 画面と、押し出された行を送る scrollback を返す
+    fn screen_and_scrollback(&mut self) -> (&mut Screen, Option<&mut Scrollback>) {
+        if self.is_alternate_screen {
+            (&mut self.alternate, None)
+        } else {
+            (&mut self.primary, Some(&mut self.scrollback))
+        }
+    }
+
+    fn write_modes(&self) -> WriteModes {
+        WriteModes {
+            is_autowrap: self.is_autowrap,
+            is_insert: self.is_insert_mode,
+        }
+    }
+
+    fn print(&mut self, ch: char) {
+        let ch = match self.charsets[self.active_charset] {
+            Charset::Ascii => ch,
+            Charset::DecSpecialGraphics => to_dec_special_graphics(ch),
+        };
+        let cell = Cell {
+            ch,
+            style: self.style,
+            link: self.link,
+        };
+        let modes = self.write_modes();
+        let (screen, scrollback) = self.screen_and_scrollback();
+        screen.put_char(cell, &modes, scrollback);
+        self.last_char = Some(ch);
+    }
+
+    fn dispatch_osc(&mut self, data: &[u8]) {
+        // タイトルは AnsiFormatter 側で扱う
+        if let Some(OscCommand::Hyperlink(link)) = parse_osc(data) {
+            if self.links_prune_at <= self.links.len() {
+                self.prune_links();
+            }
+            self.link = link.map(|url| self.links.intern(url));
+        }
+    }
+
+    /// 画面にも scrollback にも残っていないリンク先を捨てる
+    fn prune_links(&mut self) {
+        let mut is_referenced = vec![false; self.links.urls.len()];
+        if let Some(link) = self.link {
+            is_referenced[link] = true;
+        }
+        let screen_rows = [&self.primary, &self.alternate]
+            .into_iter()
+            .flat_map(|screen| (0..screen.rows()).map(|index| screen.row(index)));
+        let scrollback_rows =
+            (0..self.scrollback.len()).filter_map(|index| self.scrollback.get(index));
+        for row in screen_rows.chain(scrollback_rows) {
+            for link in row.iter().filter_map(|cell| cell.link) {
+                is_referenced[link] = true;
+            }
+        }
+        self.links.retain(&is_referenced);
+        // すべて参照されている場合に毎回走査しないよう、次に捨てる数を広げる
+        self.links_prune_at = LINKS_PRUNE_MIN.max(self.links.len() * 2);
+    }
+
+    fn line_feed(&mut self) {
+        let style = self.style;
+        let (screen, scrollback) = self.screen_and_scrollback();
+        screen.line_feed(&style, scrollback);
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            // BS
+            0x08 => {
+                let screen = self.screen_mut();
+                screen.cursor.column = screen.cursor.column.saturating_sub(1);
+                screen.is_wrap_pending = false;
+            }
+            // HT
+            0x09 => self.move_to_next_tab_stop(1),
+            // LF, VT, FF
+            0x0a..=0x0c => {
+                self.line_feed();
+                if self.is_newline_mode || self.line_discipline.is_lf_implies_cr {
+                    self.carriage_return();
+                }
+            }
+            // CR
+            0x0d => self.carriage_return(),
+            // SO, SI
+            0x0e => self.active_charset = 1,
+            0x0f => self.active_charset = 0,
+            // それ以外は何もしない
+            _ => {}
+        }
+    }
+
+    fn carriage_return(&mut self) {
+        let screen = self.screen_mut();
+        screen.cursor.column = 0;
+        screen.is_wrap_pending = false;
+    }
+
+    fn move_to_next_tab_stop(&mut self, count: usize) {
+        let columns = self.screen().columns();
+        let mut column = self.screen().cursor.column;
+        for _ in 0..count {
+            column = (column + 1..columns)
+                .find(|&index| self.tab_stops[index])
+                .unwrap_or(columns - 1);
+        }
+        let screen = self.screen_mut();
+        screen.cursor.column = column;
+        screen.is_wrap_pending = false;
+    }
+
+    fn move_to_previous_tab_stop(&mut self, count: usize) {
+        let mut column = self.screen().cursor.column;
+        for _ in 0..count {
+            column = (0..column)
+                .rev()
+                .find(|&index| self.tab_stops[index])
+                .unwrap_or(0);
+        }
+        let screen = self.screen_mut();
+        screen.cursor.column = column;
+        screen.is_wrap_pending = false;
+    }
+
+    /// 原点モードを考慮してカーソルを移動する (行・列は 0 始まり)
+    fn move_cursor_to(&mut self, row: usize, column: usize) {
+        let is_origin_mode = self.is_origin_mode;
+        let screen = self.screen_mut();
+        if is_origin_mode {
+            let (top, bottom) = screen.scroll_region();
+            screen.move_cursor_to((top + row).min(bottom), column);
+        } else {
+            screen.move_cursor_to(row, column);
+        }
+    }
+
+    /// カーソルを上下に移動する。スクロール領域内にいる場合は領域の端で止まる
+    fn move_cursor_vertically(&mut self, offset: isize) {
+        let screen = self.screen_mut();
+        let (top, bottom) = screen.scroll_region();
+        let Cursor { row, column } = screen.cursor;
+        let (min_row, max_row) = if (top..=bottom).contains(&row) {
+            (top, bottom)
+        } else {
+            (0, screen.rows() - 1)
+        };
+        let row = row.saturating_add_signed(offset).clamp(min_row, max_row);
+        screen.move_cursor_to(row, column);
+    }
+
+    fn move_cursor_horizontally(&mut self, offset: isize) {
+        let screen = self.screen_mut();
+        let Cursor { row, column } = screen.cursor;
+        screen.move_cursor_to(row, column.saturating_add_signed(offset));
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved_cursor = SavedCursor {
+            cursor: self.screen().cursor,
+            style: self.style,
+            is_origin_mode: self.is_origin_mode,
+            charsets: self.charsets,
+            active_charset: self.active_charset,
+        };
+    }
+
+    fn restore_cursor(&mut self) {
+        let saved = self.saved_cursor;
+        self.style = saved.style;
+        self.is_origin_mode = saved.is_origin_mode;
+        self.charsets = saved.charsets;
+        self.active_charset = saved.active_charset;
+        self.screen_mut()
+            .move_cursor_to(saved.cursor.row, saved.cursor.column);
+    }
+
+    fn set_alternate_screen(&mut self, is_enabled: bool, is_clear: bool) {
+        if self.is_alternate_screen == is_enabled {
+            return;
+        }
+        self.is_alternate_screen = is_enabled;
+        if is_enabled && is_clear {
+            let (columns, rows) = self.size();
+            self.alternate = Screen::new(columns, rows);
+        }
+    }
+
+    fn dispatch_csi(&mut self, csi: &CsiSequence) {
+        // 省略時に 1 となるパラメータ
+        let count = |index: usize| usize::from(csi.param_or(index, 1));
+        let style = self.style;
+
+        match (
+            csi.private_marker,
+            csi.intermediates.as_slice(),
+            csi.final_byte,
+        ) {
+            (None, [], b'@') => self.screen_mut().insert_chars(count(0), &style),
+            (None, [], b'A') => self.move_cursor_vertically(-(count(0) as isize)),
+            (None, [], b'B' | b'e') => self.move_cursor_vertically(count(0) as isize),
+            (None, [], b'C' | b'a') => self.move_cursor_horizontally(count(0) as isize),
+            (None, [], b'D') => self.move_cursor_horizontally(-(count(0) as isize)),
+            (None, [], b'E') => {
+                self.move_cursor_vertically(count(0) as isize);
+                self.carriage_return();
+            }
+            (None, [], b'F') => {
+                self.move_cursor_vertically(-(count(0) as isize));
+                self.carriage_return();
+            }
+            (None, [], b'G' | b'`') => {
+                let row = self.screen().cursor.row;
+                self.screen_mut().move_cursor_to(row, count(0) - 1);
+            }
+            (None, [], b'H' | b'f') => self.move_cursor_to(count(0) - 1, count(1) - 1),
+            (None, [], b'I') => self.move_to_next_tab_stop(count(0)),
+            (None, [], b'J') => {
+                let mode = csi.param(0);
+                if mode == 3 {
+                    self.scrollback.clear();
+                } else {
+                    self.screen_mut().erase_in_display(mode, &style);
+                }
+            }
+            (None, [], b'K') => self.screen_mut().erase_in_line(csi.param(0), &style),
+            (None, [], b'L') => self.screen_mut().insert_lines(count(0), &style),
+            (None, [], b'M') => self.screen_mut().delete_lines(count(0), &style),
+            (None, [], b'P') => self.screen_mut().delete_chars(count(0), &style),
+            (None, [], b'S') => {
+                let (screen, scrollback) = self.screen_and_scrollback();
+                screen.scroll_up(count(0), &style, scrollback);
+            }
+            (None, [], b'T') => self.screen_mut().scroll_down(count(0), &style),
+            (None, [], b'X') => self.screen_mut().erase_chars(count(0), &style),
+            (None, [], b'Z') => self.move_to_previous_tab_stop(count(0)),
+            (None, [], b'b') => {
+                if let Some(ch) = self.last_char {
+                    for _ in 0..count(0) {
+                        self.print(ch);
+                    }
+                }
+            }
+            (None, [], b'd') => {
+                let column = self.screen().cursor.column;
+                self.move_cursor_to(count(0) - 1, column);
+            }
+            (None, [], b'g') => match csi.param(0) {
+                0 => {
+                    let column = self.screen().cursor.column;
+                    self.tab_stops[column] = false;
+                }
+                3 => self.tab_stops.fill(false),
+                _ => {}
+            },
+            (None, [], b'h' | b'l') => {
+                let is_set = csi.final_byte == b'h';
+                for group in &csi.params {
+                    match group.first() {
+                        Some(4) => self.is_insert_mode = is_set,
+                        Some(20) => self.is_newline_mode = is_set,
+                        _ => {}
+                    }
+                }
+            }
+            (None, [], b'm') => self.style.apply_graphics_params(&csi.params),
+            (None, [], b'r') => {
+                let rows = self.screen().rows();
+                let top = count(0) - 1;
+                let bottom = usize::from(csi.param_or(1, rows as u16)) - 1;
+                self.screen_mut().set_scroll_region(top, bottom);
+                self.move_cursor_to(0, 0);
+            }
+            (None, [], b's') => self.save_cursor(),
+            (None, [], b'u') => self.restore_cursor(),
+            (Some(b'?'), [], b'h' | b'l') => {
+                let is_set = csi.final_byte == b'h';
+                for group in &csi.params {
+                    self.set_private_mode(group.first().copied().unwrap_or(0), is_set);
+                }
+            }
+            // DECSTR: ソフトリセット
+            (None, [b'!'], b'p') => {
+                self.style = TextStyle::default();
+                self.is_insert_mode = false;
+                self.is_origin_mode = false;
+                self.is_autowrap = true;
+                self.is_cursor_visible = true;
+                self.is_application_cursor_keys = false;
+                self.charsets = [Charset::Ascii; 2];
+                self.active_charset = 0;
+                let rows = self.screen().rows();
+                self.screen_mut().set_scroll_region(0, rows - 1);
+            }
+            // それ以外は何もしない
+            _ => {}
+        }
+    }
+
+    fn set_private_mode(&mut self, mode: u16, is_set: bool) {
+        match mode {
+            1 => self.is_application_cursor_keys = is_set,
+            6 => {
+                self.is_origin_mode = is_set;
+                self.move_cursor_to(0, 0);
+            }
+            7 => self.is_autowrap = is_set,
+            25 => self.is_cursor_visible = is_set,
+            47 => self.set_alternate_screen(is_set, false),
+            1047 => self.set_alternate_screen(is_set, true),
+            1048 => {
+                if is_set {
+                    self.save_cursor();
+                } else {
+                    self.restore_cursor();
+                }
+            }
+            1049 => {
+                if is_set {
+                    self.save_cursor();
+                    self.set_alternate_screen(true, true);
+                } else {
+                    self.set_alternate_screen(false, false);
+                    self.restore_cursor();
+                }
+            }
+            // それ以外は何もしない
+            _ => {}
+        }
+    }
+
+    fn dispatch_escape(&mut self, intermediates: &[u8], final_byte: u8) {
+        let style = self.style;
+        match (intermediates, final_byte) {
+            ([], b'7') => self.save_cursor(),
+            ([], b'8') => self.restore_cursor(),
+            ([], b'D') => self.line_feed(),
+            ([], b'E') => {
+                self.line_feed();
+                self.carriage_return();
+            }
+            ([], b'M') => self.screen_mut().reverse_line_feed(&style),
+            ([], b'H') => {
+                let column = self.screen().cursor.column;
+                self.tab_stops[column] = true;
+            }
+            ([], b'c') => self.reset(),
+            ([b'#'], b'8') => self.screen_mut().fill('E'),
+            ([designator @ (b'(' | b')')], charset) => {
+                let index = usize::from(*designator == b')');
+                self.charsets[index] = match charset {
+                    b'0' => Charset::DecSpecialGraphics,
+                    _ => Charset::Ascii,
+                };
+            }
+            // それ以外は何もしない
+            _ => {}
+        }
+    }
+}
+
+fn default_tab_stops(columns: usize) -> Vec<bool> {
+    (0..columns)
+        .map(|column| column != 0 && column % TAB_WIDTH == 0)
+        .collect()
+}
+
+/// セルの並びを、同じ書式が続く範囲ごとにまとめた LayoutJob に変換する
+fn layout_cells(row: &Row, cursor_column: Option<usize>, options: &StyleOptions) -> LayoutJob {
+    let mut job = LayoutJob::default();
+    let mut text = String::new();
+    let mut current_style: Option<TextStyle> = None;
+
+    for (column, cell) in row.iter().enumerate() {
+        let mut style = cell.style;
+        // リンクの文字には下線を引く
+        style.is_underline |= cell.link.is_some();
+        // カーソル位置は反転して表示する
+        if cursor_column == Some(column) {
+            style.is_inverse = !style.is_inverse;
+        }
+        if let Some(current) = current_style
+            && current != style
+        {
+            job.append(&text, 0.0, current.text_format(options));
+            text.clear();
+        }
+        current_style = Some(style);
+        text.push(cell.ch);
+    }
+    if let Some(style) = current_style {
+        job.append(&text, 0.0, style.text_format(options));
+    }
+
+    job
+}
+
+/// DEC Special Graphics の文字を対応する Unicode の罫線文字などに置き換える
+fn to_dec_special_graphics(ch: char) -> char {
+    match ch {
+        '`' => '◆',
+        'a' => '▒',
+        'b' => '␉',
+        'c' => '␌',
+        'd' => '␍',
+        'e' => '␊',
+        'f' => '°',
+        'g' => '±',
+        'h' => '␤',
+        'i' => '␋',
+        'j' => '┘',
+        'k' => '┐',
+        'l' => '┌',
+        'm' => '└',
+        'n' => '┼',
+        'o' => '⎺',
+        'p' => '⎻',
+        'q' => '─',
+        'r' => '⎼',
+        's' => '⎽',
+        't' => '├',
+        'u' => '┤',
+        'v' => '┴',
+        'w' => '┬',
+        'x' => '│',
+        'y' => '≤',
+        'z' => '≥',
+        '{' => 'π',
+        '|' => '≠',
+        '}' => '£',
+        '~' => '·',
+        _ => ch,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fed(columns: usize, rows: usize, bytes: &[u8]) -> Terminal {
+        let mut terminal = Terminal::default();
+        terminal.resize(columns, rows);
+        terminal.feed(bytes);
+        terminal
+    }
+
+    /// 画面の各行を、行末の空白を除いた文字列にする
+    fn screen_text(terminal: &Terminal) -> Vec<String> {
+        let screen = terminal.screen();
+        (0..screen.rows())
+            .map(|index| row_text(screen.row(index)))
+            .collect()
+    }
+
+    fn row_text(row: &Row) -> String {
+        let text: String = row.iter().map(|cell| cell.ch).collect();
+        text.trim_end().to_string()
+    }
+
+    #[test]
+    fn cursor_movement() {
+        let mut terminal = fed(10, 5, b"\x1b[3;4H");
+        assert_eq!(terminal.cursor_position(), (2, 3));
+        terminal.feed(b"\x1b[A");
+        assert_eq!(terminal.cursor_position(), (1, 3));
+        terminal.feed(b"\x1b[2B");
+        assert_eq!(terminal.cursor_position(), (3, 3));
+        terminal.feed(b"\x1b[5C");
+        assert_eq!(terminal.cursor_position(), (3, 8));
+        terminal.feed(b"\x1b[3D");
+        assert_eq!(terminal.cursor_position(), (3, 5));
+        terminal.feed(b"\x1b[H");
+        assert_eq!(terminal.cursor_position(), (0, 0));
+
+        // 画面の外には出ない
+        terminal.feed(b"\x1b[9A\x1b[9D");
+        assert_eq!(terminal.cursor_position(), (0, 0));
+        terminal.feed(b"\x1b[99;99H");
+        assert_eq!(terminal.cursor_position(), (4, 9));
+        terminal.feed(b"\x1b[9B\x1b[9C");
+        assert_eq!(terminal.cursor_position(), (4, 9));
+    }
+
+    #[test]
+    fn write_at_cursor() {
+        let terminal = fed(10, 3, b"\x1b[2;3Hab\x1b[1;1Hc");
+        assert_eq!(screen_text(&terminal), ["c", "  ab", ""]);
+        assert_eq!(terminal.cursor_position(), (0, 1));
+    }
+
+    #[test]
+    fn erase_in_line() {
+        let line = b"abcdefgh\x1b[1;4H";
+        let erased = |params: &[u8]| {
+            let mut bytes = line.to_vec();
+            bytes.extend_from_slice(b"\x1b[");
+            bytes.extend_from_slice(params);
+            bytes.push(b'K');
+            screen_text(&fed(8, 1, &bytes)).remove(0)
+        };
+        assert_eq!(erased(b""), "abc");
+        assert_eq!(erased(b"0"), "abc");
+        assert_eq!(erased(b"1"), "    efgh");
+        assert_eq!(erased(b"2"), "");
+    }
+
+    #[test]
+    fn erase_in_display() {
+        let screen = b"aaa\r\nbbb\r\nccc\x1b[2;2H";
+        let erased = |params: &[u8]| {
+            let mut bytes = screen.to_vec();
+            bytes.extend_from_slice(b"\x1b[");
+            bytes.extend_from_slice(params);
+            bytes.push(b'J');
+            screen_text(&fed(3, 3, &bytes))
+        };
+        assert_eq!(erased(b""), ["aaa", "b", ""]);
+        assert_eq!(erased(b"1"), ["", "  b", "ccc"]);
+        assert_eq!(erased(b"2"), ["", "", ""]);
+    }
+
+    #[test]
+    fn erase_in_display_3_clears_scrollback() {
+        let mut terminal = fed(3, 2, b"a\nb\nc\nd");
+        assert_eq!(terminal.scrollback.len(), 2);
+        terminal.feed(b"\x1b[3J");
+        assert_eq!(terminal.scrollback.len(), 0);
+        assert_eq!(screen_text(&terminal), ["c", "d"]);
+    }
+
+    #[test]
+    fn autowrap() {
+        let mut terminal = fed(4, 3, b"abcd");
+        // 右端に書いた直後はまだ折り返さない
+        assert_eq!(terminal.cursor_position(), (0, 3));
+        terminal.feed(b"ef");
+        assert_eq!(screen_text(&terminal), ["abcd", "ef", ""]);
+        assert_eq!(terminal.cursor_position(), (1, 2));
+
+        // DECAWM を切ると右端の文字を上書きし続ける
+        let terminal = fed(4, 2, b"\x1b[?7labcdef");
+        assert_eq!(screen_text(&terminal), ["abcf", ""]);
+        assert_eq!(terminal.cursor_position(), (0, 3));
+    }
+
+    #[test]
+    fn wrap_at_bottom_scrolls() {
+        let terminal = fed(2, 2, b"abcdef");
+        assert_eq!(screen_text(&terminal), ["cd", "ef"]);
+        assert_eq!(terminal.scrollback.len(), 1);
+        assert_eq!(row_text(terminal.scrollback.get(0).unwrap()), "ab");
+    }
+
+    #[test]
+    fn scroll_region() {
+        let mut terminal = fed(3, 5, b"1\n2\n3\n4\n5");
+        // 2-4 行目をスクロール領域にすると、カーソルは原点に戻る
+        terminal.feed(b"\x1b[2;4r");
+        assert_eq!(terminal.cursor_position(), (0, 0));
+        terminal.feed(b"\x1b[4;1H\nx");
+        assert_eq!(screen_text(&terminal), ["1", "3", "4", "x", "5"]);
+        // 領域内の行は scrollback に送らない
+        assert_eq!(terminal.scrollback.len(), 0);
+
+        // 逆方向の改行は領域の上端で下にスクロールする
+        terminal.feed(b"\x1b[2;1H\x1bMy");
+        assert_eq!(screen_text(&terminal), ["1", "y", "3", "4", "5"]);
+
+        // カーソル移動は領域の端で止まる
+        terminal.feed(b"\x1b[3;1H\x1b[9B");
+        assert_eq!(terminal.cursor_position(), (3, 0));
+        terminal.feed(b"\x1b[9A");
+        assert_eq!(terminal.cursor_position(), (1, 0));
+    }
+
+    #[test]
+    fn scroll_region_with_origin_mode() {
+        let mut terminal = fed(3, 5, b"\x1b[2;4r\x1b[?6h");
+        assert_eq!(terminal.cursor_position(), (1, 0));
+        terminal.feed(b"\x1b[2;2H");
+        assert_eq!(terminal.cursor_position(), (2, 1));
+        // 領域の外には出ない
+        terminal.feed(b"\x1b[9;1H");
+        assert_eq!(terminal.cursor_position(), (3, 0));
+    }
+
+    #[test]
+    fn invalid_scroll_region_resets_to_full_screen() {
+        let mut terminal = fed(3, 4, b"\x1b[2;4r");
+        assert_eq!(terminal.screen().scroll_region(), (1, 3));
+        terminal.feed(b"\x1b[3;2r");
+        assert_eq!(terminal.screen().scroll_region(), (0, 3));
+        terminal.feed(b"\x1b[2;9r");
+        assert_eq!(terminal.screen().scroll_region(), (0, 3));
+    }
+
+    #[test]
+    fn alternate_screen() {
+        let mut terminal = fed(4, 2, b"main\x1b[1;3H");
+        terminal.feed(b"\x1b[?1049h");
+        assert_eq!(screen_text(&terminal), ["", ""]);
+        terminal.feed(b"\x1b[Halt");
+        assert_eq!(screen_text(&terminal), ["alt", ""]);
+
+        // 代替画面では scrollback を表示も更新もしない
+        terminal.feed(b"\n\n\n");
+        assert_eq!(terminal.scrollback.len(), 0);
+        assert_eq!(terminal.total_rows(), 2);
+
+        // 元の画面とカーソル位置に戻る
+        terminal.feed(b"\x1b[?1049l");
+        assert_eq!(screen_text(&terminal), ["main", ""]);
+        assert_eq!(terminal.cursor_position(), (0, 2));
+
+        // 1049 で入り直すと代替画面は消去される
+        terminal.feed(b"\x1b[?1049h");
+        assert_eq!(screen_text(&terminal), ["", ""]);
+    }
+
+    #[test]
+    fn alternate_screen_47_keeps_contents() {
+        let mut terminal = fed(4, 2, b"\x1b[?47halt\x1b[?47l");
+        assert_eq!(screen_text(&terminal), ["", ""]);
+        terminal.feed(b"\x1b[?47h");
+        assert_eq!(screen_text(&terminal), ["alt", ""]);
+    }
+
+    #[test]
+    fn resize_keeps_cursor_on_screen() {
+        let mut terminal = fed(4, 4, b"a\nb\nc\nd");
+        assert_eq!(terminal.cursor_position(), (3, 1));
+
+        // 縮めるとカーソルより上の行が scrollback に押し出される
+        terminal.resize(2, 2);
+        assert_eq!(screen_text(&terminal), ["c", "d"]);
+        assert_eq!(terminal.cursor_position(), (1, 1));
+        assert_eq!(terminal.scrollback.len(), 2);
+        assert_eq!(terminal.total_rows(), 4);
+
+        // 広げた部分は空白で埋まる
+        terminal.resize(5, 3);
+        assert_eq!(screen_text(&terminal), ["c", "d", ""]);
+        assert_eq!(terminal.size(), (5, 3));
+        assert_eq!(terminal.screen().row(0).len(), 5);
+
+        // 列を縮めるとカーソルは右端に寄る
+        terminal.feed(b"\x1b[1;5H");
+        terminal.resize(3, 3);
+        assert_eq!(terminal.cursor_position(), (0, 2));
+    }
+
+    #[test]
+    fn resize_to_zero_keeps_one_cell() {
+        let mut terminal = fed(4, 4, b"");
+        terminal.resize(0, 0);
+        assert_eq!(terminal.size(), (1, 1));
+    }
+
+    #[test]
+    fn scrollback_is_capped() {
+        let mut terminal = fed(8, 2, b"");
+        for line in 0..SCROLLBACK_MAX_LINES + 10 {
+            terminal.feed(format!("{line}\n").as_bytes());
+        }
+        assert_eq!(terminal.scrollback.len(), SCROLLBACK_MAX_LINES);
+        // 古い行から捨てられる
+        assert_eq!(row_text(terminal.scrollback.get(0).unwrap()), "9");
+        assert_eq!(terminal.total_rows(), SCROLLBACK_MAX_LINES + 2);
+    }
+
+    fn link(url: &str, text: &str) -> Vec<u8> {
+        format!("\x1b]8;;{url}\x1b\\{text}\x1b]8;;\x1b\\").into_bytes()
+    }
+
+    #[test]
+    fn same_url_shares_link() {
+        let mut terminal = fed(8, 2, &link("https://a", "x"));
+        terminal.feed(&link("https://b", "y"));
+        terminal.feed(&link("https://a", "z"));
+        assert_eq!(terminal.link_at(0, 0), Some("https://a"));
+        assert_eq!(terminal.link_at(0, 1), Some("https://b"));
+        assert_eq!(terminal.link_at(0, 2), Some("https://a"));
+        assert_eq!(terminal.link_at(0, 3), None);
+        assert_eq!(terminal.links.len(), 2);
+    }
+
+    #[test]
+    fn unreferenced_links_are_pruned() {
+        let mut terminal = fed(4, 2, b"");
+        // 毎回上書きされるため、最後のリンク以外はどのセルにも残らない
+        for index in 0..LINKS_PRUNE_MIN * 4 {
+            terminal.feed(b"\x1b[H");
+            terminal.feed(&link(&format!("https://example.com/{index}"), "x"));
+        }
+        assert!(terminal.links.len() <= LINKS_PRUNE_MIN);
+        assert!(terminal.links.urls.len() <= LINKS_PRUNE_MIN);
+        let last = format!("https://example.com/{}", LINKS_PRUNE_MIN * 4 - 1);
+        assert_eq!(terminal.link_at(0, 0), Some(last.as_str()));
+    }
+
+    #[test]
+    fn links_in_scrollback_are_kept() {
+        let mut terminal = fed(4, 1, &link("https://kept", "k"));
+        terminal.feed(b"\n");
+        for index in 0..LINKS_PRUNE_MIN * 2 {
+            terminal.feed(b"\r");
+            terminal.feed(&link(&format!("https://example.com/{index}"), "x"));
+        }
+        assert_eq!(terminal.link_at(0, 0), Some("https://kept"));
+    }
+
+    #[test]
+    fn line_feed_without_implied_cr() {
+        let mut terminal = fed(4, 2, b"");
+        terminal.line_discipline_mut().is_lf_implies_cr = false;
+        terminal.feed(b"ab\ncd");
+        assert_eq!(screen_text(&terminal), ["ab", "  cd"]);
+    }
+}
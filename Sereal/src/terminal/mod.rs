@@ -0,0 +1,4 @@
+pub mod emulator;
+pub mod screen;
+
+pub use emulator::Terminal;
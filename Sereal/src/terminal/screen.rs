@@ -0,0 +1,447 @@
+use crate::ansi_formatter::text_style::TextStyle;
+use std::collections::VecDeque;
+
+/// 画面の 1 文字分のセル
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Cell {
+    pub ch: char,
+    pub style: TextStyle,
+    pub link: Option<usize>, // Terminal が持つリンク先の番号
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            ch: ' ',
+            style: TextStyle::default(),
+            link: None,
+        }
+    }
+}
+
+impl Cell {
+    /// 消去されたセル。背景色だけは現在の書式を引き継ぐ
+    fn blank(style: &TextStyle) -> Self {
+        Self {
+            ch: ' ',
+            style: TextStyle {
+                background_color: style.background_color,
+                ..Default::default()
+            },
+            link: None,
+        }
+    }
+}
+
+pub type Row = Vec<Cell>;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Cursor {
+    pub row: usize,
+    pub column: usize,
+}
+
+/// 文字を書き込むときの動作モード
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WriteModes {
+    /// 右端に達したら次の行に折り返す (DECAWM)
+    pub is_autowrap: bool,
+    /// 既存の文字を右にずらして挿入する (IRM)
+    pub is_insert: bool,
+}
+
+/// カーソル位置とスクロール領域を持つ文字のグリッド
+#[derive(Debug, Clone)]
+pub struct Screen {
+    columns: usize,
+    rows: usize,
+    lines: Vec<Row>,
+    pub cursor: Cursor,
+    scroll_top: usize,
+    scroll_bottom: usize, // スクロール領域の最終行 (この行を含む)
+    /// 右端に書き込んだ直後で、次の文字で折り返す状態
+    pub is_wrap_pending: bool,
+}
+
+impl Screen {
+    pub fn new(columns: usize, rows: usize) -> Self {
+        Self {
+            columns,
+            rows,
+            lines: vec![vec![Cell::default(); columns]; rows],
+            cursor: Cursor::default(),
+            scroll_top: 0,
+            scroll_bottom: rows - 1,
+            is_wrap_pending: false,
+        }
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn row(&self, index: usize) -> &Row {
+        &self.lines[index]
+    }
+
+    pub fn scroll_region(&self) -> (usize, usize) {
+        (self.scroll_top, self.scroll_bottom)
+    }
+
+    /// 画面サイズを変更する。はみ出した上側の行は scrollback に送る
+    pub fn resize(&mut self, columns: usize, rows: usize, scrollback: Option<&mut Scrollback>) {
+        for line in &mut self.lines {
+            line.resize(columns, Cell::default());
+        }
+
+        if rows < self.rows {
+            // カーソルが画面内に残るように上側の行を押し出す
+            let overflow = (self.cursor.row + 1).saturating_sub(rows);
+            let removed: Vec<Row> = self.lines.drain(..overflow).collect();
+            if let Some(scrollback) = scrollback {
+                for line in removed {
+                    scrollback.push(line);
+                }
+            }
+            self.lines.truncate(rows);
+            self.cursor.row -= overflow;
+        }
+        self.lines.resize(rows, vec![Cell::default(); columns]);
+
+        self.columns = columns;
+        self.rows = rows;
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+        self.cursor.column = self.cursor.column.min(columns - 1);
+        self.is_wrap_pending = false;
+    }
+
+    pub fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        if top < bottom && bottom < self.rows {
+            self.scroll_top = top;
+            self.scroll_bottom = bottom;
+        } else {
+            self.scroll_top = 0;
+            self.scroll_bottom = self.rows - 1;
+        }
+    }
+
+    /// カーソル位置に 1 文字書き込み、カーソルを進める
+    pub fn put_char(
+        &mut self,
+        cell: Cell,
+        modes: &WriteModes,
+        scrollback: Option<&mut Scrollback>,
+    ) {
+        if self.is_wrap_pending {
+            if modes.is_autowrap {
+                self.cursor.column = 0;
+                self.line_feed(&cell.style, scrollback);
+            }
+            self.is_wrap_pending = false;
+        }
+
+        let Cursor { row, column } = self.cursor;
+        let line = &mut self.lines[row];
+        if modes.is_insert {
+            line.pop();
+            line.insert(column, Cell::default());
+        }
+        line[column] = cell;
+
+        if column + 1 < self.columns {
+            self.cursor.column += 1;
+        } else {
+            self.is_wrap_pending = true;
+        }
+    }
+
+    /// カーソルを 1 行下げる。スクロール領域の最終行ならスクロールする
+    pub fn line_feed(&mut self, style: &TextStyle, scrollback: Option<&mut Scrollback>) {
+        self.is_wrap_pending = false;
+        if self.cursor.row == self.scroll_bottom {
+            self.scroll_up(1, style, scrollback);
+        } else if self.cursor.row + 1 < self.rows {
+            self.cursor.row += 1;
+        }
+    }
+
+    /// カーソルを 1 行上げる。スクロール領域の先頭行なら逆方向にスクロールする
+    pub fn reverse_line_feed(&mut self, style: &TextStyle) {
+        self.is_wrap_pending = false;
+        if self.cursor.row == self.scroll_top {
+            self.scroll_down(1, style);
+        } else if 0 < self.cursor.row {
+            self.cursor.row -= 1;
+        }
+    }
+
+    /// スクロール領域を上方向に count 行スクロールする
+    ///
+    /// 領域が画面の先頭から始まる場合、押し出された行は scrollback に送る。
+    pub fn scroll_up(
+        &mut self,
+        count: usize,
+        style: &TextStyle,
+        scrollback: Option<&mut Scrollback>,
+    ) {
+        let count = count.min(self.scroll_bottom - self.scroll_top + 1);
+        let removed: Vec<Row> = self
+            .lines
+            .drain(self.scroll_top..self.scroll_top + count)
+            .collect();
+        if self.scroll_top == 0
+            && let Some(scrollback) = scrollback
+        {
+            for line in removed {
+                scrollback.push(line);
+            }
+        }
+        let insert_at = self.scroll_bottom + 1 - count;
+        for _ in 0..count {
+            self.lines
+                .insert(insert_at, vec![Cell::blank(style); self.columns]);
+        }
+    }
+
+    /// スクロール領域を下方向に count 行スクロールする
+    pub fn scroll_down(&mut self, count: usize, style: &TextStyle) {
+        let count = count.min(self.scroll_bottom - self.scroll_top + 1);
+        self.lines
+            .drain(self.scroll_bottom + 1 - count..=self.scroll_bottom);
+        for _ in 0..count {
+            self.lines
+                .insert(self.scroll_top, vec![Cell::blank(style); self.columns]);
+        }
+    }
+
+    /// カーソル行に空行を挿入する (IL)
+    pub fn insert_lines(&mut self, count: usize, style: &TextStyle) {
+        if self.cursor.row < self.scroll_top || self.scroll_bottom < self.cursor.row {
+            return;
+        }
+        let top = self.scroll_top;
+        self.scroll_top = self.cursor.row;
+        self.scroll_down(count, style);
+        self.scroll_top = top;
+        self.cursor.column = 0;
+    }
+
+    /// カーソル行から行を削除する (DL)
+    pub fn delete_lines(&mut self, count: usize, style: &TextStyle) {
+        if self.cursor.row < self.scroll_top || self.scroll_bottom < self.cursor.row {
+            return;
+        }
+        let top = self.scroll_top;
+        self.scroll_top = self.cursor.row;
+        self.scroll_up(count, style, None);
+        self.scroll_top = top;
+        self.cursor.column = 0;
+    }
+
+    /// カーソル位置に空白を挿入する (ICH)
+    pub fn insert_chars(&mut self, count: usize, style: &TextStyle) {
+        let column = self.cursor.column;
+        let line = &mut self.lines[self.cursor.row];
+        let count = count.min(self.columns - column);
+        for _ in 0..count {
+            line.pop();
+            line.insert(column, Cell::blank(style));
+        }
+        self.is_wrap_pending = false;
+    }
+
+    /// カーソル位置から文字を削除して左に詰める (DCH)
+    pub fn delete_chars(&mut self, count: usize, style: &TextStyle) {
+        let column = self.cursor.column;
+        let line = &mut self.lines[self.cursor.row];
+        let count = count.min(self.columns - column);
+        line.drain(column..column + count);
+        line.resize(self.columns, Cell::blank(style));
+        self.is_wrap_pending = false;
+    }
+
+    /// カーソル位置から文字を空白で上書きする (ECH)
+    pub fn erase_chars(&mut self, count: usize, style: &TextStyle) {
+        let column = self.cursor.column;
+        let end = (column + count).min(self.columns);
+        self.lines[self.cursor.row][column..end].fill(Cell::blank(style));
+        self.is_wrap_pending = false;
+    }
+
+    /// 行内を消去する (EL)。0: カーソルから行末, 1: 行頭からカーソル, 2: 行全体
+    pub fn erase_in_line(&mut self, mode: u16, style: &TextStyle) {
+        let column = self.cursor.column;
+        let range = match mode {
+            0 => column..self.columns,
+            1 => 0..column + 1,
+            _ => 0..self.columns,
+        };
+        self.lines[self.cursor.row][range].fill(Cell::blank(style));
+        self.is_wrap_pending = false;
+    }
+
+    /// 画面を消去する (ED)。0: カーソルから末尾, 1: 先頭からカーソル, 2: 画面全体
+    pub fn erase_in_display(&mut self, mode: u16, style: &TextStyle) {
+        let row = self.cursor.row;
+        let rows = match mode {
+            0 => {
+                self.erase_in_line(0, style);
+                row + 1..self.rows
+            }
+            1 => {
+                self.erase_in_line(1, style);
+                0..row
+            }
+            _ => 0..self.rows,
+        };
+        for line in &mut self.lines[rows] {
+            line.fill(Cell::blank(style));
+        }
+        self.is_wrap_pending = false;
+    }
+
+    /// 画面全体を指定した文字で埋める (DECALN)
+    pub fn fill(&mut self, ch: char) {
+        for line in &mut self.lines {
+            line.fill(Cell {
+                ch,
+                ..Default::default()
+            });
+        }
+    }
+
+    pub fn move_cursor_to(&mut self, row: usize, column: usize) {
+        self.cursor.row = row.min(self.rows - 1);
+        self.cursor.column = column.min(self.columns - 1);
+        self.is_wrap_pending = false;
+    }
+}
+
+/// 画面の上側に押し出された行の履歴
+#[derive(Debug, Clone)]
+pub struct Scrollback {
+    lines: VecDeque<Row>,
+    max_lines: usize,
+}
+
+impl Scrollback {
+    pub fn new(max_lines: usize) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            max_lines,
+        }
+    }
+
+    pub fn push(&mut self, line: Row) {
+        self.lines.push_back(line);
+        while self.max_lines < self.lines.len() {
+            self.lines.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Row> {
+        self.lines.get(index)
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: WriteModes = WriteModes {
+        is_autowrap: true,
+        is_insert: false,
+    };
+
+    fn write(screen: &mut Screen, text: &str, modes: &WriteModes) {
+        for ch in text.chars() {
+            let cell = Cell {
+                ch,
+                ..Default::default()
+            };
+            screen.put_char(cell, modes, None);
+        }
+    }
+
+    fn line(screen: &Screen, index: usize) -> String {
+        screen.row(index).iter().map(|cell| cell.ch).collect()
+    }
+
+    fn row_of(ch: char) -> Row {
+        vec![
+            Cell {
+                ch,
+                ..Default::default()
+            };
+            1
+        ]
+    }
+
+    #[test]
+    fn insert_mode_shifts_cells() {
+        let mut screen = Screen::new(5, 1);
+        write(&mut screen, "abcd", &MODES);
+        screen.move_cursor_to(0, 1);
+        let insert = WriteModes {
+            is_insert: true,
+            ..MODES
+        };
+        write(&mut screen, "x", &insert);
+        assert_eq!(line(&screen, 0), "axbcd");
+    }
+
+    #[test]
+    fn insert_and_delete_chars() {
+        let style = TextStyle::default();
+        let mut screen = Screen::new(5, 1);
+        write(&mut screen, "abcde", &MODES);
+        screen.move_cursor_to(0, 1);
+        screen.insert_chars(2, &style);
+        assert_eq!(line(&screen, 0), "a  bc");
+        screen.delete_chars(9, &style);
+        assert_eq!(line(&screen, 0), "a    ");
+    }
+
+    #[test]
+    fn insert_and_delete_lines_inside_region() {
+        let style = TextStyle::default();
+        let mut screen = Screen::new(1, 4);
+        for (row, ch) in "abcd".chars().enumerate() {
+            screen.move_cursor_to(row, 0);
+            write(&mut screen, &ch.to_string(), &MODES);
+        }
+        screen.set_scroll_region(0, 2);
+        screen.move_cursor_to(1, 0);
+        screen.insert_lines(1, &style);
+        let lines: Vec<String> = (0..4).map(|row| line(&screen, row)).collect();
+        assert_eq!(lines, ["a", " ", "b", "d"]);
+        screen.delete_lines(2, &style);
+        let lines: Vec<String> = (0..4).map(|row| line(&screen, row)).collect();
+        assert_eq!(lines, ["a", " ", " ", "d"]);
+    }
+
+    #[test]
+    fn scrollback_drops_oldest_lines() {
+        let mut scrollback = Scrollback::new(2);
+        for ch in ['a', 'b', 'c'] {
+            scrollback.push(row_of(ch));
+        }
+        assert_eq!(scrollback.len(), 2);
+        assert_eq!(scrollback.get(0).unwrap()[0].ch, 'b');
+        assert_eq!(scrollback.get(1).unwrap()[0].ch, 'c');
+        assert!(scrollback.get(2).is_none());
+    }
+}
@@ -0,0 +1,263 @@
+pub mod crc;
+pub mod xmodem;
+pub mod zmodem;
+
+use chrono::{DateTime, Local};
+
+/// 中断を伝えるバイト列。CAN を続けて送り、相手の端末に残った分を BS で消す
+pub const CANCEL_SEQUENCE: &[u8] =
+    b"\x18\x18\x18\x18\x18\x18\x18\x18\x08\x08\x08\x08\x08\x08\x08\x08";
+
+/// 送るファイル、または受け取ったファイル
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferFile {
+    pub name: String,
+    pub content: Vec<u8>,
+}
+
+/// 転送の結果
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransferState {
+    Running,
+    Completed,
+    Failed(String),
+    /// こちら、または相手が取り消した
+    Cancelled(String),
+}
+
+/// 進み具合
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferStatus {
+    pub state: TransferState,
+    /// 転送中のファイル名。XMODEM では分からない
+    pub file_name: Option<String>,
+    pub bytes_done: u64,
+    pub bytes_total: Option<u64>,
+    /// 再送した回数 (全体)
+    pub retries: u32,
+    pub files_done: usize,
+}
+
+impl Default for TransferStatus {
+    fn default() -> Self {
+        Self {
+            state: TransferState::Running,
+            file_name: None,
+            bytes_done: 0,
+            bytes_total: None,
+            retries: 0,
+            files_done: 0,
+        }
+    }
+}
+
+/// ファイル転送のプロトコルの 1 回分
+///
+/// 送受信は行わず、受信したバイト列を feed で受け取り、送るバイト列を返す。
+/// 始めに送るバイト列や再送は poll で返す。
+pub trait Transfer {
+    /// 受信したバイト列を処理し、返答を返す
+    fn feed(&mut self, now: DateTime<Local>, bytes: &[u8]) -> Vec<u8>;
+
+    /// タイムアウトや送り続けるデータを処理し、送るバイト列を返す
+    fn poll(&mut self, now: DateTime<Local>) -> Vec<u8>;
+
+    /// 次に poll を呼んでほしい時間
+    fn next_poll(&self, now: DateTime<Local>) -> Option<std::time::Duration>;
+
+    /// 取り消して、相手に中断を伝えるバイト列を返す
+    fn cancel(&mut self) -> Vec<u8>;
+
+    fn status(&self) -> &TransferStatus;
+
+    /// 受け取り終えたファイル
+    fn take_received_files(&mut self) -> Vec<TransferFile> {
+        Vec::new()
+    }
+}
+
+/// 転送のプロトコル
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Protocol {
+    Xmodem,
+    Xmodem1k,
+    Ymodem,
+    Zmodem,
+}
+
+impl Protocol {
+    pub const ALL: [Protocol; 4] = [
+        Protocol::Xmodem,
+        Protocol::Xmodem1k,
+        Protocol::Ymodem,
+        Protocol::Zmodem,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Protocol::Xmodem => "XMODEM (CRC)",
+            Protocol::Xmodem1k => "XMODEM-1K",
+            Protocol::Ymodem => "YMODEM",
+            Protocol::Zmodem => "ZMODEM",
+        }
+    }
+
+    /// 複数のファイルとファイル名を送れるか
+    pub fn is_batch(&self) -> bool {
+        matches!(self, Protocol::Ymodem | Protocol::Zmodem)
+    }
+
+    pub fn sender(&self, files: Vec<TransferFile>) -> Box<dyn Transfer> {
+        match self {
+            Protocol::Xmodem => Box::new(xmodem::XmodemSender::new(xmodem::Variant::Xmodem, files)),
+            Protocol::Xmodem1k => {
+                Box::new(xmodem::XmodemSender::new(xmodem::Variant::Xmodem1k, files))
+            }
+            Protocol::Ymodem => Box::new(xmodem::XmodemSender::new(xmodem::Variant::Ymodem, files)),
+            Protocol::Zmodem => Box::new(zmodem::ZmodemSender::new(files)),
+        }
+    }
+
+    pub fn receiver(&self) -> Box<dyn Transfer> {
+        match self {
+            Protocol::Xmodem => Box::new(xmodem::XmodemReceiver::new(xmodem::Variant::Xmodem)),
+            Protocol::Xmodem1k => Box::new(xmodem::XmodemReceiver::new(xmodem::Variant::Xmodem1k)),
+            Protocol::Ymodem => Box::new(xmodem::XmodemReceiver::new(xmodem::Variant::Ymodem)),
+            Protocol::Zmodem => Box::new(zmodem::ZmodemReceiver::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+    use std::collections::VecDeque;
+
+    /// 一度に書き込める大きさ。Linux の tty の送信バッファに合わせる
+    const DRIVER_BUFFER: usize = 4096;
+    /// 相手に一度に届く大きさ
+    const DELIVERY_CHUNK: usize = 256;
+
+    fn content(length: usize, seed: usize) -> Vec<u8> {
+        // ZDLE や XON などのエスケープするバイトも含める
+        (0..length).map(|i| ((i * 7 + seed) % 251) as u8).collect()
+    }
+
+    fn file(name: &str, length: usize) -> TransferFile {
+        TransferFile {
+            name: name.to_string(),
+            content: content(length, name.len()),
+        }
+    }
+
+    fn take_burst(queue: &mut VecDeque<u8>) -> Vec<u8> {
+        let length = queue.len().min(DELIVERY_CHUNK);
+        queue.drain(..length).collect()
+    }
+
+    /// 送信側と受信側をメモリ上でつなぎ、終わるまで進める
+    ///
+    /// corrupt_at を指定すると、受信側に届くその番目のバイトを壊す。
+    fn run(
+        protocol: Protocol,
+        files: Vec<TransferFile>,
+        corrupt_at: Option<usize>,
+    ) -> (Box<dyn Transfer>, Box<dyn Transfer>) {
+        let mut sender = protocol.sender(files);
+        let mut receiver = protocol.receiver();
+        let mut now = Local::now();
+        let mut to_receiver = VecDeque::new();
+        let mut to_sender = VecDeque::new();
+        let mut delivered = 0;
+        let is_running =
+            |transfer: &dyn Transfer| transfer.status().state == TransferState::Running;
+        let write = |queue: &mut VecDeque<u8>, bytes: Vec<u8>| {
+            assert!(
+                bytes.len() <= DRIVER_BUFFER,
+                "{} bytes in one write",
+                bytes.len()
+            );
+            queue.extend(bytes);
+        };
+
+        for _ in 0..100_000 {
+            if !is_running(sender.as_ref()) && !is_running(receiver.as_ref()) {
+                break;
+            }
+            write(&mut to_receiver, sender.poll(now));
+            write(&mut to_sender, receiver.poll(now));
+
+            let mut burst = take_burst(&mut to_receiver);
+            if let Some(position) = corrupt_at
+                && (delivered..delivered + burst.len()).contains(&position)
+            {
+                burst[position - delivered] ^= 0x01;
+            }
+            delivered += burst.len();
+            if !burst.is_empty() {
+                write(&mut to_sender, receiver.feed(now, &burst));
+            }
+            let burst = take_burst(&mut to_sender);
+            if !burst.is_empty() {
+                write(&mut to_receiver, sender.feed(now, &burst));
+            }
+            now += TimeDelta::milliseconds(10);
+        }
+        (sender, receiver)
+    }
+
+    fn assert_transferred(protocol: Protocol, files: Vec<TransferFile>, corrupt_at: Option<usize>) {
+        let (sender, mut receiver) = run(protocol, files.clone(), corrupt_at);
+        assert_eq!(sender.status().state, TransferState::Completed);
+        assert_eq!(receiver.status().state, TransferState::Completed);
+        assert_eq!(receiver.take_received_files(), files);
+        assert_eq!(0 < receiver.status().retries, corrupt_at.is_some());
+    }
+
+    #[test]
+    fn xmodem_1k_loopback() {
+        let mut files = vec![file("", 5000)];
+        assert_transferred(Protocol::Xmodem1k, files.clone(), None);
+        files[0].content.truncate(3000);
+        assert_transferred(Protocol::Xmodem1k, files, Some(1500));
+    }
+
+    #[test]
+    fn xmodem_loopback() {
+        assert_transferred(Protocol::Xmodem, vec![file("", 1000)], Some(300));
+    }
+
+    #[test]
+    fn ymodem_loopback() {
+        let files = vec![file("a.bin", 5000), file("b.txt", 100), file("empty", 0)];
+        assert_transferred(Protocol::Ymodem, files.clone(), None);
+        assert_transferred(Protocol::Ymodem, files, Some(2000));
+    }
+
+    #[test]
+    fn zmodem_loopback() {
+        // ウィンドウより大きいファイルで ZACK を待つ流れも通す
+        let files = vec![
+            file("large.bin", 100_000),
+            file("small.txt", 10),
+            file("empty", 0),
+        ];
+        assert_transferred(Protocol::Zmodem, files.clone(), None);
+        assert_transferred(Protocol::Zmodem, files, Some(30_000));
+    }
+
+    #[test]
+    fn zmodem_sender_cancel() {
+        let mut sender = Protocol::Zmodem.sender(vec![file("a.bin", 100)]);
+        let mut receiver = Protocol::Zmodem.receiver();
+        let now = Local::now();
+        receiver.feed(now, &sender.poll(now));
+        let cancel = sender.cancel();
+        receiver.feed(now, &cancel);
+        assert!(matches!(
+            receiver.status().state,
+            TransferState::Cancelled(_)
+        ));
+    }
+}
@@ -0,0 +1,739 @@
+use super::crc::crc16;
+use super::{CANCEL_SEQUENCE, Transfer, TransferFile, TransferState, TransferStatus};
+use chrono::{DateTime, Local, TimeDelta};
+use std::collections::VecDeque;
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+const CRC_REQUEST: u8 = b'C';
+/// XMODEM で最後のブロックを埋めるバイト
+const SUB: u8 = 0x1a;
+
+/// 送信側が受信側の開始要求を待つ時間
+const START_TIMEOUT_MS: i64 = 60_000;
+/// ACK や次のブロックを待つ時間
+const BLOCK_TIMEOUT_MS: i64 = 10_000;
+/// 受信側が開始要求を繰り返す間隔
+const REQUEST_INTERVAL_MS: i64 = 3_000;
+const MAX_START_REQUESTS: u32 = 20;
+/// XMODEM で CRC を諦めてチェックサムに切り替えるまでの要求回数
+const CRC_REQUESTS_BEFORE_CHECKSUM: u32 = 3;
+/// 同じブロックで続けて失敗してよい回数
+const MAX_ERRORS: u32 = 10;
+
+/// XMODEM の種類
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Variant {
+    Xmodem,
+    Xmodem1k,
+    Ymodem,
+}
+
+impl Variant {
+    fn uses_1k_blocks(&self) -> bool {
+        matches!(self, Variant::Xmodem1k | Variant::Ymodem)
+    }
+}
+
+/// ブロックを組み立てる。data はブロックの大きさに埋めてあること
+fn build_block(number: u8, data: &[u8], is_crc: bool) -> Vec<u8> {
+    let start = if data.len() == 1024 { STX } else { SOH };
+    let mut block = vec![start, number, !number];
+    block.extend_from_slice(data);
+    if is_crc {
+        block.extend_from_slice(&crc16(data).to_be_bytes());
+    } else {
+        block.push(checksum(data));
+    }
+    block
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// YMODEM のブロック 0。ファイル名とサイズを入れる
+fn ymodem_header(file: &TransferFile) -> Vec<u8> {
+    let mut header = file.name.as_bytes().to_vec();
+    header.push(0);
+    header.extend_from_slice(file.content.len().to_string().as_bytes());
+    let size = if header.len() < 128 { 128 } else { 1024 };
+    header.resize(size, 0);
+    header
+}
+
+/// YMODEM のブロック 0 からファイル名とサイズを読む。名前が空なら一括転送の終わり
+fn parse_ymodem_header(data: &[u8]) -> (String, Option<u64>) {
+    let mut fields = data.split(|&byte| byte == 0);
+    let name = String::from_utf8_lossy(fields.next().unwrap_or_default()).into_owned();
+    let size = fields
+        .next()
+        .and_then(|info| info.split(|&byte| byte == b' ').next())
+        .and_then(|size| std::str::from_utf8(size).ok())
+        .and_then(|size| size.parse().ok());
+    (name, size)
+}
+
+/// 送ったもの
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Sent {
+    Header,
+    Data { length: usize },
+    Eot,
+    EndOfBatch,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum SenderState {
+    /// 受信側の C か NAK を待っている
+    WaitingStart {
+        is_next_header: bool,
+    },
+    WaitingAck(Sent),
+    Done,
+}
+
+/// XMODEM / YMODEM でファイルを送る
+pub struct XmodemSender {
+    variant: Variant,
+    files: VecDeque<TransferFile>,
+    current: Option<TransferFile>,
+    offset: usize,
+    block_number: u8,
+    is_crc: bool,
+    state: SenderState,
+    /// 再送に備えて最後に送ったもの
+    packet: Vec<u8>,
+    waiting_since: Option<DateTime<Local>>,
+    errors: u32,
+    can_count: u32,
+    status: TransferStatus,
+}
+
+impl XmodemSender {
+    pub fn new(variant: Variant, files: Vec<TransferFile>) -> Self {
+        let mut sender = Self {
+            variant,
+            files: files.into(),
+            current: None,
+            offset: 0,
+            block_number: 1,
+            is_crc: true,
+            state: SenderState::WaitingStart {
+                is_next_header: variant == Variant::Ymodem,
+            },
+            packet: Vec::new(),
+            waiting_since: None,
+            errors: 0,
+            can_count: 0,
+            status: TransferStatus::default(),
+        };
+        // XMODEM はファイル名を送らないため、最初のファイルだけを送る
+        if variant != Variant::Ymodem {
+            match sender.files.pop_front() {
+                Some(file) => sender.begin_file(file),
+                None => sender.fail("No file to send".to_string()),
+            }
+        }
+        sender
+    }
+
+    fn begin_file(&mut self, file: TransferFile) {
+        self.status.file_name = Some(file.name.clone());
+        self.status.bytes_total = Some(file.content.len() as u64);
+        self.status.bytes_done = 0;
+        self.offset = 0;
+        self.block_number = 1;
+        self.current = Some(file);
+    }
+
+    fn fail(&mut self, message: String) {
+        self.status.state = TransferState::Failed(message);
+        self.state = SenderState::Done;
+    }
+
+    fn send_packet(&mut self, now: DateTime<Local>, packet: Vec<u8>, sent: Sent) -> Vec<u8> {
+        self.packet = packet.clone();
+        self.state = SenderState::WaitingAck(sent);
+        self.waiting_since = Some(now);
+        packet
+    }
+
+    fn send_header(&mut self, now: DateTime<Local>) -> Vec<u8> {
+        match self.files.pop_front() {
+            Some(file) => {
+                let header = ymodem_header(&file);
+                self.begin_file(file);
+                self.send_packet(now, build_block(0, &header, true), Sent::Header)
+            }
+            None => self.send_packet(now, build_block(0, &[0; 128], true), Sent::EndOfBatch),
+        }
+    }
+
+    fn send_data_or_eot(&mut self, now: DateTime<Local>) -> Vec<u8> {
+        let content = self
+            .current
+            .as_ref()
+            .map(|file| file.content.as_slice())
+            .unwrap_or_default();
+        let remaining = content.len().saturating_sub(self.offset);
+        if remaining == 0 {
+            return self.send_packet(now, vec![EOT], Sent::Eot);
+        }
+        // 残りが少なければ 128 バイトのブロックで無駄を減らす
+        let size = if self.variant.uses_1k_blocks() && self.is_crc && 128 < remaining {
+            1024
+        } else {
+            128
+        };
+        let length = remaining.min(size);
+        let mut data = content[self.offset..self.offset + length].to_vec();
+        data.resize(size, SUB);
+        let block = build_block(self.block_number, &data, self.is_crc);
+        self.send_packet(now, block, Sent::Data { length })
+    }
+
+    fn resend(&mut self, now: DateTime<Local>) -> Vec<u8> {
+        self.errors += 1;
+        self.status.retries += 1;
+        if MAX_ERRORS < self.errors {
+            self.fail("Too many retries".to_string());
+            return CANCEL_SEQUENCE.to_vec();
+        }
+        self.waiting_since = Some(now);
+        self.packet.clone()
+    }
+
+    fn handle_ack(&mut self, now: DateTime<Local>, sent: Sent) -> Vec<u8> {
+        self.errors = 0;
+        match sent {
+            Sent::Header => {
+                // ヘッダの後にもう一度 C が来てからデータを送る
+                self.state = SenderState::WaitingStart {
+                    is_next_header: false,
+                };
+                self.waiting_since = Some(now);
+                Vec::new()
+            }
+            Sent::Data { length } => {
+                self.offset += length;
+                self.block_number = self.block_number.wrapping_add(1);
+                self.status.bytes_done = self.offset as u64;
+                self.send_data_or_eot(now)
+            }
+            Sent::Eot => {
+                self.status.files_done += 1;
+                if self.variant == Variant::Ymodem {
+                    self.state = SenderState::WaitingStart {
+                        is_next_header: true,
+                    };
+                    self.waiting_since = Some(now);
+                } else {
+                    self.status.state = TransferState::Completed;
+                    self.state = SenderState::Done;
+                }
+                Vec::new()
+            }
+            Sent::EndOfBatch => {
+                self.status.state = TransferState::Completed;
+                self.state = SenderState::Done;
+                Vec::new()
+            }
+        }
+    }
+}
+
+impl Transfer for XmodemSender {
+    fn feed(&mut self, now: DateTime<Local>, bytes: &[u8]) -> Vec<u8> {
+        let mut reply = Vec::new();
+        for &byte in bytes {
+            if self.state == SenderState::Done {
+                break;
+            }
+            if byte == CAN {
+                self.can_count += 1;
+                if 2 <= self.can_count {
+                    self.status.state =
+                        TransferState::Cancelled("Cancelled by the receiver".to_string());
+                    self.state = SenderState::Done;
+                }
+                continue;
+            }
+            self.can_count = 0;
+            match (self.state, byte) {
+                (SenderState::WaitingStart { is_next_header }, CRC_REQUEST | NAK) => {
+                    // YMODEM は CRC しか使わない
+                    self.is_crc = byte == CRC_REQUEST || self.variant == Variant::Ymodem;
+                    if is_next_header {
+                        reply.extend(self.send_header(now));
+                    } else {
+                        reply.extend(self.send_data_or_eot(now));
+                    }
+                }
+                (SenderState::WaitingAck(sent), ACK) => reply.extend(self.handle_ack(now, sent)),
+                // YMODEM の受信側は最初の EOT に NAK を返すため、再送には数えない
+                (SenderState::WaitingAck(Sent::Eot), NAK) if self.errors == 0 => {
+                    self.errors = 1;
+                    self.waiting_since = Some(now);
+                    reply.push(EOT);
+                }
+                (SenderState::WaitingAck(_), NAK) => reply.extend(self.resend(now)),
+                _ => {}
+            }
+        }
+        reply
+    }
+
+    fn poll(&mut self, now: DateTime<Local>) -> Vec<u8> {
+        let since = *self.waiting_since.get_or_insert(now);
+        match self.state {
+            SenderState::WaitingStart { .. }
+                if TimeDelta::milliseconds(START_TIMEOUT_MS) <= now - since =>
+            {
+                self.fail("The receiver did not start".to_string());
+                CANCEL_SEQUENCE.to_vec()
+            }
+            SenderState::WaitingAck(_)
+                if TimeDelta::milliseconds(BLOCK_TIMEOUT_MS) <= now - since =>
+            {
+                self.resend(now)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn next_poll(&self, now: DateTime<Local>) -> Option<std::time::Duration> {
+        let timeout = match self.state {
+            SenderState::WaitingStart { .. } => START_TIMEOUT_MS,
+            SenderState::WaitingAck(_) => BLOCK_TIMEOUT_MS,
+            SenderState::Done => return None,
+        };
+        let deadline = self.waiting_since.unwrap_or(now) + TimeDelta::milliseconds(timeout);
+        Some((deadline - now).to_std().unwrap_or_default())
+    }
+
+    fn cancel(&mut self) -> Vec<u8> {
+        self.status.state = TransferState::Cancelled("Cancelled".to_string());
+        self.state = SenderState::Done;
+        CANCEL_SEQUENCE.to_vec()
+    }
+
+    fn status(&self) -> &TransferStatus {
+        &self.status
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ReceiverState {
+    /// 送信側が始めるまで C か NAK を繰り返す
+    Starting,
+    Receiving,
+    Done,
+}
+
+/// XMODEM / YMODEM でファイルを受け取る
+///
+/// XMODEM ではファイルの長さが分からないため、最後のブロックを埋めた SUB を取り除く。
+pub struct XmodemReceiver {
+    variant: Variant,
+    state: ReceiverState,
+    buffer: Vec<u8>,
+    expected_block: u8,
+    is_crc: bool,
+    request_count: u32,
+    last_activity: Option<DateTime<Local>>,
+    errors: u32,
+    /// YMODEM で受け取った EOT の数。最初の EOT には NAK を返す
+    eot_count: u32,
+    file: Option<TransferFile>,
+    file_size: Option<u64>,
+    files: Vec<TransferFile>,
+    status: TransferStatus,
+}
+
+impl XmodemReceiver {
+    pub fn new(variant: Variant) -> Self {
+        let is_ymodem = variant == Variant::Ymodem;
+        Self {
+            variant,
+            state: ReceiverState::Starting,
+            buffer: Vec::new(),
+            expected_block: if is_ymodem { 0 } else { 1 },
+            is_crc: true,
+            request_count: 0,
+            last_activity: None,
+            errors: 0,
+            eot_count: 0,
+            file: (!is_ymodem).then(|| TransferFile {
+                name: String::new(),
+                content: Vec::new(),
+            }),
+            file_size: None,
+            files: Vec::new(),
+            status: TransferStatus::default(),
+        }
+    }
+
+    fn request_byte(&self) -> u8 {
+        if self.is_crc { CRC_REQUEST } else { NAK }
+    }
+
+    fn fail(&mut self, message: String) -> Vec<u8> {
+        self.status.state = TransferState::Failed(message);
+        self.state = ReceiverState::Done;
+        CANCEL_SEQUENCE.to_vec()
+    }
+
+    fn reject(&mut self) -> Vec<u8> {
+        self.errors += 1;
+        self.status.retries += 1;
+        // 残りのバイトは壊れたブロックの続きとして捨てる
+        self.buffer.clear();
+        if MAX_ERRORS < self.errors {
+            return self.fail("Too many errors".to_string());
+        }
+        vec![NAK]
+    }
+
+    fn handle_block(&mut self, block: &[u8]) -> Vec<u8> {
+        let number = block[1];
+        let (data, check) = if self.is_crc {
+            block[3..].split_at(block.len() - 5)
+        } else {
+            block[3..].split_at(block.len() - 4)
+        };
+        let is_check_valid = if self.is_crc {
+            check == crc16(data).to_be_bytes()
+        } else {
+            check == [checksum(data)]
+        };
+        if number != !block[2] || !is_check_valid {
+            return self.reject();
+        }
+        self.errors = 0;
+
+        let is_header = self.variant == Variant::Ymodem && self.file.is_none();
+        if number == self.expected_block.wrapping_sub(1) && !is_header {
+            // ACK が届かずに再送されたブロック
+            let mut reply = vec![ACK];
+            if number == 0 && self.variant == Variant::Ymodem {
+                reply.push(CRC_REQUEST);
+            }
+            return reply;
+        }
+        if number != self.expected_block {
+            return self.fail(format!(
+                "Expected block {} but received {number}",
+                self.expected_block
+            ));
+        }
+        self.expected_block = self.expected_block.wrapping_add(1);
+
+        if is_header {
+            let (name, size) = parse_ymodem_header(data);
+            if name.is_empty() {
+                self.status.state = TransferState::Completed;
+                self.state = ReceiverState::Done;
+                return vec![ACK];
+            }
+            self.status.file_name = Some(name.clone());
+            self.status.bytes_total = size;
+            self.status.bytes_done = 0;
+            self.file_size = size;
+            self.file = Some(TransferFile {
+                name,
+                content: Vec::new(),
+            });
+            return vec![ACK, CRC_REQUEST];
+        }
+        if let Some(file) = &mut self.file {
+            file.content.extend_from_slice(data);
+            self.status.bytes_done = file.content.len() as u64;
+        }
+        vec![ACK]
+    }
+
+    fn handle_eot(&mut self) -> Vec<u8> {
+        if self.variant == Variant::Ymodem && self.eot_count == 0 {
+            self.eot_count = 1;
+            return vec![NAK];
+        }
+        self.eot_count = 0;
+        if let Some(mut file) = self.file.take() {
+            match self.file_size {
+                Some(size) => file.content.truncate(size as usize),
+                None => {
+                    while file.content.last() == Some(&SUB) {
+                        file.content.pop();
+                    }
+                }
+            }
+            self.status.bytes_done = file.content.len() as u64;
+            self.status.files_done += 1;
+            self.files.push(file);
+        }
+        if self.variant == Variant::Ymodem {
+            // 次のファイルのヘッダを求める
+            self.expected_block = 0;
+            self.file_size = None;
+            return vec![ACK, CRC_REQUEST];
+        }
+        self.status.state = TransferState::Completed;
+        self.state = ReceiverState::Done;
+        vec![ACK]
+    }
+}
+
+impl Transfer for XmodemReceiver {
+    fn feed(&mut self, now: DateTime<Local>, bytes: &[u8]) -> Vec<u8> {
+        if self.state == ReceiverState::Done {
+            return Vec::new();
+        }
+        self.buffer.extend_from_slice(bytes);
+        self.last_activity = Some(now);
+        let mut reply = Vec::new();
+        while let Some(&first) = self.buffer.first() {
+            if self.state == ReceiverState::Done {
+                break;
+            }
+            match first {
+                SOH | STX => {
+                    let size = if first == SOH { 128 } else { 1024 };
+                    let length = 3 + size + if self.is_crc { 2 } else { 1 };
+                    if self.buffer.len() < length {
+                        break;
+                    }
+                    let block: Vec<u8> = self.buffer.drain(..length).collect();
+                    self.state = ReceiverState::Receiving;
+                    reply.extend(self.handle_block(&block));
+                }
+                EOT => {
+                    self.buffer.remove(0);
+                    self.state = ReceiverState::Receiving;
+                    reply.extend(self.handle_eot());
+                }
+                CAN => {
+                    if self.buffer.len() < 2 {
+                        break;
+                    }
+                    if self.buffer[1] == CAN {
+                        self.status.state =
+                            TransferState::Cancelled("Cancelled by the sender".to_string());
+                        self.state = ReceiverState::Done;
+                    }
+                    self.buffer.remove(0);
+                }
+                // ブロックの外の雑音は読み捨てる
+                _ => {
+                    self.buffer.remove(0);
+                }
+            }
+        }
+        reply
+    }
+
+    fn poll(&mut self, now: DateTime<Local>) -> Vec<u8> {
+        match self.state {
+            ReceiverState::Starting => {
+                if self
+                    .last_activity
+                    .is_some_and(|last| now - last < TimeDelta::milliseconds(REQUEST_INTERVAL_MS))
+                {
+                    return Vec::new();
+                }
+                if MAX_START_REQUESTS <= self.request_count {
+                    return self.fail("The sender did not start".to_string());
+                }
+                self.request_count += 1;
+                if self.variant == Variant::Xmodem
+                    && CRC_REQUESTS_BEFORE_CHECKSUM < self.request_count
+                {
+                    self.is_crc = false;
+                }
+                self.last_activity = Some(now);
+                vec![self.request_byte()]
+            }
+            ReceiverState::Receiving => {
+                if self
+                    .last_activity
+                    .is_some_and(|last| now - last < TimeDelta::milliseconds(BLOCK_TIMEOUT_MS))
+                {
+                    return Vec::new();
+                }
+                self.last_activity = Some(now);
+                let reply = self.reject();
+                // YMODEM で次のヘッダを待っている間は C で催促する
+                if reply == [NAK] && self.variant == Variant::Ymodem && self.file.is_none() {
+                    return vec![CRC_REQUEST];
+                }
+                reply
+            }
+            ReceiverState::Done => Vec::new(),
+        }
+    }
+
+    fn next_poll(&self, now: DateTime<Local>) -> Option<std::time::Duration> {
+        let interval = match self.state {
+            ReceiverState::Starting => REQUEST_INTERVAL_MS,
+            ReceiverState::Receiving => BLOCK_TIMEOUT_MS,
+            ReceiverState::Done => return None,
+        };
+        let deadline = self.last_activity.unwrap_or(now) + TimeDelta::milliseconds(interval);
+        Some((deadline - now).to_std().unwrap_or_default())
+    }
+
+    fn cancel(&mut self) -> Vec<u8> {
+        self.status.state = TransferState::Cancelled("Cancelled".to_string());
+        self.state = ReceiverState::Done;
+        CANCEL_SEQUENCE.to_vec()
+    }
+
+    fn status(&self) -> &TransferStatus {
+        &self.status
+    }
+
+    fn take_received_files(&mut self) -> Vec<TransferFile> {
+        std::mem::take(&mut self.files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // sx / sb と rx / rb がやり取りするバイト列を、仕様どおりに組み立てたもの
+    const XMODEM_CONTENT: &[u8] = b"Hello, XMODEM!\r\n";
+    const YMODEM_CONTENT: &[u8] = b"Hello, YMODEM!\r\n";
+
+    /// 128 バイトのブロック。data の後ろを fill で埋め、check を付ける
+    fn block(number: u8, data: &[u8], fill: u8, check: &[u8]) -> Vec<u8> {
+        let mut block = vec![SOH, number, !number];
+        block.extend_from_slice(data);
+        block.resize(3 + 128, fill);
+        block.extend_from_slice(check);
+        block
+    }
+
+    fn xmodem_block() -> Vec<u8> {
+        block(1, XMODEM_CONTENT, SUB, &[0xaa, 0x9f])
+    }
+
+    #[test]
+    fn xmodem_receives_from_sx() {
+        let mut receiver = XmodemReceiver::new(Variant::Xmodem);
+        let now = Local::now();
+        assert_eq!(receiver.poll(now), [CRC_REQUEST]);
+        assert_eq!(receiver.feed(now, &xmodem_block()), [ACK]);
+        assert_eq!(receiver.feed(now, &[EOT]), [ACK]);
+        assert_eq!(receiver.status().state, TransferState::Completed);
+        // 埋めた SUB は取り除く
+        let files = receiver.take_received_files();
+        assert_eq!(files[0].content, XMODEM_CONTENT);
+    }
+
+    #[test]
+    fn xmodem_receiver_rejects_corrupted_block() {
+        let mut receiver = XmodemReceiver::new(Variant::Xmodem);
+        let now = Local::now();
+        let mut corrupted = xmodem_block();
+        corrupted[10] ^= 0x01;
+        assert_eq!(receiver.feed(now, &corrupted), [NAK]);
+        assert_eq!(receiver.feed(now, &xmodem_block()), [ACK]);
+        // ACK が届かずに同じブロックが再送されても、内容は重ねない
+        assert_eq!(receiver.feed(now, &xmodem_block()), [ACK]);
+        assert_eq!(receiver.feed(now, &[EOT]), [ACK]);
+        assert_eq!(receiver.status().retries, 1);
+        assert_eq!(receiver.take_received_files()[0].content, XMODEM_CONTENT);
+    }
+
+    #[test]
+    fn xmodem_sends_to_rx() {
+        let file = TransferFile {
+            name: "hello.txt".to_string(),
+            content: XMODEM_CONTENT.to_vec(),
+        };
+        let now = Local::now();
+
+        let mut sender = XmodemSender::new(Variant::Xmodem, vec![file.clone()]);
+        assert_eq!(sender.feed(now, &[CRC_REQUEST]), xmodem_block());
+        assert_eq!(sender.feed(now, &[ACK]), [EOT]);
+        assert_eq!(sender.feed(now, &[ACK]), b"");
+        assert_eq!(sender.status().state, TransferState::Completed);
+
+        // NAK で始めた受信側にはチェックサムで送る
+        let mut sender = XmodemSender::new(Variant::Xmodem, vec![file]);
+        assert_eq!(
+            sender.feed(now, &[NAK]),
+            block(1, XMODEM_CONTENT, SUB, &[0xa2])
+        );
+    }
+
+    /// sb が送るブロック 0。ファイル名、サイズ、更新時刻 (8 進)、モード (8 進)
+    fn sb_header() -> Vec<u8> {
+        block(
+            0,
+            b"hello.txt\x0016 14720132435 100644\x00",
+            0,
+            &[0x7a, 0x4a],
+        )
+    }
+
+    fn ymodem_block() -> Vec<u8> {
+        block(1, YMODEM_CONTENT, SUB, &[0x50, 0x92])
+    }
+
+    /// 一括転送の終わりを表す空のブロック 0
+    fn end_of_batch() -> Vec<u8> {
+        block(0, &[], 0, &[0x00, 0x00])
+    }
+
+    #[test]
+    fn ymodem_receives_from_sb() {
+        let mut receiver = XmodemReceiver::new(Variant::Ymodem);
+        let now = Local::now();
+        assert_eq!(receiver.poll(now), [CRC_REQUEST]);
+        assert_eq!(receiver.feed(now, &sb_header()), [ACK, CRC_REQUEST]);
+        assert_eq!(receiver.status().bytes_total, Some(16));
+        assert_eq!(receiver.feed(now, &ymodem_block()), [ACK]);
+        assert_eq!(receiver.feed(now, &[EOT]), [NAK]);
+        assert_eq!(receiver.feed(now, &[EOT]), [ACK, CRC_REQUEST]);
+        assert_eq!(receiver.feed(now, &end_of_batch()), [ACK]);
+
+        assert_eq!(receiver.status().state, TransferState::Completed);
+        assert_eq!(
+            receiver.take_received_files(),
+            [TransferFile {
+                name: "hello.txt".to_string(),
+                content: YMODEM_CONTENT.to_vec(),
+            }]
+        );
+    }
+
+    #[test]
+    fn ymodem_sends_to_rb() {
+        let file = TransferFile {
+            name: "hello.txt".to_string(),
+            content: YMODEM_CONTENT.to_vec(),
+        };
+        let mut sender = XmodemSender::new(Variant::Ymodem, vec![file]);
+        let now = Local::now();
+
+        let header = block(0, b"hello.txt\x0016", 0, &[0x16, 0x23]);
+        assert_eq!(sender.feed(now, &[CRC_REQUEST]), header);
+        assert_eq!(sender.feed(now, &[ACK]), b"");
+        assert_eq!(sender.feed(now, &[CRC_REQUEST]), ymodem_block());
+        assert_eq!(sender.feed(now, &[ACK]), [EOT]);
+        // rb は最初の EOT に NAK を返す
+        assert_eq!(sender.feed(now, &[NAK]), [EOT]);
+        assert_eq!(sender.feed(now, &[ACK]), b"");
+        assert_eq!(sender.feed(now, &[CRC_REQUEST]), end_of_batch());
+        assert_eq!(sender.feed(now, &[ACK]), b"");
+
+        assert_eq!(sender.status().state, TransferState::Completed);
+        assert_eq!(sender.status().retries, 0);
+    }
+}
@@ -0,0 +1,1031 @@
+use super::crc::{crc16, crc32};
+use super::{CANCEL_SEQUENCE, Transfer, TransferFile, TransferState, TransferStatus};
+use crate::hex;
+use chrono::{DateTime, Local, TimeDelta};
+use std::collections::VecDeque;
+
+const ZPAD: u8 = b'*';
+const ZDLE: u8 = 0x18;
+const ZBIN: u8 = b'A';
+const ZHEX: u8 = b'B';
+const ZBIN32: u8 = b'C';
+
+const ZRQINIT: u8 = 0;
+const ZRINIT: u8 = 1;
+const ZSINIT: u8 = 2;
+const ZACK: u8 = 3;
+const ZFILE: u8 = 4;
+const ZSKIP: u8 = 5;
+const ZNAK: u8 = 6;
+const ZABORT: u8 = 7;
+const ZFIN: u8 = 8;
+const ZRPOS: u8 = 9;
+const ZDATA: u8 = 10;
+const ZEOF: u8 = 11;
+const ZFERR: u8 = 12;
+const ZCOMMAND: u8 = 18;
+
+/// データのサブパケットの終わり方
+const ZCRCE: u8 = b'h'; // フレームの終わり
+const ZCRCG: u8 = b'i'; // 続きがある
+const ZCRCQ: u8 = b'j'; // 続きがあり、ZACK を求める
+const ZCRCW: u8 = b'k'; // フレームの終わりで、ZACK を求める
+const ZRUB0: u8 = b'l';
+const ZRUB1: u8 = b'm';
+
+/// ZRINIT のフラグ。全二重で、ディスクへの書き込み中も受信できる
+const CANFDX: u8 = 0x01;
+const CANOVIO: u8 = 0x02;
+/// ZFILE のフラグ。バイナリとして受け取らせる
+const ZCBIN: u8 = 1;
+
+/// 受信側の ZRQINIT。相手が sz を始めたことを表す
+const AUTO_START_PATTERN: &[u8] = b"**\x18B00";
+/// CAN がこれだけ続いたら中断とみなす
+const ABORT_CAN_COUNT: u32 = 5;
+const MAX_SUBPACKET_LENGTH: usize = 8 * 1024;
+const SUBPACKET_LENGTH: usize = 1024;
+/// ZACK を待たずに送るバイト数
+const WINDOW_SIZE: usize = 16 * 1024;
+/// 1 回に返すデータの上限。エスケープで倍になっても OS の送信バッファに収まる大きさにし、残りは poll で送る
+const BURST_LENGTH: usize = 2 * SUBPACKET_LENGTH;
+/// この間隔で ZCRCQ を入れて ZACK を求める
+const ACK_INTERVAL: usize = 4 * 1024;
+const TIMEOUT_MS: i64 = 10_000;
+const MAX_ERRORS: u32 = 10;
+
+/// ヘッダの種類と 4 バイトの引数
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Header {
+    kind: u8,
+    data: [u8; 4],
+}
+
+impl Header {
+    fn new(kind: u8) -> Self {
+        Self { kind, data: [0; 4] }
+    }
+
+    fn with_position(kind: u8, position: usize) -> Self {
+        Self {
+            kind,
+            data: (position as u32).to_le_bytes(),
+        }
+    }
+
+    /// ZF0 (4 バイト目) にフラグを入れたヘッダ
+    fn with_flags(kind: u8, flags: u8) -> Self {
+        Self {
+            kind,
+            data: [0, 0, 0, flags],
+        }
+    }
+
+    fn position(&self) -> usize {
+        u32::from_le_bytes(self.data) as usize
+    }
+
+    fn bytes(&self) -> [u8; 5] {
+        let [p0, p1, p2, p3] = self.data;
+        [self.kind, p0, p1, p2, p3]
+    }
+
+    /// 16 進ヘッダ。ZRQINIT や ZRINIT など、制御のやり取りに使う
+    fn encode_hex(&self) -> Vec<u8> {
+        let bytes = self.bytes();
+        let mut text = String::new();
+        for byte in bytes.iter().chain(&crc16(&bytes).to_be_bytes()) {
+            text += &format!("{byte:02x}");
+        }
+        let mut encoded = vec![ZPAD, ZPAD, ZDLE, ZHEX];
+        encoded.extend_from_slice(text.as_bytes());
+        encoded.extend_from_slice(b"\r\x8a");
+        // ZFIN と ZACK の後には XON を付けない
+        if self.kind != ZFIN && self.kind != ZACK {
+            encoded.push(0x11);
+        }
+        encoded
+    }
+
+    /// CRC-16 のバイナリヘッダ。データを伴うヘッダに使う
+    fn encode_binary(&self) -> Vec<u8> {
+        let bytes = self.bytes();
+        let mut encoded = vec![ZPAD, ZDLE, ZBIN];
+        escape_into(&mut encoded, &bytes);
+        escape_into(&mut encoded, &crc16(&bytes).to_be_bytes());
+        encoded
+    }
+}
+
+/// 制御文字と ZDLE をエスケープして追加する
+fn escape_into(encoded: &mut Vec<u8>, bytes: &[u8]) {
+    for &byte in bytes {
+        match byte {
+            ZDLE | 0x10 | 0x90 | 0x11 | 0x91 | 0x13 | 0x93 => {
+                encoded.extend([ZDLE, byte ^ 0x40]);
+            }
+            byte => encoded.push(byte),
+        }
+    }
+}
+
+/// CRC-16 のデータサブパケット
+fn encode_subpacket(data: &[u8], frame_end: u8) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(data.len() + 8);
+    escape_into(&mut encoded, data);
+    encoded.extend([ZDLE, frame_end]);
+    let mut checked = data.to_vec();
+    checked.push(frame_end);
+    escape_into(&mut encoded, &crc16(&checked).to_be_bytes());
+    encoded
+}
+
+/// 受信したバイト列から組み立てたもの
+#[derive(Debug, Clone, PartialEq)]
+enum Frame {
+    Header(Header),
+    BadHeader,
+    Data {
+        data: Vec<u8>,
+        frame_end: u8,
+        is_valid: bool,
+    },
+    /// CAN が続いた
+    Abort,
+}
+
+/// エスケープを外した 1 バイト
+enum Unescaped {
+    Byte(u8),
+    FrameEnd(u8),
+    Invalid,
+}
+
+enum ParserState {
+    /// `*` と ZDLE を探している。stage はそこまでに揃った数
+    Seeking {
+        stage: u8,
+    },
+    HexHeader(Vec<u8>),
+    BinaryHeader {
+        is_crc32: bool,
+        bytes: Vec<u8>,
+    },
+    Data {
+        is_crc32: bool,
+        data: Vec<u8>,
+        frame_end: Option<u8>,
+        crc: Vec<u8>,
+    },
+}
+
+/// ZMODEM のヘッダとデータサブパケットを組み立てる
+struct FrameParser {
+    state: ParserState,
+    is_escaped: bool,
+    can_count: u32,
+}
+
+impl Default for FrameParser {
+    fn default() -> Self {
+        Self {
+            state: ParserState::Seeking { stage: 0 },
+            is_escaped: false,
+            can_count: 0,
+        }
+    }
+}
+
+impl FrameParser {
+    fn push(&mut self, bytes: &[u8]) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        for &byte in bytes {
+            if byte == ZDLE {
+                self.can_count += 1;
+                if ABORT_CAN_COUNT <= self.can_count {
+                    self.can_count = 0;
+                    self.reset();
+                    frames.push(Frame::Abort);
+                    continue;
+                }
+            } else {
+                self.can_count = 0;
+            }
+            if let Some(frame) = self.push_byte(byte) {
+                frames.push(frame);
+            }
+        }
+        frames
+    }
+
+    fn reset(&mut self) {
+        self.state = ParserState::Seeking { stage: 0 };
+        self.is_escaped = false;
+    }
+
+    fn unescape(&mut self, byte: u8) -> Option<Unescaped> {
+        // フロー制御の XON / XOFF は読み捨てる
+        if matches!(byte, 0x11 | 0x13 | 0x91 | 0x93) {
+            return None;
+        }
+        if !self.is_escaped {
+            if byte == ZDLE {
+                self.is_escaped = true;
+                return None;
+            }
+            return Some(Unescaped::Byte(byte));
+        }
+        self.is_escaped = false;
+        Some(match byte {
+            ZCRCE | ZCRCG | ZCRCQ | ZCRCW => Unescaped::FrameEnd(byte),
+            ZRUB0 => Unescaped::Byte(0x7f),
+            ZRUB1 => Unescaped::Byte(0xff),
+            byte if byte & 0x60 == 0x40 => Unescaped::Byte(byte ^ 0x40),
+            _ => Unescaped::Invalid,
+        })
+    }
+
+    fn push_byte(&mut self, byte: u8) -> Option<Frame> {
+        match &mut self.state {
+            ParserState::Seeking { stage } => {
+                *stage = match (*stage, byte) {
+                    (_, ZPAD) => 1,
+                    (1, ZDLE) => 2,
+                    (2, ZHEX) => {
+                        self.state = ParserState::HexHeader(Vec::new());
+                        return None;
+                    }
+                    (2, ZBIN | ZBIN32) => {
+                        self.state = ParserState::BinaryHeader {
+                            is_crc32: byte == ZBIN32,
+                            bytes: Vec::new(),
+                        };
+                        return None;
+                    }
+                    _ => 0,
+                };
+                None
+            }
+            ParserState::HexHeader(digits) => {
+                digits.push(byte);
+                if digits.len() < 14 {
+                    return None;
+                }
+                let bytes = std::str::from_utf8(digits).ok().and_then(hex::decode);
+                let frame = match bytes {
+                    Some(bytes) if crc16(&bytes[..5]).to_be_bytes() == bytes[5..7] => {
+                        self.header_frame(&bytes, false)
+                    }
+                    _ => {
+                        self.reset();
+                        Frame::BadHeader
+                    }
+                };
+                Some(frame)
+            }
+            ParserState::BinaryHeader { .. } => {
+                let unescaped = self.unescape(byte)?;
+                let ParserState::BinaryHeader { is_crc32, bytes } = &mut self.state else {
+                    return None;
+                };
+                let is_crc32 = *is_crc32;
+                match unescaped {
+                    Unescaped::Byte(byte) => bytes.push(byte),
+                    _ => {
+                        self.reset();
+                        return Some(Frame::BadHeader);
+                    }
+                }
+                let length = if is_crc32 { 9 } else { 7 };
+                if bytes.len() < length {
+                    return None;
+                }
+                let bytes = std::mem::take(bytes);
+                let is_valid = if is_crc32 {
+                    crc32(&bytes[..5]).to_le_bytes() == bytes[5..9]
+                } else {
+                    crc16(&bytes[..5]).to_be_bytes() == bytes[5..7]
+                };
+                if !is_valid {
+                    self.reset();
+                    return Some(Frame::BadHeader);
+                }
+                Some(self.header_frame(&bytes, is_crc32))
+            }
+            ParserState::Data { .. } => {
+                let unescaped = self.unescape(byte)?;
+                let ParserState::Data {
+                    is_crc32,
+                    data,
+                    frame_end,
+                    crc,
+                } = &mut self.state
+                else {
+                    return None;
+                };
+                match (unescaped, *frame_end) {
+                    (Unescaped::Byte(byte), None) if data.len() < MAX_SUBPACKET_LENGTH => {
+                        data.push(byte);
+                        return None;
+                    }
+                    (Unescaped::FrameEnd(end), None) => {
+                        *frame_end = Some(end);
+                        return None;
+                    }
+                    (Unescaped::Byte(byte), Some(_)) => crc.push(byte),
+                    _ => {
+                        let data = std::mem::take(data);
+                        self.reset();
+                        return Some(Frame::Data {
+                            data,
+                            frame_end: ZCRCE,
+                            is_valid: false,
+                        });
+                    }
+                }
+                let crc_length = if *is_crc32 { 4 } else { 2 };
+                if crc.len() < crc_length {
+                    return None;
+                }
+                let end = frame_end.unwrap_or(ZCRCE);
+                let mut checked = std::mem::take(data);
+                checked.push(end);
+                let is_valid = if *is_crc32 {
+                    crc32(&checked).to_le_bytes()[..] == crc[..]
+                } else {
+                    crc16(&checked).to_be_bytes()[..] == crc[..]
+                };
+                checked.pop();
+                crc.clear();
+                *frame_end = None;
+                // 続きのあるサブパケットならそのままデータを待つ
+                if !is_valid || end == ZCRCE || end == ZCRCW {
+                    self.reset();
+                }
+                Some(Frame::Data {
+                    data: checked,
+                    frame_end: end,
+                    is_valid,
+                })
+            }
+        }
+    }
+
+    fn header_frame(&mut self, bytes: &[u8], is_crc32: bool) -> Frame {
+        let header = Header {
+            kind: bytes[0],
+            data: [bytes[1], bytes[2], bytes[3], bytes[4]],
+        };
+        self.state = if matches!(header.kind, ZSINIT | ZFILE | ZDATA | ZCOMMAND) {
+            ParserState::Data {
+                is_crc32,
+                data: Vec::new(),
+                frame_end: None,
+                crc: Vec::new(),
+            }
+        } else {
+            ParserState::Seeking { stage: 0 }
+        };
+        Frame::Header(header)
+    }
+}
+
+/// 受信データの中から相手の sz の開始を見つける
+#[derive(Default)]
+pub struct AutoStartDetector {
+    tail: Vec<u8>,
+}
+
+impl AutoStartDetector {
+    pub fn push(&mut self, bytes: &[u8]) -> bool {
+        self.tail.extend_from_slice(bytes);
+        let is_found = self
+            .tail
+            .windows(AUTO_START_PATTERN.len())
+            .any(|window| window == AUTO_START_PATTERN);
+        // チャンクの境目をまたいだ場合に備えて末尾だけ残す
+        let keep = AUTO_START_PATTERN.len() - 1;
+        if is_found {
+            self.tail.clear();
+        } else if keep < self.tail.len() {
+            self.tail.drain(..self.tail.len() - keep);
+        }
+        is_found
+    }
+}
+
+/// ZFILE のサブパケット。ファイル名とサイズ
+fn file_information(file: &TransferFile) -> Vec<u8> {
+    let mut information = file.name.as_bytes().to_vec();
+    information.push(0);
+    information.extend_from_slice(file.content.len().to_string().as_bytes());
+    information.push(0);
+    information
+}
+
+fn parse_file_information(data: &[u8]) -> (String, Option<u64>) {
+    let mut fields = data.split(|&byte| byte == 0);
+    let name = String::from_utf8_lossy(fields.next().unwrap_or_default()).into_owned();
+    let size = fields
+        .next()
+        .and_then(|information| information.split(|&byte| byte == b' ').next())
+        .and_then(|size| std::str::from_utf8(size).ok())
+        .and_then(|size| size.parse().ok());
+    (name, size)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum SenderState {
+    /// ZRQINIT を送り、ZRINIT を待っている
+    WaitingInit,
+    /// ZFILE を送り、ZRPOS を待っている
+    WaitingPosition,
+    Sending,
+    /// ZEOF を送り、次の ZRINIT を待っている
+    WaitingEofAck,
+    /// ZFIN を送り、ZFIN を待っている
+    WaitingFin,
+    Done,
+}
+
+/// ZMODEM でファイルを送る (sz に相当)
+pub struct ZmodemSender {
+    parser: FrameParser,
+    files: VecDeque<TransferFile>,
+    current: Option<TransferFile>,
+    offset: usize,
+    /// 受信側が ZACK で受け取ったと伝えてきた位置
+    acknowledged: usize,
+    state: SenderState,
+    /// ZNAK やタイムアウトで再送するヘッダ
+    last_header: Vec<u8>,
+    is_started: bool,
+    waiting_since: Option<DateTime<Local>>,
+    errors: u32,
+    status: TransferStatus,
+}
+
+impl ZmodemSender {
+    pub fn new(files: Vec<TransferFile>) -> Self {
+        Self {
+            parser: FrameParser::default(),
+            files: files.into(),
+            current: None,
+            offset: 0,
+            acknowledged: 0,
+            state: SenderState::WaitingInit,
+            last_header: Vec::new(),
+            is_started: false,
+            waiting_since: None,
+            errors: 0,
+            status: TransferStatus::default(),
+        }
+    }
+
+    fn fail(&mut self, message: String) -> Vec<u8> {
+        self.status.state = TransferState::Failed(message);
+        self.state = SenderState::Done;
+        CANCEL_SEQUENCE.to_vec()
+    }
+
+    fn content_length(&self) -> usize {
+        self.current.as_ref().map_or(0, |file| file.content.len())
+    }
+
+    fn send_next_file(&mut self) -> Vec<u8> {
+        let Some(file) = self.files.pop_front() else {
+            self.state = SenderState::WaitingFin;
+            self.last_header = Header::new(ZFIN).encode_hex();
+            return self.last_header.clone();
+        };
+        self.status.file_name = Some(file.name.clone());
+        self.status.bytes_total = Some(file.content.len() as u64);
+        self.status.bytes_done = 0;
+        self.offset = 0;
+        self.acknowledged = 0;
+        self.state = SenderState::WaitingPosition;
+        self.last_header = Header::with_flags(ZFILE, ZCBIN).encode_binary();
+        self.last_header
+            .extend(encode_subpacket(&file_information(&file), ZCRCW));
+        self.current = Some(file);
+        self.last_header.clone()
+    }
+
+    /// 指定された位置から送り直す
+    fn start_data(&mut self, position: usize) -> Vec<u8> {
+        self.offset = position.min(self.content_length());
+        self.acknowledged = self.offset;
+        self.state = SenderState::Sending;
+        let mut bytes = Header::with_position(ZDATA, self.offset).encode_binary();
+        bytes.extend(self.stream());
+        bytes
+    }
+
+    /// ウィンドウに収まる範囲で、BURST_LENGTH までデータを送る。最後まで送ったら ZEOF を続ける
+    fn stream(&mut self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let Some(file) = &self.current else {
+            return bytes;
+        };
+        let length = file.content.len();
+        let burst_end = self.offset + BURST_LENGTH;
+        while self.state == SenderState::Sending {
+            if length <= self.offset {
+                if self.offset == 0 {
+                    // 空のファイルもデータフレームを閉じてから ZEOF を送る
+                    bytes.extend(encode_subpacket(&[], ZCRCE));
+                }
+                self.last_header = Header::with_position(ZEOF, length).encode_binary();
+                bytes.extend(&self.last_header);
+                self.state = SenderState::WaitingEofAck;
+                break;
+            }
+            if WINDOW_SIZE <= self.offset - self.acknowledged || burst_end <= self.offset {
+                break;
+            }
+            let start = self.offset;
+            let end = (start + SUBPACKET_LENGTH).min(length);
+            let chunk = &file.content[start..end];
+            self.offset = end;
+            // ZRPOS で半端な位置から始めても区切りをまたいだら ZACK を求める
+            let frame_end = if end == length {
+                ZCRCE
+            } else if start / ACK_INTERVAL != end / ACK_INTERVAL {
+                ZCRCQ
+            } else {
+                ZCRCG
+            };
+            bytes.extend(encode_subpacket(chunk, frame_end));
+        }
+        self.status.bytes_done = self.offset as u64;
+        bytes
+    }
+
+    /// ウィンドウに空きがあり、まだ送るデータが残っているか
+    fn can_stream(&self) -> bool {
+        self.state == SenderState::Sending && self.offset - self.acknowledged < WINDOW_SIZE
+    }
+
+    fn handle_header(&mut self, header: Header) -> Vec<u8> {
+        match (header.kind, self.state) {
+            (ZRINIT, SenderState::WaitingInit) => self.send_next_file(),
+            (ZRINIT, SenderState::WaitingEofAck) => {
+                self.status.files_done += 1;
+                self.send_next_file()
+            }
+            (ZRPOS, SenderState::WaitingPosition) => self.start_data(header.position()),
+            (ZRPOS, SenderState::Sending | SenderState::WaitingEofAck) => {
+                // 受信側が誤りを見つけて送り直しを求めてきた
+                self.errors += 1;
+                self.status.retries += 1;
+                if MAX_ERRORS < self.errors {
+                    return self.fail("Too many retries".to_string());
+                }
+                self.start_data(header.position())
+            }
+            (ZACK, SenderState::Sending) => {
+                self.errors = 0;
+                self.acknowledged = self.acknowledged.max(header.position());
+                self.stream()
+            }
+            (ZSKIP, SenderState::WaitingPosition | SenderState::Sending) => {
+                self.status.files_done += 1;
+                self.send_next_file()
+            }
+            (ZFIN, SenderState::WaitingFin) => {
+                self.status.state = TransferState::Completed;
+                self.state = SenderState::Done;
+                b"OO".to_vec()
+            }
+            (ZNAK, _) => {
+                self.status.retries += 1;
+                self.last_header.clone()
+            }
+            (ZABORT | ZFERR, _) => self.fail("The receiver aborted".to_string()),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Transfer for ZmodemSender {
+    fn feed(&mut self, now: DateTime<Local>, bytes: &[u8]) -> Vec<u8> {
+        let mut reply = Vec::new();
+        for frame in self.parser.push(bytes) {
+            if self.state == SenderState::Done {
+                break;
+            }
+            self.waiting_since = Some(now);
+            match frame {
+                Frame::Header(header) => reply.extend(self.handle_header(header)),
+                Frame::Abort => {
+                    self.status.state =
+                        TransferState::Cancelled("Cancelled by the receiver".to_string());
+                    self.state = SenderState::Done;
+                }
+                Frame::BadHeader | Frame::Data { .. } => {}
+            }
+        }
+        reply
+    }
+
+    fn poll(&mut self, now: DateTime<Local>) -> Vec<u8> {
+        if self.state == SenderState::Done {
+            return Vec::new();
+        }
+        if !self.is_started {
+            // 相手がシェルなら rz を起動させる
+            self.is_started = true;
+            self.waiting_since = Some(now);
+            self.last_header = Header::new(ZRQINIT).encode_hex();
+            let mut bytes = b"rz\r".to_vec();
+            bytes.extend(&self.last_header);
+            return bytes;
+        }
+        if self.can_stream() {
+            return self.stream();
+        }
+        let since = *self.waiting_since.get_or_insert(now);
+        if now - since < TimeDelta::milliseconds(TIMEOUT_MS) {
+            return Vec::new();
+        }
+        self.waiting_since = Some(now);
+        self.errors += 1;
+        self.status.retries += 1;
+        if MAX_ERRORS < self.errors {
+            return self.fail("No response from the receiver".to_string());
+        }
+        match self.state {
+            // ZACK が届かないときは、受け取られた位置から送り直す
+            SenderState::Sending => self.start_data(self.acknowledged),
+            _ => self.last_header.clone(),
+        }
+    }
+
+    fn next_poll(&self, now: DateTime<Local>) -> Option<std::time::Duration> {
+        if self.state == SenderState::Done {
+            return None;
+        }
+        if self.can_stream() {
+            return Some(std::time::Duration::ZERO);
+        }
+        let deadline = self.waiting_since.unwrap_or(now) + TimeDelta::milliseconds(TIMEOUT_MS);
+        Some((deadline - now).to_std().unwrap_or_default())
+    }
+
+    fn cancel(&mut self) -> Vec<u8> {
+        self.status.state = TransferState::Cancelled("Cancelled".to_string());
+        self.state = SenderState::Done;
+        CANCEL_SEQUENCE.to_vec()
+    }
+
+    fn status(&self) -> &TransferStatus {
+        &self.status
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ReceiverState {
+    /// ZRINIT を送り、ZFILE を待っている
+    WaitingFile,
+    /// ZFILE のサブパケットを待っている
+    ReadingFileInformation,
+    /// ZSINIT のサブパケットを待っている
+    ReadingSenderInit,
+    Receiving,
+    Done,
+}
+
+/// ZMODEM でファイルを受け取る (rz に相当)
+pub struct ZmodemReceiver {
+    parser: FrameParser,
+    state: ReceiverState,
+    file: Option<TransferFile>,
+    /// 誤りの後、次の ZDATA までデータを読み捨てる
+    is_discarding: bool,
+    is_started: bool,
+    last_activity: Option<DateTime<Local>>,
+    errors: u32,
+    files: Vec<TransferFile>,
+    status: TransferStatus,
+}
+
+impl Default for ZmodemReceiver {
+    fn default() -> Self {
+        Self {
+            parser: FrameParser::default(),
+            state: ReceiverState::WaitingFile,
+            file: None,
+            is_discarding: false,
+            is_started: false,
+            last_activity: None,
+            errors: 0,
+            files: Vec::new(),
+            status: TransferStatus::default(),
+        }
+    }
+}
+
+impl ZmodemReceiver {
+    fn offset(&self) -> usize {
+        self.file.as_ref().map_or(0, |file| file.content.len())
+    }
+
+    fn init_header() -> Vec<u8> {
+        Header::with_flags(ZRINIT, CANFDX | CANOVIO).encode_hex()
+    }
+
+    fn request_resend(&mut self) -> Vec<u8> {
+        self.errors += 1;
+        self.status.retries += 1;
+        if MAX_ERRORS < self.errors {
+            self.status.state = TransferState::Failed("Too many errors".to_string());
+            self.state = ReceiverState::Done;
+            return CANCEL_SEQUENCE.to_vec();
+        }
+        self.is_discarding = true;
+        Header::with_position(ZRPOS, self.offset()).encode_hex()
+    }
+
+    fn handle_header(&mut self, header: Header) -> Vec<u8> {
+        match header.kind {
+            ZRQINIT => Self::init_header(),
+            ZSINIT => {
+                self.state = ReceiverState::ReadingSenderInit;
+                Vec::new()
+            }
+            ZFILE => {
+                self.state = ReceiverState::ReadingFileInformation;
+                Vec::new()
+            }
+            ZDATA if self.state == ReceiverState::Receiving => {
+                if header.position() != self.offset() {
+                    return self.request_resend();
+                }
+                self.is_discarding = false;
+                Vec::new()
+            }
+            ZEOF if self.state == ReceiverState::Receiving => {
+                // 位置が合わない ZEOF は古いものとして無視する
+                if header.position() != self.offset() {
+                    return Vec::new();
+                }
+                if let Some(file) = self.file.take() {
+                    self.status.files_done += 1;
+                    self.files.push(file);
+                }
+                self.state = ReceiverState::WaitingFile;
+                Self::init_header()
+            }
+            ZFIN => {
+                self.status.state = TransferState::Completed;
+                self.state = ReceiverState::Done;
+                Header::new(ZFIN).encode_hex()
+            }
+            // 相手に任意のコマンドを実行させる要求には応じない
+            ZCOMMAND => {
+                self.state = ReceiverState::WaitingFile;
+                Header::new(ZNAK).encode_hex()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn handle_data(&mut self, data: Vec<u8>, frame_end: u8, is_valid: bool) -> Vec<u8> {
+        match self.state {
+            ReceiverState::ReadingSenderInit => {
+                self.state = ReceiverState::WaitingFile;
+                if is_valid {
+                    Header::new(ZACK).encode_hex()
+                } else {
+                    Header::new(ZNAK).encode_hex()
+                }
+            }
+            ReceiverState::ReadingFileInformation => {
+                if !is_valid {
+                    self.state = ReceiverState::WaitingFile;
+                    return Header::new(ZNAK).encode_hex();
+                }
+                let (name, size) = parse_file_information(&data);
+                self.status.file_name = Some(name.clone());
+                self.status.bytes_total = size;
+                self.status.bytes_done = 0;
+                self.file = Some(TransferFile {
+                    name,
+                    content: Vec::new(),
+                });
+                self.is_discarding = false;
+                self.state = ReceiverState::Receiving;
+                Header::with_position(ZRPOS, 0).encode_hex()
+            }
+            ReceiverState::Receiving if !self.is_discarding => {
+                if !is_valid {
+                    return self.request_resend();
+                }
+                self.errors = 0;
+                if let Some(file) = &mut self.file {
+                    file.content.extend_from_slice(&data);
+                    self.status.bytes_done = file.content.len() as u64;
+                }
+                if frame_end == ZCRCQ || frame_end == ZCRCW {
+                    Header::with_position(ZACK, self.offset()).encode_hex()
+                } else {
+                    Vec::new()
+                }
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Transfer for ZmodemReceiver {
+    fn feed(&mut self, now: DateTime<Local>, bytes: &[u8]) -> Vec<u8> {
+        let mut reply = Vec::new();
+        for frame in self.parser.push(bytes) {
+            if self.state == ReceiverState::Done {
+                break;
+            }
+            self.last_activity = Some(now);
+            match frame {
+                Frame::Header(header) => reply.extend(self.handle_header(header)),
+                Frame::BadHeader if self.state == ReceiverState::Receiving => {
+                    reply.extend(self.request_resend());
+                }
+                Frame::BadHeader => {
+                    self.status.retries += 1;
+                    reply.extend(Header::new(ZNAK).encode_hex());
+                }
+                Frame::Data {
+                    data,
+                    frame_end,
+                    is_valid,
+                } => reply.extend(self.handle_data(data, frame_end, is_valid)),
+                Frame::Abort => {
+                    self.status.state =
+                        TransferState::Cancelled("Cancelled by the sender".to_string());
+                    self.state = ReceiverState::Done;
+                }
+            }
+        }
+        reply
+    }
+
+    fn poll(&mut self, now: DateTime<Local>) -> Vec<u8> {
+        if self.state == ReceiverState::Done {
+            return Vec::new();
+        }
+        if !self.is_started {
+            self.is_started = true;
+            self.last_activity = Some(now);
+            return Self::init_header();
+        }
+        let last_activity = *self.last_activity.get_or_insert(now);
+        if now - last_activity < TimeDelta::milliseconds(TIMEOUT_MS) {
+            return Vec::new();
+        }
+        self.last_activity = Some(now);
+        match self.state {
+            ReceiverState::Receiving => self.request_resend(),
+            _ => {
+                self.errors += 1;
+                self.status.retries += 1;
+                if MAX_ERRORS < self.errors {
+                    self.status.state =
+                        TransferState::Failed("No response from the sender".to_string());
+                    self.state = ReceiverState::Done;
+                    return CANCEL_SEQUENCE.to_vec();
+                }
+                Self::init_header()
+            }
+        }
+    }
+
+    fn next_poll(&self, now: DateTime<Local>) -> Option<std::time::Duration> {
+        if self.state == ReceiverState::Done {
+            return None;
+        }
+        let deadline = self.last_activity.unwrap_or(now) + TimeDelta::milliseconds(TIMEOUT_MS);
+        Some((deadline - now).to_std().unwrap_or_default())
+    }
+
+    fn cancel(&mut self) -> Vec<u8> {
+        self.status.state = TransferState::Cancelled("Cancelled".to_string());
+        self.state = ReceiverState::Done;
+        CANCEL_SEQUENCE.to_vec()
+    }
+
+    fn status(&self) -> &TransferStatus {
+        &self.status
+    }
+
+    fn take_received_files(&mut self) -> Vec<TransferFile> {
+        std::mem::take(&mut self.files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // lrzsz の sz / rz が送るバイト列を、ZMODEM の仕様どおりに組み立てたもの
+    const ZRQINIT_HEX: &[u8] = b"**\x18B00000000000000\r\x8a\x11";
+    /// rz の ZRINIT。CANFC32 | CANOVIO | CANFDX
+    const RZ_ZRINIT_HEX: &[u8] = b"**\x18B0100000023be50\r\x8a\x11";
+    /// こちらの ZRINIT。CANOVIO | CANFDX
+    const ZRINIT_HEX: &[u8] = b"**\x18B01000000039a32\r\x8a\x11";
+    const ZRPOS_0_HEX: &[u8] = b"**\x18B0900000000a87c\r\x8a\x11";
+    const ZFIN_HEX: &[u8] = b"**\x18B0800000000022d\r\x8a";
+
+    /// ZDLE と XON をエスケープさせる内容
+    const CONTENT: &[u8] = b"Hello, \x18ZMODEM\x11\n";
+    /// ZFILE (ZF0 = ZCBIN) の CRC-16 バイナリヘッダ
+    const ZFILE_HEADER: &[u8] = b"*\x18A\x04\x00\x00\x00\x01\x99'";
+    /// ZDATA (位置 0) のヘッダと、CONTENT を ZCRCE で閉じたサブパケット
+    const ZDATA_FRAME: &[u8] =
+        b"*\x18A\x0a\x00\x00\x00\x00F\xaeHello, \x18XZMODEM\x18Q\n\x18hg\xb7";
+    /// ZEOF (位置 16)。0x10 はエスケープする
+    const ZEOF_HEADER: &[u8] = b"*\x18A\x0b\x18P\x00\x00\x00\xf7X";
+
+    fn hi_txt() -> TransferFile {
+        TransferFile {
+            name: "hi.txt".to_string(),
+            content: CONTENT.to_vec(),
+        }
+    }
+
+    #[test]
+    fn receives_from_sz() {
+        let mut receiver = ZmodemReceiver::default();
+        let now = Local::now();
+        assert_eq!(
+            receiver.feed(now, &[b"rz\r", ZRQINIT_HEX].concat()),
+            ZRINIT_HEX
+        );
+
+        // sz はファイル名とサイズに続けて更新時刻、モードなども送る
+        let file = [
+            ZFILE_HEADER,
+            b"hi.txt\x0016 14720132435 100644 0 1 16\x00\x18kS\x0a",
+            b"\x11",
+        ]
+        .concat();
+        assert_eq!(receiver.feed(now, &file), ZRPOS_0_HEX);
+        assert_eq!(receiver.status().bytes_total, Some(16));
+
+        let data = [ZDATA_FRAME, ZEOF_HEADER].concat();
+        assert_eq!(receiver.feed(now, &data), ZRINIT_HEX);
+        assert_eq!(receiver.feed(now, ZFIN_HEX), ZFIN_HEX);
+        assert_eq!(receiver.feed(now, b"OO"), b"");
+
+        assert_eq!(receiver.status().state, TransferState::Completed);
+        assert_eq!(receiver.take_received_files(), [hi_txt()]);
+    }
+
+    #[test]
+    fn receiver_acknowledges_zcrcw() {
+        let mut receiver = ZmodemReceiver::default();
+        let now = Local::now();
+        let file = [ZFILE_HEADER, b"hi.txt\x0016\x00\x18k\xed\xb6"].concat();
+        assert_eq!(receiver.feed(now, &file), ZRPOS_0_HEX);
+        // "Hello, \x18ZMODEM\x11\n" を ZCRCW で閉じたサブパケット
+        let data = b"*\x18A\x0a\x00\x00\x00\x00F\xaeHello, \x18XZMODEM\x18Q\n\x18kW\xd4";
+        // ZACK (位置 16)
+        assert_eq!(receiver.feed(now, data), b"**\x18B0310000000f575\r\x8a");
+    }
+
+    #[test]
+    fn sends_to_rz() {
+        let mut sender = ZmodemSender::new(vec![hi_txt()]);
+        let now = Local::now();
+        assert_eq!(sender.poll(now), [b"rz\r", ZRQINIT_HEX].concat());
+
+        let file = [ZFILE_HEADER, b"hi.txt\x0016\x00\x18k\xed\xb6"].concat();
+        assert_eq!(sender.feed(now, RZ_ZRINIT_HEX), file);
+        assert_eq!(
+            sender.feed(now, ZRPOS_0_HEX),
+            [ZDATA_FRAME, ZEOF_HEADER].concat()
+        );
+        assert_eq!(sender.feed(now, RZ_ZRINIT_HEX), ZFIN_HEX);
+        assert_eq!(sender.feed(now, ZFIN_HEX), b"OO");
+        assert_eq!(sender.status().state, TransferState::Completed);
+        assert_eq!(sender.status().files_done, 1);
+    }
+
+    #[test]
+    fn sender_resends_from_requested_position() {
+        let mut sender = ZmodemSender::new(vec![hi_txt()]);
+        let now = Local::now();
+        sender.poll(now);
+        sender.feed(now, RZ_ZRINIT_HEX);
+        sender.feed(now, ZRPOS_0_HEX);
+        // ZRPOS (位置 7) で途中から送り直す
+        let resent = sender.feed(now, b"**\x18B0907000000f951\r\x8a\x11");
+        let expected = [
+            &b"*\x18A\x0a\x07\x00\x00\x00\x17\x83"[..],
+            b"\x18XZMODEM\x18Q\n\x18h\xb5q",
+            ZEOF_HEADER,
+        ]
+        .concat();
+        assert_eq!(resent, expected);
+        assert_eq!(sender.status().retries, 1);
+    }
+}
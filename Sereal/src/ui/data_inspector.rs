@@ -0,0 +1,333 @@
+use crate::decoder::decoded_frame::to_hex;
+use crate::hex;
+use chrono::{DateTime, Local};
+use eframe::egui;
+
+/// 解釈の対象にするバイト数の上限
+pub const INSPECT_MAX_BYTES: usize = 256;
+
+/// タブで選択したバイト列と、選択したタブの名前
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Selection {
+    pub source: String,
+    pub bytes: Vec<u8>,
+}
+
+/// 選択したバイト列を数値や文字列として解釈して表示するドックのタブ
+///
+/// 16 進の入力欄は選択に合わせて更新され、手で書き換えて解釈することもできる。
+#[derive(Default)]
+pub struct DataInspector {
+    hex_input: String,
+    last_selection: Selection,
+}
+
+impl DataInspector {
+    pub fn ui(&mut self, ui: &mut egui::Ui, selection: &Selection) {
+        if &self.last_selection != selection {
+            self.last_selection = selection.clone();
+            self.hex_input = to_hex(&selection.bytes);
+        }
+
+        ui.heading("Data inspector");
+        if !selection.source.is_empty() {
+            ui.weak(format!("Selection in {}", selection.source));
+        }
+        ui.add(
+            egui::TextEdit::multiline(&mut self.hex_input)
+                .font(egui::TextStyle::Monospace)
+                .desired_rows(2)
+                .desired_width(f32::INFINITY)
+                .hint_text("Select bytes in the hex dump, or type hex here"),
+        );
+        let bytes = match parse_hex(&self.hex_input) {
+            Some(mut bytes) => {
+                bytes.truncate(INSPECT_MAX_BYTES);
+                bytes
+            }
+            None => {
+                ui.colored_label(ui.visuals().warn_fg_color, "Not a valid hex string");
+                return;
+            }
+        };
+        ui.weak(format!("{} byte(s)", bytes.len()));
+        ui.separator();
+
+        egui::ScrollArea::vertical()
+            .auto_shrink(false)
+            .show(ui, |ui| {
+                show_numbers(ui, &bytes);
+                ui.separator();
+                show_bits(ui, &bytes);
+                ui.separator();
+                show_strings(ui, &bytes);
+                ui.separator();
+                show_timestamps(ui, &bytes);
+            });
+    }
+}
+
+fn show_numbers(ui: &mut egui::Ui, bytes: &[u8]) {
+    egui::Grid::new("inspector_numbers")
+        .num_columns(3)
+        .striped(true)
+        .show(ui, |ui| {
+            ui.strong("Type");
+            ui.strong("Little endian");
+            ui.strong("Big endian");
+            ui.end_row();
+
+            number_row(
+                ui,
+                "u8",
+                bytes,
+                |b: [u8; 1]| b[0].to_string(),
+                |b| b[0].to_string(),
+            );
+            number_row(
+                ui,
+                "i8",
+                bytes,
+                |b: [u8; 1]| (b[0] as i8).to_string(),
+                |b| (b[0] as i8).to_string(),
+            );
+            number_row(
+                ui,
+                "u16",
+                bytes,
+                |b| u16::from_le_bytes(b).to_string(),
+                |b| u16::from_be_bytes(b).to_string(),
+            );
+            number_row(
+                ui,
+                "i16",
+                bytes,
+                |b| i16::from_le_bytes(b).to_string(),
+                |b| i16::from_be_bytes(b).to_string(),
+            );
+            number_row(
+                ui,
+                "u32",
+                bytes,
+                |b| u32::from_le_bytes(b).to_string(),
+                |b| u32::from_be_bytes(b).to_string(),
+            );
+            number_row(
+                ui,
+                "i32",
+                bytes,
+                |b| i32::from_le_bytes(b).to_string(),
+                |b| i32::from_be_bytes(b).to_string(),
+            );
+            number_row(
+                ui,
+                "u64",
+                bytes,
+                |b| u64::from_le_bytes(b).to_string(),
+                |b| u64::from_be_bytes(b).to_string(),
+            );
+            number_row(
+                ui,
+                "i64",
+                bytes,
+                |b| i64::from_le_bytes(b).to_string(),
+                |b| i64::from_be_bytes(b).to_string(),
+            );
+            number_row(
+                ui,
+                "f32",
+                bytes,
+                |b| f32::from_le_bytes(b).to_string(),
+                |b| f32::from_be_bytes(b).to_string(),
+            );
+            number_row(
+                ui,
+                "f64",
+                bytes,
+                |b| f64::from_le_bytes(b).to_string(),
+                |b| f64::from_be_bytes(b).to_string(),
+            );
+        });
+}
+
+/// 先頭の N バイトを両方のバイト順で解釈した 1 行。足りなければ空欄にする
+fn number_row<const N: usize>(
+    ui: &mut egui::Ui,
+    name: &str,
+    bytes: &[u8],
+    little_endian: impl Fn([u8; N]) -> String,
+    big_endian: impl Fn([u8; N]) -> String,
+) {
+    ui.monospace(name);
+    match bytes.first_chunk::<N>() {
+        Some(&array) => {
+            ui.monospace(little_endian(array));
+            ui.monospace(big_endian(array));
+        }
+        None => {
+            ui.weak("—");
+            ui.weak("—");
+        }
+    }
+    ui.end_row();
+}
+
+fn show_bits(ui: &mut egui::Ui, bytes: &[u8]) {
+    const MAX_BYTES: usize = 8;
+    ui.strong("Bits");
+    egui::Grid::new("inspector_bits")
+        .num_columns(2)
+        .striped(true)
+        .show(ui, |ui| {
+            ui.weak("Byte");
+            ui.weak("7654 3210");
+            ui.end_row();
+            for (index, byte) in bytes.iter().take(MAX_BYTES).enumerate() {
+                ui.monospace(format!("[{index}] {byte:02X}"));
+                ui.monospace(format!("{:04b} {:04b}", byte >> 4, byte & 0x0f));
+                ui.end_row();
+            }
+        });
+    if MAX_BYTES < bytes.len() {
+        ui.weak(format!("First {MAX_BYTES} bytes only"));
+    }
+}
+
+fn show_strings(ui: &mut egui::Ui, bytes: &[u8]) {
+    let utf16 = |to_unit: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| to_unit([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16(&units).ok()
+    };
+    egui::Grid::new("inspector_strings")
+        .num_columns(2)
+        .striped(true)
+        .show(ui, |ui| {
+            string_row(
+                ui,
+                "UTF-8",
+                std::str::from_utf8(bytes).ok().map(str::to_string),
+            );
+            string_row(ui, "UTF-16 LE", utf16(u16::from_le_bytes));
+            string_row(ui, "UTF-16 BE", utf16(u16::from_be_bytes));
+        });
+}
+
+fn string_row(ui: &mut egui::Ui, name: &str, text: Option<String>) {
+    ui.monospace(name);
+    match text {
+        // 制御文字はエスケープして見えるようにする
+        Some(text) => ui.monospace(format!("{text:?}")),
+        None => ui.weak("invalid"),
+    };
+    ui.end_row();
+}
+
+fn show_timestamps(ui: &mut egui::Ui, bytes: &[u8]) {
+    egui::Grid::new("inspector_timestamps")
+        .num_columns(3)
+        .striped(true)
+        .show(ui, |ui| {
+            ui.strong("Unix time");
+            ui.strong("Little endian");
+            ui.strong("Big endian");
+            ui.end_row();
+
+            number_row(
+                ui,
+                "u32 seconds",
+                bytes,
+                |b| {
+                    format_timestamp(DateTime::from_timestamp(
+                        i64::from(u32::from_le_bytes(b)),
+                        0,
+                    ))
+                },
+                |b| {
+                    format_timestamp(DateTime::from_timestamp(
+                        i64::from(u32::from_be_bytes(b)),
+                        0,
+                    ))
+                },
+            );
+            number_row(
+                ui,
+                "i64 milliseconds",
+                bytes,
+                |b| format_timestamp(DateTime::from_timestamp_millis(i64::from_le_bytes(b))),
+                |b| format_timestamp(DateTime::from_timestamp_millis(i64::from_be_bytes(b))),
+            );
+        });
+}
+
+fn format_timestamp(time: Option<DateTime<chrono::Utc>>) -> String {
+    match time {
+        Some(time) => time
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M:%S%.3f")
+            .to_string(),
+        None => "out of range".to_string(),
+    }
+}
+
+/// 空白やカンマ、0x 接頭辞を含む 16 進の文字列をバイト列に変換する
+///
+/// 区切りごとのトークンを別々に変換するため、各トークンは偶数桁でなければならない。
+pub fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    for token in text
+        .split(|ch: char| ch.is_whitespace() || ch == ',')
+        .filter(|token| !token.is_empty())
+    {
+        let digits = token
+            .strip_prefix("0x")
+            .or_else(|| token.strip_prefix("0X"))
+            .unwrap_or(token);
+        if digits.is_empty() {
+            return None;
+        }
+        bytes.extend(hex::decode(digits)?);
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trip() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(parse_hex(&to_hex(&bytes)), Some(bytes));
+        assert_eq!(to_hex(&[]), "");
+        assert_eq!(parse_hex(""), Some(Vec::new()));
+    }
+
+    #[test]
+    fn hex_separators() {
+        let expected = Some(vec![0x01, 0xab, 0xcd]);
+        assert_eq!(parse_hex("01abcd"), expected);
+        assert_eq!(parse_hex("0x01, 0xAB,0Xcd"), expected);
+        assert_eq!(parse_hex(" 01\tAB\ncd "), expected);
+        // 区切りのない 2 桁ずつの並びも 1 バイトずつに分ける
+        assert_eq!(parse_hex("0x01ABcd"), expected);
+    }
+
+    #[test]
+    fn malformed_hex() {
+        for text in ["1", "zz", "+1", "+1+2", "-1", "0x0x12", "é1", "12 3", "0x"] {
+            assert_eq!(parse_hex(text), None, "{text}");
+        }
+    }
+
+    #[test]
+    fn tokens_are_parsed_separately() {
+        // トークンをつなげて 0x12 にしない
+        assert_eq!(parse_hex("1 2"), None);
+        assert_eq!(parse_hex("0x1,0x2"), None);
+        assert_eq!(parse_hex("012 3"), None);
+        assert_eq!(parse_hex("0102 03"), Some(vec![0x01, 0x02, 0x03]));
+    }
+}
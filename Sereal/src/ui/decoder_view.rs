@@ -0,0 +1,309 @@
+use crate::ansi_formatter::LOG_FONT;
+use crate::decoder::{DECODERS, DecodedFrame, Decoder, DecoderContext, Direction, Field};
+use crate::sereal_colors;
+use chrono::{DateTime, Local};
+use eframe::egui;
+use egui::text::{LayoutJob, TextFormat};
+use std::collections::VecDeque;
+
+const DECODED_MAX_FRAMES: usize = 10_000;
+
+/// デコーダが解釈したフレームを一覧と木構造で表示するパネル
+///
+/// 一覧で選んだフレームのフィールドを下段に展開して表示する。
+#[derive(Default)]
+pub struct DecoderView {
+    decoder_index: Option<usize>, // DECODERS の位置。None ならデコードしない
+    decoder: Option<Box<dyn Decoder>>,
+    context: Option<DecoderContext>,
+    frames: VecDeque<DecodedFrame>,
+    first_number: usize,     // frames の先頭の通し番号
+    selected: Option<usize>, // 選択中のフレームの通し番号
+    is_hide_tx: bool,
+}
+
+impl DecoderView {
+    /// 接続の設定を反映する。変わっていればデコーダを作り直す
+    pub fn set_context(&mut self, context: DecoderContext) {
+        if self.context.as_ref() != Some(&context) {
+            self.context = Some(context);
+            self.create_decoder();
+        }
+    }
+
+    pub fn feed(&mut self, direction: Direction, time: DateTime<Local>, bytes: &[u8]) {
+        if let Some(decoder) = self.decoder.as_mut() {
+            let frames = decoder.feed(direction, time, bytes);
+            self.push(frames);
+        }
+    }
+
+    pub fn poll(&mut self, now: DateTime<Local>) {
+        if let Some(decoder) = self.decoder.as_mut() {
+            let frames = decoder.poll(now);
+            self.push(frames);
+        }
+    }
+
+    /// 次に poll を呼ぶまでの時間
+    pub fn next_poll(&self) -> Option<std::time::Duration> {
+        self.decoder.as_ref()?.next_poll()
+    }
+
+    pub fn clear(&mut self) {
+        self.first_number += self.frames.len();
+        self.frames.clear();
+        self.selected = None;
+        self.create_decoder();
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let selected_name = self
+                .decoder_index
+                .map_or("None", |index| DECODERS[index].name);
+            let mut decoder_index = self.decoder_index;
+            egui::ComboBox::from_id_salt(ui.id().with("decoder"))
+                .selected_text(selected_name)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut decoder_index, None, "None");
+                    for (index, info) in DECODERS.iter().enumerate() {
+                        ui.selectable_value(&mut decoder_index, Some(index), info.name);
+                    }
+                });
+            if decoder_index != self.decoder_index {
+                self.decoder_index = decoder_index;
+                self.create_decoder();
+            }
+            ui.checkbox(&mut self.is_hide_tx, "Hide TX");
+            ui.weak(format!("{} frame(s)", self.frames.len()));
+        });
+        ui.separator();
+
+        egui::TopBottomPanel::bottom(ui.id().with("decoded_fields"))
+            .resizable(true)
+            .default_height(200.0)
+            .show_inside(ui, |ui| {
+                egui::ScrollArea::both()
+                    .auto_shrink(false)
+                    .show(ui, |ui| self.show_selected(ui));
+            });
+
+        self.show_list(ui);
+    }
+
+    fn push(&mut self, frames: Vec<DecodedFrame>) {
+        self.frames.extend(frames);
+        while DECODED_MAX_FRAMES < self.frames.len() {
+            self.frames.pop_front();
+            self.first_number += 1;
+        }
+    }
+
+    fn create_decoder(&mut self) {
+        self.decoder = match (self.decoder_index, self.context.as_ref()) {
+            (Some(index), Some(context)) => Some((DECODERS[index].create)(context)),
+            _ => None,
+        };
+    }
+
+    fn show_list(&mut self, ui: &mut egui::Ui) {
+        let visible: Vec<usize> = (0..self.frames.len())
+            .filter(|&index| !self.is_hide_tx || self.frames[index].direction != Direction::Tx)
+            .collect();
+        ui.scope(|ui| {
+            ui.spacing_mut().item_spacing = egui::Vec2 { x: 0.0, y: 0.0 };
+            let row_height = ui.fonts(|fonts| fonts.row_height(&LOG_FONT));
+            egui::ScrollArea::both()
+                .auto_shrink(false)
+                .stick_to_bottom(true)
+                .show_rows(ui, row_height, visible.len(), |ui, row_range| {
+                    for &index in &visible[row_range] {
+                        let number = self.first_number + index;
+                        let job = layout_summary(&self.frames[index], ui);
+                        if ui
+                            .add(egui::Button::selectable(self.selected == Some(number), job))
+                            .clicked()
+                        {
+                            self.selected = Some(number);
+                        }
+                    }
+                });
+        });
+    }
+
+    fn show_selected(&self, ui: &mut egui::Ui) {
+        let Some(frame) = self
+            .selected
+            .and_then(|number| number.checked_sub(self.first_number))
+            .and_then(|index| self.frames.get(index))
+        else {
+            ui.weak("Select a frame to see its fields");
+            return;
+        };
+
+        ui.label(format!(
+            "{} {} {} B",
+            frame.time.format("%H:%M:%S%.3f"),
+            frame.direction.name(),
+            frame.bytes.len()
+        ));
+        for error in &frame.errors {
+            ui.colored_label(sereal_colors::UI_RED.to_egui_color32(), error);
+        }
+        egui::Grid::new(ui.id().with("fields"))
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                for (index, field) in frame.fields.iter().enumerate() {
+                    show_field(ui, field, ui.id().with("field").with(index), 0);
+                }
+            });
+        egui::CollapsingHeader::new("Raw bytes")
+            .id_salt(ui.id().with("raw"))
+            .show(ui, |ui| {
+                let hex: Vec<String> = frame
+                    .bytes
+                    .iter()
+                    .map(|byte| format!("{byte:02X}"))
+                    .collect();
+                ui.add(egui::Label::new(egui::RichText::new(hex.join(" ")).monospace()).wrap());
+            });
+    }
+}
+
+/// フィールドを表の 1 行として表示する。子を持つフィールドは折りたためるようにする
+fn show_field(ui: &mut egui::Ui, field: &Field, id: egui::Id, depth: usize) {
+    let mut state =
+        egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, true);
+    ui.horizontal(|ui| {
+        ui.add_space(depth as f32 * ui.spacing().indent);
+        if field.children.is_empty() {
+            ui.add_space(ui.spacing().icon_width);
+        } else {
+            let symbol = if state.is_open() { "⏷" } else { "⏵" };
+            if ui
+                .add(egui::Button::new(symbol).frame(false).small())
+                .clicked()
+            {
+                state.toggle(ui);
+            }
+        }
+        ui.label(&field.name);
+    });
+    ui.monospace(&field.value);
+    ui.end_row();
+
+    if state.is_open() {
+        for (index, child) in field.children.iter().enumerate() {
+            show_field(ui, child, id.with(index), depth + 1);
+        }
+    }
+    state.store(ui.ctx());
+}
+
+fn layout_summary(frame: &DecodedFrame, ui: &egui::Ui) -> LayoutJob {
+    let format = |color| TextFormat::simple(LOG_FONT, color);
+    let weak_color = ui.visuals().weak_text_color();
+    let text_color = if frame.is_valid() {
+        ui.visuals().text_color()
+    } else {
+        sereal_colors::UI_RED.to_egui_color32()
+    };
+    let direction_color = match frame.direction {
+        Direction::Rx => sereal_colors::UI_GREEN.to_egui_color32(),
+        Direction::Tx => sereal_colors::UI_ORANGE.to_egui_color32(),
+    };
+
+    let mut job = LayoutJob::default();
+    job.append(
+        &format!("{}  ", frame.time.format("%H:%M:%S%.3f")),
+        0.0,
+        format(weak_color),
+    );
+    job.append(
+        &format!("{}  ", frame.direction.name()),
+        0.0,
+        format(direction_color),
+    );
+    job.append(&frame.summary, 0.0, format(text_color));
+    job
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framing::FramingOptions;
+
+    /// 行ごとに 1 フレームを返すデコーダを選んだ状態
+    fn text_line_view() -> DecoderView {
+        let mut view = DecoderView {
+            decoder_index: DECODERS.iter().position(|info| info.name == "Text lines"),
+            ..Default::default()
+        };
+        view.set_context(DecoderContext {
+            baud_rate: 115_200,
+            framing: FramingOptions::default(),
+        });
+        view
+    }
+
+    #[test]
+    fn without_decoder_ignores_bytes() {
+        let mut view = DecoderView::default();
+        view.feed(Direction::Rx, Local::now(), b"a\n");
+        assert!(view.frames.is_empty());
+    }
+
+    #[test]
+    fn collects_decoded_frames() {
+        let mut view = text_line_view();
+        view.feed(Direction::Rx, Local::now(), b"a\nb");
+        view.feed(Direction::Tx, Local::now(), b"c\n");
+        view.feed(Direction::Rx, Local::now(), b"\n");
+        let summaries: Vec<&str> = view.frames.iter().map(|f| f.summary.as_str()).collect();
+        assert_eq!(summaries, ["\"a\"", "\"c\"", "\"b\""]);
+    }
+
+    #[test]
+    fn drops_oldest_frames() {
+        let mut view = text_line_view();
+        let bytes = b"x\n".repeat(DECODED_MAX_FRAMES + 3);
+        view.feed(Direction::Rx, Local::now(), &bytes);
+        assert_eq!(view.frames.len(), DECODED_MAX_FRAMES);
+        assert_eq!(view.first_number, 3);
+    }
+
+    #[test]
+    fn clear_discards_partial_frames() {
+        let mut view = text_line_view();
+        view.feed(Direction::Rx, Local::now(), b"a\npartial");
+        view.selected = Some(0);
+        view.clear();
+        assert!(view.frames.is_empty());
+        assert_eq!(view.first_number, 1);
+        assert_eq!(view.selected, None);
+
+        view.feed(Direction::Rx, Local::now(), b"b\n");
+        assert_eq!(view.frames[0].summary, "\"b\"");
+    }
+
+    #[test]
+    fn same_context_keeps_decoder_state() {
+        let mut view = text_line_view();
+        view.feed(Direction::Rx, Local::now(), b"a");
+        let context = view.context.clone().unwrap();
+        view.set_context(context.clone());
+        view.feed(Direction::Rx, Local::now(), b"b\n");
+        assert_eq!(view.frames[0].summary, "\"ab\"");
+
+        // 設定が変わるとデコーダを作り直す
+        view.feed(Direction::Rx, Local::now(), b"c");
+        view.set_context(DecoderContext {
+            baud_rate: 9600,
+            ..context
+        });
+        view.feed(Direction::Rx, Local::now(), b"d\n");
+        assert_eq!(view.frames[1].summary, "\"d\"");
+    }
+}
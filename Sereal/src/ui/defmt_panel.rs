@@ -0,0 +1,216 @@
+use super::utils::file_name;
+use crate::defmt::format::decode_frame;
+use crate::defmt::{FrameSplitter, Level, LogFrame, Table};
+use crate::sereal_colors;
+use eframe::egui;
+use std::path::PathBuf;
+
+/// defmt のフレームをファームウェアの ELF で復号し、ログの行にするパネル
+///
+/// ELF を紐付けている間は、受信したバイト列を色付きのテキストに変換してログに流す。
+pub struct DefmtPanel {
+    elf_path: String,
+    table: Option<(PathBuf, Table)>,
+    load_error: Option<String>,
+    splitter: FrameSplitter,
+    is_show_location: bool,
+    frame_count: usize,
+    error_count: usize,
+    events: Vec<String>,
+}
+
+impl Default for DefmtPanel {
+    fn default() -> Self {
+        Self {
+            elf_path: String::new(),
+            table: None,
+            load_error: None,
+            splitter: FrameSplitter::default(),
+            is_show_location: true,
+            frame_count: 0,
+            error_count: 0,
+            events: Vec::new(),
+        }
+    }
+}
+
+impl DefmtPanel {
+    /// 受信したバイト列を復号し、ログに表示するテキストを返す。紐付けていなければ None
+    pub fn feed_rx(&mut self, bytes: &[u8]) -> Option<Vec<u8>> {
+        let (_, table) = self.table.as_ref()?;
+        let mut text = String::new();
+        for frame in self.splitter.push(bytes) {
+            match frame.and_then(|frame| decode_frame(table, &frame)) {
+                Ok(frame) => {
+                    self.frame_count += 1;
+                    text.push_str(&format_line(&frame, self.is_show_location));
+                }
+                Err(e) => {
+                    self.error_count += 1;
+                    text.push_str(&format!("\x1b[31mdefmt: {e}\x1b[0m\r\n"));
+                }
+            }
+        }
+        Some(text.into_bytes())
+    }
+
+    /// イベントの履歴に残す内容
+    pub fn take_events(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn clear(&mut self) {
+        self.splitter.clear();
+        self.frame_count = 0;
+        self.error_count = 0;
+    }
+
+    fn link(&mut self) {
+        let path = PathBuf::from(self.elf_path.trim());
+        let result = Table::load(&path).and_then(|table| {
+            if table.encoding() == "rzcobs" {
+                Ok(table)
+            } else {
+                Err(format!(
+                    "The firmware uses the {} encoding. Enable defmt's encoding-rzcobs feature to frame logs over a UART",
+                    table.encoding()
+                ))
+            }
+        });
+        match result {
+            Ok(table) => {
+                self.events.push(format!(
+                    "Linked {} ({} defmt strings)",
+                    path.display(),
+                    table.len()
+                ));
+                self.table = Some((path, table));
+                self.load_error = None;
+                self.clear();
+            }
+            Err(e) => self.load_error = Some(e),
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("defmt");
+        ui.weak("Decode rzCOBS framed defmt logs with the firmware ELF");
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.elf_path)
+                    .desired_width(220.0)
+                    .hint_text("Local path to the firmware ELF"),
+            );
+            let label = if self.table.is_some() {
+                "Reload"
+            } else {
+                "Link"
+            };
+            if ui
+                .add_enabled(!self.elf_path.trim().is_empty(), egui::Button::new(label))
+                .clicked()
+            {
+                self.link();
+            }
+            if self.table.is_some() && ui.button("Unlink").clicked() {
+                self.table = None;
+                self.splitter.clear();
+            }
+        });
+        if let Some(error) = &self.load_error {
+            ui.colored_label(sereal_colors::UI_RED.to_egui_color32(), error);
+        }
+        ui.checkbox(&mut self.is_show_location, "Show source location");
+        ui.separator();
+
+        let Some((path, table)) = &self.table else {
+            ui.weak("Received data is shown as is until an ELF is linked");
+            return;
+        };
+        egui::Grid::new(ui.id().with("defmt_status"))
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("ELF");
+                ui.monospace(file_name(path))
+                    .on_hover_text(path.display().to_string());
+                ui.end_row();
+
+                ui.label("Strings");
+                ui.label(format!(
+                    "{} ({} with source location)",
+                    table.len(),
+                    table.location_count()
+                ));
+                ui.end_row();
+
+                ui.label("Timestamp");
+                match table.timestamp() {
+                    Some(format) => ui.monospace(format),
+                    None => ui.weak("None"),
+                };
+                ui.end_row();
+
+                ui.label("Frames");
+                ui.label(self.frame_count.to_string());
+                ui.end_row();
+
+                ui.label("Errors");
+                if 0 < self.error_count {
+                    ui.colored_label(
+                        sereal_colors::UI_RED.to_egui_color32(),
+                        self.error_count.to_string(),
+                    );
+                } else {
+                    ui.label("0");
+                }
+                ui.end_row();
+            });
+    }
+}
+
+/// ログの 1 行を SGR で色付けしたテキストにする
+///
+/// レベルの色は defmt-print に合わせる。
+fn format_line(frame: &LogFrame, is_show_location: bool) -> String {
+    let mut line = String::new();
+    if let Some(timestamp) = &frame.timestamp {
+        line.push_str(timestamp);
+        line.push(' ');
+    }
+    let message = frame.message.replace('\n', "\r\n");
+    match frame.level {
+        Some(level) => {
+            let color = match level {
+                Level::Trace => "\x1b[2m",
+                Level::Debug => "",
+                Level::Info => "\x1b[32m",
+                Level::Warn => "\x1b[33m",
+                Level::Error => "\x1b[31m",
+            };
+            line.push_str(&format!("{color}{:<5} {message}\x1b[0m", level.name()));
+        }
+        None => line.push_str(&message),
+    }
+    if is_show_location && let Some(location) = &frame.location {
+        line.push_str(&format!(
+            " \x1b[2m({}:{})\x1b[0m",
+            short_path(&location.file),
+            location.line
+        ));
+    }
+    line.push_str("\r\n");
+    line
+}
+
+/// "/home/user/app/src/main.rs" を "app/src/main.rs" のように、クレートのディレクトリから表示する
+fn short_path(path: &str) -> &str {
+    let Some(position) = path.rfind("/src/") else {
+        return path;
+    };
+    match path[..position].rfind('/') {
+        Some(start) => &path[start + 1..],
+        None => path,
+    }
+}
@@ -0,0 +1,267 @@
+use super::utils::file_name;
+use crate::esp_panic::{LineScanner, PanicLine, Symbolizer};
+use crate::sereal_colors;
+use chrono::{DateTime, Local};
+use eframe::egui;
+use std::path::Path;
+
+/// 受信ログの行の後に差し込む行
+pub struct LogNote {
+    pub text: String,
+    /// ポインタを重ねたときに表示する説明
+    pub explanation: String,
+    pub is_warning: bool,
+}
+
+/// ESP32 のパニック出力のアドレスを、タブに紐付けた ELF で関数名とソースの位置にするパネル
+#[derive(Default)]
+pub struct EspPanicPanel {
+    elf_path: String,
+    symbolizer: Option<Symbolizer>,
+    load_error: Option<String>,
+    scanner: LineScanner,
+    /// 起動時やパニック時に表示された ELF の SHA256
+    app_sha256: Option<String>,
+    last_panic: Option<String>,
+    /// 最後に変換したアドレスと結果
+    last_decoded: Vec<(u32, Vec<String>)>,
+    events: Vec<String>,
+}
+
+impl EspPanicPanel {
+    /// 受信したバイト列を読み、ログに差し込む行を返す
+    ///
+    /// 行単位で読むため、LF までのバイト列を表示した直後に呼ぶ。
+    pub fn feed_rx(&mut self, time: DateTime<Local>, bytes: &[u8]) -> Vec<LogNote> {
+        let mut notes: Vec<LogNote> = Vec::new();
+        for line in self.scanner.push(time, bytes) {
+            match line {
+                PanicLine::Panic { message, address } => {
+                    self.events.push(format!("ESP32 panic: {message}"));
+                    self.last_panic = Some(message);
+                    self.last_decoded.clear();
+                    notes.extend(
+                        address
+                            .and_then(|address| self.decode(address))
+                            .into_iter()
+                            .flatten(),
+                    );
+                }
+                PanicLine::Backtrace(addresses) => {
+                    let decoded: Vec<LogNote> = addresses
+                        .into_iter()
+                        .filter_map(|address| self.decode(address))
+                        .flatten()
+                        .collect();
+                    if decoded.is_empty()
+                        && let Some(symbolizer) = &self.symbolizer
+                    {
+                        notes.push(warning(
+                            "No backtrace address was found in the linked ELF".to_string(),
+                            format!(
+                                "{} may not be the running firmware",
+                                file_name(symbolizer.path())
+                            ),
+                        ));
+                    }
+                    notes.extend(decoded);
+                }
+                PanicLine::Registers(registers) => {
+                    for (_, address) in registers {
+                        notes.extend(self.decode(address).into_iter().flatten());
+                    }
+                }
+                PanicLine::ElfSha256(sha256) => {
+                    if let Some(symbolizer) = &self.symbolizer
+                        && !symbolizer.matches_sha256(&sha256)
+                    {
+                        let message = mismatch_message(symbolizer, &sha256);
+                        self.events.push(message.clone());
+                        notes.push(warning(
+                            message,
+                            "Addresses are decoded with the linked ELF and may point to the wrong code"
+                                .to_string(),
+                        ));
+                    }
+                    self.app_sha256 = Some(sha256);
+                }
+            }
+        }
+        notes
+    }
+
+    pub fn is_linked(&self) -> bool {
+        self.symbolizer.is_some()
+    }
+
+    /// イベントの履歴に残す内容
+    pub fn take_events(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn clear(&mut self) {
+        self.scanner.clear();
+        self.last_panic = None;
+        self.last_decoded.clear();
+    }
+
+    /// アドレスを変換してログに差し込む行にする。ELF に含まれなければ None
+    fn decode(&mut self, address: u32) -> Option<Vec<LogNote>> {
+        let symbolizer = self.symbolizer.as_ref()?;
+        let frames = symbolizer.symbolize(address);
+        if frames.is_empty() {
+            return None;
+        }
+        let lines: Vec<String> = frames
+            .iter()
+            .enumerate()
+            .map(|(index, frame)| {
+                if index == 0 {
+                    format!("0x{address:08x}: {frame}")
+                } else {
+                    format!("    (inlined by) {frame}")
+                }
+            })
+            .collect();
+        let explanation = format!("Decoded with {}", file_name(symbolizer.path()));
+        self.last_decoded.push((address, lines.clone()));
+        Some(
+            lines
+                .into_iter()
+                .map(|text| LogNote {
+                    text,
+                    explanation: explanation.clone(),
+                    is_warning: false,
+                })
+                .collect(),
+        )
+    }
+
+    fn link(&mut self) {
+        let path = self.elf_path.trim();
+        match Symbolizer::load(Path::new(path)) {
+            Ok(symbolizer) => {
+                self.events.push(format!("Linked {path}"));
+                if let Some(sha256) = &self.app_sha256
+                    && !symbolizer.matches_sha256(sha256)
+                {
+                    self.events.push(mismatch_message(&symbolizer, sha256));
+                }
+                self.symbolizer = Some(symbolizer);
+                self.load_error = None;
+            }
+            Err(e) => self.load_error = Some(e),
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("ESP32 panic");
+        ui.weak("Decode backtraces and register dumps with the firmware ELF");
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.elf_path)
+                    .desired_width(220.0)
+                    .hint_text("Local path to the app .elf"),
+            );
+            let label = if self.is_linked() { "Reload" } else { "Link" };
+            if ui
+                .add_enabled(!self.elf_path.trim().is_empty(), egui::Button::new(label))
+                .clicked()
+            {
+                self.link();
+            }
+            if self.is_linked() && ui.button("Unlink").clicked() {
+                self.symbolizer = None;
+            }
+        });
+        if let Some(error) = &self.load_error {
+            ui.colored_label(sereal_colors::UI_RED.to_egui_color32(), error);
+        }
+
+        egui::Grid::new(ui.id().with("esp_panic_status"))
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Linked ELF");
+                match &self.symbolizer {
+                    Some(symbolizer) => {
+                        ui.monospace(file_name(symbolizer.path()))
+                            .on_hover_text(symbolizer.path().display().to_string());
+                    }
+                    None => {
+                        ui.weak("None");
+                    }
+                }
+                ui.end_row();
+
+                ui.label("ELF SHA256");
+                match &self.symbolizer {
+                    Some(symbolizer) => {
+                        ui.monospace(&symbolizer.sha256()[..16])
+                            .on_hover_text(symbolizer.sha256());
+                    }
+                    None => {
+                        ui.weak("-");
+                    }
+                }
+                ui.end_row();
+
+                ui.label("Running app");
+                match (&self.app_sha256, &self.symbolizer) {
+                    (Some(sha256), Some(symbolizer)) if !symbolizer.matches_sha256(sha256) => {
+                        ui.colored_label(
+                            sereal_colors::UI_RED.to_egui_color32(),
+                            format!("{sha256} (does not match)"),
+                        );
+                    }
+                    (Some(sha256), Some(_)) => {
+                        ui.monospace(format!("{sha256} (matches)"));
+                    }
+                    (Some(sha256), None) => {
+                        ui.monospace(sha256);
+                    }
+                    (None, _) => {
+                        ui.weak("Not seen since connecting");
+                    }
+                }
+                ui.end_row();
+            });
+        ui.separator();
+
+        egui::ScrollArea::vertical()
+            .auto_shrink(false)
+            .show(ui, |ui| {
+                match &self.last_panic {
+                    Some(message) => {
+                        ui.colored_label(sereal_colors::UI_RED.to_egui_color32(), message);
+                    }
+                    None if self.last_decoded.is_empty() => {
+                        ui.weak("No panic received");
+                    }
+                    None => {}
+                }
+                for (_, lines) in &self.last_decoded {
+                    for line in lines {
+                        ui.monospace(line);
+                    }
+                }
+            });
+    }
+}
+
+fn warning(text: String, explanation: String) -> LogNote {
+    LogNote {
+        text,
+        explanation,
+        is_warning: true,
+    }
+}
+
+fn mismatch_message(symbolizer: &Symbolizer, printed: &str) -> String {
+    format!(
+        "ELF SHA256 mismatch: the running app reports {printed}, {} is {}",
+        file_name(symbolizer.path()),
+        &symbolizer.sha256()[..printed.len().min(64)]
+    )
+}
@@ -0,0 +1,45 @@
+use chrono::{DateTime, Local};
+use eframe::egui;
+use std::collections::VecDeque;
+
+const EVENT_HISTORY_MAX_ENTRIES: usize = 1000;
+
+struct EventEntry {
+    time: DateTime<Local>,
+    message: String,
+}
+
+/// タブごとの接続や応答などの出来事の履歴
+#[derive(Default)]
+pub struct EventHistory {
+    entries: VecDeque<EventEntry>,
+}
+
+impl EventHistory {
+    pub fn push(&mut self, message: impl Into<String>) {
+        self.entries.push_back(EventEntry {
+            time: Local::now(),
+            message: message.into(),
+        });
+        while EVENT_HISTORY_MAX_ENTRIES < self.entries.len() {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        egui::ScrollArea::vertical()
+            .auto_shrink(false)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                if self.entries.is_empty() {
+                    ui.weak("No events yet.");
+                }
+                for entry in &self.entries {
+                    ui.horizontal(|ui| {
+                        ui.monospace(entry.time.format("%H:%M:%S%.3f").to_string());
+                        ui.label(&entry.message);
+                    });
+                }
+            });
+    }
+}
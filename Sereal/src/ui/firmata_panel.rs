@@ -0,0 +1,408 @@
+use crate::decoder::Direction;
+use crate::decoder::firmata::{self, FirmataParser, Firmware, Message};
+use chrono::{DateTime, Local};
+use eframe::egui;
+
+/// サーボの角度の上限
+const SERVO_MAX_DEGREES: u32 = 180;
+/// ピンの数の上限。ピン番号は 7 ビットで送る
+const MAX_PINS: usize = 128;
+
+#[derive(Default)]
+struct Pin {
+    /// 対応するモードと分解能
+    modes: Vec<(u8, u8)>,
+    analog_channel: Option<u8>,
+    /// 最後に設定したモード
+    mode: Option<u8>,
+    /// 出力中の値 (デジタルは 0/1、PWM とサーボはその値)
+    output: u32,
+}
+
+impl Pin {
+    fn resolution(&self, mode: u8) -> Option<u8> {
+        self.modes
+            .iter()
+            .find(|(supported, _)| *supported == mode)
+            .map(|(_, resolution)| *resolution)
+    }
+
+    fn max_value(&self, mode: u8) -> u32 {
+        let resolution = self.resolution(mode).unwrap_or(8).clamp(1, 16);
+        (1 << resolution) - 1
+    }
+}
+
+/// Firmata のファームウェアを書き込んだボードのピンを操作するパネル
+///
+/// 問い合わせもピンの操作も、通常の送信と同じく Controller から書き込む。
+#[derive(Default)]
+pub struct FirmataPanel {
+    parser: FirmataParser,
+    protocol_version: Option<(u8, u8)>,
+    firmware: Option<Firmware>,
+    pins: Vec<Pin>,
+    /// ポートごとの入力値
+    digital_ports: [u16; 16],
+    analog_values: [Option<u16>; 16],
+    message_count: usize,
+    error_count: usize,
+    /// 受信したメッセージを生のバイト列の代わりにログへ流す
+    is_decode_log: bool,
+    events: Vec<String>,
+}
+
+impl FirmataPanel {
+    /// 受信したメッセージを反映する
+    ///
+    /// ログに復号して流すときは、ログに表示するテキストを返す。そうでなければ None
+    pub fn feed_rx(&mut self, time: DateTime<Local>, bytes: &[u8]) -> Option<Vec<u8>> {
+        let mut text = String::new();
+        for (_, message, _) in self.parser.push(time, bytes) {
+            self.message_count += 1;
+            match &message {
+                Message::DigitalPort { port, value } => {
+                    if let Some(port) = self.digital_ports.get_mut(usize::from(*port)) {
+                        *port = *value;
+                    }
+                }
+                Message::Analog { channel, value } => {
+                    if let Some(analog) = self.analog_values.get_mut(usize::from(*channel)) {
+                        *analog = Some(*value);
+                    }
+                }
+                Message::ProtocolVersion { major, minor } => {
+                    self.protocol_version = Some((*major, *minor));
+                    self.events
+                        .push(format!("Firmata protocol version {major}.{minor}"));
+                }
+                Message::SysEx { command, data } => {
+                    if let Some(event) = self.apply_sysex(*command, data) {
+                        self.events.push(event);
+                    }
+                }
+                Message::Unexpected(_) => self.error_count += 1,
+                _ => {}
+            }
+            if let Some(line) = log_line(&message) {
+                text.push_str(&line);
+            }
+        }
+        self.is_decode_log.then(|| text.into_bytes())
+    }
+
+    /// イベントの履歴に残す内容
+    pub fn take_events(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn clear(&mut self) {
+        self.message_count = 0;
+        self.error_count = 0;
+    }
+
+    fn apply_sysex(&mut self, command: u8, data: &[u8]) -> Option<String> {
+        match command {
+            firmata::REPORT_FIRMWARE => {
+                let firmware = firmata::parse_firmware(data)?;
+                let event = format!(
+                    "Firmata firmware {} {}.{}",
+                    firmware.name, firmware.major, firmware.minor
+                );
+                self.firmware = Some(firmware);
+                Some(event)
+            }
+            firmata::CAPABILITY_RESPONSE => {
+                self.pins = firmata::parse_capabilities(data)
+                    .into_iter()
+                    .take(MAX_PINS)
+                    .map(|modes| Pin {
+                        modes,
+                        ..Pin::default()
+                    })
+                    .collect();
+                Some(format!("Firmata board has {} pin(s)", self.pins.len()))
+            }
+            firmata::ANALOG_MAPPING_RESPONSE => {
+                for (pin, channel) in self
+                    .pins
+                    .iter_mut()
+                    .zip(firmata::parse_analog_mapping(data))
+                {
+                    pin.analog_channel = channel;
+                }
+                None
+            }
+            firmata::PIN_STATE_RESPONSE => {
+                if let [pin, mode, state @ ..] = data
+                    && let Some(pin) = self.pins.get_mut(usize::from(*pin))
+                {
+                    pin.mode = Some(*mode);
+                    pin.output = firmata::decode_seven_bit_value(state);
+                }
+                None
+            }
+            firmata::STRING_DATA => {
+                let text = String::from_utf8_lossy(&firmata::decode_two_byte(data)).into_owned();
+                Some(format!("Firmata: {text}"))
+            }
+            _ => None,
+        }
+    }
+
+    /// ボードに送るメッセージを返す
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> Vec<Vec<u8>> {
+        let mut commands = Vec::new();
+        ui.heading("Firmata");
+        ui.horizontal(|ui| {
+            if ui
+                .button("Connect")
+                .on_hover_text("Query the firmware, protocol version and pin capabilities")
+                .clicked()
+            {
+                commands.push(firmata::handshake());
+                self.is_decode_log = true;
+            }
+            if ui
+                .button("Reset board")
+                .on_hover_text("Send a system reset to the firmware")
+                .clicked()
+            {
+                commands.push(vec![firmata::SYSTEM_RESET]);
+                for pin in &mut self.pins {
+                    pin.mode = None;
+                    pin.output = 0;
+                }
+            }
+        });
+
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "—".to_string());
+        egui::Grid::new(ui.id().with("firmata_status"))
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Firmware");
+                ui.monospace(optional(self.firmware.as_ref().map(|firmware| {
+                    format!("{} {}.{}", firmware.name, firmware.major, firmware.minor)
+                })));
+                ui.end_row();
+                ui.label("Protocol");
+                ui.monospace(optional(
+                    self.protocol_version
+                        .map(|(major, minor)| format!("{major}.{minor}")),
+                ));
+                ui.end_row();
+            });
+        ui.weak(format!(
+            "{} message(s), {} unexpected",
+            self.message_count, self.error_count
+        ));
+        ui.checkbox(&mut self.is_decode_log, "Decode messages in the log")
+            .on_hover_text(
+                "Show firmware, version and other replies as text instead of raw bytes. Pin values are shown below",
+            );
+        ui.separator();
+
+        if self.pins.is_empty() {
+            ui.weak("Press Connect to query the pins of the board");
+            return commands;
+        }
+        egui::ScrollArea::vertical()
+            .auto_shrink(false)
+            .show(ui, |ui| {
+                egui::Grid::new(ui.id().with("firmata_pins"))
+                    .num_columns(3)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for index in 0..self.pins.len() {
+                            commands.extend(self.pin_row(ui, index));
+                            ui.end_row();
+                        }
+                    });
+            });
+        commands
+    }
+
+    fn pin_row(&mut self, ui: &mut egui::Ui, index: usize) -> Vec<Vec<u8>> {
+        let mut commands = Vec::new();
+        let number = index as u8;
+        let port = number / 8;
+        let pin = &mut self.pins[index];
+        // RX/TX など、どのモードにも使えないピンは表示だけする
+        let is_usable = pin.modes.iter().any(|(mode, _)| *mode != 0x7f);
+
+        match pin.analog_channel {
+            Some(channel) => ui.monospace(format!("D{number:<2} A{channel}")),
+            None => ui.monospace(format!("D{number}")),
+        };
+        if !is_usable {
+            ui.weak("Unavailable");
+            ui.label("");
+            return commands;
+        }
+
+        let previous_mode = pin.mode;
+        egui::ComboBox::from_id_salt(ui.id().with(("firmata_mode", index)))
+            .selected_text(pin.mode.map(firmata::mode_name).unwrap_or("—"))
+            .show_ui(ui, |ui| {
+                for (mode, _) in pin.modes.clone() {
+                    ui.selectable_value(&mut pin.mode, Some(mode), firmata::mode_name(mode));
+                }
+            });
+        if pin.mode != previous_mode
+            && let Some(mode) = pin.mode
+        {
+            pin.output = 0;
+            commands.push(firmata::set_pin_mode(number, mode));
+            // 入力は報告を有効にしないと値が届かない
+            if let (Some(firmata::MODE_ANALOG), Some(channel)) = (previous_mode, pin.analog_channel)
+            {
+                commands.push(firmata::report_analog(channel, false));
+            }
+            match (mode, pin.analog_channel) {
+                (firmata::MODE_INPUT | firmata::MODE_INPUT_PULLUP, _) => {
+                    commands.push(firmata::report_digital(port, true));
+                }
+                (firmata::MODE_ANALOG, Some(channel)) => {
+                    commands.push(firmata::report_analog(channel, true));
+                }
+                _ => {}
+            }
+        }
+
+        match pin.mode {
+            Some(firmata::MODE_OUTPUT) => {
+                let mut is_high = pin.output != 0;
+                let label = if is_high { "HIGH" } else { "LOW" };
+                if ui.toggle_value(&mut is_high, label).changed() {
+                    pin.output = u32::from(is_high);
+                    commands.push(firmata::set_digital_pin(number, is_high));
+                }
+            }
+            Some(firmata::MODE_INPUT | firmata::MODE_INPUT_PULLUP) => {
+                let is_high = self
+                    .digital_ports
+                    .get(usize::from(port))
+                    .is_some_and(|value| value & (1 << (number % 8)) != 0);
+                ui.monospace(if is_high { "HIGH" } else { "LOW" });
+            }
+            Some(firmata::MODE_ANALOG) => {
+                let max = pin.max_value(firmata::MODE_ANALOG);
+                let value = pin
+                    .analog_channel
+                    .and_then(|channel| self.analog_values.get(usize::from(channel)).copied())
+                    .flatten();
+                match value {
+                    Some(value) => {
+                        ui.add(
+                            egui::ProgressBar::new(f32::from(value) / max as f32)
+                                .desired_width(160.0)
+                                .text(format!("{value} / {max}")),
+                        );
+                    }
+                    None => {
+                        ui.weak("Waiting for a reading");
+                    }
+                }
+            }
+            Some(mode @ (firmata::MODE_PWM | firmata::MODE_SERVO)) => {
+                let max = if mode == firmata::MODE_SERVO {
+                    SERVO_MAX_DEGREES
+                } else {
+                    pin.max_value(mode)
+                };
+                let mut slider = egui::Slider::new(&mut pin.output, 0..=max);
+                if mode == firmata::MODE_SERVO {
+                    slider = slider.suffix("°");
+                }
+                if ui.add(slider).changed() {
+                    commands.push(firmata::analog_write(number, pin.output));
+                }
+            }
+            Some(_) => {
+                ui.weak("Not controllable here");
+            }
+            None => {
+                ui.weak("Choose a mode");
+            }
+        }
+        commands
+    }
+}
+
+/// ログに流す 1 行。ピンの値の報告は頻繁に届くので、パネルにだけ表示する
+fn log_line(message: &Message) -> Option<String> {
+    if matches!(
+        message,
+        Message::DigitalPort { .. } | Message::Analog { .. }
+    ) {
+        return None;
+    }
+    let (summary, _, errors) = firmata::describe(message, Direction::Rx);
+    Some(if errors.is_empty() {
+        format!("\x1b[36mFirmata\x1b[0m {summary}\r\n")
+    } else {
+        format!(
+            "\x1b[31mFirmata {summary}: {}\x1b[0m\r\n",
+            errors.join(", ")
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(panel: &mut FirmataPanel, bytes: &[u8]) -> Option<String> {
+        panel
+            .feed_rx(Local::now(), bytes)
+            .map(|text| String::from_utf8(text).unwrap())
+    }
+
+    fn firmware_report() -> Vec<u8> {
+        let mut data = vec![2, 5];
+        for byte in b"Std" {
+            data.extend_from_slice(&[byte & 0x7f, byte >> 7]);
+        }
+        firmata::sysex(firmata::REPORT_FIRMWARE, &data)
+    }
+
+    #[test]
+    fn decodes_replies_in_log() {
+        let mut panel = FirmataPanel::default();
+        assert_eq!(feed(&mut panel, &firmware_report()), None);
+        assert_eq!(panel.take_events(), ["Firmata firmware Std 2.5"]);
+
+        panel.is_decode_log = true;
+        let mut bytes = firmware_report();
+        bytes.extend_from_slice(&[firmata::PROTOCOL_VERSION, 2, 6]);
+        bytes.extend_from_slice(&[firmata::DIGITAL_MESSAGE | 1, 0x05, 0]);
+        let text = feed(&mut panel, &bytes).unwrap();
+        assert_eq!(
+            text,
+            "\x1b[36mFirmata\x1b[0m Firmware Std 2.5\r\n\x1b[36mFirmata\x1b[0m Protocol version 2.6\r\n"
+        );
+        assert_eq!(panel.digital_ports[1], 0x05);
+        assert_eq!(panel.protocol_version, Some((2, 6)));
+
+        // 単独のデータバイトは不正なメッセージとして表示する
+        let text = feed(&mut panel, &[0x12]).unwrap();
+        assert!(text.starts_with("\x1b[31mFirmata Unexpected 12"));
+        assert_eq!(panel.error_count, 1);
+    }
+
+    #[test]
+    fn limits_pin_count() {
+        let mut panel = FirmataPanel::default();
+        // どのモードにも対応しない 200 本のピン
+        let data = vec![0x7f; 200];
+        feed(
+            &mut panel,
+            &firmata::sysex(firmata::CAPABILITY_RESPONSE, &data),
+        );
+        assert_eq!(panel.pins.len(), MAX_PINS);
+        assert_eq!(
+            panel.take_events(),
+            [format!("Firmata board has {MAX_PINS} pin(s)")]
+        );
+    }
+}
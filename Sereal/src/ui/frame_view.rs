@@ -0,0 +1,83 @@
+use crate::ansi_formatter::LOG_FONT;
+use crate::framing::Frame;
+use eframe::egui;
+use egui::text::{LayoutJob, TextFormat};
+use std::collections::VecDeque;
+
+const FRAME_MAX_ROWS: usize = 100_000;
+// 1 行に表示するバイト数の上限。超えた分は省略する
+const PREVIEW_MAX_BYTES: usize = 64;
+
+/// 区切ったフレームを 1 行ずつ 時刻 | 長さ | 16 進 | ASCII の形式で表示するビュー
+#[derive(Default)]
+pub struct FrameView {
+    frames: VecDeque<Frame>,
+}
+
+impl FrameView {
+    pub fn push(&mut self, frame: Frame) {
+        self.frames.push_back(frame);
+        while FRAME_MAX_ROWS < self.frames.len() {
+            self.frames.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> egui::Rect {
+        ui.scope(|ui| {
+            ui.spacing_mut().item_spacing = egui::Vec2 { x: 0.0, y: 0.0 };
+            let row_height = ui.fonts(|fonts| fonts.row_height(&LOG_FONT));
+            egui::ScrollArea::both()
+                .auto_shrink(false)
+                .stick_to_bottom(true)
+                .show_rows(ui, row_height, self.frames.len(), |ui, row_range| {
+                    for index in row_range {
+                        let job = layout_frame(&self.frames[index], ui);
+                        ui.add(egui::Label::new(job).extend());
+                    }
+                })
+                .inner_rect
+        })
+        .inner
+    }
+}
+
+fn layout_frame(frame: &Frame, ui: &egui::Ui) -> LayoutJob {
+    let format = |color| TextFormat::simple(LOG_FONT, color);
+    let text_color = ui.visuals().text_color();
+    let weak_color = ui.visuals().weak_text_color();
+
+    let preview = &frame.bytes[..frame.bytes.len().min(PREVIEW_MAX_BYTES)];
+    let mut hex: Vec<String> = preview.iter().map(|byte| format!("{byte:02X}")).collect();
+    let ascii: String = preview
+        .iter()
+        .map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                char::from(byte)
+            } else {
+                '.'
+            }
+        })
+        .collect();
+    if preview.len() < frame.bytes.len() {
+        hex.push("…".to_string());
+    }
+
+    let mut job = LayoutJob::default();
+    job.append(
+        &format!("{}  ", frame.time.format("%H:%M:%S%.3f")),
+        0.0,
+        format(weak_color),
+    );
+    job.append(
+        &format!("{:>5} B  ", frame.bytes.len()),
+        0.0,
+        format(weak_color),
+    );
+    job.append(&hex.join(" "), 0.0, format(text_color));
+    job.append(&format!("  |{ascii}|"), 0.0, format(weak_color));
+    job
+}
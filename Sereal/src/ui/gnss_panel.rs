@@ -0,0 +1,262 @@
+use crate::decoder::nmea::{self, Position, Satellite, SentenceData};
+use crate::decoder::text_line::LineBuffer;
+use crate::sereal_colors;
+use chrono::{DateTime, Local, NaiveDate, NaiveTime};
+use eframe::egui;
+use std::collections::BTreeMap;
+
+const KNOTS_TO_KMH: f32 = 1.852;
+// SNR バーの高さを決める上限 (dB-Hz)
+const SNR_FULL_SCALE: f32 = 50.0;
+const SNR_BAR_WIDTH: f32 = 18.0;
+const SNR_BAR_HEIGHT: f32 = 80.0;
+
+/// 受信した NMEA センテンスから最新の測位状態をまとめて表示するパネル
+#[derive(Default)]
+pub struct GnssPanel {
+    line_buffer: LineBuffer,
+    quality: Option<u8>,
+    fix_type: Option<u8>,
+    position: Option<Position>,
+    altitude_m: Option<f32>,
+    speed_kmh: Option<f32>,
+    course: Option<f32>,
+    hdop: Option<f32>,
+    satellites_used: Vec<u16>,
+    date: Option<NaiveDate>,
+    time: Option<NaiveTime>,
+    /// 発信元ごとの見えている衛星。GSV が揃ったら置き換える
+    satellites: BTreeMap<String, Vec<Satellite>>,
+    pending_satellites: BTreeMap<String, Vec<Satellite>>,
+    sentence_count: usize,
+    error_count: usize,
+    last_update: Option<DateTime<Local>>,
+}
+
+impl GnssPanel {
+    pub fn feed_rx(&mut self, time: DateTime<Local>, bytes: &[u8]) {
+        for line in self.line_buffer.push(time, bytes) {
+            // NMEA 以外の行は数えない
+            if !line.content().starts_with(b"$") {
+                continue;
+            }
+            match nmea::parse_sentence(line.content()) {
+                Ok(sentence) => {
+                    self.sentence_count += 1;
+                    self.last_update = Some(line.time);
+                    self.apply(sentence.talker, sentence.data);
+                }
+                Err(_) => self.error_count += 1,
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.heading("GNSS");
+            if ui.small_button("Reset").clicked() {
+                self.clear();
+            }
+        });
+        ui.weak(format!(
+            "{} sentence(s), {} bad checksum(s) or malformed",
+            self.sentence_count, self.error_count
+        ));
+        ui.separator();
+
+        egui::ScrollArea::vertical()
+            .auto_shrink(false)
+            .show(ui, |ui| {
+                self.status_grid(ui);
+                ui.separator();
+                self.satellite_bars(ui);
+            });
+    }
+
+    fn apply(&mut self, talker: String, data: SentenceData) {
+        match data {
+            SentenceData::Gga(gga) => {
+                self.quality = Some(gga.quality);
+                self.time = gga.time.or(self.time);
+                self.position = gga.position;
+                self.altitude_m = gga.altitude_m;
+                self.hdop = gga.hdop.or(self.hdop);
+            }
+            SentenceData::Rmc(rmc) => {
+                self.time = rmc.time.or(self.time);
+                self.date = rmc.date.or(self.date);
+                if rmc.is_valid {
+                    self.position = rmc.position.or(self.position);
+                    self.speed_kmh = rmc.speed_knots.map(|knots| knots * KNOTS_TO_KMH);
+                    self.course = rmc.course;
+                }
+            }
+            SentenceData::Gsa(gsa) => {
+                self.fix_type = Some(gsa.fix_type);
+                self.hdop = gsa.hdop.or(self.hdop);
+                self.satellites_used = gsa.prns;
+            }
+            SentenceData::Gsv(gsv) => {
+                let pending = self.pending_satellites.entry(talker.clone()).or_default();
+                if gsv.message_number <= 1 {
+                    pending.clear();
+                }
+                pending.extend(gsv.satellites);
+                if gsv.total_messages <= gsv.message_number {
+                    let satellites = self.pending_satellites.remove(&talker).unwrap_or_default();
+                    self.satellites.insert(talker, satellites);
+                }
+            }
+            SentenceData::Vtg(vtg) => {
+                self.speed_kmh = vtg
+                    .speed_kmh
+                    .or(vtg.speed_knots.map(|knots| knots * KNOTS_TO_KMH));
+                self.course = vtg.course;
+            }
+            SentenceData::Gll(gll) => {
+                self.time = gll.time.or(self.time);
+                if gll.is_valid {
+                    self.position = gll.position.or(self.position);
+                }
+            }
+            SentenceData::Other => {}
+        }
+    }
+
+    fn status_grid(&self, ui: &mut egui::Ui) {
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "—".to_string());
+        let fix = match (self.quality, self.fix_type) {
+            (Some(0), _) | (None, Some(1)) => "No fix".to_string(),
+            (Some(quality), Some(fix_type @ (2 | 3))) => {
+                format!(
+                    "{} ({})",
+                    nmea::quality_name(quality),
+                    nmea::fix_type_name(fix_type)
+                )
+            }
+            (Some(quality), _) => nmea::quality_name(quality).to_string(),
+            (None, Some(fix_type)) => nmea::fix_type_name(fix_type).to_string(),
+            (None, None) => "—".to_string(),
+        };
+        let utc = match (self.date, self.time) {
+            (Some(date), Some(time)) => format!("{date} {}", time.format("%H:%M:%S%.3f")),
+            (None, Some(time)) => time.format("%H:%M:%S%.3f").to_string(),
+            _ => "—".to_string(),
+        };
+        let in_view: usize = self.satellites.values().map(Vec::len).sum();
+
+        egui::Grid::new(ui.id().with("gnss_status"))
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                let mut row = |name: &str, value: String| {
+                    ui.label(name);
+                    ui.monospace(value);
+                    ui.end_row();
+                };
+                row("Fix", fix);
+                row(
+                    "Position",
+                    optional(self.position.as_ref().map(nmea::format_position)),
+                );
+                row(
+                    "Altitude",
+                    optional(self.altitude_m.map(|altitude| format!("{altitude:.1} m"))),
+                );
+                row(
+                    "Speed",
+                    optional(self.speed_kmh.map(|speed| format!("{speed:.1} km/h"))),
+                );
+                row(
+                    "Course",
+                    optional(self.course.map(|course| format!("{course:.1}°"))),
+                );
+                row("HDOP", optional(self.hdop.map(|hdop| format!("{hdop:.2}"))));
+                row(
+                    "Satellites",
+                    format!("{} used, {in_view} in view", self.satellites_used.len()),
+                );
+                row("UTC", utc);
+                row(
+                    "Last sentence",
+                    optional(
+                        self.last_update
+                            .map(|time| time.format("%H:%M:%S%.3f").to_string()),
+                    ),
+                );
+            });
+    }
+
+    /// 衛星ごとの SNR を棒グラフで表示する。測位に使っている衛星は枠を付ける
+    fn satellite_bars(&self, ui: &mut egui::Ui) {
+        if self.satellites.is_empty() {
+            ui.weak("No satellites in view (waiting for GSV)");
+            return;
+        }
+        for (talker, satellites) in &self.satellites {
+            ui.label(format!("{talker} ({} in view)", satellites.len()));
+            ui.horizontal_wrapped(|ui| {
+                for satellite in satellites {
+                    self.snr_bar(ui, satellite);
+                }
+            });
+        }
+    }
+
+    fn snr_bar(&self, ui: &mut egui::Ui, satellite: &Satellite) {
+        let (rect, response) = ui.allocate_exact_size(
+            egui::vec2(SNR_BAR_WIDTH, SNR_BAR_HEIGHT + 14.0),
+            egui::Sense::hover(),
+        );
+        let bar_area =
+            egui::Rect::from_min_size(rect.min, egui::vec2(SNR_BAR_WIDTH, SNR_BAR_HEIGHT));
+        let painter = ui.painter();
+        painter.rect_filled(bar_area, 2.0, ui.visuals().extreme_bg_color);
+
+        let snr = satellite.snr.unwrap_or(0);
+        let ratio = (f32::from(snr) / SNR_FULL_SCALE).clamp(0.0, 1.0);
+        let color = match snr {
+            35.. => sereal_colors::UI_GREEN,
+            20.. => sereal_colors::UI_ORANGE,
+            _ => sereal_colors::UI_RED,
+        };
+        let bar = egui::Rect::from_min_max(
+            egui::pos2(bar_area.min.x, bar_area.max.y - SNR_BAR_HEIGHT * ratio),
+            bar_area.max,
+        );
+        painter.rect_filled(bar, 2.0, color.to_egui_color32());
+        if self.satellites_used.contains(&satellite.prn) {
+            painter.rect_stroke(
+                bar_area,
+                2.0,
+                ui.visuals().selection.stroke,
+                egui::StrokeKind::Inside,
+            );
+        }
+        painter.text(
+            egui::pos2(rect.center().x, rect.max.y),
+            egui::Align2::CENTER_BOTTOM,
+            satellite.prn.to_string(),
+            egui::FontId::monospace(9.0),
+            ui.visuals().text_color(),
+        );
+
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "—".to_string());
+        response.on_hover_text(format!(
+            "PRN {}\nSNR {}\nElevation {}\nAzimuth {}{}",
+            satellite.prn,
+            optional(satellite.snr.map(|snr| format!("{snr} dB-Hz"))),
+            optional(satellite.elevation.map(|value| format!("{value}°"))),
+            optional(satellite.azimuth.map(|value| format!("{value}°"))),
+            if self.satellites_used.contains(&satellite.prn) {
+                "\nUsed in the fix"
+            } else {
+                ""
+            }
+        ));
+    }
+}
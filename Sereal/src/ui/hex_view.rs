@@ -0,0 +1,465 @@
+use crate::ansi_formatter::LOG_FONT;
+use crate::serial::ReceivedData;
+use base64::Engine;
+use chrono::{DateTime, Local};
+use eframe::egui;
+use egui::text::{LayoutJob, TextFormat};
+use std::collections::VecDeque;
+
+const BYTES_PER_ROW: usize = 16;
+// 保持する受信データの上限。超えた分は古い行から捨てる
+const HEX_MAX_BYTES: usize = 16 * 1024 * 1024;
+// 保持する行と受信の区切りの上限。1 バイトずつ受信しても、区切りの情報だけで膨らまないようにする
+const HEX_MAX_ENTRIES: usize = 1_000_000;
+const TIME_FORMAT: &str = "%H:%M:%S%.3f";
+
+/// 行を区切る位置
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum RowSplit {
+    /// 16 バイトごとに区切る
+    #[default]
+    Continuous,
+    /// 受信の区切りで改行する
+    Chunk,
+    /// 受信の間隔が空いたら改行する
+    IdleGap,
+}
+
+struct Chunk {
+    offset: usize,
+    time: DateTime<Local>,
+}
+
+struct HexRow {
+    offset: usize,
+    len: usize,
+    time: Option<DateTime<Local>>, // 区切りの先頭の行のみ受信時刻を持つ
+}
+
+/// 受信したバイト列を オフセット | 16 進 | ASCII の形式で表示するビュー
+pub struct HexView {
+    bytes: VecDeque<u8>,
+    first_offset: usize, // bytes の先頭の通し番号
+    chunks: VecDeque<Chunk>,
+    rows: VecDeque<HexRow>,
+    row_split: RowSplit,
+    idle_gap_ms: u32,
+    hovered: Option<usize>,
+    selection: Option<(usize, usize)>, // ドラッグを始めた位置と現在の位置
+    is_selecting: bool,
+}
+
+impl Default for HexView {
+    fn default() -> Self {
+        Self {
+            bytes: VecDeque::new(),
+            first_offset: 0,
+            chunks: VecDeque::new(),
+            rows: VecDeque::new(),
+            row_split: RowSplit::default(),
+            idle_gap_ms: 20,
+            hovered: None,
+            selection: None,
+            is_selecting: false,
+        }
+    }
+}
+
+impl HexView {
+    pub fn push(&mut self, data: &ReceivedData) {
+        if data.bytes.is_empty() {
+            return;
+        }
+        let offset = self.end_offset();
+        let previous_time = self.chunks.back().map(|chunk| chunk.time);
+        self.bytes.extend(&data.bytes);
+        self.chunks.push_back(Chunk {
+            offset,
+            time: data.time,
+        });
+        let is_split = self.is_split(previous_time, data.time);
+        self.append_rows(offset, data.bytes.len(), data.time, is_split);
+        self.trim();
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self {
+            row_split: self.row_split,
+            idle_gap_ms: self.idle_gap_ms,
+            ..Default::default()
+        };
+    }
+
+    pub fn row_split(&self) -> RowSplit {
+        self.row_split
+    }
+
+    pub fn idle_gap_ms(&self) -> u32 {
+        self.idle_gap_ms
+    }
+
+    /// 行の区切り方を変更し、受信済みのデータを並べ直す
+    pub fn set_row_split(&mut self, row_split: RowSplit, idle_gap_ms: u32) {
+        if (self.row_split, self.idle_gap_ms) == (row_split, idle_gap_ms) {
+            return;
+        }
+        self.row_split = row_split;
+        self.idle_gap_ms = idle_gap_ms;
+
+        self.rows.clear();
+        let mut previous_time = None;
+        for index in 0..self.chunks.len() {
+            let chunk = &self.chunks[index];
+            let start = chunk.offset.max(self.first_offset);
+            let end = self
+                .chunks
+                .get(index + 1)
+                .map_or(self.end_offset(), |next| next.offset);
+            let time = chunk.time;
+            let is_split = self.is_split(previous_time, time);
+            if start < end {
+                self.append_rows(start, end - start, time, is_split);
+            }
+            previous_time = Some(time);
+        }
+        self.trim();
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> egui::Rect {
+        let (row_height, char_width) = ui.fonts(|fonts| {
+            (
+                fonts.row_height(&LOG_FONT),
+                fonts.glyph_width(&LOG_FONT, '0'),
+            )
+        });
+        let pointer = ui.input(|input| input.pointer.interact_pos());
+        let is_primary_down = ui.input(|input| input.pointer.primary_down());
+        let mut hovered = None;
+
+        let rect = ui
+            .scope(|ui| {
+                ui.spacing_mut().item_spacing = egui::Vec2 { x: 0.0, y: 0.0 };
+                egui::ScrollArea::both()
+                    .auto_shrink(false)
+                    .stick_to_bottom(true)
+                    .show_rows(ui, row_height, self.rows.len(), |ui, row_range| {
+                        for index in row_range {
+                            let mut layout = self.layout_row(&self.rows[index], ui);
+                            let job = std::mem::take(&mut layout.job);
+                            let galley = ui.fonts(|fonts| fonts.layout_job(job));
+                            let (rect, response) = ui.allocate_exact_size(
+                                egui::vec2(galley.size().x, row_height),
+                                egui::Sense::click_and_drag(),
+                            );
+                            ui.painter()
+                                .galley(rect.min, galley, ui.visuals().text_color());
+
+                            // ドラッグ中は他の行が hover にならないため、ポインタの位置で判定する
+                            let byte = pointer.filter(|pointer| rect.contains(*pointer)).and_then(
+                                |pointer| {
+                                    let column = ((pointer.x - rect.min.x) / char_width) as usize;
+                                    layout.byte_at_column(column)
+                                },
+                            );
+                            if byte.is_some() {
+                                hovered = byte;
+                            }
+                            if response.drag_started() || response.clicked() {
+                                self.selection = byte.map(|byte| (byte, byte));
+                                self.is_selecting = response.drag_started();
+                            }
+                            response.context_menu(|ui| self.copy_menu(ui));
+                        }
+                    })
+                    .inner_rect
+            })
+            .inner;
+
+        self.is_selecting &= is_primary_down;
+        if self.is_selecting
+            && let (Some((anchor, _)), Some(byte)) = (self.selection, hovered)
+        {
+            self.selection = Some((anchor, byte));
+        }
+        if self.hovered != hovered {
+            self.hovered = hovered;
+            ui.ctx().request_repaint();
+        }
+        rect
+    }
+
+    fn copy_menu(&self, ui: &mut egui::Ui) {
+        let selected = self.selected_bytes(usize::MAX);
+        ui.add_enabled_ui(!selected.is_empty(), |ui| {
+            if ui.button("Copy as hex").clicked() {
+                let text: Vec<String> = selected.iter().map(|byte| format!("{byte:02X}")).collect();
+                ui.ctx().copy_text(text.join(" "));
+            }
+            if ui.button("Copy as C array").clicked() {
+                let text: Vec<String> = selected
+                    .iter()
+                    .map(|byte| format!("0x{byte:02X}"))
+                    .collect();
+                ui.ctx().copy_text(format!("{{ {} }}", text.join(", ")));
+            }
+            if ui.button("Copy as base64").clicked() {
+                ui.ctx()
+                    .copy_text(base64::engine::general_purpose::STANDARD.encode(&selected));
+            }
+        });
+        if selected.is_empty() {
+            ui.weak("Drag over bytes to select them");
+        }
+    }
+
+    /// 選択中のバイト列を先頭から最大 max_len バイト返す
+    pub fn selected_bytes(&self, max_len: usize) -> Vec<u8> {
+        let Some((anchor, end)) = self.selection else {
+            return Vec::new();
+        };
+        let start = anchor.min(end).max(self.first_offset);
+        let end = anchor.max(end).min(self.end_offset().saturating_sub(1));
+        (start..=end)
+            .filter_map(|offset| self.bytes.get(offset - self.first_offset).copied())
+            .take(max_len)
+            .collect()
+    }
+
+    fn is_selected(&self, offset: usize) -> bool {
+        self.selection
+            .is_some_and(|(anchor, end)| (anchor.min(end)..=anchor.max(end)).contains(&offset))
+    }
+
+    fn end_offset(&self) -> usize {
+        self.first_offset + self.bytes.len()
+    }
+
+    fn is_split(&self, previous_time: Option<DateTime<Local>>, time: DateTime<Local>) -> bool {
+        match self.row_split {
+            RowSplit::Continuous => false,
+            RowSplit::Chunk => true,
+            RowSplit::IdleGap => previous_time.is_none_or(|previous| {
+                i64::from(self.idle_gap_ms) <= (time - previous).num_milliseconds()
+            }),
+        }
+    }
+
+    fn append_rows(&mut self, offset: usize, len: usize, time: DateTime<Local>, is_split: bool) {
+        let mut offset = offset;
+        let end = offset + len;
+
+        // 区切らない場合は末尾の行の残りを埋める
+        if !is_split
+            && let Some(last) = self.rows.back_mut()
+            && last.len < BYTES_PER_ROW
+        {
+            let fill = (BYTES_PER_ROW - last.len).min(len);
+            last.len += fill;
+            offset += fill;
+        }
+
+        let mut row_time = is_split.then_some(time);
+        while offset < end {
+            let len = (end - offset).min(BYTES_PER_ROW);
+            self.rows.push_back(HexRow {
+                offset,
+                len,
+                time: row_time.take(),
+            });
+            offset += len;
+        }
+    }
+
+    /// 上限を超えた分を古い行から捨てる
+    fn trim(&mut self) {
+        while !self.rows.is_empty()
+            && (HEX_MAX_BYTES < self.bytes.len()
+                || HEX_MAX_ENTRIES < self.rows.len()
+                || HEX_MAX_ENTRIES < self.chunks.len())
+        {
+            self.drop_first_row();
+        }
+    }
+
+    fn drop_first_row(&mut self) {
+        let Some(row) = self.rows.pop_front() else {
+            return;
+        };
+        self.bytes.drain(..row.len.min(self.bytes.len()));
+        self.first_offset += row.len;
+        while 1 < self.chunks.len() && self.chunks[1].offset <= self.first_offset {
+            self.chunks.pop_front();
+        }
+    }
+
+    fn layout_row(&self, row: &HexRow, ui: &egui::Ui) -> RowLayout {
+        let text_color = ui.visuals().text_color();
+        let weak_color = ui.visuals().weak_text_color();
+        let hovered_background = ui.visuals().widgets.hovered.weak_bg_fill;
+        let selected_background = ui.visuals().selection.bg_fill;
+
+        let transparent = egui::Color32::TRANSPARENT;
+        let mut layout = RowLayout::default();
+
+        if self.row_split != RowSplit::Continuous {
+            let time = row
+                .time
+                .map(|time| time.format(TIME_FORMAT).to_string())
+                .unwrap_or_default();
+            layout.append(&format!("{time:12}  "), weak_color, transparent);
+        }
+        layout.append(&format!("{:08X}  ", row.offset), weak_color, transparent);
+
+        let background = |offset: usize| {
+            if self.is_selected(offset) {
+                selected_background
+            } else if self.hovered == Some(offset) {
+                hovered_background
+            } else {
+                transparent
+            }
+        };
+        let row_bytes: Vec<(usize, u8)> = (row.offset..row.offset + row.len)
+            .filter_map(|offset| {
+                let byte = self.bytes.get(offset.checked_sub(self.first_offset)?)?;
+                Some((offset, *byte))
+            })
+            .collect();
+
+        for column in 0..BYTES_PER_ROW {
+            match row_bytes.get(column) {
+                Some(&(offset, byte)) => {
+                    layout.hex_columns.push((layout.columns, offset));
+                    layout.append(&format!("{byte:02X}"), text_color, background(offset));
+                }
+                None => layout.append("  ", text_color, transparent),
+            }
+            // 8 バイトごとに空白を広げる
+            let separator = if column == BYTES_PER_ROW / 2 - 1 {
+                "  "
+            } else {
+                " "
+            };
+            layout.append(separator, text_color, transparent);
+        }
+        layout.append(" ", text_color, transparent);
+
+        for &(offset, byte) in &row_bytes {
+            layout.ascii_columns.push((layout.columns, offset));
+            let ch = if byte.is_ascii_graphic() || byte == b' ' {
+                char::from(byte)
+            } else {
+                '.'
+            };
+            layout.append(&ch.to_string(), text_color, background(offset));
+        }
+
+        layout
+    }
+}
+
+/// 1 行分の LayoutJob と、文字の列からバイトを引くための対応表
+#[derive(Default)]
+struct RowLayout {
+    job: LayoutJob,
+    columns: usize,
+    hex_columns: Vec<(usize, usize)>, // 16 進の 2 文字の先頭の列と通し番号
+    ascii_columns: Vec<(usize, usize)>, // ASCII の列と通し番号
+}
+
+impl RowLayout {
+    fn append(&mut self, text: &str, color: egui::Color32, background: egui::Color32) {
+        self.job.append(
+            text,
+            0.0,
+            TextFormat {
+                font_id: LOG_FONT,
+                color,
+                background,
+                ..Default::default()
+            },
+        );
+        self.columns += text.chars().count();
+    }
+
+    fn byte_at_column(&self, column: usize) -> Option<usize> {
+        self.hex_columns
+            .iter()
+            .find(|(start, _)| (*start..*start + 2).contains(&column))
+            .or_else(|| {
+                self.ascii_columns
+                    .iter()
+                    .find(|(start, _)| *start == column)
+            })
+            .map(|(_, offset)| *offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn received(bytes: &[u8], time: DateTime<Local>) -> ReceivedData {
+        ReceivedData {
+            time,
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    fn row_lengths(view: &HexView) -> Vec<usize> {
+        view.rows.iter().map(|row| row.len).collect()
+    }
+
+    #[test]
+    fn splits_rows_by_mode() {
+        let mut view = HexView::default();
+        let time = Local::now();
+        view.push(&received(&[0; 10], time));
+        view.push(&received(
+            &[1; 10],
+            time + chrono::TimeDelta::milliseconds(5),
+        ));
+        view.push(&received(
+            &[2; 3],
+            time + chrono::TimeDelta::milliseconds(100),
+        ));
+        assert_eq!(row_lengths(&view), [16, 7]);
+
+        view.set_row_split(RowSplit::Chunk, 20);
+        assert_eq!(row_lengths(&view), [10, 10, 3]);
+
+        view.set_row_split(RowSplit::IdleGap, 20);
+        assert_eq!(row_lengths(&view), [16, 4, 3]);
+    }
+
+    #[test]
+    fn bounds_rows_of_single_byte_reads() {
+        let mut view = HexView::default();
+        view.set_row_split(RowSplit::Chunk, 20);
+        let time = Local::now();
+        for index in 0..HEX_MAX_ENTRIES + 10 {
+            view.push(&received(&[index as u8], time));
+        }
+        assert_eq!(view.rows.len(), HEX_MAX_ENTRIES);
+        assert_eq!(view.chunks.len(), HEX_MAX_ENTRIES);
+        assert_eq!(view.bytes.len(), HEX_MAX_ENTRIES);
+        assert_eq!(view.first_offset, 10);
+        assert_eq!(view.rows[0].offset, 10);
+
+        // まとめて並べ直しても上限を超えない
+        view.set_row_split(RowSplit::Continuous, 20);
+        assert_eq!(view.rows.len(), HEX_MAX_ENTRIES / BYTES_PER_ROW);
+        assert!(view.chunks.len() <= HEX_MAX_ENTRIES);
+    }
+
+    #[test]
+    fn selects_bytes_within_buffer() {
+        let mut view = HexView::default();
+        view.push(&received(b"0123456789", Local::now()));
+        view.selection = Some((7, 2));
+        assert_eq!(view.selected_bytes(usize::MAX), b"234567");
+        assert_eq!(view.selected_bytes(3), b"234");
+        view.selection = Some((8, 100));
+        assert_eq!(view.selected_bytes(usize::MAX), b"89");
+    }
+}
@@ -0,0 +1,284 @@
+use eframe::egui;
+use egui::{Event, Key, Modifiers};
+
+/// 対話モードを抜けるためのキー (Ctrl+])
+pub const EXIT_CHORD: (Modifiers, Key) = (Modifiers::CTRL, Key::CloseBracket);
+pub const EXIT_CHORD_LABEL: &str = "Ctrl+]";
+
+/// キー入力をデバイスに送るバイト列に変換する
+///
+/// `is_application_cursor_keys` が有効なときはカーソルキーを `ESC O A` の形式で送る。
+pub fn translate_event(event: &Event, is_application_cursor_keys: bool) -> Option<Vec<u8>> {
+    match event {
+        Event::Text(text) => Some(text.as_bytes().to_vec()),
+        Event::Paste(text) => Some(text.replace('\n', "\r").into_bytes()),
+        // macOS 以外では Ctrl+C/Ctrl+X がコピー/切り取りのイベントになる
+        Event::Copy if !cfg!(target_os = "macos") => Some(vec![0x03]),
+        Event::Cut if !cfg!(target_os = "macos") => Some(vec![0x18]),
+        Event::Key {
+            key,
+            pressed: true,
+            modifiers,
+            ..
+        } => translate_key(*key, *modifiers, is_application_cursor_keys),
+        _ => None,
+    }
+}
+
+pub fn is_exit_chord(event: &Event) -> bool {
+    let (chord_modifiers, chord_key) = EXIT_CHORD;
+    matches!(
+        event,
+        Event::Key { key, pressed: true, modifiers, .. }
+            if *key == chord_key && modifiers.matches_exact(chord_modifiers)
+    )
+}
+
+/// ローカルエコーで表示するバイト列
+///
+/// 表示できる文字と CR/LF/BS だけを残す。エスケープシーケンスは丸ごと捨て、DEL は BS として表示する。
+pub fn local_echo(bytes: &[u8]) -> Vec<u8> {
+    let text: String = bytes.utf8_chunks().map(|chunk| chunk.valid()).collect();
+    let mut echo = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                // CSI は終端文字まで読み飛ばす
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('\x40'..='\x7e').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // SS3 (ESC O P など) は続く 1 文字まで
+                Some('O') => {
+                    chars.next();
+                }
+                _ => {}
+            },
+            '\r' | '\n' | '\x08' => echo.push(c),
+            '\x7f' => echo.push('\x08'),
+            _ if c.is_control() => {}
+            _ => echo.push(c),
+        }
+    }
+    echo.into_bytes()
+}
+
+fn translate_key(
+    key: Key,
+    modifiers: Modifiers,
+    is_application_cursor_keys: bool,
+) -> Option<Vec<u8>> {
+    if modifiers.ctrl
+        && !modifiers.alt
+        && let Some(control) = control_code(key)
+    {
+        return Some(vec![control]);
+    }
+
+    let cursor_key = |final_byte: u8| {
+        if is_application_cursor_keys {
+            vec![0x1b, b'O', final_byte]
+        } else {
+            vec![0x1b, b'[', final_byte]
+        }
+    };
+    let bytes = match key {
+        Key::Enter => b"\r".to_vec(),
+        Key::Tab if modifiers.shift => b"\x1b[Z".to_vec(),
+        Key::Tab => b"\t".to_vec(),
+        Key::Backspace => vec![0x7f],
+        Key::Escape => vec![0x1b],
+        Key::ArrowUp => cursor_key(b'A'),
+        Key::ArrowDown => cursor_key(b'B'),
+        Key::ArrowRight => cursor_key(b'C'),
+        Key::ArrowLeft => cursor_key(b'D'),
+        Key::Home => cursor_key(b'H'),
+        Key::End => cursor_key(b'F'),
+        Key::Insert => b"\x1b[2~".to_vec(),
+        Key::Delete => b"\x1b[3~".to_vec(),
+        Key::PageUp => b"\x1b[5~".to_vec(),
+        Key::PageDown => b"\x1b[6~".to_vec(),
+        Key::F1 => b"\x1bOP".to_vec(),
+        Key::F2 => b"\x1bOQ".to_vec(),
+        Key::F3 => b"\x1bOR".to_vec(),
+        Key::F4 => b"\x1bOS".to_vec(),
+        Key::F5 => b"\x1b[15~".to_vec(),
+        Key::F6 => b"\x1b[17~".to_vec(),
+        Key::F7 => b"\x1b[18~".to_vec(),
+        Key::F8 => b"\x1b[19~".to_vec(),
+        Key::F9 => b"\x1b[20~".to_vec(),
+        Key::F10 => b"\x1b[21~".to_vec(),
+        Key::F11 => b"\x1b[23~".to_vec(),
+        Key::F12 => b"\x1b[24~".to_vec(),
+        // 文字の入力は Event::Text で受け取る
+        _ => return None,
+    };
+    Some(bytes)
+}
+
+/// Ctrl と組み合わせたキーに対応する C0 制御文字
+fn control_code(key: Key) -> Option<u8> {
+    let name = key.name();
+    match key {
+        Key::Space | Key::Num2 => Some(0x00),
+        Key::OpenBracket => Some(0x1b),
+        Key::Backslash => Some(0x1c),
+        Key::CloseBracket => Some(0x1d),
+        Key::Num6 => Some(0x1e),
+        Key::Minus => Some(0x1f),
+        _ if name.len() == 1 && name.as_bytes()[0].is_ascii_uppercase() => {
+            Some(name.as_bytes()[0] - b'A' + 1)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: Key, modifiers: Modifiers) -> Event {
+        Event::Key {
+            key,
+            physical_key: None,
+            pressed: true,
+            repeat: false,
+            modifiers,
+        }
+    }
+
+    fn translate(event: &Event) -> Option<Vec<u8>> {
+        translate_event(event, false)
+    }
+
+    #[test]
+    fn cursor_keys() {
+        let arrows = [
+            (Key::ArrowUp, b'A'),
+            (Key::ArrowDown, b'B'),
+            (Key::ArrowRight, b'C'),
+            (Key::ArrowLeft, b'D'),
+            (Key::Home, b'H'),
+            (Key::End, b'F'),
+        ];
+        for (arrow, final_byte) in arrows {
+            let event = key(arrow, Modifiers::NONE);
+            assert_eq!(translate(&event), Some(vec![0x1b, b'[', final_byte]));
+            // アプリケーションカーソルキーモードでは SS3 で送る
+            assert_eq!(
+                translate_event(&event, true),
+                Some(vec![0x1b, b'O', final_byte])
+            );
+        }
+    }
+
+    #[test]
+    fn function_keys() {
+        let expected: [(Key, &[u8]); 6] = [
+            (Key::F1, b"\x1bOP"),
+            (Key::F4, b"\x1bOS"),
+            (Key::F5, b"\x1b[15~"),
+            (Key::F6, b"\x1b[17~"),
+            (Key::F11, b"\x1b[23~"),
+            (Key::F12, b"\x1b[24~"),
+        ];
+        for (function_key, bytes) in expected {
+            assert_eq!(
+                translate(&key(function_key, Modifiers::NONE)).as_deref(),
+                Some(bytes),
+                "{function_key:?}"
+            );
+        }
+        assert_eq!(
+            translate(&key(Key::Delete, Modifiers::NONE)).as_deref(),
+            Some(&b"\x1b[3~"[..])
+        );
+        assert_eq!(
+            translate(&key(Key::Tab, Modifiers::SHIFT)).as_deref(),
+            Some(&b"\x1b[Z"[..])
+        );
+    }
+
+    #[test]
+    fn control_letters() {
+        assert_eq!(translate(&key(Key::A, Modifiers::CTRL)), Some(vec![0x01]));
+        assert_eq!(translate(&key(Key::D, Modifiers::CTRL)), Some(vec![0x04]));
+        assert_eq!(translate(&key(Key::Z, Modifiers::CTRL)), Some(vec![0x1a]));
+        assert_eq!(
+            translate(&key(Key::OpenBracket, Modifiers::CTRL)),
+            Some(vec![0x1b])
+        );
+        assert_eq!(
+            translate(&key(Key::Space, Modifiers::CTRL)),
+            Some(vec![0x00])
+        );
+        // Ctrl+Alt は制御文字にしない
+        assert_eq!(
+            translate(&key(Key::A, Modifiers::CTRL | Modifiers::ALT)),
+            None
+        );
+        // Ctrl なしの文字キーは Event::Text で届くので、ここでは送らない
+        assert_eq!(translate(&key(Key::A, Modifiers::NONE)), None);
+        // 離したときのイベントは送らない
+        let released = Event::Key {
+            key: Key::A,
+            physical_key: None,
+            pressed: false,
+            repeat: false,
+            modifiers: Modifiers::CTRL,
+        };
+        assert_eq!(translate(&released), None);
+    }
+
+    #[test]
+    fn copy_and_cut_events() {
+        let (copy, cut) = if cfg!(target_os = "macos") {
+            (None, None)
+        } else {
+            (Some(vec![0x03]), Some(vec![0x18]))
+        };
+        assert_eq!(translate(&Event::Copy), copy);
+        assert_eq!(translate(&Event::Cut), cut);
+    }
+
+    #[test]
+    fn text_and_paste() {
+        assert_eq!(
+            translate(&Event::Text("aé".to_string())),
+            Some("aé".as_bytes().to_vec())
+        );
+        assert_eq!(
+            translate(&Event::Paste("ls\nexit\n".to_string())),
+            Some(b"ls\rexit\r".to_vec())
+        );
+    }
+
+    #[test]
+    fn exit_chord() {
+        assert!(is_exit_chord(&key(Key::CloseBracket, Modifiers::CTRL)));
+        assert!(!is_exit_chord(&key(Key::CloseBracket, Modifiers::NONE)));
+        assert!(!is_exit_chord(&key(
+            Key::CloseBracket,
+            Modifiers::CTRL | Modifiers::SHIFT
+        )));
+        assert!(!is_exit_chord(&key(Key::OpenBracket, Modifiers::CTRL)));
+    }
+
+    #[test]
+    fn echoes_only_text_and_line_control() {
+        assert_eq!(local_echo(b"ls -l\r\n"), b"ls -l\r\n");
+        assert_eq!(local_echo("é".as_bytes()), "é".as_bytes());
+        assert_eq!(local_echo(&[0x7f]), [0x08]);
+        assert_eq!(local_echo(&[0x08]), [0x08]);
+        // 制御文字とエスケープシーケンスは表示しない
+        assert_eq!(local_echo(&[0x03, 0x04, b'\t', 0x1b]), b"");
+        assert_eq!(local_echo(b"\x1b[A\x1b[15~\x1bOPx"), b"x");
+        assert_eq!(local_echo(b"a\x1bbc"), b"ac");
+        // 不正な UTF-8 は捨てる
+        assert_eq!(local_echo(&[b'a', 0xff, b'b']), b"ab");
+    }
+}
@@ -0,0 +1,458 @@
+use eframe::egui;
+use egui::text::{LayoutJob, TextFormat};
+use std::cell::OnceCell;
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/// 1 行に保持する文字数の上限。超えた分は次の行に折り返す
+const LINE_MAX_CHARS: usize = 4096;
+
+/// 文字に付ける補足情報
+#[derive(Debug, Clone, PartialEq)]
+pub enum Annotation {
+    /// OSC 8 のリンク先
+    Link(String),
+    /// ポインタを重ねたときに表示する説明
+    Explanation(String),
+}
+
+/// テキストを追加するときの書式
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanStyle {
+    pub format: TextFormat,
+    pub is_blink: bool,
+    pub annotation: Option<Annotation>,
+}
+
+/// 整形済みの 1 行
+#[derive(Default)]
+pub struct StyledLine {
+    job: LayoutJob,
+    blink_sections: Vec<usize>, // 点滅させる LayoutJob のセクション番号
+    annotations: Vec<(Range<usize>, Annotation)>, // 補足情報を付けた文字の範囲 (文字単位)
+    char_count: usize,
+}
+
+impl StyledLine {
+    /// 描画用の LayoutJob を返す。点滅の消灯中は該当箇所を透明にする
+    pub fn layout_job(&self, is_blink_visible: bool) -> LayoutJob {
+        let mut job = self.job.clone();
+        if !is_blink_visible {
+            for &index in &self.blink_sections {
+                let format = &mut job.sections[index].format;
+                format.color = egui::Color32::TRANSPARENT;
+                format.underline = egui::Stroke::NONE;
+                format.strikethrough = egui::Stroke::NONE;
+            }
+        }
+        job
+    }
+
+    pub fn text(&self) -> &str {
+        &self.job.text
+    }
+
+    pub fn has_blink(&self) -> bool {
+        !self.blink_sections.is_empty()
+    }
+
+    /// char_index 文字目に付けた補足情報を返す
+    pub fn annotation_at(&self, char_index: usize) -> Option<&Annotation> {
+        self.annotations
+            .iter()
+            .find(|(range, _)| range.contains(&char_index))
+            .map(|(_, annotation)| annotation)
+    }
+
+    fn append(&mut self, text: &str, style: &SpanStyle) {
+        self.job.append(text, 0.0, style.format.clone());
+        if style.is_blink {
+            self.blink_sections.push(self.job.sections.len() - 1);
+        }
+        let start = self.char_count;
+        self.char_count += text.chars().count();
+        if let Some(annotation) = &style.annotation {
+            self.annotations
+                .push((start..self.char_count, annotation.clone()));
+        }
+    }
+}
+
+/// 改行をまだ受信していない行
+///
+/// 復帰 (CR) やバックスペース (BS) で既に書いた文字を上書きできるように、
+/// 1 文字ずつ書式と合わせて保持する。
+#[derive(Default)]
+struct LineBuilder {
+    chars: Vec<(char, usize)>, // 文字と styles の番号
+    styles: Vec<SpanStyle>,
+    cursor: usize,
+}
+
+impl LineBuilder {
+    /// カーソル位置から text を書き込む。行が上限に達して書き込めなかった残りを返す
+    fn write<'a>(&mut self, text: &'a str, style: &SpanStyle) -> &'a str {
+        let style_index = self.style_index(style);
+        for (offset, ch) in text.char_indices() {
+            if let Some(slot) = self.chars.get_mut(self.cursor) {
+                *slot = (ch, style_index);
+            } else if self.chars.len() < LINE_MAX_CHARS {
+                self.chars.push((ch, style_index));
+            } else {
+                return &text[offset..];
+            }
+            self.cursor += 1;
+        }
+        ""
+    }
+
+    /// style の styles での番号。なければ追加する
+    fn style_index(&mut self, style: &SpanStyle) -> usize {
+        if self.styles.last() != Some(style) {
+            // CR や BS の後の上書きで参照されなくなった書式が溜まったら捨てる
+            if 2 * self.chars.len().max(1) < self.styles.len() {
+                self.compact_styles();
+            }
+            self.styles.push(style.clone());
+        }
+        self.styles.len() - 1
+    }
+
+    /// どの文字からも参照されていない書式を取り除き、番号を詰める
+    fn compact_styles(&mut self) {
+        let mut new_indices: Vec<Option<usize>> = vec![None; self.styles.len()];
+        let mut styles = Vec::new();
+        for (_, style_index) in &mut self.chars {
+            *style_index = *new_indices[*style_index].get_or_insert_with(|| {
+                styles.push(self.styles[*style_index].clone());
+                styles.len() - 1
+            });
+        }
+        self.styles = styles;
+    }
+
+    /// 行末の空白を symbol に置き換える。末尾にある制御文字の記号 (␍ など) は飛ばす
+    fn mark_trailing_spaces(&mut self, symbol: char, style: &SpanStyle) {
+        let is_control_picture = |ch: char| ('\u{2400}'..='\u{2421}').contains(&ch);
+        let end = self.chars.len()
+            - self
+                .chars
+                .iter()
+                .rev()
+                .take_while(|(ch, _)| is_control_picture(*ch))
+                .count();
+        let start = end
+            - self.chars[..end]
+                .iter()
+                .rev()
+                .take_while(|(ch, _)| *ch == ' ')
+                .count();
+        if start == end {
+            return;
+        }
+        let style_index = self.style_index(style);
+        for slot in &mut self.chars[start..end] {
+            *slot = (symbol, style_index);
+        }
+    }
+
+    fn build(&self) -> StyledLine {
+        let mut line = StyledLine::default();
+        let mut text = String::new();
+        let mut current_style = None;
+
+        for &(ch, style_index) in &self.chars {
+            if let Some(current) = current_style
+                && current != style_index
+            {
+                line.append(&text, &self.styles[current]);
+                text.clear();
+            }
+            current_style = Some(style_index);
+            text.push(ch);
+        }
+        if let Some(current) = current_style {
+            line.append(&text, &self.styles[current]);
+        }
+
+        line
+    }
+
+    fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+}
+
+/// 受信ログの 1 行ずつを整形済みの LayoutJob として保持するバッファ
+///
+/// 各行は受信時に一度だけ整形され、描画時は見えている行だけを参照する。
+pub struct LineStore {
+    lines: VecDeque<StyledLine>,
+    current: LineBuilder, // 改行をまだ受信していない末尾の行
+    /// current を整形したもの。描画のたびに整形し直さないよう、書き込むまで使い回す
+    current_line: OnceCell<StyledLine>,
+    max_lines: usize,
+    dropped_lines: usize, // 上限を超えて捨てた行数。行の通し番号に使う
+}
+
+impl LineStore {
+    pub fn new(max_lines: usize) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            current: LineBuilder::default(),
+            current_line: OnceCell::new(),
+            max_lines,
+            dropped_lines: 0,
+        }
+    }
+
+    /// 末尾の行のカーソル位置に書式付きのテキストを書き込む
+    ///
+    /// 行が LINE_MAX_CHARS 文字に達したら行を確定させ、残りは次の行に書き込む。
+    pub fn append(&mut self, text: &str, style: &SpanStyle) {
+        let mut text = text;
+        loop {
+            text = self.current.write(text, style);
+            self.current_line.take();
+            if text.is_empty() {
+                break;
+            }
+            self.end_line();
+        }
+    }
+
+    /// 末尾の行のカーソルを行頭に戻す (CR)
+    pub fn carriage_return(&mut self) {
+        self.current.cursor = 0;
+    }
+
+    /// 末尾の行のカーソルを 1 文字戻す (BS)
+    pub fn backspace(&mut self) {
+        self.current.cursor = self.current.cursor.saturating_sub(1);
+    }
+
+    /// 末尾の行の行末の空白を symbol に置き換えて見えるようにする
+    pub fn mark_trailing_spaces(&mut self, symbol: char, style: &SpanStyle) {
+        self.current.mark_trailing_spaces(symbol, style);
+        self.current_line.take();
+    }
+
+    /// 末尾の行を確定させる。上限を超えた分は古い行から捨てる
+    pub fn end_line(&mut self) {
+        let builder = std::mem::take(&mut self.current);
+        let line = self.current_line.take().unwrap_or_else(|| builder.build());
+        self.lines.push_back(line);
+        while self.max_lines < self.lines.len() {
+            self.lines.pop_front();
+            self.dropped_lines += 1;
+        }
+    }
+
+    /// 描画対象の行数 (未確定の行を含む)
+    pub fn len(&self) -> usize {
+        self.lines.len() + usize::from(!self.current.is_empty())
+    }
+
+    /// 0 行目の通し番号。古い行を捨てても選択した行を指せるようにする
+    pub fn first_line_number(&self) -> usize {
+        self.dropped_lines
+    }
+
+    /// index 行目のテキスト
+    pub fn text(&self, index: usize) -> Option<String> {
+        if index < self.lines.len() {
+            Some(self.lines[index].text().to_string())
+        } else if index == self.lines.len() && !self.current.is_empty() {
+            Some(self.current_line().text().to_string())
+        } else {
+            None
+        }
+    }
+
+    /// index 行目の描画用の LayoutJob を返す
+    pub fn layout_job(&self, index: usize, is_blink_visible: bool) -> Option<LayoutJob> {
+        if index < self.lines.len() {
+            Some(self.lines[index].layout_job(is_blink_visible))
+        } else if index == self.lines.len() && !self.current.is_empty() {
+            Some(self.current_line().layout_job(is_blink_visible))
+        } else {
+            None
+        }
+    }
+
+    /// index 行目に点滅する文字があるか
+    pub fn has_blink(&self, index: usize) -> bool {
+        if index < self.lines.len() {
+            self.lines[index].has_blink()
+        } else {
+            index == self.lines.len() && self.current_line().has_blink()
+        }
+    }
+
+    /// index 行目の char_index 文字目に付けた補足情報を返す
+    pub fn annotation_at(&self, index: usize, char_index: usize) -> Option<Annotation> {
+        if index < self.lines.len() {
+            self.lines[index].annotation_at(char_index).cloned()
+        } else if index == self.lines.len() {
+            self.current_line().annotation_at(char_index).cloned()
+        } else {
+            None
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.dropped_lines += self.lines.len();
+        self.lines.clear();
+        self.current = LineBuilder::default();
+        self.current_line.take();
+    }
+
+    fn current_line(&self) -> &StyledLine {
+        self.current_line.get_or_init(|| self.current.build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(color: egui::Color32) -> SpanStyle {
+        SpanStyle {
+            format: TextFormat {
+                color,
+                ..Default::default()
+            },
+            is_blink: false,
+            annotation: None,
+        }
+    }
+
+    fn texts(store: &LineStore) -> Vec<String> {
+        (0..store.len())
+            .map(|index| store.text(index).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn rebuilds_current_line_after_writes() {
+        let mut store = LineStore::new(10);
+        let plain = style(egui::Color32::WHITE);
+        store.append("abc", &plain);
+        assert_eq!(store.layout_job(0, true).unwrap().text, "abc");
+
+        // 整形済みの行を使い回さず、書き込んだ内容を反映する
+        store.carriage_return();
+        store.append("X", &style(egui::Color32::RED));
+        let job = store.layout_job(0, true).unwrap();
+        assert_eq!(job.text, "Xbc");
+        assert_eq!(job.sections.len(), 2);
+
+        // 書き込んだ空白は "bc" を上書きする
+        store.backspace();
+        store.append("Y", &plain);
+        store.append("  ", &plain);
+        store.mark_trailing_spaces('·', &plain);
+        assert_eq!(texts(&store), ["Y··"]);
+
+        store.end_line();
+        store.append("d", &plain);
+        assert_eq!(texts(&store), ["Y··", "d"]);
+    }
+
+    #[test]
+    fn finds_annotations_in_current_line() {
+        let mut store = LineStore::new(10);
+        let plain = style(egui::Color32::WHITE);
+        let link = SpanStyle {
+            annotation: Some(Annotation::Link("https://example.com".to_string())),
+            ..plain.clone()
+        };
+        store.append("see ", &plain);
+        assert_eq!(store.annotation_at(0, 4), None);
+        store.append("here", &link);
+        assert_eq!(store.annotation_at(0, 3), None);
+        assert_eq!(store.annotation_at(0, 4), link.annotation);
+        store.end_line();
+        assert_eq!(store.annotation_at(0, 7), link.annotation);
+    }
+
+    #[test]
+    fn drops_overwritten_styles() {
+        let mut store = LineStore::new(10);
+        let plain = style(egui::Color32::WHITE);
+        // 進捗表示のように、CR で同じ位置を色を変えながら書き直し続ける
+        for index in 0..1000 {
+            store.carriage_return();
+            store.append("[", &plain);
+            store.append("##", &style(egui::Color32::from_gray(index as u8)));
+            store.append("]", &plain);
+        }
+        assert!(store.current.styles.len() <= 2 * store.current.chars.len());
+
+        let job = store.layout_job(0, true).unwrap();
+        assert_eq!(job.text, "[##]");
+        assert_eq!(job.sections.len(), 3);
+        assert_eq!(
+            job.sections[1].format.color,
+            egui::Color32::from_gray((999 % 256) as u8)
+        );
+        assert_eq!(job.sections[2].format.color, egui::Color32::WHITE);
+    }
+
+    #[test]
+    fn reports_blinking_lines() {
+        let mut store = LineStore::new(10);
+        let plain = style(egui::Color32::WHITE);
+        let blink = SpanStyle {
+            is_blink: true,
+            ..plain.clone()
+        };
+        store.append("a", &plain);
+        store.end_line();
+        store.append("b", &blink);
+        assert!(!store.has_blink(0));
+        assert!(store.has_blink(1));
+        assert!(!store.has_blink(2));
+
+        // 消灯中は点滅する文字だけを透明にする
+        let job = store.layout_job(1, false).unwrap();
+        assert_eq!(job.sections[0].format.color, egui::Color32::TRANSPARENT);
+        store.end_line();
+        assert!(store.has_blink(1));
+    }
+
+    #[test]
+    fn wraps_long_lines() {
+        let mut store = LineStore::new(10);
+        let plain = style(egui::Color32::WHITE);
+        store.append(&"a".repeat(LINE_MAX_CHARS - 1), &plain);
+        store.append("bcd", &plain);
+        let lines = texts(&store);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].chars().count(), LINE_MAX_CHARS);
+        assert!(lines[0].ends_with("ab"));
+        assert_eq!(lines[1], "cd");
+
+        // 上限に達していても、CR の後の上書きは同じ行に書き込む
+        store.carriage_return();
+        store.append("x", &plain);
+        assert_eq!(texts(&store)[1], "xd");
+    }
+
+    #[test]
+    fn numbers_lines_across_dropped_lines() {
+        let mut store = LineStore::new(2);
+        let plain = style(egui::Color32::WHITE);
+        for text in ["a", "b", "c"] {
+            store.append(text, &plain);
+            store.end_line();
+        }
+        assert_eq!(store.first_line_number(), 1);
+        assert_eq!(texts(&store), ["b", "c"]);
+
+        store.clear();
+        assert_eq!(store.first_line_number(), 3);
+        assert_eq!(store.len(), 0);
+        assert_eq!(store.layout_job(0, true), None);
+    }
+}
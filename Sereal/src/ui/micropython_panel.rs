@@ -0,0 +1,487 @@
+use crate::micropython::{DeviceEntry, RawRepl, ReplEvent, files};
+use crate::sereal_colors;
+use chrono::{DateTime, Local};
+use eframe::egui;
+use std::collections::VecDeque;
+
+/// ログでトレースバックを目立たせる色
+const TRACEBACK_START: &[u8] = b"\x1b[31m";
+const TRACEBACK_END: &[u8] = b"\x1b[0m";
+
+enum JobKind {
+    Run { name: String },
+    List { path: String },
+    Upload { remote: String, size: usize },
+    Download { remote: String, local: String },
+}
+
+/// raw REPL で順に実行する一連のコード
+struct Job {
+    kind: JobKind,
+    steps: VecDeque<Vec<u8>>,
+    step_count: usize,
+    /// 受け取った標準出力のバイト数
+    stdout_length: usize,
+    /// ダウンロードするファイルのサイズ。1 行目が揃うまでは先頭を溜めておく
+    file_size: Option<u64>,
+    stdout_head: Vec<u8>,
+}
+
+impl Job {
+    fn new(kind: JobKind, steps: Vec<Vec<u8>>) -> Self {
+        Self {
+            kind,
+            step_count: steps.len(),
+            steps: steps.into(),
+            stdout_length: 0,
+            file_size: None,
+            stdout_head: Vec::new(),
+        }
+    }
+
+    fn description(&self) -> String {
+        match &self.kind {
+            JobKind::Run { name } => format!("Running {name}"),
+            JobKind::List { path } => format!("Listing {path}"),
+            JobKind::Upload { remote, size } => format!("Uploading {remote} ({size} bytes)"),
+            JobKind::Download { remote, .. } => format!("Downloading {remote}"),
+        }
+    }
+
+    /// 進み具合 (0.0 - 1.0)。分からないものは None
+    fn progress(&self) -> Option<f32> {
+        match &self.kind {
+            JobKind::Upload { .. } => {
+                let done = self.step_count - self.steps.len();
+                Some(done as f32 / self.step_count.max(1) as f32)
+            }
+            // 1 バイトが 16 進の 2 文字で届く
+            JobKind::Download { .. } => self
+                .file_size
+                .map(|size| (self.stdout_length as f32 / 2.0 / size.max(1) as f32).clamp(0.0, 1.0)),
+            _ => None,
+        }
+    }
+}
+
+/// MicroPython / CircuitPython のボードでスクリプトを実行し、ファイルをやり取りするパネル
+///
+/// raw REPL のやり取りはタブの接続をそのまま使う。
+/// 実行中は受信データを横取りし、スクリプトの出力とトレースバックだけをログに流す。
+pub struct MicroPythonPanel {
+    repl: RawRepl,
+    job: Option<Job>,
+    script_path: String,
+    device_directory: String,
+    entries: Vec<DeviceEntry>,
+    upload_local: String,
+    upload_remote: String,
+    download_remote: String,
+    download_local: String,
+    status: Option<(String, bool)>, // (内容, エラーかどうか)
+    /// ログに流すバイト列
+    display: Vec<u8>,
+    events: Vec<String>,
+}
+
+impl Default for MicroPythonPanel {
+    fn default() -> Self {
+        Self {
+            repl: RawRepl::default(),
+            job: None,
+            script_path: String::new(),
+            device_directory: "/".to_string(),
+            entries: Vec::new(),
+            upload_local: String::new(),
+            upload_remote: String::new(),
+            download_remote: String::new(),
+            download_local: String::new(),
+            status: None,
+            display: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+}
+
+impl MicroPythonPanel {
+    pub fn is_busy(&self) -> bool {
+        self.job.is_some()
+    }
+
+    /// 受信したバイト列を取り込み、ログに表示するバイト列を返す
+    ///
+    /// 実行中でなければそのまま返す。
+    pub fn feed_rx(&mut self, time: DateTime<Local>, bytes: &[u8]) -> Vec<u8> {
+        if !self.is_busy() {
+            return bytes.to_vec();
+        }
+        self.repl.feed_rx(time, bytes);
+        self.handle_events();
+        std::mem::take(&mut self.display)
+    }
+
+    /// ボードに送るバイト列があれば返す
+    pub fn poll(&mut self, now: DateTime<Local>) -> Option<Vec<u8>> {
+        let bytes = self.repl.poll(now);
+        self.handle_events();
+        bytes
+    }
+
+    pub fn next_poll(&self, now: DateTime<Local>) -> Option<std::time::Duration> {
+        self.repl.next_poll(now)
+    }
+
+    /// イベントの履歴に残す内容
+    pub fn take_events(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn handle_events(&mut self) {
+        for event in self.repl.take_events() {
+            let Some(job) = &mut self.job else {
+                return;
+            };
+            match event {
+                ReplEvent::Entered => self.execute_next_step(),
+                ReplEvent::Stdout(output) => {
+                    job.stdout_length += output.len();
+                    if let JobKind::Run { .. } = job.kind {
+                        self.display.extend_from_slice(&output);
+                    } else if job.file_size.is_none() {
+                        job.stdout_head.extend_from_slice(&output);
+                        job.file_size = files::parse_file_size(&job.stdout_head);
+                    }
+                }
+                ReplEvent::Finished { stdout, stderr } => {
+                    if !stderr.is_empty() {
+                        self.fail_with_exception(&stderr);
+                    } else if job.steps.is_empty() {
+                        self.complete(&stdout);
+                    } else {
+                        self.execute_next_step();
+                    }
+                }
+                ReplEvent::Failed(error) => {
+                    // raw REPL に残っているかもしれないため、止めてから戻る
+                    self.repl.cancel();
+                    self.finish(error, true);
+                }
+            }
+        }
+    }
+
+    fn execute_next_step(&mut self) {
+        if let Some(step) = self.job.as_mut().and_then(|job| job.steps.pop_front()) {
+            self.repl.execute(&step);
+        }
+    }
+
+    fn fail_with_exception(&mut self, stderr: &[u8]) {
+        let summary = files::exception_summary(stderr);
+        if let Some(Job {
+            kind: JobKind::Run { .. },
+            ..
+        }) = &self.job
+        {
+            self.display.extend_from_slice(TRACEBACK_START);
+            self.display.extend_from_slice(stderr);
+            self.display.extend_from_slice(TRACEBACK_END);
+        }
+        self.repl.exit();
+        self.finish(summary, true);
+    }
+
+    fn complete(&mut self, stdout: &[u8]) {
+        self.repl.exit();
+        let Some(job) = &self.job else {
+            return;
+        };
+        let result = match &job.kind {
+            JobKind::Run { name } => Ok(format!("{name} finished")),
+            JobKind::List { path } => files::parse_directory_listing(stdout).map(|entries| {
+                let message = format!("{path}: {} entries", entries.len());
+                self.entries = entries;
+                message
+            }),
+            JobKind::Upload { remote, size } => Ok(format!("Uploaded {remote} ({size} bytes)")),
+            JobKind::Download { remote, local } => {
+                files::parse_file_content(stdout).and_then(|content| {
+                    std::fs::write(local, &content)
+                        .map_err(|error| format!("Failed to write {local}: {error}"))?;
+                    Ok(format!(
+                        "Downloaded {remote} to {local} ({} bytes)",
+                        content.len()
+                    ))
+                })
+            }
+        };
+        match result {
+            Ok(message) => self.finish(message, false),
+            Err(error) => self.finish(error, true),
+        }
+    }
+
+    fn finish(&mut self, message: String, is_error: bool) {
+        if let Some(job) = self.job.take() {
+            let message = if is_error {
+                format!("{} failed: {message}", job.description())
+            } else {
+                message
+            };
+            self.events.push(format!("MicroPython: {message}"));
+            self.status = Some((message, is_error));
+        }
+    }
+
+    fn start(&mut self, kind: JobKind, steps: Vec<Vec<u8>>) {
+        if self.is_busy() {
+            return;
+        }
+        let job = Job::new(kind, steps);
+        self.events
+            .push(format!("MicroPython: {}", job.description()));
+        self.status = None;
+        self.job = Some(job);
+        self.repl.enter();
+    }
+
+    fn run_script(&mut self) {
+        let path = self.script_path.trim().to_string();
+        match std::fs::read(&path) {
+            Ok(code) => {
+                let name = std::path::Path::new(&path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or(path);
+                self.start(JobKind::Run { name }, vec![code]);
+            }
+            Err(error) => self.status = Some((format!("Failed to read {path}: {error}"), true)),
+        }
+    }
+
+    fn list_directory(&mut self, path: String) {
+        let code = files::list_directory_code(&path);
+        self.device_directory = path.clone();
+        self.start(JobKind::List { path }, vec![code.into_bytes()]);
+    }
+
+    fn upload(&mut self) {
+        let local = self.upload_local.trim().to_string();
+        let remote = self.upload_remote.trim().to_string();
+        let content = match std::fs::read(&local) {
+            Ok(content) => content,
+            Err(error) => {
+                self.status = Some((format!("Failed to read {local}: {error}"), true));
+                return;
+            }
+        };
+        let mut steps = vec![files::open_for_write_code(&remote).into_bytes()];
+        steps.extend(
+            content
+                .chunks(files::UPLOAD_CHUNK_SIZE)
+                .map(|chunk| files::write_chunk_code(chunk).into_bytes()),
+        );
+        steps.push(files::close_code().as_bytes().to_vec());
+        let size = content.len();
+        self.start(JobKind::Upload { remote, size }, steps);
+    }
+
+    fn download(&mut self) {
+        let remote = self.download_remote.trim().to_string();
+        let local = self.download_local.trim().to_string();
+        let code = files::read_file_code(&remote);
+        self.start(JobKind::Download { remote, local }, vec![code.into_bytes()]);
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("MicroPython");
+        self.job_status(ui);
+        ui.separator();
+
+        egui::ScrollArea::vertical()
+            .auto_shrink(false)
+            .show(ui, |ui| {
+                ui.add_enabled_ui(!self.is_busy(), |ui| {
+                    self.run_form(ui);
+                    ui.separator();
+                    self.transfer_form(ui);
+                    ui.separator();
+                    self.device_files(ui);
+                });
+            });
+    }
+
+    fn job_status(&mut self, ui: &mut egui::Ui) {
+        if let Some(job) = &self.job {
+            let description = job.description();
+            let progress = job.progress();
+            ui.horizontal(|ui| {
+                match progress {
+                    Some(progress) => {
+                        ui.add(
+                            egui::ProgressBar::new(progress)
+                                .desired_width(160.0)
+                                .show_percentage(),
+                        );
+                    }
+                    None => {
+                        ui.spinner();
+                    }
+                }
+                if ui
+                    .button("Cancel")
+                    .on_hover_text("Interrupt with Ctrl-C and leave the raw REPL")
+                    .clicked()
+                {
+                    self.repl.cancel();
+                    self.finish("Cancelled".to_string(), true);
+                }
+            });
+            ui.label(description);
+            return;
+        }
+        match &self.status {
+            Some((message, true)) => {
+                ui.colored_label(sereal_colors::UI_RED.to_egui_color32(), message);
+            }
+            Some((message, false)) => {
+                ui.label(message);
+            }
+            None => {
+                ui.weak("Uses the raw REPL (Ctrl-A) of the board");
+            }
+        }
+    }
+
+    fn run_form(&mut self, ui: &mut egui::Ui) {
+        ui.label("Run a local script");
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.script_path)
+                    .desired_width(200.0)
+                    .hint_text("Path to a .py file"),
+            );
+            if ui
+                .add_enabled(
+                    !self.script_path.trim().is_empty(),
+                    egui::Button::new("Run"),
+                )
+                .on_hover_text("Output and tracebacks appear in the log")
+                .clicked()
+            {
+                self.run_script();
+            }
+        });
+    }
+
+    fn transfer_form(&mut self, ui: &mut egui::Ui) {
+        let mut action = None;
+        egui::Grid::new(ui.id().with("micropython_transfer"))
+            .num_columns(3)
+            .show(ui, |ui| {
+                ui.label("Upload");
+                ui.vertical(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.upload_local)
+                            .desired_width(200.0)
+                            .hint_text("Local file"),
+                    );
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.upload_remote)
+                            .desired_width(200.0)
+                            .hint_text("Path on the board, e.g. /main.py"),
+                    );
+                });
+                let is_ready =
+                    !self.upload_local.trim().is_empty() && !self.upload_remote.trim().is_empty();
+                if ui
+                    .add_enabled(is_ready, egui::Button::new("Upload"))
+                    .clicked()
+                {
+                    action = Some(true);
+                }
+                ui.end_row();
+
+                ui.label("Download");
+                ui.vertical(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.download_remote)
+                            .desired_width(200.0)
+                            .hint_text("Path on the board"),
+                    );
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.download_local)
+                            .desired_width(200.0)
+                            .hint_text("Local file"),
+                    );
+                });
+                let is_ready = !self.download_remote.trim().is_empty()
+                    && !self.download_local.trim().is_empty();
+                if ui
+                    .add_enabled(is_ready, egui::Button::new("Download"))
+                    .clicked()
+                {
+                    action = Some(false);
+                }
+                ui.end_row();
+            });
+        match action {
+            Some(true) => self.upload(),
+            Some(false) => self.download(),
+            None => {}
+        }
+    }
+
+    fn device_files(&mut self, ui: &mut egui::Ui) {
+        let mut listed = None;
+        ui.horizontal(|ui| {
+            ui.label("Files on the board");
+            ui.add(egui::TextEdit::singleline(&mut self.device_directory).desired_width(120.0));
+            if ui.button("List").clicked() {
+                listed = Some(self.device_directory.trim().to_string());
+            }
+            if ui
+                .add_enabled(self.device_directory.trim() != "/", egui::Button::new("Up"))
+                .clicked()
+            {
+                let directory = self.device_directory.trim().trim_end_matches('/');
+                let parent = match directory.rfind('/') {
+                    Some(0) | None => "/",
+                    Some(index) => &directory[..index],
+                };
+                listed = Some(parent.to_string());
+            }
+        });
+
+        egui::Grid::new(ui.id().with("micropython_files"))
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                for entry in &self.entries {
+                    let path = files::join_path(&self.device_directory, &entry.name);
+                    if entry.is_directory {
+                        ui.monospace(format!("{}/", entry.name));
+                        ui.label("");
+                        if ui.small_button("Open").clicked() {
+                            listed = Some(path);
+                        }
+                    } else {
+                        ui.monospace(&entry.name);
+                        ui.label(format!("{} B", entry.size));
+                        if ui
+                            .small_button("Download…")
+                            .on_hover_text("Fill in the download form")
+                            .clicked()
+                        {
+                            self.download_remote = path;
+                            self.download_local = entry.name.clone();
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        if let Some(path) = listed {
+            self.list_directory(path);
+        }
+    }
+}
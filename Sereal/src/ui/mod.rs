@@ -1,3 +1,21 @@
+pub mod data_inspector;
+pub mod decoder_view;
+pub mod defmt_panel;
+pub mod esp_panic_panel;
+pub mod event_history;
+pub mod firmata_panel;
+pub mod frame_view;
+pub mod gnss_panel;
+pub mod hex_view;
+pub mod key_input;
+pub mod line_store;
+pub mod micropython_panel;
+pub mod modbus_master;
+pub mod send_bar;
 pub mod serial_view;
+pub mod slcan_panel;
+pub mod transfer_dialog;
+pub mod upload_dialog;
+pub mod utils;
 
 pub use serial_view::SerialView;
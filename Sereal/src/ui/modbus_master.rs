@@ -0,0 +1,510 @@
+use crate::decoder::modbus::{self, ModbusRtuDecoder};
+use crate::decoder::{DecodedFrame, Decoder, Direction};
+use crate::sereal_colors;
+use chrono::{DateTime, Local, TimeDelta};
+use eframe::egui;
+
+/// ポーリングで読み出すテーブル
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Table {
+    Coils,
+    DiscreteInputs,
+    HoldingRegisters,
+    InputRegisters,
+}
+
+impl Table {
+    const ALL: [Table; 4] = [
+        Table::Coils,
+        Table::DiscreteInputs,
+        Table::HoldingRegisters,
+        Table::InputRegisters,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Table::Coils => "Coils",
+            Table::DiscreteInputs => "Discrete inputs",
+            Table::HoldingRegisters => "Holding registers",
+            Table::InputRegisters => "Input registers",
+        }
+    }
+
+    fn function(&self) -> u8 {
+        match self {
+            Table::Coils => 1,
+            Table::DiscreteInputs => 2,
+            Table::HoldingRegisters => 3,
+            Table::InputRegisters => 4,
+        }
+    }
+
+    fn is_bit(&self) -> bool {
+        matches!(self, Table::Coils | Table::DiscreteInputs)
+    }
+
+    /// 1 回の要求で読める数の上限
+    fn max_quantity(&self) -> u16 {
+        if self.is_bit() { 2000 } else { 125 }
+    }
+
+    /// 応答に含まれるバイト数
+    fn byte_count(&self, quantity: u16) -> usize {
+        if self.is_bit() {
+            usize::from(quantity).div_ceil(8)
+        } else {
+            usize::from(quantity) * 2
+        }
+    }
+}
+
+/// 読み出す範囲と、最後に読めた値
+#[derive(Debug, Clone)]
+struct PollItem {
+    slave: u8,
+    table: Table,
+    start: u16,
+    quantity: u16,
+    values: Vec<u16>,
+    updated: Option<DateTime<Local>>,
+    error: Option<String>,
+}
+
+impl PollItem {
+    fn request(&self) -> Vec<u8> {
+        let mut data = self.start.to_be_bytes().to_vec();
+        data.extend_from_slice(&self.quantity.to_be_bytes());
+        modbus::build_adu(self.slave, self.table.function(), &data)
+    }
+
+    fn settings(&self) -> (u8, Table, u16, u16) {
+        (self.slave, self.table, self.start, self.quantity)
+    }
+
+    /// 応答を反映する
+    fn apply_response(&mut self, function: u8, data: &[u8], time: DateTime<Local>) {
+        if function == self.table.function() | 0x80 {
+            let code = data.first().copied().unwrap_or(0);
+            self.error = Some(format!(
+                "Exception 0x{code:02X} {}",
+                modbus::exception_name(code)
+            ));
+            return;
+        }
+        let expected = self.table.byte_count(self.quantity);
+        let Some(values) = data
+            .split_first()
+            .filter(|(count, values)| usize::from(**count) == expected && values.len() == expected)
+            .map(|(_, values)| values)
+        else {
+            self.error = Some("Unexpected byte count in the response".to_string());
+            return;
+        };
+        self.values = if self.table.is_bit() {
+            (0..usize::from(self.quantity))
+                .map(|index| u16::from(values[index / 8] >> (index % 8) & 1))
+                .collect()
+        } else {
+            values
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect()
+        };
+        self.updated = Some(time);
+        self.error = None;
+    }
+}
+
+impl Default for PollItem {
+    fn default() -> Self {
+        Self {
+            slave: 1,
+            table: Table::HoldingRegisters,
+            start: 0,
+            quantity: 10,
+            values: Vec::new(),
+            updated: None,
+            error: None,
+        }
+    }
+}
+
+/// 応答を待っている要求
+struct Pending {
+    index: usize,
+    sent_at: DateTime<Local>,
+}
+
+/// 設定したレジスタやコイルを周期的に読み出し、値を表で表示する Modbus RTU マスター
+///
+/// 要求は 1 つずつ送り、応答かタイムアウトを待ってから次の要求に進む。
+pub struct ModbusMaster {
+    items: Vec<PollItem>,
+    is_running: bool,
+    interval_ms: u32,
+    timeout_ms: u32,
+    baud_rate: u32,
+    decoder: ModbusRtuDecoder,
+    pending: Option<Pending>,
+    next_index: usize,
+    cycle_start: Option<DateTime<Local>>,
+    /// ポーリングを止めた理由
+    stop_reason: Option<String>,
+    events: Vec<String>,
+}
+
+impl Default for ModbusMaster {
+    fn default() -> Self {
+        Self {
+            items: vec![PollItem::default()],
+            is_running: false,
+            interval_ms: 1000,
+            timeout_ms: 500,
+            baud_rate: 0,
+            decoder: ModbusRtuDecoder::new(0),
+            pending: None,
+            next_index: 0,
+            cycle_start: None,
+            stop_reason: None,
+            events: Vec::new(),
+        }
+    }
+}
+
+impl ModbusMaster {
+    pub fn set_baud_rate(&mut self, baud_rate: u32) {
+        if self.baud_rate != baud_rate {
+            self.baud_rate = baud_rate;
+            self.decoder = ModbusRtuDecoder::new(baud_rate);
+        }
+    }
+
+    pub fn feed_rx(&mut self, time: DateTime<Local>, bytes: &[u8]) {
+        if !self.is_running {
+            return;
+        }
+        for frame in self.decoder.feed(Direction::Rx, time, bytes) {
+            self.handle_frame(frame);
+        }
+    }
+
+    /// 送るべき要求があれば返す
+    ///
+    /// ポートが閉じていれば、要求を送っても応答は来ないのでポーリングを止める。
+    pub fn poll(&mut self, now: DateTime<Local>, is_connected: bool) -> Option<Vec<u8>> {
+        if !self.is_running {
+            return None;
+        }
+        if !is_connected {
+            let reason = "Stopped polling because the port is not connected".to_string();
+            self.events.push(format!("Modbus master: {reason}"));
+            self.stop_reason = Some(reason);
+            self.is_running = false;
+            self.restart();
+            return None;
+        }
+        for frame in self.decoder.poll(now) {
+            self.handle_frame(frame);
+        }
+
+        if let Some(pending) = &self.pending {
+            if now - pending.sent_at < TimeDelta::milliseconds(i64::from(self.timeout_ms)) {
+                return None;
+            }
+            if let Some(item) = self.items.get_mut(pending.index) {
+                item.error = Some("No response".to_string());
+            }
+            self.finish_pending();
+        }
+
+        if self.items.len() <= self.next_index {
+            let interval = TimeDelta::milliseconds(i64::from(self.interval_ms));
+            if self
+                .cycle_start
+                .is_some_and(|cycle_start| now - cycle_start < interval)
+            {
+                return None;
+            }
+            self.next_index = 0;
+        }
+        if self.next_index == 0 {
+            self.cycle_start = Some(now);
+        }
+
+        let item = self.items.get(self.next_index)?;
+        self.pending = Some(Pending {
+            index: self.next_index,
+            sent_at: now,
+        });
+        Some(item.request())
+    }
+
+    /// 次に poll を呼ぶまでの時間
+    pub fn next_poll(&self, now: DateTime<Local>) -> Option<std::time::Duration> {
+        if !self.is_running {
+            return None;
+        }
+        let deadline = match &self.pending {
+            Some(pending) => pending.sent_at + TimeDelta::milliseconds(i64::from(self.timeout_ms)),
+            None => self.cycle_start? + TimeDelta::milliseconds(i64::from(self.interval_ms)),
+        };
+        let until_deadline = (deadline - now).to_std().unwrap_or_default();
+        Some(
+            self.decoder
+                .next_poll()
+                .map_or(until_deadline, |delay| delay.min(until_deadline)),
+        )
+    }
+
+    /// イベントの履歴に残す内容
+    pub fn take_events(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Modbus master");
+        ui.horizontal(|ui| {
+            if ui.toggle_value(&mut self.is_running, "Poll").changed() {
+                self.stop_reason = None;
+                self.restart();
+            }
+            ui.label("Every");
+            ui.add(
+                egui::DragValue::new(&mut self.interval_ms)
+                    .range(10..=600_000)
+                    .suffix(" ms"),
+            );
+            ui.label("Timeout");
+            ui.add(
+                egui::DragValue::new(&mut self.timeout_ms)
+                    .range(10..=10_000)
+                    .suffix(" ms"),
+            );
+        });
+        if let Some(reason) = &self.stop_reason {
+            ui.colored_label(sereal_colors::UI_RED.to_egui_color32(), reason);
+        }
+        ui.separator();
+
+        egui::ScrollArea::vertical()
+            .auto_shrink(false)
+            .show(ui, |ui| {
+                self.items_editor(ui);
+                ui.separator();
+                self.values_table(ui);
+            });
+    }
+
+    fn handle_frame(&mut self, frame: DecodedFrame) {
+        let Some(pending) = &self.pending else {
+            return;
+        };
+        let Some(item) = self.items.get_mut(pending.index) else {
+            self.finish_pending();
+            return;
+        };
+        match modbus::parse_adu(&frame.bytes) {
+            Ok(adu)
+                if adu.address == item.slave && adu.function & 0x7f == item.table.function() =>
+            {
+                item.apply_response(adu.function, adu.data, frame.time);
+            }
+            // 他のスレーブ宛ての通信は無視する
+            Ok(_) => return,
+            Err(error) if frame.bytes.first() == Some(&item.slave) => item.error = Some(error),
+            Err(_) => return,
+        }
+        self.finish_pending();
+    }
+
+    fn finish_pending(&mut self) {
+        self.pending = None;
+        self.next_index += 1;
+    }
+
+    /// 設定を変えたときは最初の要求からやり直す
+    fn restart(&mut self) {
+        self.pending = None;
+        self.next_index = 0;
+        self.cycle_start = None;
+    }
+
+    fn items_editor(&mut self, ui: &mut egui::Ui) {
+        let mut removed = None;
+        let mut is_changed = false;
+        egui::Grid::new(ui.id().with("modbus_items"))
+            .num_columns(5)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Slave");
+                ui.strong("Table");
+                ui.strong("Start");
+                ui.strong("Quantity");
+                ui.label("");
+                ui.end_row();
+
+                for (index, item) in self.items.iter_mut().enumerate() {
+                    let settings = item.settings();
+                    ui.add(egui::DragValue::new(&mut item.slave).range(1..=247));
+                    egui::ComboBox::from_id_salt(ui.id().with(("modbus_table", index)))
+                        .selected_text(item.table.name())
+                        .show_ui(ui, |ui| {
+                            for table in Table::ALL {
+                                ui.selectable_value(&mut item.table, table, table.name());
+                            }
+                        });
+                    ui.add(egui::DragValue::new(&mut item.start).range(0..=65_535));
+                    item.quantity = item.quantity.min(item.table.max_quantity());
+                    ui.add(
+                        egui::DragValue::new(&mut item.quantity)
+                            .range(1..=item.table.max_quantity()),
+                    );
+                    if ui.small_button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                    ui.end_row();
+
+                    if item.settings() != settings {
+                        item.values.clear();
+                        item.updated = None;
+                        item.error = None;
+                        is_changed = true;
+                    }
+                }
+            });
+        if ui.button("Add").clicked() {
+            let item = self.items.last().cloned().unwrap_or_default();
+            self.items.push(PollItem {
+                start: item.start.saturating_add(item.quantity),
+                values: Vec::new(),
+                updated: None,
+                error: None,
+                ..item
+            });
+            is_changed = true;
+        }
+        if let Some(index) = removed {
+            self.items.remove(index);
+            is_changed = true;
+        }
+        if is_changed {
+            self.restart();
+        }
+    }
+
+    fn values_table(&self, ui: &mut egui::Ui) {
+        egui::Grid::new(ui.id().with("modbus_values"))
+            .num_columns(4)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Slave");
+                ui.strong("Address");
+                ui.strong("Value");
+                ui.strong("Hex");
+                ui.end_row();
+
+                for item in &self.items {
+                    ui.label(item.slave.to_string());
+                    ui.label(item.table.name());
+                    match (&item.error, item.updated) {
+                        (Some(error), _) => {
+                            ui.colored_label(sereal_colors::UI_RED.to_egui_color32(), error)
+                        }
+                        (None, Some(updated)) => {
+                            ui.weak(format!("Updated {}", updated.format("%H:%M:%S%.3f")))
+                        }
+                        (None, None) => ui.weak("Not read yet"),
+                    };
+                    ui.label("");
+                    ui.end_row();
+
+                    for (offset, value) in item.values.iter().enumerate() {
+                        ui.label("");
+                        ui.monospace((usize::from(item.start) + offset).to_string());
+                        if item.table.is_bit() {
+                            ui.monospace(if *value != 0 { "ON" } else { "OFF" });
+                            ui.label("");
+                        } else {
+                            ui.monospace(value.to_string());
+                            ui.monospace(format!("0x{value:04X}"));
+                        }
+                        ui.end_row();
+                    }
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_polling_when_disconnected() {
+        let mut master = ModbusMaster {
+            is_running: true,
+            ..Default::default()
+        };
+        let now = Local::now();
+        let request = master.poll(now, true).unwrap();
+        assert_eq!(request, modbus::build_adu(1, 3, &[0, 0, 0, 10]));
+
+        // 応答を待っている間に切断しても、No response を付けずに止める
+        assert_eq!(master.poll(now + TimeDelta::seconds(1), false), None);
+        assert!(!master.is_running);
+        assert!(master.pending.is_none());
+        assert_eq!(master.items[0].error, None);
+        assert_eq!(master.take_events().len(), 1);
+        assert_eq!(master.poll(now + TimeDelta::seconds(2), false), None);
+        assert!(master.take_events().is_empty());
+        assert_eq!(master.next_poll(now), None);
+    }
+
+    #[test]
+    fn applies_register_response() {
+        let mut master = ModbusMaster {
+            is_running: true,
+            ..Default::default()
+        };
+        master.items[0].quantity = 2;
+        let now = Local::now();
+        master.poll(now, true).unwrap();
+        let response = modbus::build_adu(1, 3, &[4, 0x12, 0x34, 0xff, 0xfe]);
+        master.handle_frame(DecodedFrame {
+            time: now,
+            direction: Direction::Rx,
+            bytes: response,
+            summary: String::new(),
+            fields: Vec::new(),
+            errors: Vec::new(),
+        });
+        assert_eq!(master.items[0].values, [0x1234, 0xfffe]);
+        assert_eq!(master.items[0].error, None);
+        assert!(master.pending.is_none());
+    }
+
+    #[test]
+    fn reports_exceptions_and_timeouts() {
+        let mut item = PollItem::default();
+        item.apply_response(0x83, &[0x02], Local::now());
+        assert_eq!(
+            item.error.as_deref(),
+            Some(format!("Exception 0x02 {}", modbus::exception_name(2)).as_str())
+        );
+        item.apply_response(0x03, &[3, 0, 0], Local::now());
+        assert_eq!(
+            item.error.as_deref(),
+            Some("Unexpected byte count in the response")
+        );
+
+        let mut master = ModbusMaster {
+            is_running: true,
+            ..Default::default()
+        };
+        let now = Local::now();
+        master.poll(now, true).unwrap();
+        assert_eq!(master.poll(now + TimeDelta::milliseconds(100), true), None);
+        master.poll(now + TimeDelta::seconds(1), true);
+        assert_eq!(master.items[0].error.as_deref(), Some("No response"));
+    }
+}
@@ -0,0 +1,67 @@
+use super::data_inspector;
+use crate::decoder::byte_stuffing::Stuffing;
+use eframe::egui;
+
+/// 入力した文字列や 16 進のバイト列をデバイスに送るための入力欄
+///
+/// 包み方を選ぶと、入力をペイロードとして SLIP や COBS で包んでから送る。
+#[derive(Default)]
+pub struct SendBar {
+    input: String,
+    is_hex: bool,
+    stuffing: Option<Stuffing>,
+}
+
+impl SendBar {
+    /// 送信ボタンか Enter で確定したときに、送るバイト列を返す
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> Option<Vec<u8>> {
+        let payload = if self.is_hex {
+            data_inspector::parse_hex(&self.input)
+        } else {
+            Some(self.input.as_bytes().to_vec())
+        };
+
+        let mut is_submitted = false;
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.is_hex, "Hex")
+                .on_hover_text("Type bytes like 01 02 0A instead of text");
+            egui::ComboBox::from_id_salt(ui.id().with("stuffing"))
+                .selected_text(
+                    self.stuffing
+                        .map_or("No wrapping", |stuffing| stuffing.name()),
+                )
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.stuffing, None, "No wrapping");
+                    for stuffing in [Stuffing::Slip, Stuffing::Cobs] {
+                        ui.selectable_value(&mut self.stuffing, Some(stuffing), stuffing.name());
+                    }
+                });
+            let send_button = ui.add_enabled(payload.is_some(), egui::Button::new("Send"));
+            let hint = if self.is_hex {
+                "Bytes to send, e.g. 01 02 0A"
+            } else {
+                "Text to send"
+            };
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.input)
+                    .font(egui::TextStyle::Monospace)
+                    .desired_width(f32::INFINITY)
+                    .hint_text(hint),
+            );
+            if response.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter)) {
+                is_submitted = true;
+                response.request_focus();
+            }
+            is_submitted |= send_button.clicked();
+        });
+        if payload.is_none() {
+            ui.colored_label(ui.visuals().warn_fg_color, "Not a valid hex string");
+        }
+
+        let payload = payload.filter(|_| is_submitted)?;
+        Some(match self.stuffing {
+            Some(stuffing) => stuffing.encode(&payload),
+            None => payload,
+        })
+    }
+}
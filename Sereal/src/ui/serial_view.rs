@@ -1,22 +1,111 @@
+use std::ops::Range;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 
+use super::data_inspector;
+use super::decoder_view::DecoderView;
+use super::defmt_panel::DefmtPanel;
+use super::esp_panic_panel::{EspPanicPanel, LogNote};
+use super::event_history::EventHistory;
+use super::firmata_panel::FirmataPanel;
+use super::frame_view::FrameView;
+use super::gnss_panel::GnssPanel;
+use super::hex_view::{HexView, RowSplit};
+use super::key_input;
+use super::line_store::{Annotation, LineStore, SpanStyle};
+use super::micropython_panel::MicroPythonPanel;
+use super::modbus_master::ModbusMaster;
+use super::send_bar::SendBar;
+use super::slcan_panel::SlcanPanel;
+use super::transfer_dialog::TransferDialog;
+use super::upload_dialog::UploadDialog;
 use crate::ansi_formatter;
+use crate::decoder::{DecoderContext, Direction};
+use crate::framing::{FrameRule, Framer};
 use crate::sereal_colors;
 use crate::serial;
 use crate::serial::BaudRate;
+use crate::terminal;
 use eframe::egui;
 
-const HISTORY_MAX_LINES: usize = 5000;
+const HISTORY_MAX_LINES: usize = 1_000_000;
+// 点滅する文字を 1 秒周期のうち表示しておく割合
+const BLINK_VISIBLE_RATIO: f64 = 0.5;
+
+/// 受信データの表示方法
+#[derive(PartialEq, Default, Clone, Copy)]
+enum ViewMode {
+    /// 受信したテキストを追記していくログ表示
+    #[default]
+    Log,
+    /// カーソル移動や画面消去を解釈する端末表示
+    Terminal,
+    /// 受信したバイト列の 16 進ダンプ
+    Hex,
+    /// 設定に従って区切ったフレームの一覧
+    Frames,
+}
+
+/// ログの中の文字の位置
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct TextPosition {
+    /// 消した行も数えた行番号
+    line: usize,
+    /// 行の中の文字の番号
+    column: usize,
+}
+
+/// 次に作るタブの番号
+static NEXT_TAB_ID: AtomicU64 = AtomicU64::new(0);
 
-#[derive(Default)]
 pub struct SerialView {
+    /// タブの ID。タイトルが変わってもスクロール位置やフォーカスを保つため、作ったときに決める
+    id: egui::Id,
     serial_service: Arc<std::sync::Mutex<serial::service::SerialService>>,
     port_name: String,
     baud_rate: serial::BaudRate,
-    received_text: String,
-    received_line_count: usize,
+    line_store: LineStore,
     formatter: ansi_formatter::AnsiFormatter,
+    terminal: terminal::Terminal,
+    hex_view: HexView,
+    /// ログで選択したテキスト。ドラッグを始めた位置と現在の位置
+    text_selection: Option<(TextPosition, TextPosition)>,
+    is_selecting_text: bool,
+    /// 最後にデータインスペクタへ渡したバイト列
+    inspected_bytes: Vec<u8>,
+    is_inspector_requested: bool,
+    framer: Framer,
+    frame_view: FrameView,
+    decoder_view: DecoderView,
+    is_show_decoder: bool,
+    modbus_master: ModbusMaster,
+    is_show_modbus_master: bool,
+    gnss_panel: GnssPanel,
+    is_show_gnss: bool,
+    slcan_panel: SlcanPanel,
+    is_show_slcan: bool,
+    firmata_panel: FirmataPanel,
+    is_show_firmata: bool,
+    micropython_panel: MicroPythonPanel,
+    is_show_micropython: bool,
+    esp_panic_panel: EspPanicPanel,
+    is_show_esp_panic: bool,
+    defmt_panel: DefmtPanel,
+    is_show_defmt: bool,
+    transfer_dialog: TransferDialog,
+    is_show_transfer: bool,
+    upload_dialog: UploadDialog,
+    is_show_upload: bool,
+    send_bar: SendBar,
+    is_show_send_bar: bool,
+    delimiter_input: String, // 区切りのバイト列の入力欄 (16 進)
+    view_mode: ViewMode,
     is_autoscroll_enabled: bool,
+    is_interactive: bool, // キー入力をそのままデバイスに送る
+    is_local_echo: bool,
+    is_answer_queries: bool, // 端末への問い合わせに自動で応答する
+    is_show_events: bool,
+    event_history: EventHistory,
 }
 
 impl Drop for SerialView {
@@ -32,39 +121,193 @@ impl SerialView {
         serial_service: Arc<std::sync::Mutex<serial::service::SerialService>>,
     ) -> Self {
         Self {
+            id: egui::Id::new(("serial_view", NEXT_TAB_ID.fetch_add(1, Ordering::Relaxed))),
             serial_service,
             port_name,
             baud_rate: serial::BaudRate::default(),
-            received_text: String::new(),
-            received_line_count: 0,
+            line_store: LineStore::new(HISTORY_MAX_LINES),
             formatter: ansi_formatter::AnsiFormatter::default(),
+            terminal: terminal::Terminal::default(),
+            hex_view: HexView::default(),
+            text_selection: None,
+            is_selecting_text: false,
+            inspected_bytes: Vec::new(),
+            is_inspector_requested: false,
+            framer: Framer::default(),
+            frame_view: FrameView::default(),
+            decoder_view: DecoderView::default(),
+            is_show_decoder: false,
+            modbus_master: ModbusMaster::default(),
+            is_show_modbus_master: false,
+            gnss_panel: GnssPanel::default(),
+            is_show_gnss: false,
+            slcan_panel: SlcanPanel::default(),
+            is_show_slcan: false,
+            firmata_panel: FirmataPanel::default(),
+            is_show_firmata: false,
+            micropython_panel: MicroPythonPanel::default(),
+            is_show_micropython: false,
+            esp_panic_panel: EspPanicPanel::default(),
+            is_show_esp_panic: false,
+            defmt_panel: DefmtPanel::default(),
+            is_show_defmt: false,
+            transfer_dialog: TransferDialog::default(),
+            is_show_transfer: false,
+            upload_dialog: UploadDialog::default(),
+            is_show_upload: false,
+            send_bar: SendBar::default(),
+            is_show_send_bar: false,
+            delimiter_input: "0A".to_string(),
+            view_mode: ViewMode::default(),
             is_autoscroll_enabled: true,
+            is_interactive: false,
+            is_local_echo: false,
+            is_answer_queries: true,
+            is_show_events: false,
+            event_history: EventHistory::default(),
         }
     }
 
     pub fn ui(&mut self, ui: &mut egui::Ui) {
         // シリアルの受信処理
-        {
+        let (received, sent): (Vec<serial::ReceivedData>, Vec<serial::SentData>) = {
             let service = self.serial_service.lock().unwrap();
-            if let Some(controller) = service.get_controller(&self.port_name) {
-                if let Some(receiver) = &controller.receiver {
-                    for text in receiver.try_iter() {
-                        self.received_text.push_str(&text);
-                        if text.contains('\n') {
-                            self.received_line_count += 1;
-                        }
-                    }
+            let controller = service.get_controller(&self.port_name);
+            (
+                controller
+                    .and_then(|controller| controller.receiver.as_ref())
+                    .map(|receiver| receiver.try_iter().collect())
+                    .unwrap_or_default(),
+                controller
+                    .and_then(|controller| controller.sent.as_ref())
+                    .map(|sent| sent.try_iter().collect())
+                    .unwrap_or_default(),
+            )
+        };
+        // 実際に書き込めたバイト列だけを送信として扱う
+        for data in sent {
+            if !data.bytes.is_empty() {
+                self.decoder_view
+                    .feed(Direction::Tx, data.time, &data.bytes);
+            }
+            if let Some(error) = data.error {
+                self.event_history.push(error);
+            }
+        }
+        self.decoder_view.set_context(DecoderContext {
+            baud_rate: self.baud_rate as u32,
+            framing: self.framer.options().clone(),
+        });
+        self.modbus_master.set_baud_rate(self.baud_rate as u32);
+        for data in received {
+            // ファイル転送中は受信データをすべて転送に回す
+            if let Some(reply) = self.transfer_dialog.feed_rx(data.time, &data.bytes) {
+                if !reply.is_empty() {
+                    self.transmit(reply);
                 }
+                continue;
+            }
+            // defmt の ELF を紐付けている間や Firmata を復号している間は、復号したログの行を流す
+            let firmata_lines = self.firmata_panel.feed_rx(data.time, &data.bytes);
+            let display = match self.defmt_panel.feed_rx(&data.bytes).or(firmata_lines) {
+                Some(lines) => lines,
+                // raw REPL の実行中は、スクリプトの出力だけをログに流す
+                None => self.micropython_panel.feed_rx(data.time, &data.bytes),
+            };
+            self.display_received(data.time, &display);
+            self.decoder_view
+                .feed(Direction::Rx, data.time, &data.bytes);
+            self.modbus_master.feed_rx(data.time, &data.bytes);
+            self.gnss_panel.feed_rx(data.time, &data.bytes);
+            self.slcan_panel.feed_rx(data.time, &data.bytes);
+            self.hex_view.push(&data);
+            for frame in self.framer.push(&data) {
+                self.frame_view.push(frame);
             }
         }
-
-        // FIXME:単純に削ると以前のデザイン情報が削られるため直す必要あり
-        if HISTORY_MAX_LINES < self.received_line_count {
-            if let Some(index) = self.received_text.find('\n') {
-                self.received_text.drain(..=index);
-                self.received_line_count -= 1;
+        let now = chrono::Local::now();
+        if let Some(frame) = self.framer.poll_idle(now) {
+            self.frame_view.push(frame);
+        }
+        self.decoder_view.poll(now);
+        if let Some(delay) = self.decoder_view.next_poll() {
+            ui.ctx().request_repaint_after(delay);
+        }
+        let is_connected = self
+            .serial_service
+            .lock()
+            .unwrap()
+            .is_connected(&self.port_name);
+        if let Some(request) = self.modbus_master.poll(now, is_connected) {
+            self.transmit(request);
+        }
+        for event in self.modbus_master.take_events() {
+            self.event_history.push(event);
+        }
+        if let Some(delay) = self.modbus_master.next_poll(now) {
+            ui.ctx().request_repaint_after(delay);
+        }
+        if let Some(bytes) = self.micropython_panel.poll(now) {
+            self.transmit(bytes);
+        }
+        if let Some(delay) = self.micropython_panel.next_poll(now) {
+            ui.ctx().request_repaint_after(delay);
+        }
+        for event in self.micropython_panel.take_events() {
+            self.event_history.push(event);
+        }
+        let bytes = self.transfer_dialog.poll(now);
+        if !bytes.is_empty() {
+            self.transmit(bytes);
+        }
+        if let Some(delay) = self.transfer_dialog.next_poll(now) {
+            ui.ctx().request_repaint_after(delay);
+        }
+        for event in self.transfer_dialog.take_events() {
+            self.event_history.push(event);
+        }
+        if self.transfer_dialog.is_running() {
+            // 自動で始まった受信も進み具合を見せる
+            self.is_show_transfer = true;
+        }
+        if self.framer.is_waiting_for_idle() {
+            // 受信が途絶えてもフレームを区切れるように再描画する
+            let idle_gap = u64::from(self.framer.options().idle_gap_ms);
+            ui.ctx()
+                .request_repaint_after(std::time::Duration::from_millis(idle_gap));
+        }
+        if let Some(is_reconnect) = self.upload_dialog.poll()
+            && is_reconnect
+        {
+            let mut service = self.serial_service.lock().unwrap();
+            match service.connect(&self.port_name, self.baud_rate) {
+                Ok(_) => self.event_history.push(format!(
+                    "Reconnected to {} at {}",
+                    self.port_name, self.baud_rate
+                )),
+                Err(e) => self
+                    .event_history
+                    .push(format!("Failed to reconnect to {}: {e}", self.port_name)),
             }
         }
+        if self.upload_dialog.is_running() {
+            // 書き込みスレッドの進み具合を見る
+            ui.ctx()
+                .request_repaint_after(std::time::Duration::from_millis(100));
+        }
+        for event in self.upload_dialog.take_events() {
+            self.event_history.push(event);
+        }
+        for event in self.esp_panic_panel.take_events() {
+            self.event_history.push(event);
+        }
+        for event in self.defmt_panel.take_events() {
+            self.event_history.push(event);
+        }
+        for event in self.firmata_panel.take_events() {
+            self.event_history.push(event);
+        }
+        self.answer_queries();
 
         ui.vertical(|ui| {
             // SerialPort を選択する ComboBox を用意
@@ -90,12 +333,14 @@ impl SerialView {
                             if ui
                                 .selectable_value(&mut self.port_name, port.clone(), port.clone())
                                 .changed()
-                            {
-                                self.disconnect_and_connect(
+                                && let Err(e) = self.disconnect_and_connect(
                                     &last_port_name,
                                     &self.port_name,
                                     self.baud_rate,
-                                );
+                                )
+                            {
+                                self.event_history
+                                    .push(format!("Failed to connect to {}: {e}", self.port_name));
                             }
                         }
                     }
@@ -111,12 +356,14 @@ impl SerialView {
                         if ui
                             .selectable_value(&mut self.baud_rate, rate, format!("{}", rate))
                             .changed()
-                        {
-                            self.disconnect_and_connect(
+                            && let Err(e) = self.disconnect_and_connect(
                                 &self.port_name,
                                 &self.port_name,
                                 self.baud_rate,
-                            );
+                            )
+                        {
+                            self.event_history
+                                .push(format!("Failed to connect to {}: {e}", self.port_name));
                         }
                     }
                 });
@@ -158,8 +405,9 @@ impl SerialView {
                         ui.visuals().code_bg_color
                     });
 
+                    // 書き込み中はポートを書き込みスレッドが使っている
                     if ui
-                        .add(connect_button)
+                        .add_enabled(!self.upload_dialog.is_running(), connect_button)
                         .on_hover_text(if is_connected {
                             "Disconnect"
                         } else {
@@ -171,14 +419,24 @@ impl SerialView {
                         if !is_connected {
                             // 接続処理
                             match service.connect(&self.port_name, self.baud_rate) {
-                                Ok(_) => {}
+                                Ok(_) => {
+                                    self.event_history.push(format!(
+                                        "Connected to {} at {}",
+                                        self.port_name, self.baud_rate
+                                    ));
+                                }
                                 Err(e) => {
-                                    eprintln!("Error:{e}");
+                                    self.event_history.push(format!(
+                                        "Failed to connect to {}: {e}",
+                                        self.port_name
+                                    ));
                                 }
                             }
                         } else {
                             // 切断処理
                             service.disconnect(&self.port_name);
+                            self.event_history
+                                .push(format!("Disconnected from {}", self.port_name));
                         }
                     }
                 }
@@ -195,53 +453,851 @@ impl SerialView {
                     .clicked()
                 {
                     self.formatter.reset();
-                    self.received_text.clear();
-                    self.received_line_count = 0;
+                    self.line_store.clear();
+                    self.text_selection = None;
+                    self.terminal.reset();
+                    self.hex_view.clear();
+                    self.framer.reset();
+                    self.frame_view.clear();
+                    self.decoder_view.clear();
+                    self.gnss_panel.clear();
+                    self.slcan_panel.clear();
+                    self.firmata_panel.clear();
+                    self.esp_panic_panel.clear();
+                    self.defmt_panel.clear();
                 }
+
+                // 対話モードの切り替え
+                ui.toggle_value(&mut self.is_interactive, "Interactive")
+                    .on_hover_text(format!(
+                        "Send every key to the device. Press {} to leave",
+                        key_input::EXIT_CHORD_LABEL
+                    ));
+
+                // 表示設定
+                ui.menu_button("View", |ui| {
+                    ui.radio_value(&mut self.view_mode, ViewMode::Log, "Log");
+                    ui.radio_value(&mut self.view_mode, ViewMode::Terminal, "Terminal");
+                    ui.radio_value(&mut self.view_mode, ViewMode::Hex, "Hex dump");
+                    ui.radio_value(&mut self.view_mode, ViewMode::Frames, "Frames");
+                    if ui
+                        .button("Data inspector")
+                        .on_hover_text(
+                            "Open a tab that interprets the bytes selected in the hex dump",
+                        )
+                        .clicked()
+                    {
+                        self.is_inspector_requested = true;
+                        ui.close();
+                    }
+                    ui.checkbox(&mut self.is_show_decoder, "Decoder")
+                        .on_hover_text("Decode the received and sent data as a protocol");
+                    ui.checkbox(&mut self.is_show_modbus_master, "Modbus master")
+                        .on_hover_text("Poll registers and coils of Modbus RTU slaves");
+                    ui.checkbox(&mut self.is_show_gnss, "GNSS status")
+                        .on_hover_text("Show the fix, position and satellites from NMEA sentences");
+                    ui.checkbox(&mut self.is_show_slcan, "SLCAN")
+                        .on_hover_text("Set up a CAN adapter and send or receive CAN frames");
+                    ui.checkbox(&mut self.is_show_firmata, "Firmata")
+                        .on_hover_text("Control the pins of a board running Firmata");
+                    ui.checkbox(&mut self.is_show_micropython, "MicroPython")
+                        .on_hover_text("Run scripts and transfer files through the raw REPL");
+                    ui.checkbox(&mut self.is_show_esp_panic, "ESP32 panic")
+                        .on_hover_text("Decode panic backtraces with a local ELF file");
+                    ui.checkbox(&mut self.is_show_defmt, "defmt")
+                        .on_hover_text("Decode defmt log frames with the firmware ELF");
+                    ui.separator();
+                    let line_options = self.formatter.line_options_mut();
+                    ui.radio_value(
+                        &mut line_options.carriage_return_mode,
+                        ansi_formatter::CarriageReturnMode::Overwrite,
+                        "CR returns to line start",
+                    )
+                    .on_hover_text("Log: overwrite the line after CR, e.g. progress bars");
+                    ui.radio_value(
+                        &mut line_options.carriage_return_mode,
+                        ansi_formatter::CarriageReturnMode::LineBreak,
+                        "CR is a line break",
+                    )
+                    .on_hover_text("Log: for devices that end lines with CR only");
+                    ui.checkbox(
+                        &mut line_options.is_show_control_chars,
+                        "Show control characters",
+                    )
+                    .on_hover_text("Log: show CR, LF, NUL, invalid bytes and trailing spaces");
+                    ui.checkbox(
+                        &mut line_options.is_show_escape_sequences,
+                        "Show escape sequences",
+                    )
+                    .on_hover_text("Log: show sequences as tokens. Unknown ones are highlighted");
+                    ui.separator();
+                    ui.checkbox(
+                        &mut self.terminal.line_discipline_mut().is_lf_implies_cr,
+                        "LF implies CR",
+                    )
+                    .on_hover_text("Terminal: move to the line start on LF");
+                    ui.checkbox(
+                        &mut self.formatter.style_options_mut().is_bold_as_bright,
+                        "Bold as bright",
+                    )
+                    .on_hover_text("Applies to newly received text");
+                    ui.separator();
+                    let mut row_split = self.hex_view.row_split();
+                    let mut idle_gap_ms = self.hex_view.idle_gap_ms();
+                    ui.label("Hex dump rows");
+                    ui.radio_value(&mut row_split, RowSplit::Continuous, "16 bytes per row");
+                    ui.radio_value(&mut row_split, RowSplit::Chunk, "Split at each read");
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut row_split, RowSplit::IdleGap, "Split after idle");
+                        ui.add(
+                            egui::DragValue::new(&mut idle_gap_ms)
+                                .range(1..=10_000)
+                                .suffix(" ms"),
+                        );
+                    });
+                    self.hex_view.set_row_split(row_split, idle_gap_ms);
+                });
+
+                // フレームの区切り方
+                ui.menu_button("Framing", |ui| {
+                    self.framing_menu(ui);
+                });
+
+                // 送受信の設定
+                ui.menu_button("Session", |ui| {
+                    ui.checkbox(&mut self.is_local_echo, "Local echo")
+                        .on_hover_text("Interactive: show typed keys in the view");
+                    ui.checkbox(&mut self.is_answer_queries, "Answer terminal queries")
+                        .on_hover_text(
+                            "Reply to cursor position and device attribute requests. \
+                             Cursor positions come from the terminal view, even in the log",
+                        );
+                    ui.checkbox(&mut self.is_show_send_bar, "Send bar")
+                        .on_hover_text(
+                            "Type text or hex bytes to send, optionally SLIP or COBS wrapped",
+                        );
+                    ui.checkbox(&mut self.is_show_transfer, "File transfer")
+                        .on_hover_text("Send or receive files with XMODEM, YMODEM or ZMODEM");
+                    ui.checkbox(&mut self.is_show_upload, "Upload .hex")
+                        .on_hover_text("Flash an Arduino sketch through its Optiboot bootloader");
+                    ui.separator();
+                    ui.checkbox(&mut self.is_show_events, "Show events");
+                });
             });
         });
 
         // コントロール部と表示部の区切り線
         ui.separator();
 
-        egui::ScrollArea::vertical()
-            .stick_to_bottom(self.is_autoscroll_enabled)
-            .show(ui, |ui| {
-                ui.set_min_width(ui.available_width());
-                ui.scope(|ui| {
-                    ui.spacing_mut().item_spacing = egui::Vec2 { x: 0.0, y: 0.0 };
-
-                    // FIXME:毎回変換を行っているが、処理負荷が重いため修正する
-                    for line in self.received_text.lines() {
-                        ui.horizontal_wrapped(|ui| {
-                            for rich_text in self.formatter.to_rich_text(&line.to_string()) {
-                                ui.label(rich_text);
-                            }
-                        });
-                    }
+        if self.is_show_events {
+            egui::TopBottomPanel::bottom(ui.id().with("event_history"))
+                .resizable(true)
+                .default_height(120.0)
+                .show_inside(ui, |ui| {
+                    self.event_history.ui(ui);
                 });
-                self.formatter.reset();
-            });
+        }
+
+        if self.is_show_send_bar {
+            let bytes = egui::TopBottomPanel::bottom(ui.id().with("send_bar"))
+                .show_inside(ui, |ui| self.send_bar.ui(ui))
+                .inner;
+            if let Some(bytes) = bytes {
+                self.send(bytes);
+            }
+        }
+
+        if self.is_show_decoder {
+            egui::SidePanel::right(ui.id().with("decoder"))
+                .resizable(true)
+                .default_width(400.0)
+                .show_inside(ui, |ui| {
+                    self.decoder_view.ui(ui);
+                });
+        }
+
+        if self.is_show_modbus_master {
+            egui::SidePanel::right(ui.id().with("modbus_master"))
+                .resizable(true)
+                .default_width(360.0)
+                .show_inside(ui, |ui| {
+                    self.modbus_master.ui(ui);
+                });
+        }
+
+        if self.is_show_gnss {
+            egui::SidePanel::right(ui.id().with("gnss"))
+                .resizable(true)
+                .default_width(300.0)
+                .show_inside(ui, |ui| {
+                    self.gnss_panel.ui(ui);
+                });
+        }
+
+        if self.is_show_slcan {
+            let commands = egui::SidePanel::right(ui.id().with("slcan"))
+                .resizable(true)
+                .default_width(360.0)
+                .show_inside(ui, |ui| self.slcan_panel.ui(ui))
+                .inner;
+            for command in commands {
+                self.transmit(command);
+            }
+        }
+
+        if self.is_show_firmata {
+            let commands = egui::SidePanel::right(ui.id().with("firmata"))
+                .resizable(true)
+                .default_width(360.0)
+                .show_inside(ui, |ui| self.firmata_panel.ui(ui))
+                .inner;
+            for command in commands {
+                self.transmit(command);
+            }
+        }
+
+        if self.is_show_defmt {
+            egui::SidePanel::right(ui.id().with("defmt"))
+                .resizable(true)
+                .default_width(360.0)
+                .show_inside(ui, |ui| {
+                    self.defmt_panel.ui(ui);
+                });
+        }
+
+        if self.is_show_esp_panic {
+            egui::SidePanel::right(ui.id().with("esp_panic"))
+                .resizable(true)
+                .default_width(360.0)
+                .show_inside(ui, |ui| {
+                    self.esp_panic_panel.ui(ui);
+                });
+        }
+
+        if self.is_show_micropython {
+            egui::SidePanel::right(ui.id().with("micropython"))
+                .resizable(true)
+                .default_width(360.0)
+                .show_inside(ui, |ui| {
+                    self.micropython_panel.ui(ui);
+                });
+        }
+
+        if self.is_show_transfer {
+            let bytes = self.transfer_dialog.ui(ui, &mut self.is_show_transfer);
+            if !bytes.is_empty() {
+                self.transmit(bytes);
+            }
+        }
+
+        if self.is_show_upload
+            && let Some(image) = self.upload_dialog.ui(ui, &mut self.is_show_upload)
+        {
+            // モニターを閉じてポートを書き込みに譲る
+            let is_connected = {
+                let mut service = self.serial_service.lock().unwrap();
+                let is_connected = service.is_connected(&self.port_name);
+                service.disconnect(&self.port_name);
+                is_connected
+            };
+            self.upload_dialog
+                .start(&self.port_name, image, is_connected);
+        }
+
+        let display_rect = match self.view_mode {
+            ViewMode::Log => self.show_log(ui),
+            ViewMode::Terminal => self.show_terminal(ui),
+            ViewMode::Hex => self.hex_view.ui(ui),
+            ViewMode::Frames => self.frame_view.ui(ui),
+        };
+
+        if self.is_interactive {
+            self.handle_interactive_input(ui, display_rect);
+        }
     }
 
     pub fn get_port_name(&self) -> String {
         self.port_name.to_string()
     }
 
+    pub fn id(&self) -> egui::Id {
+        self.id
+    }
+
+    /// タブのタイトル。デバイスがタイトルを設定していればポート名に続けて表示する
+    pub fn title(&self) -> String {
+        match self.formatter.title() {
+            Some(title) => format!("{} - {title}", self.port_name),
+            None => self.get_port_name(),
+        }
+    }
+
+    fn framing_menu(&mut self, ui: &mut egui::Ui) {
+        let mut options = self.framer.options().clone();
+        ui.radio_value(&mut options.rule, FrameRule::IdleGap, "Idle gap");
+        ui.radio_value(&mut options.rule, FrameRule::Delimiter, "Delimiter");
+        ui.radio_value(&mut options.rule, FrameRule::FixedLength, "Fixed length");
+        ui.radio_value(&mut options.rule, FrameRule::LengthField, "Length field");
+        ui.separator();
+
+        match options.rule {
+            FrameRule::IdleGap => {
+                ui.horizontal(|ui| {
+                    ui.label("End a frame after");
+                    ui.add(
+                        egui::DragValue::new(&mut options.idle_gap_ms)
+                            .range(1..=10_000)
+                            .suffix(" ms"),
+                    );
+                });
+            }
+            FrameRule::Delimiter => {
+                ui.horizontal(|ui| {
+                    ui.label("Delimiter (hex)");
+                    ui.text_edit_singleline(&mut self.delimiter_input);
+                });
+                match data_inspector::parse_hex(&self.delimiter_input) {
+                    Some(delimiter) if !delimiter.is_empty() => options.delimiter = delimiter,
+                    _ => {
+                        ui.colored_label(ui.visuals().warn_fg_color, "Enter bytes like 0D 0A");
+                    }
+                }
+            }
+            FrameRule::FixedLength => {
+                ui.horizontal(|ui| {
+                    ui.label("Frame length");
+                    ui.add(
+                        egui::DragValue::new(&mut options.fixed_length)
+                            .range(1..=65_536)
+                            .suffix(" bytes"),
+                    );
+                });
+            }
+            FrameRule::LengthField => {
+                egui::Grid::new("length_field")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("Offset");
+                        ui.add(egui::DragValue::new(&mut options.length_offset).range(0..=1024));
+                        ui.end_row();
+                        ui.label("Size");
+                        ui.horizontal(|ui| {
+                            for size in [1, 2, 4] {
+                                ui.radio_value(&mut options.length_size, size, format!("{size}"));
+                            }
+                        });
+                        ui.end_row();
+                        ui.label("Byte order");
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut options.is_length_big_endian, true, "Big");
+                            ui.radio_value(&mut options.is_length_big_endian, false, "Little");
+                        });
+                        ui.end_row();
+                        ui.label("Adjustment");
+                        ui.add(
+                            egui::DragValue::new(&mut options.length_adjustment)
+                                .range(-1024..=1024),
+                        )
+                        .on_hover_text("Bytes added to the field value, e.g. a trailing checksum");
+                        ui.end_row();
+                    });
+            }
+        }
+
+        self.framer.set_options(options);
+    }
+
+    /// 受信したバイト列をログと端末の両方に反映する
+    ///
+    /// 表示を切り替えても内容が揃うように、常に両方を更新しておく。
+    fn display_bytes(&mut self, bytes: &[u8]) {
+        self.formatter.feed(bytes, &mut self.line_store);
+        self.terminal.feed(bytes);
+    }
+
+    /// 受信したバイト列を表示し、パニックのアドレスを変換した行をその行の直後に差し込む
+    fn display_received(&mut self, time: chrono::DateTime<chrono::Local>, bytes: &[u8]) {
+        for segment in bytes.split_inclusive(|&byte| byte == b'\n') {
+            self.display_bytes(segment);
+            for note in self.esp_panic_panel.feed_rx(time, segment) {
+                self.insert_note(note);
+            }
+        }
+    }
+
+    /// デバイスから受信していない行をログに書き込む。端末表示には書き込まない
+    fn insert_note(&mut self, note: LogNote) {
+        let color = if note.is_warning {
+            sereal_colors::UI_RED
+        } else {
+            sereal_colors::YELLOW
+        };
+        let style = SpanStyle {
+            format: egui::TextFormat::simple(ansi_formatter::LOG_FONT, color.to_egui_color32()),
+            is_blink: false,
+            annotation: Some(Annotation::Explanation(note.explanation)),
+        };
+        self.line_store.append(&note.text, &style);
+        self.line_store.end_line();
+    }
+
+    /// 表示部がフォーカスを持っている間、キー入力を変換してデバイスに送る
+    fn handle_interactive_input(&mut self, ui: &mut egui::Ui, display_rect: egui::Rect) {
+        let response = ui.interact(
+            display_rect,
+            ui.id().with("interactive_input"),
+            egui::Sense::click(),
+        );
+        if response.clicked() {
+            response.request_focus();
+        }
+        if !response.has_focus() {
+            return;
+        }
+
+        // Tab や矢印キーでフォーカスが移動しないようにする
+        ui.memory_mut(|memory| {
+            memory.set_focus_lock_filter(
+                response.id,
+                egui::EventFilter {
+                    tab: true,
+                    horizontal_arrows: true,
+                    vertical_arrows: true,
+                    escape: true,
+                },
+            )
+        });
+        ui.painter().rect_stroke(
+            display_rect,
+            0.0,
+            ui.visuals().selection.stroke,
+            egui::StrokeKind::Inside,
+        );
+
+        let events = ui.input(|input| input.events.clone());
+        let is_application_cursor_keys = *self.terminal.is_application_cursor_keys();
+        for event in &events {
+            if key_input::is_exit_chord(event) {
+                self.is_interactive = false;
+                response.surrender_focus();
+                return;
+            }
+            if let Some(bytes) = key_input::translate_event(event, is_application_cursor_keys) {
+                self.send(bytes);
+            }
+        }
+    }
+
+    /// 受信した問い合わせに標準的な応答を返す
+    fn answer_queries(&mut self) {
+        for response in self.query_responses() {
+            self.transmit(response);
+        }
+    }
+
+    /// 受信した問い合わせへの応答を作り、イベント履歴に残す
+    ///
+    /// 端末エミュレータは表示方法によらず常に受信データを処理しているため、カーソル位置 (CPR) は
+    /// ログ表示中も端末側の位置で答える。ログの行と桁は CR の扱いや記号表示の設定で変わり、
+    /// デバイスが想定する画面上の位置を表さない。
+    fn query_responses(&mut self) -> Vec<Vec<u8>> {
+        let mut responses = Vec::new();
+        for query in self.formatter.take_queries() {
+            let sequence = ansi_formatter::printable_sequence(query.sequence());
+            if !self.is_answer_queries {
+                self.event_history
+                    .push(format!("Ignored {} ({sequence})", query.name()));
+                continue;
+            }
+            let (row, column) = self.terminal.cursor_position();
+            let response = query.response(row + 1, column + 1);
+            self.event_history.push(format!(
+                "Answered {} ({sequence}) with {}",
+                query.name(),
+                ansi_formatter::printable_sequence(&response)
+            ));
+            responses.push(response);
+        }
+        responses
+    }
+
+    fn send(&mut self, bytes: Vec<u8>) {
+        if self.is_local_echo {
+            self.display_bytes(&key_input::local_echo(&bytes));
+        }
+        self.transmit(bytes);
+    }
+
+    /// 表示には反映せずにデバイスへ送る。書き込めたバイト列は次の描画でデコーダに渡す
+    fn transmit(&mut self, bytes: Vec<u8>) {
+        let result = {
+            let service = self.serial_service.lock().unwrap();
+            service.send(&self.port_name, bytes)
+        };
+        if let Err(e) = result {
+            self.event_history.push(format!("Failed to send: {e}"));
+        }
+    }
+
+    /// 見えている行だけを描画する
+    ///
+    /// ドラッグで選択したテキストはコピーでき、データインスペクタにも渡す。
+    fn show_log(&mut self, ui: &mut egui::Ui) -> egui::Rect {
+        let (pointer, is_primary_down, press_origin) = ui.input(|input| {
+            (
+                input.pointer.interact_pos(),
+                input.pointer.primary_down(),
+                input.pointer.press_origin(),
+            )
+        });
+        if !is_primary_down {
+            self.is_selecting_text = false;
+        }
+        let first_line = self.line_store.first_line_number();
+
+        let rect = ui
+            .scope(|ui| {
+                ui.spacing_mut().item_spacing = egui::Vec2 { x: 0.0, y: 0.0 };
+                let row_height = ui.fonts(|fonts| fonts.row_height(&ansi_formatter::LOG_FONT));
+                let (is_blink_visible, next_blink) = blink_phase(ui.input(|input| input.time));
+                let mut has_blink = false;
+
+                let output = egui::ScrollArea::both()
+                    .auto_shrink(false)
+                    .stick_to_bottom(self.is_autoscroll_enabled)
+                    .show_rows(ui, row_height, self.line_store.len(), |ui, row_range| {
+                        for index in row_range {
+                            let Some(job) = self.line_store.layout_job(index, is_blink_visible)
+                            else {
+                                continue;
+                            };
+                            has_blink |= self.line_store.has_blink(index);
+                            let line = first_line + index;
+                            let (response, galley) =
+                                add_log_row(ui, job, self.selected_columns(line), |char_index| {
+                                    self.line_store.annotation_at(index, char_index)
+                                });
+                            let column_at = |position: egui::Pos2| {
+                                galley.cursor_from_pos(position - response.rect.min).index
+                            };
+                            if response.drag_started()
+                                && let Some(origin) = press_origin
+                            {
+                                let position = TextPosition {
+                                    line,
+                                    column: column_at(origin),
+                                };
+                                self.text_selection = Some((position, position));
+                                self.is_selecting_text = true;
+                            } else if self.is_selecting_text
+                                && let Some(pointer) = pointer
+                                && response.rect.y_range().contains(pointer.y)
+                                && let Some((anchor, _)) = self.text_selection
+                            {
+                                let position = TextPosition {
+                                    line,
+                                    column: column_at(pointer),
+                                };
+                                self.text_selection = Some((anchor, position));
+                            } else if response.clicked() {
+                                self.text_selection = None;
+                            }
+                            response.context_menu(|ui| {
+                                if ui
+                                    .add_enabled(
+                                        self.text_selection.is_some(),
+                                        egui::Button::new("Copy"),
+                                    )
+                                    .clicked()
+                                {
+                                    ui.ctx().copy_text(self.selected_text());
+                                    ui.close();
+                                }
+                            });
+                        }
+                    });
+                if has_blink {
+                    // 点滅する文字が見えている間は、次に表示を切り替える時刻に描き直す
+                    ui.ctx().request_repaint_after(next_blink);
+                }
+                output.inner_rect
+            })
+            .inner;
+
+        // 入力欄などがフォーカスを持っていなければ、選択したテキストをコピーする
+        let is_copy = ui.input(|input| {
+            input
+                .events
+                .iter()
+                .any(|event| matches!(event, egui::Event::Copy))
+        });
+        if is_copy
+            && !self.is_interactive
+            && self.text_selection.is_some()
+            && ui.memory(|memory| memory.focused().is_none())
+        {
+            ui.ctx().copy_text(self.selected_text());
+        }
+        rect
+    }
+
+    /// line 行目で選択している文字の範囲と、選択が次の行に続くか
+    fn selected_columns(&self, line: usize) -> Option<(Range<usize>, bool)> {
+        let (anchor, end) = self.text_selection?;
+        let (start, end) = (anchor.min(end), anchor.max(end));
+        if line < start.line || end.line < line {
+            return None;
+        }
+        let from = if line == start.line { start.column } else { 0 };
+        let (to, is_continued) = if line == end.line {
+            (end.column, false)
+        } else {
+            (usize::MAX, true)
+        };
+        (from < to).then_some((from..to, is_continued))
+    }
+
+    /// 選択したテキスト
+    fn selected_text(&self) -> String {
+        let Some((anchor, end)) = self.text_selection else {
+            return String::new();
+        };
+        let (start, end) = (anchor.min(end), anchor.max(end));
+        let first_line = self.line_store.first_line_number();
+        let mut text = String::new();
+        for line in start.line.max(first_line)..=end.line {
+            let Some(content) = self.line_store.text(line - first_line) else {
+                break;
+            };
+            let from = if line == start.line { start.column } else { 0 };
+            let to = if line == end.line {
+                end.column
+            } else {
+                usize::MAX
+            };
+            text.extend(content.chars().skip(from).take(to.saturating_sub(from)));
+            if line != end.line {
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    /// 選択が変わったら、データインスペクタに渡すバイト列を返す
+    ///
+    /// 16 進ダンプで選択したバイト列だけを渡す。ログの表示は制御文字の置き換えや
+    /// 書式の除去を経ているため、表示されたテキストから受信したバイト列には戻せない。
+    pub fn take_selection_update(&mut self) -> Option<Vec<u8>> {
+        let bytes = match self.view_mode {
+            ViewMode::Hex => self
+                .hex_view
+                .selected_bytes(data_inspector::INSPECT_MAX_BYTES),
+            ViewMode::Log | ViewMode::Terminal | ViewMode::Frames => return None,
+        };
+        if bytes == self.inspected_bytes {
+            return None;
+        }
+        self.inspected_bytes = bytes.clone();
+        Some(bytes)
+    }
+
+    /// View メニューでデータインスペクタを開くよう求められたか
+    pub fn take_inspector_request(&mut self) -> bool {
+        std::mem::take(&mut self.is_inspector_requested)
+    }
+
+    /// 表示領域に合わせた大きさの端末画面を描画する
+    fn show_terminal(&mut self, ui: &mut egui::Ui) -> egui::Rect {
+        ui.scope(|ui| {
+            ui.spacing_mut().item_spacing = egui::Vec2 { x: 0.0, y: 0.0 };
+            let (row_height, char_width) = ui.fonts(|fonts| {
+                (
+                    fonts.row_height(&ansi_formatter::LOG_FONT),
+                    fonts.glyph_width(&ansi_formatter::LOG_FONT, 'M'),
+                )
+            });
+            let available_size = ui.available_size();
+            let scroll_bar_width = ui.spacing().scroll.allocated_width();
+            self.terminal.resize(
+                ((available_size.x - scroll_bar_width) / char_width).floor() as usize,
+                (available_size.y / row_height).floor() as usize,
+            );
+
+            let style_options = *self.formatter.style_options();
+            egui::ScrollArea::vertical()
+                .auto_shrink(false)
+                .stick_to_bottom(true)
+                .show_rows(
+                    ui,
+                    row_height,
+                    self.terminal.total_rows(),
+                    |ui, row_range| {
+                        for index in row_range {
+                            let job = self.terminal.layout_row(index, &style_options);
+                            add_row(ui, job, |column| {
+                                self.terminal
+                                    .link_at(index, column)
+                                    .map(|url| Annotation::Link(url.to_string()))
+                            });
+                        }
+                    },
+                )
+                .inner_rect
+        })
+        .inner
+    }
+
     fn disconnect_and_connect(
         &self,
         disconnect_port_name: &str,
         connect_port_name: &str,
         connect_baud_rate: BaudRate,
-    ) {
+    ) -> Result<(), serialport::Error> {
         let mut service = self.serial_service.lock().unwrap();
 
         service.disconnect(disconnect_port_name);
-        match service.connect(connect_port_name, connect_baud_rate) {
-            Ok(_) => {}
-            Err(e) => {
-                eprintln!("Error:{e}");
+        service.connect(connect_port_name, connect_baud_rate)
+    }
+}
+
+/// 1 行を描画する
+fn add_row(
+    ui: &mut egui::Ui,
+    job: egui::text::LayoutJob,
+    annotation_at: impl Fn(usize) -> Option<Annotation>,
+) {
+    let galley = ui.fonts(|fonts| fonts.layout_job(job));
+    let response = ui.add(egui::Label::new(galley.clone()));
+    show_annotation(ui, &galley, response, annotation_at);
+}
+
+/// 選択できるログの 1 行を描画する
+///
+/// 選択した範囲を取り出せるように、ラベルの選択は使わずに背景を塗ってから文字を描く。
+/// time 秒の時点で点滅する文字を表示するかと、次に表示を切り替えるまでの時間
+fn blink_phase(time: f64) -> (bool, std::time::Duration) {
+    let phase = time.fract();
+    let (is_visible, next_change) = if phase < BLINK_VISIBLE_RATIO {
+        (true, BLINK_VISIBLE_RATIO)
+    } else {
+        (false, 1.0)
+    };
+    (
+        is_visible,
+        std::time::Duration::from_secs_f64(next_change - phase),
+    )
+}
+
+fn add_log_row(
+    ui: &mut egui::Ui,
+    job: egui::text::LayoutJob,
+    selected: Option<(Range<usize>, bool)>,
+    annotation_at: impl Fn(usize) -> Option<Annotation>,
+) -> (egui::Response, Arc<egui::Galley>) {
+    let galley = ui.fonts(|fonts| fonts.layout_job(job));
+    let (rect, response) = ui.allocate_exact_size(galley.size(), egui::Sense::click_and_drag());
+    if ui.is_rect_visible(rect) {
+        if let Some((range, is_continued)) = selected {
+            let char_count = galley.text().chars().count();
+            let x_at = |index: usize| {
+                let cursor = egui::text::CCursor::new(index.min(char_count));
+                rect.min.x + galley.pos_from_cursor(cursor).min.x
+            };
+            let mut right = x_at(range.end);
+            if is_continued {
+                // 改行も選んでいることが分かるように少し延ばす
+                right += rect.height() / 2.0;
             }
+            let selection = egui::Rect::from_x_y_ranges(x_at(range.start)..=right, rect.y_range());
+            ui.painter()
+                .rect_filled(selection, 0.0, ui.visuals().selection.bg_fill);
+        }
+        ui.painter()
+            .galley(rect.min, galley.clone(), ui.visuals().text_color());
+    }
+    let response = response.on_hover_cursor(egui::CursorIcon::Text);
+    show_annotation(ui, &galley, response.clone(), annotation_at);
+    (response, galley)
+}
+
+/// リンクの上ではポインタを変えてクリックでリンク先を開き、説明の付いた文字では説明を表示する
+fn show_annotation(
+    ui: &egui::Ui,
+    galley: &egui::Galley,
+    response: egui::Response,
+    annotation_at: impl Fn(usize) -> Option<Annotation>,
+) {
+    let Some(pointer) = response.hover_pos() else {
+        return;
+    };
+    let char_index = galley.cursor_from_pos(pointer - response.rect.min).index;
+    match annotation_at(char_index) {
+        Some(Annotation::Link(url)) => {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+            if response.clicked() {
+                ui.ctx().open_url(egui::OpenUrl::new_tab(&url));
+            }
+            response.on_hover_text(url);
+        }
+        Some(Annotation::Explanation(text)) => {
+            response.on_hover_text(text);
         }
+        None => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn serial_view() -> SerialView {
+        let service = Arc::new(std::sync::Mutex::new(
+            serial::service::SerialService::default(),
+        ));
+        SerialView::new("test".to_string(), service)
+    }
+
+    #[test]
+    fn answers_status_and_attribute_queries() {
+        let mut view = serial_view();
+        view.display_bytes(b"\x1b[5n\x1b[c\x1b[>c");
+        assert_eq!(
+            view.query_responses(),
+            [
+                b"\x1b[0n".to_vec(),
+                b"\x1b[?1;2c".to_vec(),
+                b"\x1b[>0;0;0c".to_vec()
+            ]
+        );
+        assert!(view.query_responses().is_empty());
+    }
+
+    #[test]
+    fn reports_terminal_cursor_position() {
+        let mut view = serial_view();
+        view.display_bytes(b"login:\r\n> ab\x1b[6n");
+        assert_eq!(view.query_responses(), [b"\x1b[2;5R".to_vec()]);
+
+        // ログ表示中も端末側のカーソル位置で答える
+        view.view_mode = ViewMode::Log;
+        view.display_bytes(b"\x1b[10;20H\x1b[6n");
+        assert_eq!(view.query_responses(), [b"\x1b[10;20R".to_vec()]);
+    }
+
+    #[test]
+    fn ignores_queries_when_disabled() {
+        let mut view = serial_view();
+        view.is_answer_queries = false;
+        view.display_bytes(b"\x1b[6n\x1b[c");
+        assert!(view.query_responses().is_empty());
+    }
+
+    #[test]
+    fn blink_phase_schedules_next_change() {
+        assert_eq!(blink_phase(10.0), (true, Duration::from_millis(500)));
+        assert_eq!(blink_phase(10.25), (true, Duration::from_millis(250)));
+        assert_eq!(blink_phase(10.5), (false, Duration::from_millis(500)));
+        assert_eq!(blink_phase(10.75), (false, Duration::from_millis(250)));
     }
 }
@@ -0,0 +1,297 @@
+use super::data_inspector;
+use crate::ansi_formatter::LOG_FONT;
+use crate::decoder::decoded_frame::to_hex;
+use crate::decoder::slcan::{self, CanFrame, CommandBuffer};
+use crate::hex;
+use chrono::{DateTime, Local};
+use eframe::egui;
+use egui::text::{LayoutJob, TextFormat};
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+
+const CAN_MAX_ROWS: usize = 10_000;
+
+struct ReceivedCanFrame {
+    time: DateTime<Local>,
+    frame: CanFrame,
+}
+
+/// SLCAN アダプタの設定と CAN フレームの送受信を行うパネル
+///
+/// 設定のコマンドも CAN フレームも、通常の送信と同じく Controller から書き込む。
+pub struct SlcanPanel {
+    command_buffer: CommandBuffer,
+    frames: VecDeque<ReceivedCanFrame>,
+    error_count: usize,
+    bitrate_index: usize, // slcan::BITRATES_KBPS の位置
+    is_timestamp_enabled: bool,
+    id_filter: String,
+    send_id: String,
+    send_data: String,
+    is_send_extended: bool,
+    is_send_remote: bool,
+    send_remote_dlc: u8,
+}
+
+impl Default for SlcanPanel {
+    fn default() -> Self {
+        Self {
+            command_buffer: CommandBuffer::default(),
+            frames: VecDeque::new(),
+            error_count: 0,
+            bitrate_index: 6,
+            is_timestamp_enabled: false,
+            id_filter: String::new(),
+            send_id: "123".to_string(),
+            send_data: String::new(),
+            is_send_extended: false,
+            is_send_remote: false,
+            send_remote_dlc: 0,
+        }
+    }
+}
+
+impl SlcanPanel {
+    pub fn feed_rx(&mut self, time: DateTime<Local>, bytes: &[u8]) {
+        for command in self.command_buffer.push(time, bytes) {
+            if command.is_error() {
+                self.error_count += 1;
+                continue;
+            }
+            if let Ok(frame) = slcan::parse_frame(command.content()) {
+                self.frames.push_back(ReceivedCanFrame {
+                    time: command.time,
+                    frame,
+                });
+            }
+        }
+        while CAN_MAX_ROWS < self.frames.len() {
+            self.frames.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.error_count = 0;
+    }
+
+    /// アダプタに送るコマンドを返す
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> Vec<Vec<u8>> {
+        let mut commands = Vec::new();
+        ui.heading("SLCAN");
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt(ui.id().with("bitrate"))
+                .selected_text(format!(
+                    "{} kbit/s",
+                    slcan::BITRATES_KBPS[self.bitrate_index]
+                ))
+                .show_ui(ui, |ui| {
+                    for (index, kbps) in slcan::BITRATES_KBPS.iter().enumerate() {
+                        ui.selectable_value(
+                            &mut self.bitrate_index,
+                            index,
+                            format!("{kbps} kbit/s"),
+                        );
+                    }
+                });
+            ui.checkbox(&mut self.is_timestamp_enabled, "Timestamps")
+                .on_hover_text("Ask the adapter to add a millisecond timestamp to each frame");
+        });
+        ui.horizontal(|ui| {
+            // 設定は閉じている間しか受け付けないため、開く前にまとめて送る
+            if ui.button("Open").clicked() {
+                commands.push(slcan::bitrate_command(self.bitrate_index));
+                commands.push(slcan::timestamp_command(self.is_timestamp_enabled));
+                commands.push(slcan::OPEN_COMMAND.to_vec());
+            }
+            if ui
+                .button("Listen only")
+                .on_hover_text("Open without acknowledging or sending frames")
+                .clicked()
+            {
+                commands.push(slcan::bitrate_command(self.bitrate_index));
+                commands.push(slcan::timestamp_command(self.is_timestamp_enabled));
+                commands.push(slcan::LISTEN_ONLY_COMMAND.to_vec());
+            }
+            if ui.button("Close").clicked() {
+                commands.push(slcan::CLOSE_COMMAND.to_vec());
+            }
+        });
+        ui.separator();
+
+        commands.extend(self.send_form(ui));
+        ui.separator();
+
+        let filter = parse_id_filter(&self.id_filter);
+        ui.horizontal(|ui| {
+            ui.label("ID filter");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.id_filter)
+                    .desired_width(f32::INFINITY)
+                    .hint_text("e.g. 100, 200-2FF"),
+            );
+        });
+        if filter.is_none() {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                "Enter hex IDs or ranges like 200-2FF",
+            );
+        }
+        ui.weak(format!(
+            "{} frame(s), {} error response(s)",
+            self.frames.len(),
+            self.error_count
+        ));
+        self.frame_table(ui, filter.as_deref().unwrap_or_default());
+        commands
+    }
+
+    fn send_form(&mut self, ui: &mut egui::Ui) -> Option<Vec<u8>> {
+        let id = hex::parse_u32(self.send_id.trim());
+        let data = data_inspector::parse_hex(&self.send_data);
+        let max_id = if self.is_send_extended {
+            0x1fff_ffff
+        } else {
+            0x7ff
+        };
+
+        let mut is_submitted = false;
+        egui::Grid::new(ui.id().with("can_send"))
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("ID (hex)");
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.send_id).desired_width(80.0));
+                    ui.checkbox(&mut self.is_send_extended, "Extended");
+                    ui.checkbox(&mut self.is_send_remote, "Remote");
+                });
+                ui.end_row();
+                if self.is_send_remote {
+                    ui.label("DLC");
+                    ui.add(egui::DragValue::new(&mut self.send_remote_dlc).range(0..=8));
+                } else {
+                    ui.label("Data (hex)");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.send_data).hint_text("Up to 8 bytes"),
+                    );
+                }
+                ui.end_row();
+            });
+
+        let error = match (id, &data) {
+            (None, _) => Some("Enter the ID in hex".to_string()),
+            (Some(id), _) if max_id < id => Some(format!("ID must be at most {max_id:X}")),
+            (_, None) if !self.is_send_remote => Some("Not a valid hex string".to_string()),
+            (_, Some(data)) if !self.is_send_remote && 8 < data.len() => {
+                Some("At most 8 data bytes".to_string())
+            }
+            _ => None,
+        };
+        ui.horizontal(|ui| {
+            is_submitted = ui
+                .add_enabled(error.is_none(), egui::Button::new("Send frame"))
+                .clicked();
+            if let Some(error) = &error {
+                ui.colored_label(ui.visuals().warn_fg_color, error);
+            }
+        });
+        if !is_submitted || error.is_some() {
+            return None;
+        }
+
+        let (dlc, data) = if self.is_send_remote {
+            (self.send_remote_dlc, Vec::new())
+        } else {
+            let data = data.unwrap_or_default();
+            (data.len() as u8, data)
+        };
+        let frame = CanFrame {
+            id: id?,
+            is_extended: self.is_send_extended,
+            is_remote: self.is_send_remote,
+            dlc,
+            data,
+            timestamp_ms: None,
+        };
+        Some(frame.encode())
+    }
+
+    fn frame_table(&self, ui: &mut egui::Ui, filter: &[RangeInclusive<u32>]) {
+        let visible: Vec<&ReceivedCanFrame> = self
+            .frames
+            .iter()
+            .filter(|received| {
+                filter.is_empty()
+                    || filter
+                        .iter()
+                        .any(|range| range.contains(&received.frame.id))
+            })
+            .collect();
+
+        ui.scope(|ui| {
+            ui.spacing_mut().item_spacing = egui::Vec2 { x: 0.0, y: 0.0 };
+            let row_height = ui.fonts(|fonts| fonts.row_height(&LOG_FONT));
+            ui.label(
+                egui::RichText::new("Time          Stamp  ID        DLC  Data")
+                    .font(LOG_FONT)
+                    .strong(),
+            );
+            egui::ScrollArea::both()
+                .auto_shrink(false)
+                .stick_to_bottom(true)
+                .show_rows(ui, row_height, visible.len(), |ui, row_range| {
+                    for received in &visible[row_range] {
+                        let job = layout_frame(received, ui);
+                        ui.add(egui::Label::new(job).extend());
+                    }
+                });
+        });
+    }
+}
+
+/// `100, 200-2FF` 形式の ID の指定。空なら全てを表す空の Vec、不正なら None
+fn parse_id_filter(text: &str) -> Option<Vec<RangeInclusive<u32>>> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .map(|token| {
+            let parse = |text: &str| hex::parse_u32(text.trim());
+            match token.split_once('-') {
+                Some((start, end)) => Some(parse(start)?..=parse(end)?),
+                None => parse(token).map(|id| id..=id),
+            }
+        })
+        .collect()
+}
+
+fn layout_frame(received: &ReceivedCanFrame, ui: &egui::Ui) -> LayoutJob {
+    let format = |color| TextFormat::simple(LOG_FONT, color);
+    let text_color = ui.visuals().text_color();
+    let weak_color = ui.visuals().weak_text_color();
+    let frame = &received.frame;
+
+    let timestamp = frame
+        .timestamp_ms
+        .map(|timestamp| format!("{timestamp:5}"))
+        .unwrap_or_else(|| "    —".to_string());
+    let data = if frame.is_remote {
+        "RTR".to_string()
+    } else {
+        to_hex(&frame.data)
+    };
+
+    let mut job = LayoutJob::default();
+    job.append(
+        &format!("{}  {timestamp}  ", received.time.format("%H:%M:%S%.3f")),
+        0.0,
+        format(weak_color),
+    );
+    job.append(
+        &format!("{:<8}  ", frame.id_text()),
+        0.0,
+        format(text_color),
+    );
+    job.append(&format!("[{}]  ", frame.dlc), 0.0, format(weak_color));
+    job.append(&data, 0.0, format(text_color));
+    job
+}
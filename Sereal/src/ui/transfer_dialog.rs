@@ -0,0 +1,438 @@
+use super::utils::file_name;
+use crate::sereal_colors;
+use crate::transfer::zmodem::AutoStartDetector;
+use crate::transfer::{Protocol, Transfer, TransferFile, TransferState};
+use chrono::{DateTime, Local};
+use eframe::egui;
+use std::path::{Path, PathBuf};
+
+/// XMODEM はファイル名を送らないため、受け取ったファイルはこの名前で保存する
+const DEFAULT_XMODEM_FILE_NAME: &str = "received.bin";
+/// 同じ名前のファイルがあるときに試す番号の上限
+const SAVE_MAX_NUMBER: usize = 999;
+
+/// XMODEM / YMODEM / ZMODEM でファイルを送受信するダイアログ
+///
+/// 転送中はタブの受信データをすべて転送に回し、ログや他のパネルには渡さない。
+pub struct TransferDialog {
+    protocol: Protocol,
+    is_sending: bool,
+    /// 送るファイルのパス。1 行に 1 つ
+    send_paths: String,
+    save_directory: String,
+    xmodem_file_name: String,
+    /// 相手が sz を始めたら ZMODEM で受け取る。意図しない受信を避けるため既定では無効
+    is_auto_start: bool,
+    detector: AutoStartDetector,
+    transfer: Option<Box<dyn Transfer>>,
+    /// 実行中の転送の説明
+    description: String,
+    result: Option<(String, bool)>, // (内容, エラーかどうか)
+    events: Vec<String>,
+}
+
+impl Default for TransferDialog {
+    fn default() -> Self {
+        Self {
+            protocol: Protocol::Ymodem,
+            is_sending: true,
+            send_paths: String::new(),
+            save_directory: ".".to_string(),
+            xmodem_file_name: DEFAULT_XMODEM_FILE_NAME.to_string(),
+            is_auto_start: false,
+            detector: AutoStartDetector::default(),
+            transfer: None,
+            description: String::new(),
+            result: None,
+            events: Vec::new(),
+        }
+    }
+}
+
+impl TransferDialog {
+    pub fn is_running(&self) -> bool {
+        self.transfer.is_some()
+    }
+
+    /// 受信したバイト列を転送に渡し、返答を返す。転送していなければ None
+    pub fn feed_rx(&mut self, time: DateTime<Local>, bytes: &[u8]) -> Option<Vec<u8>> {
+        if self.transfer.is_none() {
+            if !self.is_auto_start || !self.detector.push(bytes) {
+                return None;
+            }
+            self.events
+                .push("ZMODEM transfer detected, receiving".to_string());
+            self.start(Protocol::Zmodem, false, Vec::new());
+            return Some(Vec::new());
+        }
+        let reply = self
+            .transfer
+            .as_mut()
+            .map(|transfer| transfer.feed(time, bytes))
+            .unwrap_or_default();
+        self.check_finished();
+        Some(reply)
+    }
+
+    /// 送るバイト列を返す
+    pub fn poll(&mut self, now: DateTime<Local>) -> Vec<u8> {
+        let bytes = self
+            .transfer
+            .as_mut()
+            .map(|transfer| transfer.poll(now))
+            .unwrap_or_default();
+        self.check_finished();
+        bytes
+    }
+
+    pub fn next_poll(&self, now: DateTime<Local>) -> Option<std::time::Duration> {
+        self.transfer.as_ref()?.next_poll(now)
+    }
+
+    /// イベントの履歴に残す内容
+    pub fn take_events(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn start(&mut self, protocol: Protocol, is_sending: bool, files: Vec<TransferFile>) {
+        self.description = if is_sending {
+            let names: Vec<&str> = files.iter().map(|file| file.name.as_str()).collect();
+            format!("Sending {} with {}", names.join(", "), protocol.name())
+        } else {
+            format!("Receiving with {}", protocol.name())
+        };
+        self.events.push(self.description.clone());
+        self.result = None;
+        self.transfer = Some(if is_sending {
+            protocol.sender(files)
+        } else {
+            protocol.receiver()
+        });
+    }
+
+    fn start_sending(&mut self) {
+        let mut files = Vec::new();
+        for path in self
+            .send_paths
+            .lines()
+            .map(str::trim)
+            .filter(|path| !path.is_empty())
+        {
+            match std::fs::read(path) {
+                Ok(content) => files.push(TransferFile {
+                    name: file_name(path),
+                    content,
+                }),
+                Err(error) => {
+                    self.result = Some((format!("Failed to read {path}: {error}"), true));
+                    return;
+                }
+            }
+        }
+        if !self.protocol.is_batch() {
+            files.truncate(1);
+        }
+        self.start(self.protocol, true, files);
+    }
+
+    /// 終わった転送を片付け、受け取ったファイルを保存する
+    fn check_finished(&mut self) {
+        let Some(transfer) = &mut self.transfer else {
+            return;
+        };
+        let files = transfer.take_received_files();
+        for file in files {
+            let name = if file.name.is_empty() {
+                self.xmodem_file_name.trim().to_string()
+            } else {
+                file_name(&file.name)
+            };
+            let path = Path::new(self.save_directory.trim()).join(name);
+            self.events.push(match save_new_file(&path, &file.content) {
+                Ok(saved) => format!("Saved {} ({} bytes)", saved.display(), file.content.len()),
+                Err(error) => format!("Failed to save {}: {error}", path.display()),
+            });
+        }
+
+        let status = transfer.status();
+        let result = match &status.state {
+            TransferState::Running => return,
+            TransferState::Completed => (
+                format!(
+                    "Completed: {} file(s), {} retries",
+                    status.files_done, status.retries
+                ),
+                false,
+            ),
+            TransferState::Failed(error) => (format!("Failed: {error}"), true),
+            TransferState::Cancelled(reason) => (reason.clone(), true),
+        };
+        self.events
+            .push(format!("{}: {}", self.description, result.0));
+        self.result = Some(result);
+        self.transfer = None;
+    }
+
+    /// 転送を取り消したときに相手へ送るバイト列を返す
+    pub fn ui(&mut self, ui: &mut egui::Ui, is_open: &mut bool) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let is_running = self.is_running();
+        // 転送中は閉じられないようにする
+        let mut is_window_open = *is_open;
+        let mut window = egui::Window::new("File transfer")
+            .id(ui.id().with("file_transfer"))
+            .collapsible(false)
+            .resizable(false);
+        if !is_running {
+            window = window.open(&mut is_window_open);
+        }
+        window.show(ui.ctx(), |ui| {
+            if is_running {
+                bytes = self.progress(ui);
+            } else {
+                self.settings(ui);
+            }
+        });
+        *is_open = is_window_open;
+        bytes
+    }
+
+    fn progress(&mut self, ui: &mut egui::Ui) -> Vec<u8> {
+        let Some(transfer) = &mut self.transfer else {
+            return Vec::new();
+        };
+        let status = transfer.status().clone();
+        ui.label(&self.description);
+        if let Some(name) = &status.file_name {
+            ui.monospace(name);
+        }
+        match status.bytes_total {
+            Some(total) => {
+                let ratio = status.bytes_done as f32 / total.max(1) as f32;
+                ui.add(
+                    egui::ProgressBar::new(ratio)
+                        .desired_width(280.0)
+                        .text(format!("{} / {total} bytes", status.bytes_done)),
+                );
+            }
+            None => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(format!("{} bytes", status.bytes_done));
+                });
+            }
+        }
+        ui.label(format!(
+            "{} file(s) done, {} retries",
+            status.files_done, status.retries
+        ));
+        let mut bytes = Vec::new();
+        if ui.button("Cancel").clicked() {
+            bytes = transfer.cancel();
+            self.check_finished();
+        }
+        bytes
+    }
+
+    fn settings(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new(ui.id().with("transfer_settings"))
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Protocol");
+                egui::ComboBox::from_id_salt(ui.id().with("transfer_protocol"))
+                    .selected_text(self.protocol.name())
+                    .show_ui(ui, |ui| {
+                        for protocol in Protocol::ALL {
+                            ui.selectable_value(&mut self.protocol, protocol, protocol.name());
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Direction");
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.is_sending, true, "Send");
+                    ui.radio_value(&mut self.is_sending, false, "Receive");
+                });
+                ui.end_row();
+
+                if self.is_sending {
+                    ui.label("Files");
+                    let hint = if self.protocol.is_batch() {
+                        "Local paths, one per line"
+                    } else {
+                        "Local path"
+                    };
+                    ui.add(
+                        egui::TextEdit::multiline(&mut self.send_paths)
+                            .desired_rows(if self.protocol.is_batch() { 3 } else { 1 })
+                            .desired_width(280.0)
+                            .hint_text(hint),
+                    );
+                    ui.end_row();
+                } else {
+                    ui.label("Save to");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.save_directory)
+                            .desired_width(280.0)
+                            .hint_text("Directory"),
+                    );
+                    ui.end_row();
+                    if !self.protocol.is_batch() {
+                        ui.label("File name");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.xmodem_file_name)
+                                .desired_width(280.0),
+                        );
+                        ui.end_row();
+                    }
+                }
+            });
+
+        ui.checkbox(&mut self.is_auto_start, "Receive when the remote starts sz")
+            .on_hover_text(
+                "Start a ZMODEM receive when the ZMODEM start sequence arrives. \
+                 Files are saved to the Save to directory and existing files are never overwritten",
+            );
+
+        let path_count = self
+            .send_paths
+            .lines()
+            .filter(|path| !path.trim().is_empty())
+            .count();
+        let error = if self.is_sending && path_count == 0 {
+            Some("Enter a file to send")
+        } else if self.is_sending && !self.protocol.is_batch() && 1 < path_count {
+            Some("XMODEM sends only the first file")
+        } else if !self.is_sending
+            && !self.protocol.is_batch()
+            && self.xmodem_file_name.trim().is_empty()
+        {
+            Some("Enter a file name to save as")
+        } else {
+            None
+        };
+        let is_startable = error.is_none_or(|_| self.is_sending && 0 < path_count);
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(is_startable, egui::Button::new("Start"))
+                .clicked()
+            {
+                if self.is_sending {
+                    self.start_sending();
+                } else {
+                    self.start(self.protocol, false, Vec::new());
+                }
+            }
+            if let Some(error) = error {
+                ui.colored_label(ui.visuals().warn_fg_color, error);
+            }
+        });
+
+        match &self.result {
+            Some((message, true)) => {
+                ui.colored_label(sereal_colors::UI_RED.to_egui_color32(), message);
+            }
+            Some((message, false)) => {
+                ui.label(message);
+            }
+            None => {}
+        }
+    }
+}
+
+/// 受け取ったファイルを書き込み、書き込んだパスを返す
+///
+/// 既にあるファイルは上書きせず、`name (1).ext` のように番号を付けた名前で保存する。
+fn save_new_file(path: &Path, content: &[u8]) -> std::io::Result<PathBuf> {
+    use std::io::Write;
+    let candidates = std::iter::once(path.to_path_buf())
+        .chain((1..=SAVE_MAX_NUMBER).map(|n| numbered_path(path, n)));
+    for candidate in candidates {
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(mut file) => {
+                file.write_all(content)?;
+                return Ok(candidate);
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error),
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::AlreadyExists,
+        "too many files with the same name",
+    ))
+}
+
+/// `dir/name.ext` に番号を付けた `dir/name (number).ext`
+fn numbered_path(path: &Path, number: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem} ({number}).{}", extension.to_string_lossy()),
+        None => format!("{stem} ({number})"),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// テストごとに空の作業ディレクトリを作る
+    fn scratch_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir()
+            .join(format!("sereal-transfer-dialog-{}", std::process::id()))
+            .join(name);
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn numbers_file_names() {
+        let path = Path::new("dir").join("log.tar.gz");
+        assert_eq!(
+            numbered_path(&path, 1),
+            Path::new("dir").join("log.tar (1).gz")
+        );
+        assert_eq!(
+            numbered_path(Path::new("README"), 12),
+            Path::new("README (12)")
+        );
+        assert_eq!(
+            numbered_path(Path::new(".bashrc"), 2),
+            Path::new(".bashrc (2)")
+        );
+    }
+
+    #[test]
+    fn does_not_overwrite_existing_files() {
+        let directory = scratch_directory("overwrite");
+        let path = directory.join("data.bin");
+
+        assert_eq!(save_new_file(&path, b"first").unwrap(), path);
+        let second = save_new_file(&path, b"second").unwrap();
+        assert_eq!(second, directory.join("data (1).bin"));
+        let third = save_new_file(&path, b"third").unwrap();
+        assert_eq!(third, directory.join("data (2).bin"));
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"first");
+        assert_eq!(std::fs::read(&second).unwrap(), b"second");
+        assert_eq!(std::fs::read(&third).unwrap(), b"third");
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn reports_other_errors() {
+        let directory = scratch_directory("missing");
+        let path = directory.join("no-such-directory").join("data.bin");
+        assert_eq!(
+            save_new_file(&path, b"x").unwrap_err().kind(),
+            std::io::ErrorKind::NotFound
+        );
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
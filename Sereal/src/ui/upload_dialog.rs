@@ -0,0 +1,201 @@
+use crate::sereal_colors;
+use crate::stk500::programmer::{Progress, Stage};
+use crate::stk500::{FlashImage, UploadEvent, UploadJob, intel_hex};
+use eframe::egui;
+
+/// Optiboot の通信速度の候補
+const BOOTLOADER_BAUD_RATES: [(u32, &str); 2] = [
+    (115200, "115200 (Uno, Optiboot)"),
+    (57600, "57600 (Nano, old bootloader)"),
+];
+
+/// 書き込み中の状態
+struct RunningUpload {
+    job: UploadJob,
+    /// 終わったらモニターを繋ぎ直す
+    is_reconnect: bool,
+    progress: Option<Progress>,
+}
+
+/// Intel HEX を Arduino の Optiboot に書き込むダイアログ
+pub struct UploadDialog {
+    hex_path: String,
+    baud_rate: u32,
+    upload: Option<RunningUpload>,
+    result: Option<(String, bool)>, // (内容, エラーかどうか)
+    events: Vec<String>,
+}
+
+impl Default for UploadDialog {
+    fn default() -> Self {
+        Self {
+            hex_path: String::new(),
+            baud_rate: BOOTLOADER_BAUD_RATES[0].0,
+            upload: None,
+            result: None,
+            events: Vec::new(),
+        }
+    }
+}
+
+impl UploadDialog {
+    pub fn is_running(&self) -> bool {
+        self.upload.is_some()
+    }
+
+    /// ポートを閉じた後に呼び、書き込みを始める
+    pub fn start(&mut self, port_name: &str, image: FlashImage, is_reconnect: bool) {
+        self.events.push(format!(
+            "Uploading {} ({} bytes) to {port_name} at {}",
+            self.hex_path.trim(),
+            image.data.len(),
+            self.baud_rate
+        ));
+        self.result = None;
+        self.upload = Some(RunningUpload {
+            job: UploadJob::start(port_name, self.baud_rate, image),
+            is_reconnect,
+            progress: None,
+        });
+    }
+
+    /// 書き込みが終わったら、モニターを繋ぎ直すかどうかを返す
+    pub fn poll(&mut self) -> Option<bool> {
+        let upload = self.upload.as_mut()?;
+        let mut finished = None;
+        for event in upload.job.try_events() {
+            match event {
+                UploadEvent::Progress(progress) => upload.progress = Some(progress),
+                UploadEvent::Finished(result) => finished = Some(result),
+            }
+        }
+        let result = match finished? {
+            Ok(summary) => (
+                format!(
+                    "Uploaded {} bytes to the {} (Optiboot {}.{}) and verified",
+                    summary.bytes,
+                    summary.device,
+                    summary.bootloader_version.0,
+                    summary.bootloader_version.1
+                ),
+                false,
+            ),
+            Err(e) => (format!("Upload failed: {e}"), true),
+        };
+        self.events.push(result.0.clone());
+        self.result = Some(result);
+        self.upload.take().map(|upload| upload.is_reconnect)
+    }
+
+    /// イベントの履歴に残す内容
+    pub fn take_events(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Upload が押されたら、読み込んだ HEX ファイルの内容を返す
+    pub fn ui(&mut self, ui: &mut egui::Ui, is_open: &mut bool) -> Option<FlashImage> {
+        let mut image = None;
+        let is_running = self.is_running();
+        // 書き込み中は閉じられないようにする
+        let mut is_window_open = *is_open;
+        let mut window = egui::Window::new("Upload .hex")
+            .id(ui.id().with("upload_hex"))
+            .collapsible(false)
+            .resizable(false);
+        if !is_running {
+            window = window.open(&mut is_window_open);
+        }
+        window.show(ui.ctx(), |ui| {
+            if let Some(upload) = &self.upload {
+                progress(ui, upload.progress);
+            } else {
+                image = self.settings(ui);
+            }
+        });
+        *is_open = is_window_open;
+        image
+    }
+
+    fn settings(&mut self, ui: &mut egui::Ui) -> Option<FlashImage> {
+        let mut image = None;
+        egui::Grid::new(ui.id().with("upload_settings"))
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("HEX file");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.hex_path)
+                        .desired_width(280.0)
+                        .hint_text("Local path to the compiled .hex"),
+                );
+                ui.end_row();
+
+                ui.label("Bootloader");
+                let selected = BOOTLOADER_BAUD_RATES
+                    .iter()
+                    .find(|(baud_rate, _)| *baud_rate == self.baud_rate)
+                    .map_or("", |(_, name)| name);
+                egui::ComboBox::from_id_salt(ui.id().with("bootloader_baud_rate"))
+                    .selected_text(selected)
+                    .show_ui(ui, |ui| {
+                        for (baud_rate, name) in BOOTLOADER_BAUD_RATES {
+                            ui.selectable_value(&mut self.baud_rate, baud_rate, name);
+                        }
+                    });
+                ui.end_row();
+            });
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(
+                    !self.hex_path.trim().is_empty(),
+                    egui::Button::new("Upload"),
+                )
+                .on_hover_text("Reset the board, write and verify the sketch, then reconnect")
+                .clicked()
+            {
+                let path = self.hex_path.trim();
+                match std::fs::read_to_string(path)
+                    .map_err(|e| format!("Failed to read {path}: {e}"))
+                    .and_then(|text| intel_hex::parse(&text))
+                {
+                    Ok(parsed) => image = Some(parsed),
+                    Err(e) => self.result = Some((e, true)),
+                }
+            }
+        });
+
+        match &self.result {
+            Some((message, true)) => {
+                ui.colored_label(sereal_colors::UI_RED.to_egui_color32(), message);
+            }
+            Some((message, false)) => {
+                ui.label(message);
+            }
+            None => {}
+        }
+        image
+    }
+}
+
+fn progress(ui: &mut egui::Ui, progress: Option<Progress>) {
+    let Some(progress) = progress else {
+        ui.horizontal(|ui| {
+            ui.spinner();
+            ui.label("Resetting the board and waiting for the bootloader");
+        });
+        return;
+    };
+    let stage = match progress.stage {
+        Stage::Writing => "Writing",
+        Stage::Verifying => "Verifying",
+    };
+    ui.label(stage);
+    ui.add(
+        egui::ProgressBar::new(progress.bytes_done as f32 / progress.bytes_total.max(1) as f32)
+            .desired_width(280.0)
+            .text(format!(
+                "{} / {} bytes",
+                progress.bytes_done, progress.bytes_total
+            )),
+    );
+}
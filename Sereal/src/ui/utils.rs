@@ -0,0 +1,21 @@
+use std::path::Path;
+
+/// パスを除いたファイル名。取り出せなければパス全体を返す
+pub fn file_name(path: impl AsRef<Path>) -> String {
+    let path = path.as_ref();
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_directories() {
+        assert_eq!(file_name("/home/user/firmware.elf"), "firmware.elf");
+        assert_eq!(file_name(Path::new("image.bin")), "image.bin");
+        assert_eq!(file_name("/"), "/");
+    }
+}